@@ -273,6 +273,7 @@ async fn cmd_store(mut l: Level<Stuff>) -> Result<()> {
 async fn cmd_store_get(mut l: Level<Stuff>) -> Result<()> {
     l.usage_args(Some("NAME"));
     l.optflag("W", "", "do not wait for the value to exist in the store");
+    l.optflag("r", "", "print the value exactly as stored");
 
     let a = args!(l);
 
@@ -282,6 +283,7 @@ async fn cmd_store_get(mut l: Level<Stuff>) -> Result<()> {
 
     let name = a.args()[0].to_string();
     let no_wait = a.opts().opt_present("W");
+    let raw = a.opts().opt_present("r");
     let mut printed_wait = false;
 
     loop {
@@ -312,7 +314,7 @@ async fn cmd_store_get(mut l: Level<Stuff>) -> Result<()> {
                          * what "buildomat job store get" does outside a job;
                          * see the "buildomat" crate.
                          */
-                        if ent.value.ends_with("\n") {
+                        if raw || ent.value.ends_with("\n") {
                             print!("{}", ent.value);
                         } else {
                             println!("{}", ent.value);
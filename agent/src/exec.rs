@@ -3,7 +3,7 @@
  */
 
 use std::io::{BufRead, BufReader, Read};
-use std::os::unix::process::ExitStatusExt;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
 use std::process::{Command, Stdio};
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc::{channel, Receiver, Sender};
@@ -81,6 +81,7 @@ pub struct ExitDetails {
     pub duration_ms: u64,
     pub when: DateTime<Utc>,
     pub code: i32,
+    pub signal: Option<i32>,
 }
 
 #[derive(Clone, Debug)]
@@ -108,11 +109,17 @@ pub enum Activity {
 }
 
 impl Activity {
-    fn exit(start: &Instant, end: &Instant, code: i32) -> Activity {
+    fn exit(
+        start: &Instant,
+        end: &Instant,
+        code: i32,
+        signal: Option<i32>,
+    ) -> Activity {
         Activity::Exit(ExitDetails {
             duration_ms: end.duration_since(*start).as_millis() as u64,
             when: Utc::now(),
             code,
+            signal,
         })
     }
 
@@ -175,90 +182,123 @@ pub fn thread_done(
     }
 }
 
-pub fn run(mut cmd: Command) -> Result<Receiver<Activity>> {
+/**
+ * Wait for a process started by "run()" to exit, and for its output to drain,
+ * reporting the activity on the channel.  Returns None if the channel is
+ * closed.
+ */
+fn wait_child(
+    mut child: std::process::Child,
+    start: Instant,
+    tx: &Sender<Activity>,
+    mut readout: Option<std::thread::JoinHandle<()>>,
+    mut readerr: Option<std::thread::JoinHandle<()>>,
+) -> Option<()> {
+    let wait = child.wait();
+    let end = Instant::now();
+    let stdio_warning = match wait {
+        Err(e) => {
+            tx.blocking_send(Activity::err(&format!(
+                "child wait failed: {:?}",
+                e
+            )))
+            .ok()?;
+            tx.blocking_send(Activity::exit(&start, &end, std::i32::MAX, None))
+                .ok()?;
+            false
+        }
+        Ok(es) => {
+            /*
+             * Wait up to five seconds for stdio threads to flush out before
+             * we report exit status.  In general we expect this to complete
+             * within milliseconds.  This is really just cosmetic; we would
+             * like, when possible, the final log output of a command to
+             * appear prior to its exit status.
+             */
+            let until =
+                Instant::now().checked_add(Duration::from_secs(5)).unwrap();
+            let stdio_warning = !thread_done(&mut readout, "stdout", until)
+                | !thread_done(&mut readerr, "stderr", until);
+
+            if let Some(sig) = es.signal() {
+                tx.blocking_send(Activity::warn(&format!(
+                    "child terminated by signal {}",
+                    sig
+                )))
+                .ok()?;
+            }
+            let code =
+                if let Some(code) = es.code() { code } else { std::i32::MAX };
+            tx.blocking_send(Activity::exit(&start, &end, code, es.signal()))
+                .ok()?;
+            stdio_warning
+        }
+    };
+
+    if stdio_warning {
+        tx.blocking_send(Activity::warn(&format!(
+            "stdio descriptors remain open after task exit; \
+            waiting 60 seconds for them to close",
+        )))
+        .ok()?;
+    }
+
+    let until = Instant::now().checked_add(Duration::from_secs(60)).unwrap();
+    if !thread_done(&mut readout, "stdout", until) {
+        tx.blocking_send(Activity::warn(
+            "stdout descriptor may be held open by a background process; \
+            giving up!",
+        ))
+        .ok()?;
+    }
+    if !thread_done(&mut readerr, "stderr", until) {
+        tx.blocking_send(Activity::warn(
+            "stderr descriptor may be held open by a background process; \
+            giving up!",
+        ))
+        .ok()?;
+    }
+
+    tx.blocking_send(Activity::Complete).ok()
+}
+
+/**
+ * Start a process, returning a channel that reports its output and exit
+ * status, and its process ID.  The process is placed in a new process group,
+ * so that it and any descendants can be terminated with "kill()".
+ */
+pub fn run(mut cmd: Command) -> Result<(Receiver<Activity>, u32)> {
     let (tx, rx) = channel::<Activity>(64);
 
     cmd.stdin(Stdio::null());
     cmd.stdout(Stdio::piped());
     cmd.stderr(Stdio::piped());
+    cmd.process_group(0);
 
     let start = Instant::now();
     let mut child = cmd.spawn()?;
+    let pid = child.id();
 
-    let mut readout = spawn_reader(tx.clone(), "stdout", child.stdout.take());
-    let mut readerr = spawn_reader(tx.clone(), "stderr", child.stderr.take());
+    let readout = spawn_reader(tx.clone(), "stdout", child.stdout.take());
+    let readerr = spawn_reader(tx.clone(), "stderr", child.stderr.take());
 
     std::thread::spawn(move || {
-        let wait = child.wait();
-        let end = Instant::now();
-        let stdio_warning = match wait {
-            Err(e) => {
-                tx.blocking_send(Activity::err(&format!(
-                    "child wait failed: {:?}",
-                    e
-                )))
-                .unwrap();
-                tx.blocking_send(Activity::exit(&start, &end, std::i32::MAX))
-                    .unwrap();
-                false
-            }
-            Ok(es) => {
-                /*
-                 * Wait up to five seconds for stdio threads to flush out before
-                 * we report exit status.  In general we expect this to complete
-                 * within milliseconds.  This is really just cosmetic; we would
-                 * like, when possible, the final log output of a command to
-                 * appear prior to its exit status.
-                 */
-                let until =
-                    Instant::now().checked_add(Duration::from_secs(5)).unwrap();
-                let stdio_warning = !thread_done(&mut readout, "stdout", until)
-                    | !thread_done(&mut readerr, "stderr", until);
-
-                if let Some(sig) = es.signal() {
-                    tx.blocking_send(Activity::warn(&format!(
-                        "child terminated by signal {}",
-                        sig
-                    )))
-                    .unwrap();
-                }
-                let code = if let Some(code) = es.code() {
-                    code
-                } else {
-                    std::i32::MAX
-                };
-                tx.blocking_send(Activity::exit(&start, &end, code)).unwrap();
-                stdio_warning
-            }
-        };
-
-        if stdio_warning {
-            tx.blocking_send(Activity::warn(&format!(
-                "stdio descriptors remain open after task exit; \
-                waiting 60 seconds for them to close",
-            )))
-            .unwrap();
-        }
-
-        let until =
-            Instant::now().checked_add(Duration::from_secs(60)).unwrap();
-        if !thread_done(&mut readout, "stdout", until) {
-            tx.blocking_send(Activity::warn(
-                "stdout descriptor may be held open by a background process; \
-                giving up!",
-            ))
-            .unwrap();
-        }
-        if !thread_done(&mut readerr, "stderr", until) {
-            tx.blocking_send(Activity::warn(
-                "stderr descriptor may be held open by a background process; \
-                giving up!",
-            ))
-            .unwrap();
-        }
-
-        tx.blocking_send(Activity::Complete).unwrap();
+        /*
+         * If the agent has abandoned the task (e.g., because the job was
+         * cancelled), nobody is listening for the rest of the activity.
+         */
+        wait_child(child, start, &tx, readout, readerr);
     });
 
-    Ok(rx)
+    Ok((rx, pid))
+}
+
+/**
+ * Forcibly terminate every process in the process group of a process started
+ * by "run()".
+ */
+pub fn kill(pid: u32) {
+    if let Ok(pid) = libc::pid_t::try_from(pid) {
+        unsafe { libc::kill(-pid, libc::SIGKILL) };
+    }
 }
@@ -31,6 +31,7 @@ use buildomat_types::*;
 mod control;
 mod download;
 mod exec;
+mod stream;
 #[cfg(target_os = "illumos")]
 mod uadmin;
 mod upload;
@@ -71,6 +72,7 @@ impl ConfigFile {
                 .build()
                 .expect("new client"),
             job: None,
+            events: None,
         }
     }
 }
@@ -89,36 +91,35 @@ impl OutputRecord {
             msg: msg.to_string(),
         }
     }
+
+    fn to_event(&self, task: Option<u32>) -> WorkerAppendJobBatchEvent {
+        WorkerAppendJobBatchEvent {
+            payload: self.msg.to_string(),
+            stream: self.stream.to_string(),
+            task,
+            time: self.time,
+        }
+    }
 }
 
 #[derive(Clone)]
 pub(crate) struct ClientWrap {
     client: buildomat_client::Client,
     job: Option<WorkerPingJob>,
+    events: Option<stream::EventStream>,
 }
 
 impl ClientWrap {
-    async fn append(&self, rec: &OutputRecord) {
-        let job = self.job.as_ref().unwrap();
+    fn adopt(&mut self, job: &WorkerPingJob) {
+        self.job = Some(job.clone());
+        self.events = Some(stream::EventStream::start(
+            self.client.clone(),
+            job.id.to_string(),
+        ));
+    }
 
-        loop {
-            match self
-                .client
-                .worker_job_append()
-                .job(&job.id)
-                .body_map(|body| {
-                    body.stream(&rec.stream).time(rec.time).payload(&rec.msg)
-                })
-                .send()
-                .await
-            {
-                Ok(_) => return,
-                Err(e) => {
-                    println!("ERROR: append: {:?}", e);
-                    sleep_ms(1000).await;
-                }
-            }
-        }
+    async fn append(&self, rec: &OutputRecord) {
+        self.events.as_ref().unwrap().append(rec.to_event(None)).await;
     }
 
     async fn append_msg(&self, msg: &str) {
@@ -126,43 +127,55 @@ impl ClientWrap {
     }
 
     async fn append_task(&self, task: &WorkerPingTask, rec: &OutputRecord) {
+        self.events.as_ref().unwrap().append(rec.to_event(Some(task.id))).await;
+    }
+
+    async fn append_task_msg(&self, task: &WorkerPingTask, msg: &str) {
+        self.append_task(task, &OutputRecord::new("task", msg)).await;
+    }
+
+    async fn task_env(&self, task: &WorkerPingTask) -> WorkerTaskEnv {
         let job = self.job.as_ref().unwrap();
 
         loop {
             match self
                 .client
-                .worker_task_append()
+                .worker_task_env()
                 .job(&job.id)
                 .task(task.id)
-                .body_map(|body| {
-                    body.stream(&rec.stream).time(rec.time).payload(&rec.msg)
-                })
                 .send()
                 .await
             {
-                Ok(_) => return,
+                Ok(res) => return res.into_inner(),
                 Err(e) => {
-                    println!("ERROR: append: {:?}", e);
+                    println!("ERROR: task env: {:?}", e);
                     sleep_ms(1000).await;
                 }
             }
         }
     }
 
-    async fn append_task_msg(&self, task: &WorkerPingTask, msg: &str) {
-        self.append_task(task, &OutputRecord::new("task", msg)).await;
-    }
-
-    async fn task_complete(&self, task: &WorkerPingTask, failed: bool) {
+    async fn task_complete(
+        &self,
+        task: &WorkerPingTask,
+        failed: bool,
+        failure: Option<FailureSummary>,
+    ) {
         let job = self.job.as_ref().unwrap();
 
+        /*
+         * Make sure the server has all of the output from the task before we
+         * report that it is complete.
+         */
+        self.events.as_ref().unwrap().flush().await;
+
         loop {
             match self
                 .client
                 .worker_task_complete()
                 .job(&job.id)
                 .task(task.id)
-                .body_map(|body| body.failed(failed))
+                .body_map(|body| body.failed(failed).failure(failure.clone()))
                 .send()
                 .await
             {
@@ -178,6 +191,8 @@ impl ClientWrap {
     async fn job_complete(&self, failed: bool) {
         let job = self.job.as_ref().unwrap();
 
+        self.events.as_ref().unwrap().flush().await;
+
         loop {
             match self
                 .client
@@ -232,6 +247,11 @@ impl ClientWrap {
             path: path.to_str().unwrap().to_string(),
             size,
             commit_id: commit_id.to_string(),
+            /*
+             * The agent does not know what kind of file the job has produced,
+             * so the type is left for the server to determine on download.
+             */
+            content_type: None,
         };
 
         loop {
@@ -336,6 +356,21 @@ impl ClientWrap {
     }
 }
 
+/**
+ * Summarise the exit of a task process for the server, so that the failure can
+ * be described to the user without reference to the task output.
+ */
+fn exit_failure(ex: &ExitDetails) -> FailureSummary {
+    /*
+     * If the process was killed by a signal, or we could not wait for it, we
+     * do not have a meaningful exit code.
+     */
+    let code =
+        (ex.signal.is_none() && ex.code != std::i32::MAX).then_some(ex.code);
+
+    FailureSummary { code, signal: ex.signal, message: None }
+}
+
 fn load<P, T>(p: P) -> Result<T>
 where
     P: AsRef<Path>,
@@ -442,11 +477,27 @@ fn hard_reset() -> Result<()> {
     Ok(())
 }
 
+/**
+ * A task that was submitted with a timeout must be killed if it is still
+ * running at the deadline.
+ */
+struct TaskDeadline {
+    when: tokio::time::Instant,
+    seconds: u64,
+    expired: bool,
+}
+
 enum Stage {
     Ready,
     Download(mpsc::Receiver<download::Activity>),
     NextTask,
-    Child(mpsc::Receiver<exec::Activity>, WorkerPingTask, Option<bool>),
+    Child(
+        mpsc::Receiver<exec::Activity>,
+        WorkerPingTask,
+        Option<bool>,
+        u32,
+        Option<TaskDeadline>,
+    ),
     Upload(mpsc::Receiver<upload::Activity>),
     Complete,
 }
@@ -630,6 +681,7 @@ async fn cmd_run(mut l: Level<()>) -> Result<()> {
     let mut tasks: VecDeque<WorkerPingTask> = VecDeque::new();
     let mut stage = Stage::Ready;
     let mut exit_details: Vec<ExitDetails> = Vec::new();
+    let mut secrets: Vec<String> = Vec::new();
     let mut upload_errors = false;
 
     let mut pingfreq = tokio::time::interval(Duration::from_secs(5));
@@ -660,6 +712,26 @@ async fn cmd_run(mut l: Level<()>) -> Result<()> {
                         continue;
                     }
 
+                    if p.cancel
+                        && cw.job.is_some()
+                        && !matches!(stage, Stage::Complete)
+                    {
+                        /*
+                         * The job was cancelled while we were working on it.
+                         * The server has already recorded the cancellation
+                         * and will fail the job and recycle this worker, so
+                         * we just need to stop running tasks and uploading
+                         * outputs in the meantime.  If a task is running,
+                         * kill it and any processes it has started.
+                         */
+                        println!("abandoning job at server request");
+                        if let Stage::Child(_, _, _, pid, _) = &stage {
+                            exec::kill(*pid);
+                        }
+                        tasks.clear();
+                        stage = Stage::Complete;
+                    }
+
                     /*
                      * If we have not yet been assigned a task, check for one:
                      */
@@ -678,7 +750,7 @@ async fn cmd_run(mut l: Level<()>) -> Result<()> {
                             );
                             tasks.clear();
                             tasks.extend(j.tasks.iter().cloned());
-                            cw.job = Some(j.clone());
+                            cw.adopt(j);
                             pingfreq.reset();
                             stage = Stage::Download(download::download(
                                 cw.clone(),
@@ -768,17 +840,21 @@ async fn cmd_run(mut l: Level<()>) -> Result<()> {
                 let job = cw.job.as_ref().unwrap();
 
                 /*
-                 * If any task fails, we will not execute subsequent tasks.
-                 * In case it is useful for diagnostic purposes, we will
-                 * still attempt to upload output files before we complete
-                 * the job.
+                 * If any task fails, we will not execute subsequent tasks
+                 * unless they have been marked to run regardless; e.g., to
+                 * collect logs from a failed build.  In case it is useful for
+                 * diagnostic purposes, we will still attempt to upload output
+                 * files before we complete the job.
                  */
                 let failures = exit_details.iter().any(|ex| ex.code != 0);
                 if failures {
-                    println!("aborting after failed task");
+                    while matches!(tasks.front(), Some(t) if !t.always_run) {
+                        let t = tasks.pop_front().unwrap();
+                        println!("skipping task {} after failed task", t.id);
+                    }
                 }
 
-                if failures || tasks.is_empty() {
+                if tasks.is_empty() {
                     /*
                      * There are no more tasks to complete, so move on to
                      * uploading outputs.
@@ -801,6 +877,46 @@ async fn cmd_run(mut l: Level<()>) -> Result<()> {
                 let msg = format!("starting task {}: \"{}\"", t.id, t.name);
                 cw.append_task_msg(&t, &msg).await;
 
+                /*
+                 * The task environment may refer to values in the job store,
+                 * which the server resolves now that the task is starting.  If
+                 * a value the task needs is missing, the task fails without
+                 * running.
+                 */
+                let te = cw.task_env(&t).await;
+                if !te.missing.is_empty() {
+                    for name in te.missing.iter() {
+                        let msg = format!(
+                            "task environment refers to job store entry \
+                            {name:?}, which does not exist",
+                        );
+                        cw.append_task_msg(&t, &msg).await;
+                    }
+                    exit_details.push(ExitDetails {
+                        duration_ms: 0,
+                        when: Utc::now(),
+                        code: 1,
+                        signal: None,
+                    });
+                    let failure = FailureSummary {
+                        code: None,
+                        signal: None,
+                        message: Some(format!(
+                            "missing job store entries: {}",
+                            te.missing.join(", "),
+                        )),
+                    };
+                    cw.task_complete(&t, true, Some(failure)).await;
+                    continue;
+                }
+                secrets =
+                    te.secrets.into_iter().filter(|s| !s.is_empty()).collect();
+                /*
+                 * Redact longer values first, in case one secret value
+                 * contains another.
+                 */
+                secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+
                 /*
                  * Write the submitted script to a file.
                  */
@@ -835,7 +951,7 @@ async fn cmd_run(mut l: Level<()>) -> Result<()> {
                     cmd.env("BUILDOMAT_JOB_ID", &job.id);
                     cmd.env("BUILDOMAT_TASK_ID", t.id.to_string());
                 }
-                for (k, v) in t.env.iter() {
+                for (k, v) in te.env.iter() {
                     /*
                      * Overlay the user-provided environment onto what
                      * we have so far, thus allowing them to replace
@@ -853,8 +969,15 @@ async fn cmd_run(mut l: Level<()>) -> Result<()> {
                 cmd.gid(t.gid as u32);
 
                 match exec::run(cmd) {
-                    Ok(c) => {
-                        stage = Stage::Child(c, t, None);
+                    Ok((c, pid)) => {
+                        let deadline =
+                            t.timeout_seconds.map(|seconds| TaskDeadline {
+                                when: tokio::time::Instant::now()
+                                    + Duration::from_secs(seconds),
+                                seconds,
+                                expired: false,
+                            });
+                        stage = Stage::Child(c, t, None, pid, deadline);
                     }
                     Err(e) => {
                         /*
@@ -876,7 +999,10 @@ async fn cmd_run(mut l: Level<()>) -> Result<()> {
                     }
                 }
             }
-            Stage::Child(ch, t, failed) => {
+            Stage::Child(ch, t, failed, pid, deadline) => {
+                let when =
+                    deadline.as_ref().filter(|d| !d.expired).map(|d| d.when);
+
                 let a = tokio::select! {
                     _ = pingfreq.tick() => {
                         do_ping = true;
@@ -886,12 +1012,40 @@ async fn cmd_run(mut l: Level<()>) -> Result<()> {
                         creq = req;
                         continue;
                     }
+                    _ = tokio::time::sleep_until(
+                        when.unwrap_or_else(tokio::time::Instant::now)
+                    ), if when.is_some() => {
+                        /*
+                         * The task has run for longer than its timeout.  Kill
+                         * it; we will record the failure when the process
+                         * exits.
+                         */
+                        let d = deadline.as_mut().unwrap();
+                        d.expired = true;
+
+                        let msg = format!(
+                            "task {} \"{}\" exceeded its timeout of {} \
+                            seconds and was killed",
+                            t.id, t.name, d.seconds,
+                        );
+                        cw.append(&OutputRecord::new("control", &msg)).await;
+                        exec::kill(*pid);
+                        continue;
+                    }
                     a = ch.recv() => a,
                 };
 
                 match a {
                     Some(exec::Activity::Output(o)) => {
-                        cw.append_task(t, &o.to_record()).await;
+                        /*
+                         * Secret values from the job store must not appear in
+                         * the event log, even if the task prints them.
+                         */
+                        let mut rec = o.to_record();
+                        for s in secrets.iter() {
+                            rec.msg = rec.msg.replace(s.as_str(), "[secret]");
+                        }
+                        cw.append_task(t, &rec).await;
                     }
                     Some(exec::Activity::Exit(ex)) => {
                         let msg = format!(
@@ -918,7 +1072,22 @@ async fn cmd_run(mut l: Level<()>) -> Result<()> {
                         /*
                          * Record completion of this task within the job.
                          */
-                        cw.task_complete(t, failed.unwrap()).await;
+                        let failure = failed
+                            .unwrap()
+                            .then(|| exit_details.last().map(exit_failure))
+                            .flatten()
+                            .map(|mut f| {
+                                if let Some(d) =
+                                    deadline.as_ref().filter(|d| d.expired)
+                                {
+                                    f.message = Some(format!(
+                                        "timed out after {} seconds",
+                                        d.seconds,
+                                    ));
+                                }
+                                f
+                            });
+                        cw.task_complete(t, failed.unwrap(), failure).await;
                         stage = Stage::NextTask;
                     }
                     None => {
@@ -0,0 +1,502 @@
+/*
+ * Copyright 2026 Oxide Computer Company
+ */
+
+/*
+ * Job and task events are sent to the server as a sequence of frames in the
+ * body of a long-lived request, rather than with a request for each event.
+ * Each frame is a big-endian 32-bit length followed by a JSON object with a
+ * sequence number and a batch of events.  The server commits each frame as it
+ * arrives, ignoring any it has already seen, and reports the last sequence
+ * number it committed when the request ends.  Frames remain buffered here
+ * until they have been acknowledged, and are resent on a new request if
+ * something goes wrong.
+ */
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use buildomat_client::types::WorkerAppendJobBatchEvent;
+use buildomat_common::*;
+use bytes::Bytes;
+use futures::SinkExt;
+use tokio::sync::{mpsc, oneshot};
+
+/*
+ * Keep each frame well inside the batch limits enforced by the server.  The
+ * size limit applies to the encoded frame, as escaping can make the JSON form
+ * of an event several times larger than its payload.
+ */
+const MAX_FRAME_EVENTS: usize = 500;
+const MAX_FRAME_BYTES: usize = 512 * 1024;
+
+/*
+ * The most space that the sequence number and the surrounding JSON can take up
+ * in an encoded frame:
+ */
+const FRAME_OVERHEAD: usize = 64;
+
+/*
+ * We end each request, and thus learn which frames the server has committed,
+ * after a few seconds or once we have sent enough data.  The server will not
+ * accept a request body larger than 10MB.  A request always has room for at
+ * least one frame.
+ */
+const REQUEST_DURATION: Duration = Duration::from_secs(5);
+const MAX_REQUEST_BYTES: usize = 4 * 1024 * 1024;
+
+/**
+ * Encode an event for inclusion in a frame.  If the event would not fit in a
+ * frame on its own, the payload is truncated until it does.
+ */
+fn encode_event(ev: &WorkerAppendJobBatchEvent) -> Vec<u8> {
+    let json = serde_json::to_vec(ev).unwrap();
+    if json.len() + FRAME_OVERHEAD <= MAX_FRAME_BYTES {
+        return json;
+    }
+
+    let mut keep = ev.payload.len();
+    loop {
+        keep /= 2;
+        while !ev.payload.is_char_boundary(keep) {
+            keep -= 1;
+        }
+
+        let mut short = ev.clone();
+        short.payload = format!("{} [truncated]", &ev.payload[..keep]);
+
+        let json = serde_json::to_vec(&short).unwrap();
+        if json.len() + FRAME_OVERHEAD <= MAX_FRAME_BYTES {
+            return json;
+        }
+    }
+}
+
+/**
+ * Events that have not yet been sealed into a frame, and frames that the
+ * server has not yet acknowledged, in sequence order.
+ */
+struct Frames {
+    next_seq: u64,
+    pending: Vec<u8>,
+    pending_events: usize,
+    unacked: VecDeque<(u64, Bytes)>,
+}
+
+impl Frames {
+    fn new() -> Frames {
+        Frames {
+            next_seq: 0,
+            pending: Vec::new(),
+            pending_events: 0,
+            unacked: VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, ev: &WorkerAppendJobBatchEvent) {
+        let json = encode_event(ev);
+
+        if self.pending.len() + 1 + json.len() + FRAME_OVERHEAD
+            > MAX_FRAME_BYTES
+        {
+            self.seal();
+        }
+
+        if self.pending_events > 0 {
+            self.pending.push(b',');
+        }
+        self.pending.extend_from_slice(&json);
+        self.pending_events += 1;
+
+        if self.pending_events >= MAX_FRAME_EVENTS {
+            self.seal();
+        }
+    }
+
+    fn seal(&mut self) {
+        if self.pending_events == 0 {
+            return;
+        }
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        let head = format!("{{\"seq\":{seq},\"events\":[");
+        let len = head.len() + self.pending.len() + 2;
+        let mut buf = Vec::with_capacity(4 + len);
+        buf.extend_from_slice(&u32::try_from(len).unwrap().to_be_bytes());
+        buf.extend_from_slice(head.as_bytes());
+        buf.extend_from_slice(&self.pending);
+        buf.extend_from_slice(b"]}");
+
+        self.unacked.push_back((seq, buf.into()));
+        self.pending.clear();
+        self.pending_events = 0;
+    }
+
+    /**
+     * Discard the frames that the server has committed.
+     */
+    fn ack(&mut self, seq: u64) {
+        self.unacked.retain(|(s, _)| *s > seq);
+    }
+}
+
+enum Message {
+    Event(WorkerAppendJobBatchEvent),
+    Flush(oneshot::Sender<()>),
+}
+
+#[derive(Clone)]
+pub(crate) struct EventStream {
+    tx: mpsc::Sender<Message>,
+}
+
+impl EventStream {
+    pub(crate) fn start(
+        client: buildomat_client::Client,
+        job: String,
+    ) -> EventStream {
+        let (tx, rx) = mpsc::channel(1024);
+
+        let s = Streamer {
+            client,
+            job,
+            rx,
+            closed: false,
+            frames: Frames::new(),
+            flushes: Vec::new(),
+        };
+        tokio::spawn(s.run());
+
+        EventStream { tx }
+    }
+
+    pub(crate) async fn append(&self, ev: WorkerAppendJobBatchEvent) {
+        if self.tx.send(Message::Event(ev)).await.is_err() {
+            panic!("event stream task has gone away");
+        }
+    }
+
+    /**
+     * Wait until the server has committed every event appended so far.
+     */
+    pub(crate) async fn flush(&self) {
+        let (tx, rx) = oneshot::channel();
+        if self.tx.send(Message::Flush(tx)).await.is_err() {
+            panic!("event stream task has gone away");
+        }
+        rx.await.ok();
+    }
+}
+
+struct Streamer {
+    client: buildomat_client::Client,
+    job: String,
+    rx: mpsc::Receiver<Message>,
+    closed: bool,
+    frames: Frames,
+
+    /*
+     * The callers waiting for everything to be acknowledged:
+     */
+    flushes: Vec<oneshot::Sender<()>>,
+}
+
+impl Streamer {
+    /**
+     * Accept a message from the job, returning true if the current request
+     * should be ended so that a flush can complete.
+     */
+    fn accept(&mut self, msg: Message) -> bool {
+        match msg {
+            Message::Event(ev) => {
+                self.frames.push(&ev);
+                false
+            }
+            Message::Flush(tx) => {
+                self.flushes.push(tx);
+                true
+            }
+        }
+    }
+
+    async fn run(mut self) {
+        /*
+         * The server remembers the last frame it committed for this job, which
+         * may be from a previous run of the agent.  A request without any
+         * frames tells us where to resume.
+         */
+        self.frames.next_seq = loop {
+            match self
+                .client
+                .worker_job_stream()
+                .job(&self.job)
+                .body(Bytes::new())
+                .send()
+                .await
+            {
+                Ok(ack) => break ack.into_inner().seq + 1,
+                Err(e) => {
+                    println!("ERROR: event stream: {:?}", e);
+                    sleep_ms(1000).await;
+                }
+            }
+        };
+
+        loop {
+            if self.frames.unacked.is_empty() {
+                for tx in self.flushes.drain(..) {
+                    tx.send(()).ok();
+                }
+
+                if self.closed {
+                    return;
+                }
+
+                /*
+                 * Wait for something to send before we start a request.
+                 */
+                match self.rx.recv().await {
+                    Some(msg) => {
+                        self.accept(msg);
+                        self.frames.seal();
+                    }
+                    None => self.closed = true,
+                }
+                continue;
+            }
+
+            if !self.request().await {
+                sleep_ms(1000).await;
+            }
+        }
+    }
+
+    /**
+     * Make one request, returning true if the server acknowledged the frames
+     * we sent.
+     */
+    async fn request(&mut self) -> bool {
+        let client = self.client.clone();
+        let (btx, brx) =
+            futures::channel::mpsc::channel::<std::io::Result<Bytes>>(16);
+        let res = client
+            .worker_job_stream()
+            .job(&self.job)
+            .body(reqwest::Body::wrap_stream(brx))
+            .send();
+        tokio::pin!(res);
+
+        /*
+         * Resend any frames that were not acknowledged, as long as they fit in
+         * this request.  If there are more than that, we must not send any new
+         * frames until the backlog has been committed, as the server will
+         * ignore frames that arrive out of order.
+         */
+        let mut sent = 0;
+        let mut queue = VecDeque::new();
+        for (_, buf) in self.frames.unacked.iter() {
+            if sent + buf.len() > MAX_REQUEST_BYTES {
+                break;
+            }
+            sent += buf.len();
+            queue.push_back(buf.clone());
+        }
+        let mut ending = queue.len() < self.frames.unacked.len();
+
+        let deadline = tokio::time::sleep(REQUEST_DURATION);
+        tokio::pin!(deadline);
+
+        let mut btx = Some(btx);
+        let res = loop {
+            let Some(tx) = btx.as_mut() else {
+                /*
+                 * The request body is complete; wait for the acknowledgement.
+                 */
+                break (&mut res).await;
+            };
+
+            if let Some(buf) = queue.pop_front() {
+                tokio::select! {
+                    r = tx.send(Ok(buf)) => {
+                        if r.is_err() {
+                            /*
+                             * The body has been dropped, so the request has
+                             * failed.  The response will tell us why.
+                             */
+                            btx = None;
+                        }
+                    }
+                    r = &mut res => break r,
+                }
+                continue;
+            }
+
+            if ending {
+                btx = None;
+                continue;
+            }
+
+            tokio::select! {
+                r = &mut res => break r,
+                _ = &mut deadline => {
+                    ending = true;
+                }
+                msg = self.rx.recv() => {
+                    let first = self.frames.next_seq;
+
+                    match msg {
+                        Some(msg) => {
+                            ending = self.accept(msg);
+
+                            /*
+                             * Take whatever else is waiting, so that events
+                             * are sent in batches where possible.
+                             */
+                            while !ending {
+                                match self.rx.try_recv() {
+                                    Ok(msg) => ending = self.accept(msg),
+                                    Err(_) => break,
+                                }
+                            }
+                        }
+                        None => {
+                            self.closed = true;
+                            ending = true;
+                        }
+                    }
+
+                    /*
+                     * Send the new frames, as long as they fit in this
+                     * request.  Any that do not will be sent, in order, with
+                     * the next request.
+                     */
+                    self.frames.seal();
+                    for (_, buf) in self
+                        .frames
+                        .unacked
+                        .iter()
+                        .filter(|(seq, _)| *seq >= first)
+                    {
+                        if sent + buf.len() > MAX_REQUEST_BYTES {
+                            ending = true;
+                            break;
+                        }
+                        sent += buf.len();
+                        queue.push_back(buf.clone());
+                    }
+                }
+            }
+        };
+
+        match res {
+            Ok(ack) => {
+                self.frames.ack(ack.into_inner().seq);
+                true
+            }
+            Err(e) => {
+                println!("ERROR: event stream: {:?}", e);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn event(payload: &str) -> WorkerAppendJobBatchEvent {
+        WorkerAppendJobBatchEvent {
+            payload: payload.to_string(),
+            stream: "stdout".to_string(),
+            task: Some(0),
+            time: chrono::Utc::now(),
+        }
+    }
+
+    /*
+     * Check the length prefix of a frame and decode the rest.
+     */
+    fn decode(buf: &Bytes) -> (u64, Vec<WorkerAppendJobBatchEvent>) {
+        assert!(buf.len() <= MAX_FRAME_BYTES + 4);
+        let len = u32::from_be_bytes(buf[0..4].try_into().unwrap()) as usize;
+        assert_eq!(len, buf.len() - 4);
+
+        let v: serde_json::Value = serde_json::from_slice(&buf[4..]).unwrap();
+        (
+            v["seq"].as_u64().unwrap(),
+            serde_json::from_value(v["events"].clone()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn frames_by_count() {
+        let mut f = Frames::new();
+        f.next_seq = 5;
+
+        f.seal();
+        assert!(f.unacked.is_empty());
+
+        for _ in 0..(2 * MAX_FRAME_EVENTS + 1) {
+            f.push(&event("line"));
+        }
+        assert_eq!(f.unacked.len(), 2);
+
+        f.seal();
+        let frames =
+            f.unacked.iter().map(|(_, buf)| decode(buf)).collect::<Vec<_>>();
+        assert_eq!(
+            frames
+                .iter()
+                .map(|(seq, evs)| (*seq, evs.len()))
+                .collect::<Vec<_>>(),
+            vec![(5, MAX_FRAME_EVENTS), (6, MAX_FRAME_EVENTS), (7, 1)],
+        );
+        assert_eq!(f.next_seq, 8);
+
+        f.ack(6);
+        assert_eq!(
+            f.unacked.iter().map(|(seq, _)| *seq).collect::<Vec<_>>(),
+            vec![7]
+        );
+    }
+
+    #[test]
+    fn frames_by_encoded_size() {
+        let mut f = Frames::new();
+
+        /*
+         * Each quote is escaped, so these events take up twice as much space
+         * in a frame as their payloads do.
+         */
+        let quotes = "\"".repeat(100 * 1024);
+        for _ in 0..10 {
+            f.push(&event(&quotes));
+        }
+        f.seal();
+
+        assert!(f.unacked.len() > 1);
+        let mut total = 0;
+        for (_, buf) in f.unacked.iter() {
+            let (_, evs) = decode(buf);
+            assert!(evs.iter().all(|ev| ev.payload == quotes));
+            total += evs.len();
+        }
+        assert_eq!(total, 10);
+    }
+
+    #[test]
+    fn frames_truncate_large_event() {
+        let mut f = Frames::new();
+
+        f.push(&event(&"\u{1}".repeat(MAX_FRAME_BYTES)));
+        f.seal();
+
+        assert_eq!(f.unacked.len(), 1);
+        let (_, evs) = decode(&f.unacked[0].1);
+        assert_eq!(evs.len(), 1);
+        assert!(evs[0].payload.ends_with(" [truncated]"));
+    }
+}
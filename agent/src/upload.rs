@@ -43,6 +43,14 @@ impl Uploader {
             false
         }
     }
+
+    async fn chunk_size(&self) -> usize {
+        let max_bytes_per_chunk = self.cw.quota().await.max_bytes_per_chunk;
+
+        usize::try_from(max_bytes_per_chunk)
+            .unwrap_or(usize::MAX)
+            .clamp(1, 5 * 1024 * 1024)
+    }
 }
 
 struct Upload {
@@ -239,13 +247,16 @@ pub(crate) fn upload(
             };
 
             /*
-             * Read 5MB chunks of the file and upload them to the server.
+             * Read the file in chunks of at most 5MB, or the server's chunk
+             * size limit if that is smaller, and upload them to the server.
+             * Only one chunk is held in memory at a time.
              */
+            let chunk_size = upl.chunk_size().await;
             let mut total = 0;
             let mut chunks = Vec::new();
             loop {
                 let mut buf = bytes::BytesMut::new();
-                buf.resize(5 * 1024 * 1024, 0);
+                buf.resize(chunk_size, 0);
 
                 let buf = match f.read(&mut buf) {
                     Ok(sz) if sz == 0 => break,
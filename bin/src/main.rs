@@ -124,6 +124,11 @@ async fn do_job_run(mut l: Level<Stuff>) -> Result<()> {
     l.optmulti("i", "input", "input file to pass to job", "[NAME=]FILE");
     l.optmulti("d", "depend-on", "depend on prior job", "NAME=JOB_ID");
     l.optmulti("T", "tag", "informational tag to identify job", "KEY=VALUE");
+    l.optflag(
+        "U",
+        "unique",
+        "fail if you already have a job with the same name",
+    );
     l.optflag("v", "", "debugging output");
 
     l.mutually_exclusive(&[("c", "script"), ("C", "script-file")]);
@@ -132,7 +137,8 @@ async fn do_job_run(mut l: Level<Stuff>) -> Result<()> {
 
     let nowait = a.opts().opt_present("no-wait");
     let name = a.opts().opt_str("name").unwrap();
-    let target = a.opts().opt_str("target").unwrap_or_else(|| "default".into());
+    let unique_name = a.opts().opt_present("unique");
+    let target = a.opts().opt_str("target");
     let script = if let Some(script) = a.opts().opt_str("script") {
         script
     } else if let Some(path) = a.opts().opt_str("script-file") {
@@ -216,6 +222,7 @@ async fn do_job_run(mut l: Level<Stuff>) -> Result<()> {
                     name.to_string(),
                     DependSubmit {
                         copy_outputs: true,
+                        copy_outputs_globs: Default::default(),
                         on_completed: true,
                         on_failed: false,
                         prior_job: job.to_string(),
@@ -276,10 +283,14 @@ async fn do_job_run(mut l: Level<Stuff>) -> Result<()> {
                 gid: None,
                 uid: None,
                 workdir: None,
+                always_run: false,
+                timeout_seconds: None,
             }],
             inputs: inputs.keys().cloned().collect(),
             tags,
             depends,
+            idempotency_key: None,
+            unique_name,
         })
         .send()
         .await?;
@@ -488,6 +499,7 @@ async fn do_control(mut l: Level<Stuff>) -> Result<()> {
 
 async fn do_job_cancel(mut l: Level<Stuff>) -> Result<()> {
     l.usage_args(Some("JOB"));
+    l.optopt("r", "reason", "why the job is being cancelled", "REASON");
 
     let a = args!(l);
 
@@ -495,7 +507,80 @@ async fn do_job_cancel(mut l: Level<Stuff>) -> Result<()> {
         bad_args!(l, "specify job ID");
     }
 
-    l.context().user().job_cancel().job(a.args()[0].as_str()).send().await?;
+    l.context()
+        .user()
+        .job_cancel()
+        .job(a.args()[0].as_str())
+        .body(JobCancel { reason: a.opts().opt_str("reason") })
+        .send()
+        .await?;
+
+    Ok(())
+}
+
+async fn do_job_rerun(mut l: Level<Stuff>) -> Result<()> {
+    l.usage_args(Some("JOB"));
+
+    let a = args!(l);
+
+    if a.args().len() != 1 {
+        bad_args!(l, "specify job ID");
+    }
+
+    let x =
+        l.context().user().job_rerun().job(a.args()[0].as_str()).send().await?;
+
+    println!("{}", x.id);
+
+    Ok(())
+}
+
+async fn do_job_share(mut l: Level<Stuff>) -> Result<()> {
+    l.usage_args(Some("JOB"));
+    l.optopt("e", "expiry", "token expires after this many seconds", "SECONDS");
+
+    let a = args!(l);
+
+    if a.args().len() != 1 {
+        bad_args!(l, "specify job ID");
+    }
+
+    let expiry_seconds = a
+        .opts()
+        .opt_str("expiry")
+        .map(|s| s.parse::<u64>())
+        .transpose()
+        .map_err(|e| anyhow!("invalid expiry: {e}"))?;
+
+    let x = l
+        .context()
+        .user()
+        .job_share()
+        .job(a.args()[0].as_str())
+        .body(JobShare { expiry_seconds })
+        .send()
+        .await?;
+
+    println!("{}", x.token);
+
+    Ok(())
+}
+
+async fn do_job_unshare(mut l: Level<Stuff>) -> Result<()> {
+    l.usage_args(Some("JOB"));
+
+    let a = args!(l);
+
+    if a.args().len() != 1 {
+        bad_args!(l, "specify job ID");
+    }
+
+    l.context()
+        .user()
+        .job_share_revoke()
+        .job(a.args()[0].as_str())
+        .send()
+        .await?;
 
     Ok(())
 }
@@ -974,6 +1059,13 @@ async fn do_job(mut l: Level<Stuff>) -> Result<()> {
     l.cmda("list", "ls", "list jobs", cmd!(do_job_list))?;
     l.cmd("run", "run a job", cmd!(do_job_run))?;
     l.cmd("cancel", "cancel a job", cmd!(do_job_cancel))?;
+    l.cmd("rerun", "run an existing job again", cmd!(do_job_rerun))?;
+    l.cmd("share", "create a read-only token for a job", cmd!(do_job_share))?;
+    l.cmd(
+        "unshare",
+        "revoke the read-only tokens for a job",
+        cmd!(do_job_unshare),
+    )?;
     l.cmd("tail", "listen for events from a job", cmd!(do_job_tail))?;
     l.cmd("store", "manage the job store", cmd!(do_job_store))?;
     l.cmd("outputs", "list job outputs", cmd!(do_job_outputs))?;
@@ -1126,6 +1218,8 @@ async fn do_worker_list(mut l: Level<Stuff>) -> Result<()> {
     l.add_column("info", 20, false);
 
     l.optflag("A", "active", "display only workers not yet destroyed");
+    l.optopt("F", "factory", "display only workers from this factory", "ID");
+    l.optopt("T", "target", "display only workers for this target", "ID");
 
     let a = no_args!(l);
     let active = a.opts().opt_present("active");
@@ -1133,15 +1227,15 @@ async fn do_worker_list(mut l: Level<Stuff>) -> Result<()> {
 
     let mut t = a.table();
 
-    for w in c
-        .admin()
-        .workers_list()
-        .active(active)
-        .send()
-        .await?
-        .into_inner()
-        .workers
-    {
+    let mut req = c.admin().workers_list().active(active);
+    if let Some(factory) = a.opts().opt_str("factory") {
+        req = req.factory(factory);
+    }
+    if let Some(target) = a.opts().opt_str("target") {
+        req = req.target(target);
+    }
+
+    for w in req.send().await?.into_inner().workers {
         if active && w.deleted {
             continue;
         }
@@ -1723,8 +1817,32 @@ async fn do_admin_job_archive(mut l: Level<Stuff>) -> Result<()> {
     Ok(())
 }
 
+async fn do_admin_job_reimport(mut l: Level<Stuff>) -> Result<()> {
+    l.usage_args(Some("JOB..."));
+
+    let a = args!(l);
+    if a.args().is_empty() {
+        bad_args!(l, "specify a job to reimport");
+    }
+
+    for arg in a.args() {
+        if let Err(e) =
+            l.context().admin().admin_job_reimport().job(arg).send().await
+        {
+            bail!("ERROR: reimporting {}: {:?}", arg, e);
+        }
+    }
+
+    Ok(())
+}
+
 async fn do_admin_job(mut l: Level<Stuff>) -> Result<()> {
     l.cmd("archive", "request archive of a job", cmd!(do_admin_job_archive))?;
+    l.cmd(
+        "reimport",
+        "restore an archived job to the database",
+        cmd!(do_admin_job_reimport),
+    )?;
 
     sel!(l).run().await
 }
@@ -1,12 +1,115 @@
+use crate::{types, Client, Error, ResponseValue};
 use anyhow::Result;
+use chrono::Utc;
 use rusty_ulid::Ulid;
+use std::future::Future;
+use std::ops::Deref;
 use std::{str::FromStr, time::Duration};
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobState {
+    Queued,
+    Waiting,
+    Running,
+    Completed,
+    Failed,
+    Unknown(String),
+}
+
+impl JobState {
+    /**
+     * Returns true if the job has finished, whether or not it was successful.
+     */
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, JobState::Completed | JobState::Failed)
+    }
+
+    pub fn is_running(&self) -> bool {
+        matches!(self, JobState::Running)
+    }
+
+    pub fn is_failed(&self) -> bool {
+        matches!(self, JobState::Failed)
+    }
+}
+
+impl FromStr for JobState {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "queued" => JobState::Queued,
+            "waiting" => JobState::Waiting,
+            "running" => JobState::Running,
+            "completed" => JobState::Completed,
+            "failed" => JobState::Failed,
+            other => JobState::Unknown(other.to_string()),
+        })
+    }
+}
+
+impl std::fmt::Display for JobState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobState::Queued => write!(f, "queued"),
+            JobState::Waiting => write!(f, "waiting"),
+            JobState::Running => write!(f, "running"),
+            JobState::Completed => write!(f, "completed"),
+            JobState::Failed => write!(f, "failed"),
+            JobState::Unknown(s) => write!(f, "{}", s),
+        }
+    }
+}
+
 pub trait ClientJobExt {
     fn duration(&self, from: &str, until: &str) -> Option<Duration>;
+    fn state_parsed(&self) -> JobState;
+
+    /**
+     * Time from submission of the job until it completed.
+     */
+    fn total_duration(&self) -> Option<Duration> {
+        self.duration("submit", "complete")
+    }
+
+    /**
+     * Time from submission of the job until it was assigned to a worker.
+     */
+    fn queue_duration(&self) -> Option<Duration> {
+        self.duration("submit", "assigned")
+    }
+
+    /**
+     * Time from assignment of the job to a worker until it completed.
+     */
+    fn run_duration(&self) -> Option<Duration> {
+        self.duration("assigned", "complete")
+    }
+
+    /**
+     * Time elapsed since the job was submitted.
+     */
+    fn age(&self) -> Duration;
 }
 
 impl ClientJobExt for crate::types::Job {
+    fn state_parsed(&self) -> JobState {
+        JobState::from_str(&self.state).unwrap()
+    }
+
+    fn age(&self) -> Duration {
+        /*
+         * Older jobs may not have a recorded submit time, but the job ID
+         * includes a timestamp from when the job was created.
+         */
+        self.times
+            .get("submit")
+            .copied()
+            .or_else(|| to_ulid(&self.id).ok().map(|id| id.datetime()))
+            .and_then(|t| Utc::now().signed_duration_since(t).to_std().ok())
+            .unwrap_or(Duration::ZERO)
+    }
+
     fn duration(&self, from: &str, until: &str) -> Option<Duration> {
         let from = if let Some(from) = self.times.get(from) {
             from
@@ -50,3 +153,203 @@ impl ClientIdExt for crate::types::Job {
 fn to_ulid(id: &str) -> Result<Ulid> {
     Ok(Ulid::from_str(id)?)
 }
+
+/**
+ * A wrapper around the generated client that retries idempotent requests
+ * which fail because of a communication error or a server error.  Only the
+ * requests for which this type provides its own methods are retried; all
+ * other requests, such as job submission or cancellation, are passed through
+ * to the underlying client and are attempted only once.
+ */
+pub struct RetryClient {
+    client: Client,
+    attempts: u32,
+    delay: Duration,
+}
+
+pub struct RetryClientBuilder {
+    client: Client,
+    attempts: u32,
+    delay: Duration,
+}
+
+impl RetryClientBuilder {
+    pub fn new(client: Client) -> RetryClientBuilder {
+        RetryClientBuilder {
+            client,
+            attempts: 3,
+            delay: Duration::from_secs(1),
+        }
+    }
+
+    /**
+     * The total number of times a request will be attempted, including the
+     * first attempt.
+     */
+    pub fn attempts(&mut self, attempts: u32) -> &mut Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /**
+     * The delay before the first retry.  The delay doubles for each
+     * subsequent retry.
+     */
+    pub fn delay(&mut self, delay: Duration) -> &mut Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn build(&mut self) -> RetryClient {
+        RetryClient {
+            client: self.client.clone(),
+            attempts: self.attempts,
+            delay: self.delay,
+        }
+    }
+}
+
+impl Deref for RetryClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+type ClientResult<T> = Result<ResponseValue<T>, Error<types::Error>>;
+
+fn retryable<E>(e: &Error<E>) -> bool {
+    match e {
+        Error::CommunicationError(_) => true,
+        _ => e.status().map(|s| s.is_server_error()).unwrap_or(false),
+    }
+}
+
+impl RetryClient {
+    pub fn builder(client: Client) -> RetryClientBuilder {
+        RetryClientBuilder::new(client)
+    }
+
+    async fn retry<T, F, Fut>(&self, func: F) -> ClientResult<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = ClientResult<T>>,
+    {
+        let mut attempt = 1;
+        let mut delay = self.delay;
+
+        loop {
+            match func().await {
+                Err(e) if attempt < self.attempts && retryable(&e) => {
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                    delay = delay.saturating_mul(2);
+                }
+                res => return res,
+            }
+        }
+    }
+
+    pub fn job_get(&self) -> RetryJobGet<'_> {
+        RetryJobGet { rc: self, job: None }
+    }
+
+    pub fn job_events_get(&self) -> RetryJobEventsGet<'_> {
+        RetryJobEventsGet { rc: self, job: None, minseq: None, task: None }
+    }
+
+    pub fn job_outputs_get(&self) -> RetryJobOutputsGet<'_> {
+        RetryJobOutputsGet { rc: self, job: None }
+    }
+}
+
+pub struct RetryJobGet<'a> {
+    rc: &'a RetryClient,
+    job: Option<String>,
+}
+
+impl<'a> RetryJobGet<'a> {
+    pub fn job<S: ToString>(mut self, job: S) -> Self {
+        self.job = Some(job.to_string());
+        self
+    }
+
+    pub async fn send(self) -> ClientResult<types::Job> {
+        self.rc
+            .retry(|| {
+                let mut b = self.rc.client.job_get();
+                if let Some(job) = &self.job {
+                    b = b.job(job);
+                }
+                b.send()
+            })
+            .await
+    }
+}
+
+pub struct RetryJobEventsGet<'a> {
+    rc: &'a RetryClient,
+    job: Option<String>,
+    minseq: Option<u32>,
+    task: Option<u32>,
+}
+
+impl<'a> RetryJobEventsGet<'a> {
+    pub fn job<S: ToString>(mut self, job: S) -> Self {
+        self.job = Some(job.to_string());
+        self
+    }
+
+    pub fn minseq(mut self, minseq: u32) -> Self {
+        self.minseq = Some(minseq);
+        self
+    }
+
+    pub fn task(mut self, task: u32) -> Self {
+        self.task = Some(task);
+        self
+    }
+
+    pub async fn send(self) -> ClientResult<Vec<types::JobEvent>> {
+        self.rc
+            .retry(|| {
+                let mut b = self.rc.client.job_events_get();
+                if let Some(job) = &self.job {
+                    b = b.job(job);
+                }
+                if let Some(minseq) = self.minseq {
+                    b = b.minseq(minseq);
+                }
+                if let Some(task) = self.task {
+                    b = b.task(task);
+                }
+                b.send()
+            })
+            .await
+    }
+}
+
+pub struct RetryJobOutputsGet<'a> {
+    rc: &'a RetryClient,
+    job: Option<String>,
+}
+
+impl<'a> RetryJobOutputsGet<'a> {
+    pub fn job<S: ToString>(mut self, job: S) -> Self {
+        self.job = Some(job.to_string());
+        self
+    }
+
+    pub async fn send(self) -> ClientResult<Vec<types::JobOutput>> {
+        self.rc
+            .retry(|| {
+                let mut b = self.rc.client.job_outputs_get();
+                if let Some(job) = &self.job {
+                    b = b.job(job);
+                }
+                b.send()
+            })
+            .await
+    }
+}
@@ -24,7 +24,7 @@ pub mod prelude {
     pub use super::gen::prelude::*;
     pub use futures::StreamExt;
 }
-pub use gen::{types, Client, Error};
+pub use gen::{types, Client, Error, ResponseValue};
 
 pub struct ClientBuilder {
     url: String,
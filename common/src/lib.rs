@@ -2,6 +2,7 @@
  * Copyright 2023 Oxide Computer Company
  */
 
+use std::collections::HashMap;
 use std::io::{IsTerminal, Read};
 use std::path::Path;
 use std::sync::Mutex;
@@ -84,7 +85,34 @@ impl UlidDateExt for Ulid {
 }
 
 pub fn guess_mime_type(filename: &str) -> String {
-    if filename == "Cargo.lock" {
+    guess_mime_type_with(filename, &HashMap::new())
+}
+
+/**
+ * Guess the MIME type of a file from its name, as with "guess_mime_type()",
+ * but first consult a map of additional file extensions to MIME types.  An
+ * extension may have more than one part; e.g., "tar.zst".  Extensions are
+ * matched without regard to case, and if more than one matches, the longest
+ * is used.
+ */
+pub fn guess_mime_type_with(
+    filename: &str,
+    extra: &HashMap<String, String>,
+) -> String {
+    let lower = filename.to_ascii_lowercase();
+    let custom = extra
+        .iter()
+        .map(|(ext, mt)| (ext.trim_start_matches('.').to_ascii_lowercase(), mt))
+        .filter(|(ext, _)| {
+            !ext.is_empty()
+                && lower.len() > ext.len() + 1
+                && lower.ends_with(&format!(".{ext}"))
+        })
+        .max_by_key(|(ext, _)| ext.len());
+
+    if let Some((_, mt)) = custom {
+        mt.to_string()
+    } else if filename == "Cargo.lock" {
         /*
          * This file may be TOML, but is almost certainly plain text.
          */
@@ -179,3 +207,50 @@ impl DateTimeExt for DateTime<Utc> {
 pub fn looks_like_a_ulid(s: &str) -> bool {
     s.len() == 26 && s.chars().all(|c| c.is_ascii_alphanumeric())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mime_type_overrides() {
+        let extra = [
+            ("log", "text/plain"),
+            (".tar.zst", "application/x-zstd-tar"),
+            ("ZST", "application/zstd"),
+            ("", "application/x-empty"),
+        ]
+        .into_iter()
+        .map(|(ext, mt)| (ext.to_string(), mt.to_string()))
+        .collect::<HashMap<_, _>>();
+
+        for (filename, expect) in [
+            /*
+             * Configured extensions, matched without regard to case or a
+             * leading period, with the longest match preferred:
+             */
+            ("build.log", "text/plain"),
+            ("BUILD.LOG", "text/plain"),
+            ("proto.tar.zst", "application/x-zstd-tar"),
+            ("proto.zst", "application/zstd"),
+            /*
+             * A name that is only the extension does not match:
+             */
+            ("log", "application/octet-stream"),
+            (".log", "application/octet-stream"),
+            /*
+             * Anything else falls back to the built-in guess:
+             */
+            ("Cargo.lock", "text/plain"),
+            ("index.html", "text/html"),
+            ("data.bin", "application/octet-stream"),
+            ("noextension", "application/octet-stream"),
+        ] {
+            assert_eq!(
+                guess_mime_type_with(filename, &extra),
+                expect,
+                "filename {filename:?}",
+            );
+        }
+    }
+}
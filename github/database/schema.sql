@@ -87,3 +87,15 @@ ALTER TABLE check_run ADD COLUMN
 
 -- v 13
 CREATE INDEX check_run_check_suite ON check_run (check_suite);
+
+-- v 14
+ALTER TABLE check_suite ADD COLUMN
+    pr_number       INTEGER;
+
+-- v 15
+ALTER TABLE check_suite ADD COLUMN
+    pr_base_ref     TEXT;
+
+-- v 16
+ALTER TABLE check_suite ADD COLUMN
+    pr_head_ref     TEXT;
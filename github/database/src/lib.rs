@@ -579,6 +579,9 @@ impl Database {
                 pr_by: None,
                 requested_by: None,
                 approved_by: None,
+                pr_number: None,
+                pr_base_ref: None,
+                pr_head_ref: None,
             };
 
             let ic = diesel::insert_into(dsl::check_suite)
@@ -620,6 +623,9 @@ impl Database {
                     dsl::pr_by.eq(&check_suite.pr_by),
                     dsl::requested_by.eq(&check_suite.requested_by),
                     dsl::approved_by.eq(&check_suite.approved_by),
+                    dsl::pr_number.eq(&check_suite.pr_number),
+                    dsl::pr_base_ref.eq(&check_suite.pr_base_ref),
+                    dsl::pr_head_ref.eq(&check_suite.pr_head_ref),
                 ))
                 .execute(tx)?;
             assert_eq!(uc, 1);
@@ -280,6 +280,9 @@ pub struct CheckSuite {
     pub pr_by: Option<i64>,
     pub requested_by: Option<i64>,
     pub approved_by: Option<i64>,
+    pub pr_number: Option<i64>,
+    pub pr_base_ref: Option<String>,
+    pub pr_head_ref: Option<String>,
 }
 
 #[derive(Debug, Clone, Queryable, Insertable, Identifiable)]
@@ -33,6 +33,9 @@ table! {
         pr_by -> Nullable<BigInt>,
         requested_by -> Nullable<BigInt>,
         approved_by -> Nullable<BigInt>,
+        pr_number -> Nullable<BigInt>,
+        pr_base_ref -> Nullable<Text>,
+        pr_head_ref -> Nullable<Text>,
     }
 }
 
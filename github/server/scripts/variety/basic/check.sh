@@ -0,0 +1,32 @@
+#!/bin/bash
+#
+# Confirm that a job-provided or configured setup script has created the
+# environment that the remaining tasks in a basic job expect.
+#
+
+set -o errexit
+set -o pipefail
+
+fail() {
+	printf 'ERROR: setup did not complete: %s\n' "$*" >&2
+	exit 1
+}
+
+ent="$(getent passwd build)" || fail 'there is no "build" user'
+IFS=: read -r _ _ uid gid _ <<< "$ent"
+if [[ "$uid" != 12345 || "$gid" != 12345 ]]; then
+	fail "the \"build\" user has uid $uid and gid $gid, not 12345"
+fi
+
+for dir in /home/build /work; do
+	if [[ ! -d "$dir" ]]; then
+		fail "directory $dir does not exist"
+	fi
+	if [[ "$(ls -nd "$dir" | awk '{ print $3 }')" != 12345 ]]; then
+		fail "directory $dir is not owned by the \"build\" user"
+	fi
+done
+
+if [[ "$NEED_GIT" == yes ]] && ! command -v git >/dev/null; then
+	fail 'git is required to clone the repository (see "skip_clone")'
+fi
@@ -2,8 +2,9 @@
  * Copyright 2021 Oxide Computer Company
  */
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs::OpenOptions;
 use std::io::Read;
 use std::path::Path;
@@ -18,6 +19,35 @@ pub struct Sqlite {
 pub struct Buildomat {
     pub token: String,
     pub url: String,
+    /**
+     * How many times to attempt idempotent requests to the buildomat server
+     * before giving up.  If not specified, requests are not retried.
+     */
+    #[serde(default)]
+    pub retry_attempts: Option<u32>,
+}
+
+/**
+ * A named setup profile for Basic variety jobs, which replaces the built-in
+ * script that prepares the build user and work area.
+ */
+#[derive(Deserialize)]
+pub struct Setup {
+    /**
+     * The path of a file that contains the setup script.
+     */
+    pub script: String,
+    /**
+     * Jobs that name one of these targets use this profile unless they select
+     * a different one.  A target may appear in the list for at most one
+     * profile.  Jobs that do not name a target are left to the server to
+     * place, so we cannot match them to a profile by target.
+     */
+    #[serde(default)]
+    pub targets: Vec<String>,
+
+    #[serde(skip)]
+    pub contents: String,
 }
 
 #[derive(Deserialize)]
@@ -30,6 +60,45 @@ pub struct Config {
     pub buildomat: Buildomat,
     pub allow_owners: Vec<String>,
     pub sqlite: Sqlite,
+    #[serde(default)]
+    pub setup: HashMap<String, Setup>,
+    /**
+     * How long, in seconds, to remember the commit at the head of a branch
+     * when asked to resolve it, rather than asking GitHub again.
+     */
+    #[serde(default = "default_branch_cache_seconds")]
+    pub branch_cache_seconds: u64,
+    /**
+     * Additional mappings from file extension to MIME type, consulted before
+     * the built-in table when serving artefacts and published files; e.g.,
+     * "tar.zst" = "application/zstd".  An extension may have more than one
+     * part.
+     */
+    #[serde(default)]
+    pub mime_types: HashMap<String, String>,
+}
+
+fn default_branch_cache_seconds() -> u64 {
+    30
+}
+
+impl Config {
+    /**
+     * Locate the setup profile for a job, either by the name the job has
+     * selected or by the target it has named.
+     */
+    pub fn setup_profile(
+        &self,
+        name: Option<&str>,
+        target: Option<&str>,
+    ) -> Option<&Setup> {
+        if let Some(name) = name {
+            self.setup.get(name)
+        } else {
+            let target = target?;
+            self.setup.values().find(|s| s.targets.iter().any(|t| t == target))
+        }
+    }
 }
 
 pub fn load_toml<T, P: AsRef<Path>>(p: P) -> Result<T>
@@ -49,5 +118,35 @@ pub fn load_bytes<P: AsRef<Path>>(p: P) -> Result<Vec<u8>> {
 }
 
 pub fn load_config<P: AsRef<Path>>(p: P) -> Result<Config> {
-    load_toml(p)
+    let mut c: Config = load_toml(p)?;
+
+    let mut targets: HashMap<&str, &str> = HashMap::new();
+    for (name, s) in c.setup.iter() {
+        for t in s.targets.iter() {
+            if let Some(other) = targets.insert(t, name) {
+                bail!(
+                    "setup: target {t:?} appears in both profile {other:?} \
+                    and profile {name:?}"
+                );
+            }
+        }
+    }
+
+    for (name, s) in c.setup.iter_mut() {
+        s.contents = String::from_utf8(load_bytes(&s.script)?)
+            .with_context(|| format!("setup profile {name:?}"))?;
+    }
+
+    for (ext, mt) in c.mime_types.iter() {
+        if ext.trim_start_matches('.').is_empty() {
+            bail!("mime_types: extension must not be empty");
+        }
+        if !mt.contains('/')
+            || hyper::header::HeaderValue::from_str(mt).is_err()
+        {
+            bail!("mime_types: {ext:?} maps to invalid MIME type {mt:?}");
+        }
+    }
+
+    Ok(c)
 }
@@ -18,7 +18,7 @@ use slog::{debug, error, info, o, trace, warn, Logger};
 use std::collections::{HashMap, HashSet};
 use std::result::Result as SResult;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use super::{variety, App};
 
@@ -97,6 +97,68 @@ impl ArtefactPath {
     }
 }
 
+/**
+ * Determine whether the client already has the version of a resource with
+ * this entity tag, based on any If-None-Match header in the request.
+ */
+fn etag_matches(rc: &RequestContext<Arc<App>>, etag: &str) -> bool {
+    rc.request
+        .headers()
+        .get_all(hyper::header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|t| t.trim())
+        .any(|t| t == "*" || t.trim_start_matches("W/") == etag)
+}
+
+fn not_found(what: &str) -> SResult<hyper::Response<hyper::Body>, HttpError> {
+    let out = format!(
+        "<html><head><title>404 Not Found</title>\
+        <body>{what} not found!</body></html>"
+    );
+
+    Ok(hyper::Response::builder()
+        .status(hyper::StatusCode::NOT_FOUND)
+        .header(hyper::header::CONTENT_TYPE, "text/html")
+        .header(hyper::header::CONTENT_LENGTH, out.as_bytes().len())
+        .body(hyper::Body::from(out))?)
+}
+
+/*
+ * Characters that must be escaped when a value is used as one segment of the
+ * path in a URL:
+ */
+const PATH_SEGMENT: &percent_encoding::AsciiSet = &percent_encoding::CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+fn encode_segment(s: &str) -> percent_encoding::PercentEncode<'_> {
+    percent_encoding::utf8_percent_encode(s, PATH_SEGMENT)
+}
+
+fn not_modified(
+    etag: &str,
+    cache_control: Option<&str>,
+) -> SResult<hyper::Response<hyper::Body>, HttpError> {
+    let mut res = hyper::Response::builder()
+        .status(hyper::StatusCode::NOT_MODIFIED)
+        .header(hyper::header::ETAG, etag);
+    if let Some(cc) = cache_control {
+        res = res.header(hyper::header::CACHE_CONTROL, cc);
+    }
+    Ok(res.body(hyper::Body::empty())?)
+}
+
 #[derive(Deserialize, JsonSchema)]
 struct ArtefactQuery {
     pub format: Option<String>,
@@ -121,6 +183,20 @@ async fn artefact(
         return interr(&rc.log, "url key mismatch");
     }
 
+    /*
+     * Job outputs never change once uploaded, so the output ID is enough to
+     * identify the contents.  The same output may be rendered in different
+     * ways, though, so the format is included as well.
+     */
+    let etag = if let Some(format) = &query.format {
+        format!("\"{}-{}\"", path.output, format)
+    } else {
+        format!("\"{}\"", path.output)
+    };
+    if etag_matches(&rc, &etag) {
+        return not_modified(&etag, None);
+    }
+
     let response = match cr.variety {
         CheckRunVariety::Basic => variety::basic::artefact(
             app,
@@ -135,7 +211,12 @@ async fn artefact(
         _ => None,
     };
 
-    if let Some(response) = response {
+    if let Some(mut response) = response {
+        if let (hyper::StatusCode::OK, Ok(v)) =
+            (response.status(), hyper::header::HeaderValue::from_str(&etag))
+        {
+            response.headers_mut().insert(hyper::header::ETAG, v);
+        }
         Ok(response)
     } else {
         let out = "<html><head><title>404 Not Found</title>\
@@ -194,9 +275,14 @@ async fn details(
 
     let mut out = String::new();
     out += "<html>\n";
-    out += &format!("<head><title>Check Run: {}</title></head>\n", cr.name);
+    /*
+     * The check run name comes from the job file in the repository, and all
+     * other content on this page must be treated with similar suspicion.
+     */
+    let name = html_escape::encode_safe(&cr.name);
+    out += &format!("<head><title>Check Run: {}</title></head>\n", name);
     out += "<body>\n";
-    out += &format!("<h1>{}: {}</h1>\n", cr.id, cr.name);
+    out += &format!("<h1>{}: {}</h1>\n", cr.id, name);
 
     match cr.variety {
         CheckRunVariety::Control => {
@@ -206,11 +292,17 @@ async fn details(
         }
         CheckRunVariety::FailFirst => {
             let p: super::FailFirstPrivate = cr.get_private().to_500()?;
-            out += &format!("<pre>{:#?}</pre>\n", p);
+            out += &format!(
+                "<pre>{}</pre>\n",
+                html_escape::encode_safe(&format!("{:#?}", p)),
+            );
         }
         CheckRunVariety::AlwaysPass => {
             let p: super::AlwaysPassPrivate = cr.get_private().to_500()?;
-            out += &format!("<pre>{:#?}</pre>\n", p);
+            out += &format!(
+                "<pre>{}</pre>\n",
+                html_escape::encode_safe(&format!("{:#?}", p)),
+            );
         }
         CheckRunVariety::Basic => {
             out += &variety::basic::details(app, &cs, &cr, local_time)
@@ -324,38 +416,221 @@ async fn webhook(
     Ok(HttpResponseOk(()))
 }
 
+#[derive(Deserialize, JsonSchema)]
+struct StatusQuery {
+    pub format: Option<String>,
+    pub refresh: Option<u64>,
+    pub completed: Option<u64>,
+    pub owner: Option<String>,
+    pub repo: Option<String>,
+}
+
+impl StatusQuery {
+    fn filtered(&self) -> bool {
+        self.owner.is_some() || self.repo.is_some()
+    }
+
+    /**
+     * Determine whether a job with these tags should be displayed.  If the
+     * user has asked for a particular GitHub owner or repository, jobs that
+     * are not associated with it are excluded.
+     */
+    fn matches(&self, tags: &HashMap<String, String>) -> bool {
+        if let Some(owner) = &self.owner {
+            if tags.get("gong.repo.owner") != Some(owner) {
+                return false;
+            }
+        }
+        if let Some(repo) = &self.repo {
+            if tags.get("gong.repo.name") != Some(repo) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /**
+     * The generated client can pass only one tag filter to the job list, so
+     * choose the most selective one and have the server apply it.  Any other
+     * part of the filter is then applied with matches().
+     */
+    fn tag_filter(&self) -> Option<String> {
+        if let Some(repo) = &self.repo {
+            Some(format!("gong.repo.name={repo}"))
+        } else {
+            self.owner.as_ref().map(|owner| format!("gong.repo.owner={owner}"))
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct StatusGithub {
+    owner: String,
+    repo: String,
+    branch: Option<String>,
+    name: Option<String>,
+    sha: Option<String>,
+    url: Option<String>,
+}
+
+impl StatusGithub {
+    fn from_tags(tags: &HashMap<String, String>) -> Option<StatusGithub> {
+        let owner = tags.get("gong.repo.owner")?;
+        let repo = tags.get("gong.repo.name")?;
+
+        Some(StatusGithub {
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+            branch: tags.get("gong.head.branch").cloned(),
+            name: tags.get("gong.name").cloned(),
+            sha: tags.get("gong.head.sha").cloned(),
+            url: tags.get("gong.run.github_id").map(|checkrun| {
+                format!(
+                    "https://github.com/{}/{}/runs/{}",
+                    owner, repo, checkrun
+                )
+            }),
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct StatusJob {
+    id: String,
+    name: String,
+    owner: String,
+    state: String,
+    cancelled: bool,
+    target: Option<String>,
+    target_real: Option<String>,
+    times: HashMap<String, DateTime<Utc>>,
+    github: Option<StatusGithub>,
+}
+
+impl StatusJob {
+    fn from_job(
+        job: &buildomat_client::types::Job,
+        users: &HashMap<String, String>,
+    ) -> StatusJob {
+        StatusJob {
+            id: job.id.to_string(),
+            name: job.name.to_string(),
+            owner: users.get(&job.owner).unwrap_or(&job.owner).to_string(),
+            state: job.state.to_string(),
+            cancelled: job.cancelled,
+            target: Some(job.target.to_string()),
+            target_real: Some(job.target_real.to_string()),
+            times: job.times.clone(),
+            github: StatusGithub::from_tags(&job.tags),
+        }
+    }
+
+    fn from_worker_job(
+        job: &buildomat_client::types::WorkerJob,
+        users: &HashMap<String, String>,
+    ) -> StatusJob {
+        StatusJob {
+            id: job.id.to_string(),
+            name: job.name.to_string(),
+            owner: users.get(&job.owner).unwrap_or(&job.owner).to_string(),
+            state: job.state.to_string(),
+            cancelled: false,
+            target: None,
+            target_real: None,
+            times: Default::default(),
+            github: StatusGithub::from_tags(&job.tags),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct StatusWorker {
+    id: String,
+    target: Option<String>,
+    factory_private: Option<String>,
+    time_create: DateTime<Utc>,
+    jobs: Vec<StatusJob>,
+}
+
+#[derive(Serialize)]
+struct Status {
+    workers: Vec<StatusWorker>,
+    queued: Vec<StatusJob>,
+    waiting: Vec<StatusJob>,
+    other: Vec<StatusJob>,
+    completed: Vec<StatusJob>,
+}
+
 #[endpoint {
     method = GET,
     path = "/status",
 }]
 async fn status(
     rc: RequestContext<Arc<App>>,
+    query: dropshot::Query<StatusQuery>,
 ) -> SResult<hyper::Response<hyper::Body>, HttpError> {
     let app = rc.context();
     let b = app.buildomat_admin();
 
-    let mut out = String::new();
-    out += "<html>\n";
-    out += "<head><title>Buildomat Status</title></head>\n";
-    out += "<body>\n";
-    out += "<h1>Buildomat Status</h1>\n";
+    let query = query.into_inner();
+    let json = query.format.as_deref() == Some("json");
+
+    /*
+     * By default we show a modest number of recently completed jobs, but allow
+     * the user to look a bit further back if they want.
+     */
+    let completed = query.completed.unwrap_or(40).min(200);
 
     /*
      * Load active jobs, recently completed jobs, and active workers:
      */
-    let jobs = b.admin_jobs_get().active(true).send().await.to_500()?;
+    let tag = query.tag_filter();
+    let mut jobs = {
+        let mut req = b.admin_jobs_get().active(true);
+        if let Some(tag) = &tag {
+            req = req.tag(tag);
+        }
+        req.send().await.to_500()?
+    };
+    jobs.retain(|job| query.matches(&job.tags));
     let oldjobs = {
-        let mut oldjobs =
-            b.admin_jobs_get().completed(40).send().await.to_500()?;
+        let mut req = b.admin_jobs_get().completed(completed);
+        if let Some(tag) = &tag {
+            req = req.tag(tag);
+        }
+        let mut oldjobs = req.send().await.to_500()?;
         /*
          * Display most recent job first by sorting the ID backwards; a ULID
          * begins with a timestamp prefix, so a lexicographical sort is ordered
          * by creation time.
          */
         oldjobs.sort_by(|a, b| b.id.cmp(&a.id));
+        oldjobs.retain(|job| query.matches(&job.tags));
         oldjobs
     };
     let workers = b.workers_list().active(true).send().await.to_500()?;
+    let workers = workers
+        .workers
+        .iter()
+        .filter(|w| !w.deleted)
+        .filter_map(|w| {
+            let wjobs = w
+                .jobs
+                .iter()
+                .filter(|job| query.matches(&job.tags))
+                .collect::<Vec<_>>();
+
+            /*
+             * If the user has asked for jobs from a particular repository,
+             * only display the workers that are running those jobs.
+             */
+            if query.filtered() && wjobs.is_empty() {
+                None
+            } else {
+                Some((w, wjobs))
+            }
+        })
+        .collect::<Vec<_>>();
     let targets = b
         .targets_list()
         .send()
@@ -364,7 +639,134 @@ async fn status(
         .iter()
         .map(|t| (t.id.to_string(), t.name.to_string()))
         .collect::<HashMap<String, String>>();
+
+    /*
+     * Sort the jobs into the sections in which they will be displayed.  Jobs
+     * that are assigned to a worker are displayed with that worker, and each
+     * job is displayed only once.
+     */
+    let mut seen = workers
+        .iter()
+        .flat_map(|(_, wjobs)| wjobs.iter().map(|job| job.id.to_string()))
+        .collect::<HashSet<_>>();
+    let mut sections = Vec::new();
+    for state in [Some("queued"), Some("waiting"), None] {
+        let section = jobs
+            .iter()
+            .filter(|job| {
+                if seen.contains(&job.id) {
+                    false
+                } else if job.state == "completed" || job.state == "failed" {
+                    /*
+                     * Completed jobs will be displayed in a later section.
+                     */
+                    false
+                } else if let Some(state) = state {
+                    /*
+                     * This round, we are displaying jobs of a particular
+                     * status.
+                     */
+                    state == job.state
+                } else {
+                    /*
+                     * Catch all the stragglers.
+                     */
+                    true
+                }
+            })
+            .collect::<Vec<_>>();
+
+        seen.extend(section.iter().map(|job| job.id.to_string()));
+        sections.push(section);
+    }
+    let oldjobs = oldjobs
+        .iter()
+        .filter(|job| !seen.contains(&job.id))
+        .collect::<Vec<_>>();
+
+    /*
+     * Look up the name of each user that owns a job we are going to display.
+     */
     let mut users: HashMap<String, String> = Default::default();
+    let owners = workers
+        .iter()
+        .flat_map(|(_, wjobs)| wjobs.iter().map(|job| &job.owner))
+        .chain(sections.iter().flatten().map(|job| &job.owner))
+        .chain(oldjobs.iter().map(|job| &job.owner));
+    for owner in owners {
+        if !users.contains_key(owner) {
+            let u = b.user_get().user(owner).send().await.to_500()?;
+            users.insert(owner.to_string(), u.name.to_string());
+        }
+    }
+
+    if json {
+        let mut sections = sections.into_iter().map(|section| {
+            section
+                .into_iter()
+                .map(|job| StatusJob::from_job(job, &users))
+                .collect::<Vec<_>>()
+        });
+
+        let status = Status {
+            workers: workers
+                .iter()
+                .map(|(w, wjobs)| {
+                    Ok(StatusWorker {
+                        id: w.id.to_string(),
+                        target: targets.get(&w.target).cloned(),
+                        factory_private: w.factory_private.clone(),
+                        time_create: w.id().to_500()?.creation(),
+                        jobs: wjobs
+                            .iter()
+                            .map(|wj| {
+                                if let Some(job) =
+                                    jobs.iter().find(|j| j.id == wj.id)
+                                {
+                                    StatusJob::from_job(job, &users)
+                                } else {
+                                    StatusJob::from_worker_job(wj, &users)
+                                }
+                            })
+                            .collect(),
+                    })
+                })
+                .collect::<SResult<Vec<_>, HttpError>>()?,
+            queued: sections.next().unwrap(),
+            waiting: sections.next().unwrap(),
+            other: sections.next().unwrap(),
+            completed: oldjobs
+                .iter()
+                .map(|job| StatusJob::from_job(job, &users))
+                .collect(),
+        };
+
+        let out =
+            serde_json::to_vec(&status).map_err(|e| anyhow!(e)).to_500()?;
+
+        return Ok(hyper::Response::builder()
+            .status(hyper::StatusCode::OK)
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .header(hyper::header::CONTENT_LENGTH, out.len())
+            .body(hyper::Body::from(out))?);
+    }
+
+    let mut out = String::new();
+    out += "<html>\n";
+    out += "<head><title>Buildomat Status</title>\n";
+    if let Some(refresh) = query.refresh {
+        /*
+         * Allow the page to be left open as a dashboard, but do not allow it
+         * to be reloaded too frequently.
+         */
+        out += &format!(
+            "<meta http-equiv=\"refresh\" content=\"{}\">\n",
+            refresh.max(5)
+        );
+    }
+    out += "</head>\n";
+    out += "<body>\n";
+    out += "<h1>Buildomat Status</h1>\n";
 
     fn github_url(tags: &HashMap<String, String>) -> Option<String> {
         let owner = tags.get("gong.repo.owner")?;
@@ -374,7 +776,11 @@ async fn status(
         let url =
             format!("https://github.com/{}/{}/runs/{}", owner, name, checkrun);
 
-        Some(format!("<a href=\"{}\">{}</a>", url, url))
+        Some(format!(
+            "<a href=\"{}\">{}</a>",
+            html_escape::encode_double_quoted_attribute(&url),
+            html_escape::encode_safe(&url),
+        ))
     }
 
     fn commit_url(tags: &HashMap<String, String>) -> Option<String> {
@@ -385,7 +791,11 @@ async fn status(
         let url =
             format!("https://github.com/{}/{}/commit/{}", owner, name, sha);
 
-        Some(format!("<a href=\"{}\">{}</a>", url, sha))
+        Some(format!(
+            "<a href=\"{}\">{}</a>",
+            html_escape::encode_double_quoted_attribute(&url),
+            html_escape::encode_safe(sha),
+        ))
     }
 
     fn github_info(tags: &HashMap<String, String>) -> Option<String> {
@@ -395,11 +805,19 @@ async fn status(
 
         let url = format!("https://github.com/{}/{}", owner, name);
 
-        let mut out = format!("<a href=\"{}\">{}/{}</a>", url, owner, name);
+        /*
+         * Tag values are provided by the submitter of the job, so they must be
+         * escaped before they are included in the page.
+         */
+        let mut out = format!(
+            "<a href=\"{}\">{}</a>",
+            html_escape::encode_double_quoted_attribute(&url),
+            html_escape::encode_safe(&format!("{}/{}", owner, name)),
+        );
         if let Some(branch) = tags.get("gong.head.branch") {
-            out.push_str(&format!(" ({})", branch));
+            out.push_str(&format!(" ({})", html_escape::encode_safe(branch)));
         }
-        out.push_str(&format!(": {}", title));
+        out.push_str(&format!(": {}", html_escape::encode_safe(title)));
 
         Some(out)
     }
@@ -420,12 +838,13 @@ async fn status(
         if job.target == job.target_real {
             out += &format!(
                 "&nbsp;&nbsp;&nbsp;<b>target:</b> {}<br>\n",
-                job.target
+                html_escape::encode_safe(&job.target),
             );
         } else {
             out += &format!(
                 "&nbsp;&nbsp;&nbsp;<b>target:</b> {} &rarr; {}<br>\n",
-                job.target, job.target_real
+                html_escape::encode_safe(&job.target),
+                html_escape::encode_safe(&job.target_real),
             );
         }
 
@@ -457,7 +876,7 @@ async fn status(
         if let Some(t) = job.duration("ready", "assigned") {
             times.push(format!("queued {}", t.render()));
         }
-        if let Some(t) = job.duration("assigned", "complete") {
+        if let Some(t) = job.run_duration() {
             times.push(format!("ran for {}", t.render()));
         }
         if !times.is_empty() {
@@ -473,25 +892,19 @@ async fn status(
         out
     }
 
-    let mut seen = HashSet::new();
-
-    if workers.workers.iter().any(|w| !w.deleted) {
+    if !workers.is_empty() {
         out += "<h2>Active Workers</h2>\n";
         out += "<ul>\n";
 
-        for w in workers.workers.iter() {
-            if w.deleted {
-                continue;
-            }
-
+        for (w, wjobs) in workers.iter() {
             out += "<li>";
             out += &w.id;
             let mut things = Vec::new();
             if let Some(t) = targets.get(&w.target) {
-                things.push(t.to_string());
+                things.push(html_escape::encode_safe(t).to_string());
             }
             if let Some(fp) = &w.factory_private {
-                things.push(fp.to_string());
+                things.push(html_escape::encode_safe(fp).to_string());
             }
             if !things.is_empty() {
                 out += &format!(" ({})", things.join(", "));
@@ -505,27 +918,17 @@ async fn status(
                 w.id().to_500()?.age().render(),
             );
 
-            if !w.jobs.is_empty() {
+            if !wjobs.is_empty() {
                 out += "<ul>\n";
 
-                for job in w.jobs.iter() {
-                    seen.insert(job.id.to_string());
-
-                    if !users.contains_key(&job.owner) {
-                        let owner = b
-                            .user_get()
-                            .user(&job.owner)
-                            .send()
-                            .await
-                            .to_500()?;
-                        users.insert(job.owner.clone(), owner.name.to_string());
-                    }
-
+                for job in wjobs.iter() {
                     out += "<li>";
                     out += &format!(
                         "job {} user {}",
                         job.id,
-                        users.get(&job.owner).unwrap()
+                        html_escape::encode_safe(
+                            users.get(&job.owner).unwrap()
+                        ),
                     );
                     if let Some(job) = jobs.iter().find(|j| j.id == job.id) {
                         out += &dump_info(&job);
@@ -540,79 +943,44 @@ async fn status(
         out += "</ul>\n";
     }
 
-    for (heading, state) in [
-        ("Queued Jobs (waiting for capacity)", Some("queued")),
-        ("Waiting Jobs (waiting for a dependency)", Some("waiting")),
-        ("Other Jobs", None),
-    ] {
-        let mut did_heading = false;
-
-        for job in jobs.iter() {
-            if seen.contains(&job.id) {
-                continue;
-            }
-
-            let display = if job.state == "completed" || job.state == "failed" {
-                /*
-                 * Completed jobs will be displayed in a later section.
-                 */
-                false
-            } else if let Some(state) = state.as_deref() {
-                /*
-                 * This round, we are displaying jobs of a particular status.
-                 */
-                state == &job.state
-            } else {
-                /*
-                 * Catch all the stragglers.
-                 */
-                true
-            };
-
-            if !display {
-                continue;
-            }
-
-            seen.insert(job.id.to_string());
-
-            if !did_heading {
-                did_heading = true;
-                out += &format!("<h2>{}</h2>\n", heading);
-                out += "<ul>\n";
-            }
+    for (heading, section) in [
+        "Queued Jobs (waiting for capacity)",
+        "Waiting Jobs (waiting for a dependency)",
+        "Other Jobs",
+    ]
+    .into_iter()
+    .zip(sections.iter())
+    {
+        if section.is_empty() {
+            continue;
+        }
 
-            if !users.contains_key(&job.owner) {
-                let owner =
-                    b.user_get().user(&job.owner).send().await.to_500()?;
-                users.insert(job.owner.clone(), owner.name.to_string());
-            }
+        out += &format!("<h2>{}</h2>\n", heading);
+        out += "<ul>\n";
 
+        for job in section.iter() {
             out += "<li>";
-            out +=
-                &format!("{} user {}", job.id, users.get(&job.owner).unwrap());
+            out += &format!(
+                "{} user {}",
+                job.id,
+                html_escape::encode_safe(users.get(&job.owner).unwrap()),
+            );
             out += &dump_info(&job);
             out += "<br>\n";
         }
 
-        if did_heading {
-            out += "</ul>\n";
-        }
+        out += "</ul>\n";
     }
 
-    out += "<h2>Recently Completed Jobs</h2>\n";
+    out += &format!("<h2>Recently Completed Jobs (last {})</h2>\n", completed);
     out += "<ul>\n";
     for job in oldjobs.iter() {
-        if seen.contains(&job.id) {
-            continue;
-        }
-
-        if !users.contains_key(&job.owner) {
-            let owner = b.user_get().user(&job.owner).send().await.to_500()?;
-            users.insert(job.owner.clone(), owner.name.to_string());
-        }
-
         out += "<li>";
-        out += &format!("{} user {}", job.id, users.get(&job.owner).unwrap());
+        out += &format!(
+            "{} user {}",
+            job.id,
+            html_escape::encode_safe(users.get(&job.owner).unwrap()),
+        );
         let (colour, word) = if job.state == "failed" {
             if job.cancelled {
                 ("dabea6", "CANCEL")
@@ -669,36 +1037,155 @@ async fn published_file(
     {
         app.buildomat_username(&repo)
     } else {
-        let out = "<html><head><title>404 Not Found</title>\
-            <body>Artefact not found!</body></html>";
-
-        return Ok(hyper::Response::builder()
-            .status(hyper::StatusCode::NOT_FOUND)
-            .header(hyper::header::CONTENT_TYPE, "text/html")
-            .header(hyper::header::CONTENT_LENGTH, out.as_bytes().len())
-            .body(hyper::Body::from(out))?);
+        return not_found("Artefact");
     };
 
     let b = app.buildomat_admin();
 
+    /*
+     * Find out which file this name refers to before we fetch it.  The "latest"
+     * version is resolved to a specific version here, and we then download
+     * exactly that version so that the entity tag matches the file we send.
+     */
+    let info = b
+        .public_file_info()
+        .username(&bmu)
+        .series(&path.series)
+        .version(&path.version)
+        .name(&path.name)
+        .send()
+        .await
+        .to_500()?
+        .into_inner();
+
+    /*
+     * A particular version of a published file never changes, so caches may
+     * hold on to it indefinitely.  The "latest" version of a file changes
+     * whenever a new version is published, so caches must check with us each
+     * time; the entity tag allows them to do so without fetching the file
+     * again, and allows us to answer them without starting a download from
+     * the core server.
+     */
+    let cc = if path.version == "latest" {
+        "no-cache"
+    } else {
+        "public, max-age=31536000, immutable"
+    };
+    let etag = format!("\"{}\"", info.file);
+
+    if etag_matches(&rc, &etag) {
+        return not_modified(&etag, Some(cc));
+    }
+
     let backend = b
         .public_file_download()
         .username(&bmu)
         .series(&path.series)
-        .version(&path.version)
+        .version(&info.version)
         .name(&path.name)
         .send()
         .await
         .to_500()?;
 
-    let ct = guess_mime_type(&path.name);
+    let ct = guess_mime_type_with(&path.name, &app.config.mime_types);
     let cl = backend.content_length().unwrap();
 
-    Ok(hyper::Response::builder()
+    let res = hyper::Response::builder()
         .status(hyper::StatusCode::OK)
         .header(hyper::header::CONTENT_TYPE, ct)
         .header(hyper::header::CONTENT_LENGTH, cl)
-        .body(hyper::Body::wrap_stream(backend.into_inner_stream()))?)
+        .header(hyper::header::CACHE_CONTROL, cc)
+        .header(hyper::header::ETAG, etag);
+
+    Ok(res.body(hyper::Body::wrap_stream(backend.into_inner_stream()))?)
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct PublishedSeriesPath {
+    pub owner: String,
+    pub repo: String,
+    pub series: String,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/public/file/{owner}/{repo}/{series}",
+}]
+async fn published_series(
+    rc: RequestContext<Arc<App>>,
+    path: dropshot::Path<PublishedSeriesPath>,
+) -> SResult<hyper::Response<hyper::Body>, HttpError> {
+    let app = rc.context();
+    let path = path.into_inner();
+
+    /*
+     * Determine the buildomat username for this GitHub owner/repository:
+     */
+    let Some(repo) =
+        app.db.lookup_repository(&path.owner, &path.repo).to_500()?
+    else {
+        return not_found("Series");
+    };
+    let bmu = app.buildomat_username(&repo);
+
+    let versions = app
+        .buildomat_admin()
+        .public_file_versions()
+        .username(&bmu)
+        .series(&path.series)
+        .send()
+        .await
+        .to_500()?
+        .into_inner();
+
+    if versions.is_empty() {
+        return not_found("Series");
+    }
+
+    let title = html_escape::encode_safe(&format!(
+        "{}/{}: {}",
+        path.owner, path.repo, path.series
+    ))
+    .to_string();
+
+    let mut out = String::new();
+    out += "<html>\n";
+    out += &format!("<head><title>{title}</title></head>\n");
+    out += "<body>\n";
+    out += &format!("<h1>{title}</h1>\n");
+    out += "<ul>\n";
+    for v in versions {
+        out += &format!(
+            "<li><b>{}</b> (published {})\n<ul>\n",
+            html_escape::encode_safe(&v.version),
+            v.time_publish.to_rfc3339_opts(SecondsFormat::Secs, true),
+        );
+        for name in v.names {
+            let href = format!(
+                "/public/file/{}/{}/{}/{}/{}",
+                encode_segment(&path.owner),
+                encode_segment(&path.repo),
+                encode_segment(&path.series),
+                encode_segment(&v.version),
+                encode_segment(&name),
+            );
+            out += &format!(
+                "<li><a href=\"{}\">{}</a></li>\n",
+                html_escape::encode_double_quoted_attribute(&href),
+                html_escape::encode_safe(&name),
+            );
+        }
+        out += "</ul></li>\n";
+    }
+    out += "</ul>\n";
+    out += "</body>\n";
+    out += "</html>\n";
+
+    Ok(hyper::Response::builder()
+        .status(hyper::StatusCode::OK)
+        .header(hyper::header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .header(hyper::header::CONTENT_LENGTH, out.as_bytes().len())
+        .body(hyper::Body::from(out))?)
 }
 
 #[derive(Deserialize, JsonSchema)]
@@ -744,19 +1231,47 @@ async fn branch_to_commit(
         HttpError::for_internal_error(format!("repo {repo:?} to install: {e}"))
     })?;
 
-    let branch = app
-        .install_client(install.id)
-        .repos()
-        .get_branch(&repo.owner, &repo.name, &path.branch)
-        .await
-        .map_err(|e| HttpError::for_internal_error(e.to_string()))?;
+    /*
+     * This endpoint is polled by deployment tooling, so remember the result
+     * for a short time rather than asking GitHub every time.
+     */
+    let ttl = Duration::from_secs(app.config.branch_cache_seconds);
+    let key = (repo.id, path.branch.to_string());
+    let cached = app
+        .branch_cache
+        .lock()
+        .unwrap()
+        .get(&key)
+        .map(|(when, sha)| (when.elapsed(), sha.to_string()))
+        .filter(|(age, _)| *age < ttl);
+
+    let (age, sha) = if let Some(cached) = cached {
+        cached
+    } else {
+        let branch = app
+            .install_client(install.id)
+            .repos()
+            .get_branch(&repo.owner, &repo.name, &path.branch)
+            .await
+            .map_err(|e| HttpError::for_internal_error(e.to_string()))?;
+
+        let mut bc = app.branch_cache.lock().unwrap();
+        bc.retain(|_, (when, _)| when.elapsed() < ttl);
+        bc.insert(key, (Instant::now(), branch.commit.sha.to_string()));
+
+        (Duration::ZERO, branch.commit.sha)
+    };
 
-    let body = format!("{}\n", branch.commit.sha);
+    let body = format!("{}\n", sha);
 
     Ok(hyper::Response::builder()
         .status(hyper::StatusCode::OK)
         .header(hyper::header::CONTENT_TYPE, "text/plain")
         .header(hyper::header::CONTENT_LENGTH, body.as_bytes().len())
+        .header(
+            hyper::header::CACHE_CONTROL,
+            format!("max-age={}", ttl.saturating_sub(age).as_secs()),
+        )
         .body(body.into())?)
 }
 
@@ -776,6 +1291,7 @@ pub(crate) async fn server(
     api.register(artefact).unwrap();
     api.register(status).unwrap();
     api.register(published_file).unwrap();
+    api.register(published_series).unwrap();
     api.register(branch_to_commit).unwrap();
 
     let log = app.log.clone();
@@ -5,6 +5,7 @@
 #![allow(clippy::vec_init_then_push)]
 
 use anyhow::{anyhow, bail, Context, Result};
+use buildomat_client::ext::RetryClient;
 use buildomat_common::*;
 use buildomat_github_common::hooktypes;
 use buildomat_github_database::types::*;
@@ -12,7 +13,9 @@ use serde::{Deserialize, Serialize};
 #[allow(unused_imports)]
 use slog::{debug, error, info, o, trace, warn, Logger};
 use std::collections::{HashMap, HashSet};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use variety::basic::DependencyError;
 use variety::control::{ControlPrivate, CONTROL_RUN_NAME};
 
 mod config;
@@ -79,6 +82,11 @@ struct App {
     db: buildomat_github_database::Database,
     config: config::Config,
     jwt: octorust::auth::JWTCredentials,
+    /*
+     * Recently resolved branch heads, keyed by repository ID and branch name,
+     * with the time at which each was resolved.
+     */
+    branch_cache: Mutex<HashMap<(i64, String), (Instant, String)>>,
 }
 
 impl App {
@@ -358,7 +366,27 @@ impl App {
          */
         for job in jobfiles.iter() {
             match job.variety {
-                CheckRunVariety::Basic => {}
+                CheckRunVariety::Basic => {
+                    /*
+                     * Basic variety jobs wait for the buildomat job of each
+                     * dependency, so every dependency must also be a Basic
+                     * variety job.  Report this now, rather than after some
+                     * of the jobs in the plan have already started.
+                     */
+                    let deps = job.dependencies.values().filter_map(|dep| {
+                        jobfiles.iter().find(|j| j.name == dep.job)
+                    });
+                    for depjob in deps {
+                        if !matches!(depjob.variety, CheckRunVariety::Basic) {
+                            return Err(DependencyError::Variety {
+                                job: job.name.to_string(),
+                                dependency: depjob.name.to_string(),
+                                variety: depjob.variety,
+                            }
+                            .into());
+                        }
+                    }
+                }
                 CheckRunVariety::AlwaysPass | CheckRunVariety::FailFirst => {
                     if !job.dependencies.is_empty() {
                         bail!(
@@ -491,12 +519,17 @@ impl App {
         format!("gong-{}", repo.id)
     }
 
-    fn buildomat(&self, repo: &Repository) -> buildomat_client::Client {
-        buildomat_client::ClientBuilder::new(&self.config.buildomat.url)
-            .bearer_token(&self.config.buildomat.token)
-            .delegated_user(&self.buildomat_username(repo))
+    fn buildomat(&self, repo: &Repository) -> RetryClient {
+        let client =
+            buildomat_client::ClientBuilder::new(&self.config.buildomat.url)
+                .bearer_token(&self.config.buildomat.token)
+                .delegated_user(&self.buildomat_username(repo))
+                .build()
+                .unwrap();
+
+        RetryClient::builder(client)
+            .attempts(self.config.buildomat.retry_attempts.unwrap_or(1))
             .build()
-            .unwrap()
     }
 
     fn buildomat_admin(&self) -> buildomat_client::Client {
@@ -817,6 +850,17 @@ async fn process_deliveries(app: &Arc<App>) -> Result<()> {
                     app.db.update_check_suite(&cs)?;
                 }
 
+                /*
+                 * Record the pull request to which this check suite belongs, so
+                 * that jobs can be told about the refs involved.
+                 */
+                if cs.pr_number.is_none() {
+                    cs.pr_number = Some(pr.number);
+                    cs.pr_base_ref = Some(pr.base.ref_.to_string());
+                    cs.pr_head_ref = Some(pr.head.ref_.to_string());
+                    app.db.update_check_suite(&cs)?;
+                }
+
                 info!(
                     log,
                     "delivery {}: check suite {} -> {}",
@@ -916,14 +960,21 @@ async fn process_deliveries(app: &Arc<App>) -> Result<()> {
                         continue;
                     }
                     CheckRunVariety::Basic => {
+                        let reason = format!(
+                            "cancelled via GitHub by {}",
+                            payload.sender.login,
+                        );
+
                         match actid.as_str() {
                             "cancel" => {
                                 /*
                                  * Cancel any work that has been queued but not
                                  * yet performed:
                                  */
-                                variety::basic::cancel(app, &cs, &mut cr)
-                                    .await?;
+                                variety::basic::cancel(
+                                    app, &cs, &mut cr, &reason,
+                                )
+                                .await?;
                             }
                             "cancel_all" => {
                                 /*
@@ -943,8 +994,10 @@ async fn process_deliveries(app: &Arc<App>) -> Result<()> {
                                         continue;
                                     }
 
-                                    variety::basic::cancel(app, &cs, &mut cr)
-                                        .await?;
+                                    variety::basic::cancel(
+                                        app, &cs, &mut cr, &reason,
+                                    )
+                                    .await?;
                                 }
                             }
                             other => {
@@ -2177,6 +2230,7 @@ async fn main() -> Result<()> {
             config.sqlite.cache_kb,
         )?,
         config,
+        branch_cache: Default::default(),
     });
 
     /*
@@ -2,9 +2,11 @@
  * Copyright 2023 Oxide Computer Company
  */
 
+use crate::config::Config;
 use crate::{App, FlushOut, FlushState};
 use anyhow::{bail, Result};
-use buildomat_client::types::{DependSubmit, JobOutput};
+use buildomat_client::ext::*;
+use buildomat_client::types::{DependSubmit, JobCancel, JobOutput};
 use buildomat_common::*;
 use buildomat_github_database::types::*;
 use chrono::SecondsFormat;
@@ -20,9 +22,47 @@ const KILOBYTE: f64 = 1024.0;
 const MEGABYTE: f64 = 1024.0 * KILOBYTE;
 const GIGABYTE: f64 = 1024.0 * MEGABYTE;
 
-const MAX_OUTPUTS: usize = 25;
+const DEFAULT_MAX_OUTPUTS: usize = 25;
+/*
+ * The check run summary is limited in size by GitHub, so we cannot list an
+ * arbitrary number of artefacts there.
+ */
+const MAX_OUTPUTS_LIMIT: usize = 200;
 const MAX_TAIL_LINES: usize = 20;
+/*
+ * These environment variables are established by the job setup and cannot be
+ * overridden through the "environment" or "secrets" configuration options.  Any
+ * name with the "GITHUB_" prefix is also reserved.
+ */
+const RESERVED_ENV: &[&str] =
+    &["BASH_ENV", "CI", "HOME", "LOGNAME", "PATH", "USER"];
+/*
+ * Secret values are written to this file in the home directory of the build
+ * user, which is then loaded by bash at the start of the build program through
+ * the BASH_ENV mechanism.  Bash expands the value of BASH_ENV before use.
+ */
+const SECRETS_FILE: &str = "$HOME/.buildomat-secrets";
 const MAX_LINE_LENGTH: usize = 90;
+/*
+ * If the clock on a worker disagrees with the server by more than this many
+ * seconds, show the worker time alongside the server time for each event so
+ * that the skew is visible.
+ */
+const CLOCK_SKEW_SECONDS: i64 = 5;
+
+/**
+ * A problem with the dependencies of a Basic variety job.  Dependencies are
+ * resolved to buildomat job IDs, which only Basic variety jobs have.
+ */
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum DependencyError {
+    #[error(
+        "job {job:?} depends on job {dependency:?}, which has variety \
+        {variety}; Basic variety jobs can only depend on other Basic variety \
+        jobs"
+    )]
+    Variety { job: String, dependency: String, variety: CheckRunVariety },
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 struct BasicConfig {
@@ -36,6 +76,84 @@ struct BasicConfig {
     publish: Vec<BasicConfigPublish>,
     #[serde(default)]
     skip_clone: bool,
+    #[serde(default = "default_max_outputs")]
+    max_outputs: usize,
+    #[serde(default)]
+    environment: HashMap<String, String>,
+    #[serde(default)]
+    secrets: Vec<String>,
+    setup: Option<String>,
+    setup_script: Option<String>,
+}
+
+fn check_env_name(n: &str) -> Option<String> {
+    let valid = n.chars().enumerate().all(|(i, c)| {
+        c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit())
+    });
+    if n.is_empty() || !valid {
+        return Some(format!("environment variable name {:?} is not valid", n));
+    }
+
+    if n.starts_with("GITHUB_") || RESERVED_ENV.contains(&n) {
+        return Some(format!("environment variable {:?} is reserved", n));
+    }
+
+    None
+}
+
+impl BasicConfig {
+    /**
+     * Check for configuration values that are syntactically valid, but which
+     * we cannot accept.  Returns a message suitable for the user on failure.
+     */
+    fn check(&self, config: &Config) -> Option<String> {
+        if self.setup.is_some() && self.setup_script.is_some() {
+            return Some(
+                "setup and setup_script cannot both be specified".into(),
+            );
+        }
+        if let Some(name) = self.setup.as_deref() {
+            if !config.setup.contains_key(name) {
+                return Some(format!(
+                    "setup profile {:?} does not exist",
+                    name
+                ));
+            }
+        }
+
+        if self.max_outputs == 0 || self.max_outputs > MAX_OUTPUTS_LIMIT {
+            return Some(format!(
+                "max_outputs must be between 1 and {}, not {}",
+                MAX_OUTPUTS_LIMIT, self.max_outputs,
+            ));
+        }
+
+        let mut names = self.environment.keys().collect::<Vec<_>>();
+        names.sort_unstable();
+        for n in names {
+            if let Some(msg) = check_env_name(n) {
+                return Some(msg);
+            }
+        }
+
+        for n in self.secrets.iter() {
+            if let Some(msg) = check_env_name(n) {
+                return Some(format!("secrets: {}", msg));
+            }
+            if self.environment.contains_key(n) {
+                return Some(format!(
+                    "{:?} cannot be both a secret and in the environment",
+                    n
+                ));
+            }
+        }
+
+        None
+    }
+}
+
+fn default_max_outputs() -> usize {
+    DEFAULT_MAX_OUTPUTS
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -43,6 +161,8 @@ struct BasicConfigPublish {
     from_output: String,
     series: String,
     name: String,
+    #[serde(default)]
+    on_failure: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -50,6 +170,10 @@ struct BasicPrivate {
     #[serde(default)]
     complete: bool,
     job_state: Option<String>,
+    #[serde(default)]
+    job_queue_position: Option<u64>,
+    #[serde(default)]
+    job_failure_reason: Option<String>,
     buildomat_id: Option<String>,
     error: Option<String>,
     #[serde(default)]
@@ -67,6 +191,8 @@ struct BasicPrivate {
     job_outputs: Vec<BasicOutput>,
     #[serde(default)]
     job_outputs_extra: usize,
+    #[serde(default)]
+    warnings: Vec<String>,
 
     #[serde(default)]
     extra_repo_ids: Vec<i64>,
@@ -101,18 +227,24 @@ impl BasicOutput {
             cs.id, cs.url_key, cr.id, o.id, name
         ));
 
-        let szf = o.size as f64;
-        let size = if szf > GIGABYTE {
-            format!("{:<.2}GiB", szf / GIGABYTE)
-        } else if szf > MEGABYTE {
-            format!("{:<.2}MiB", szf / MEGABYTE)
-        } else if szf > KILOBYTE {
-            format!("{:<.2}KiB", szf / KILOBYTE)
-        } else {
-            format!("{}B", szf)
-        };
+        BasicOutput {
+            path: o.path.to_string(),
+            href,
+            size: format_size(o.size),
+        }
+    }
+}
 
-        BasicOutput { path: o.path.to_string(), href, size }
+fn format_size(size: u64) -> String {
+    let szf = size as f64;
+    if szf > GIGABYTE {
+        format!("{:<.2}GiB", szf / GIGABYTE)
+    } else if szf > MEGABYTE {
+        format!("{:<.2}MiB", szf / MEGABYTE)
+    } else if szf > KILOBYTE {
+        format!("{:<.2}KiB", szf / KILOBYTE)
+    } else {
+        format!("{}B", szf)
     }
 }
 
@@ -177,6 +309,14 @@ pub(crate) async fn flush(
         summary += "The job was cancelled by a user.\n\n";
     }
 
+    if !p.warnings.is_empty() {
+        summary += "Warnings:\n";
+        for w in p.warnings.iter() {
+            summary += &format!("* {}\n", w);
+        }
+        summary += "\n\n";
+    }
+
     if !p.job_outputs.is_empty() {
         summary += "The job produced the following artefacts:\n";
         for bo in p.job_outputs.iter() {
@@ -230,6 +370,14 @@ pub(crate) async fn flush(
                 state: FlushState::Success,
                 actions: Default::default(),
             }
+        } else if let Some(reason) = p.job_failure_reason.as_deref() {
+            FlushOut {
+                title: "Failure!".into(),
+                summary: format!("{}Job failed: {}", summary, reason),
+                detail,
+                state: FlushState::Failure,
+                actions: Default::default(),
+            }
         } else {
             FlushOut {
                 title: "Failure!".into(),
@@ -244,9 +392,17 @@ pub(crate) async fn flush(
         }
     } else if let Some(ts) = p.job_state.as_deref() {
         if ts == "queued" {
+            let position = if let Some(n) = p.job_queue_position {
+                format!(" (position {n} in the queue)")
+            } else {
+                "".to_string()
+            };
             FlushOut {
                 title: "Waiting to execute...".into(),
-                summary: format!("{}The job is in line to run.", summary),
+                summary: format!(
+                    "{}The job is in line to run{}.",
+                    summary, position
+                ),
                 detail,
                 state: FlushState::Queued,
                 actions: cancel,
@@ -315,6 +471,15 @@ pub(crate) async fn run(
         return Ok(false);
     };
 
+    if let Some(msg) = c.check(&app.config) {
+        p.complete = true;
+        p.error = Some(msg);
+        cr.set_private(p)?;
+        cr.flushed = false;
+        db.update_check_run(cr)?;
+        return Ok(false);
+    }
+
     let b = app.buildomat(&repo);
     if let Some(jid) = &p.buildomat_id {
         /*
@@ -322,13 +487,22 @@ pub(crate) async fn run(
          * to update our state.
          */
         let bt = b.job_get().job(jid).send().await?.into_inner();
-        let running = bt.state == "running";
-        let complete = bt.state == "completed" || bt.state == "failed";
+        let running = bt.state_parsed().is_running();
+        let complete = bt.state_parsed().is_terminal();
+        let failed = bt.state_parsed().is_failed();
         let new_state = Some(bt.state);
         if new_state != p.job_state {
             cr.flushed = false;
             p.job_state = new_state;
         }
+        if bt.queue_position != p.job_queue_position {
+            cr.flushed = false;
+            p.job_queue_position = bt.queue_position;
+        }
+        if bt.failure_reason != p.job_failure_reason {
+            cr.flushed = false;
+            p.job_failure_reason = bt.failure_reason;
+        }
 
         if running {
             let store =
@@ -479,14 +653,15 @@ pub(crate) async fn run(
 
         if complete {
             /*
-             * Collect the list of uploaded artefacts.  Keep at most 25 of them.
+             * Collect the list of uploaded artefacts.  Keep at most the
+             * configured number of them.
              */
             let outputs = b.job_outputs_get().job(jid).send().await?;
             if !outputs.is_empty() {
                 cr.flushed = false;
             }
             for o in outputs.iter() {
-                if p.job_outputs.len() < MAX_OUTPUTS {
+                if p.job_outputs.len() < c.max_outputs {
                     p.job_outputs.push(BasicOutput::new(app, cs, cr, o));
                 } else {
                     p.job_outputs_extra += 1;
@@ -494,29 +669,67 @@ pub(crate) async fn run(
             }
 
             /*
-             * Resolve any publishing directives.  For now, we do not handle
-             * publish rules that did not match any output from the actual job.
-             * We also do not yet correctly handle a failure to publish, which
-             * will require more nuance in reported errors from Dropshot and
-             * Progenitor.  This feature is broadly still experimental.
+             * Resolve any publishing directives.  Unless requested otherwise,
+             * we only publish files from jobs that were successful.  A publish
+             * rule that does not match any output is reported to the user as a
+             * warning, but a failure to publish a file that does exist will
+             * fail the check run.
              */
-            for p in c.publish.iter() {
-                if let Some(o) =
-                    outputs.iter().find(|o| o.path == p.from_output)
+            let mut errors = Vec::new();
+            for pc in c.publish.iter() {
+                if failed && !pc.on_failure {
+                    continue;
+                }
+
+                let o = if let Some(o) =
+                    outputs.iter().find(|o| o.path == pc.from_output)
                 {
-                    b.job_output_publish()
-                        .job(jid)
-                        .output(&o.id)
-                        .body_map(|body| {
-                            body.series(&p.series)
-                                .version(&cs.head_sha)
-                                .name(&p.name)
-                        })
-                        .send()
-                        .await
-                        .ok();
+                    o
+                } else {
+                    p.warnings.push(format!(
+                        "publish rule for {:?} did not match any output",
+                        pc.from_output,
+                    ));
+                    cr.flushed = false;
+                    continue;
+                };
+
+                let res = b
+                    .job_output_publish()
+                    .job(jid)
+                    .output(&o.id)
+                    .body_map(|body| {
+                        body.series(&pc.series)
+                            .version(&cs.head_sha)
+                            .name(&pc.name)
+                    })
+                    .send()
+                    .await;
+
+                if let Err(e) = res {
+                    let msg = match e {
+                        buildomat_client::Error::ErrorResponse(rv) => {
+                            rv.message.to_string()
+                        }
+                        e => e.to_string(),
+                    };
+                    warn!(
+                        log,
+                        "check run {} could not publish {:?}: {}",
+                        cr.id,
+                        pc.from_output,
+                        msg,
+                    );
+                    errors.push(format!(
+                        "Could not publish {:?} as {:?}: {}",
+                        pc.from_output, pc.name, msg,
+                    ));
                 }
             }
+            if !errors.is_empty() && p.error.is_none() {
+                p.error = Some(errors.join("; "));
+                cr.flushed = false;
+            }
         }
     } else if !cr.active {
         /*
@@ -537,11 +750,19 @@ pub(crate) async fn run(
                 db.load_check_run_for_suite_by_name(&cs.id, &crd.job())?
             {
                 if !matches!(ocr.variety, CheckRunVariety::Basic) {
+                    /*
+                     * This should have been caught when the plan was loaded,
+                     * but check runs created before that validation existed
+                     * may still arrive here.
+                     */
                     p.complete = true;
                     p.error = Some(
-                        "Basic variety jobs can only depend on other Basic \
-                        variety jobs."
-                            .into(),
+                        DependencyError::Variety {
+                            job: cr.name.to_string(),
+                            dependency: ocr.name.to_string(),
+                            variety: ocr.variety,
+                        }
+                        .to_string(),
                     );
                     cr.set_private(p)?;
                     cr.flushed = false;
@@ -558,6 +779,7 @@ pub(crate) async fn run(
                         name.to_string(),
                         DependSubmit {
                             copy_outputs: true,
+                            copy_outputs_globs: Default::default(),
                             on_completed: true,
                             on_failed: false,
                             prior_job: jobid.to_string(),
@@ -680,17 +902,55 @@ pub(crate) async fn run(
         /*
          * Set up a non-root user with which to run the build job, with a work
          * area at "/work".  The user will have the right to escalate to root
-         * privileges via pfexec(1).
+         * privileges via pfexec(1).  The job may provide its own setup script,
+         * or one may be selected from the profiles in our configuration, either
+         * by name or by the target of the job.
          */
+        let setup = if let Some(script) = c.setup_script.as_deref() {
+            Some(script)
+        } else {
+            app.config
+                .setup_profile(c.setup.as_deref(), c.target.as_deref())
+                .map(|s| s.contents.as_str())
+        };
         tasks.push(buildomat_client::types::TaskSubmit {
             name: "setup".into(),
             env: Default::default(),
             env_clear: false,
+            always_run: false,
+            timeout_seconds: None,
             gid: None,
             uid: None,
             workdir: None,
-            script: include_str!("../../scripts/variety/basic/setup.sh").into(),
+            script: setup
+                .unwrap_or(include_str!("../../scripts/variety/basic/setup.sh"))
+                .into(),
         });
+        if setup.is_some() {
+            /*
+             * The rest of the job assumes the environment that the built-in
+             * setup script creates.  Check that a replacement script has done
+             * the same, so that a mistake is reported clearly here rather than
+             * as some confusing failure in a later task.
+             */
+            let mut env = HashMap::new();
+            env.insert(
+                "NEED_GIT".to_string(),
+                if c.skip_clone { "no" } else { "yes" }.to_string(),
+            );
+            tasks.push(buildomat_client::types::TaskSubmit {
+                name: "setup check".into(),
+                env,
+                env_clear: false,
+                always_run: false,
+                timeout_seconds: None,
+                gid: None,
+                uid: None,
+                workdir: None,
+                script: include_str!("../../scripts/variety/basic/check.sh")
+                    .into(),
+            });
+        }
 
         /*
          * Create the base environment for tasks that will run as
@@ -711,12 +971,51 @@ pub(crate) async fn run(
             format!("{}/{}", repo.owner, repo.name),
         );
         buildenv.insert("GITHUB_SHA".to_string(), cs.head_sha.to_string());
-        if let Some(branch) = cs.head_branch.as_deref() {
-            buildenv.insert("GITHUB_BRANCH".to_string(), branch.to_string());
+        if let Some(name) = cs.head_branch.as_deref() {
+            /*
+             * When a tag is pushed, GitHub reports the name of the tag as the
+             * head branch of the check suite.  Ask GitHub whether a tag of
+             * this name exists so that we can present the correct ref.
+             */
+            let tag = cs.pr_number.is_none()
+                && app
+                    .install_client(cs.install)
+                    .git()
+                    .get_ref(&repo.owner, &repo.name, &format!("tags/{}", name))
+                    .await
+                    .is_ok();
+
+            if tag {
+                buildenv.insert(
+                    "GITHUB_REF".to_string(),
+                    format!("refs/tags/{}", name),
+                );
+                buildenv.insert("GITHUB_REF_TYPE".to_string(), "tag".into());
+            } else {
+                buildenv.insert("GITHUB_BRANCH".to_string(), name.to_string());
+                buildenv.insert(
+                    "GITHUB_REF".to_string(),
+                    format!("refs/heads/{}", name),
+                );
+                buildenv.insert("GITHUB_REF_TYPE".to_string(), "branch".into());
+            }
+        }
+        if let Some(number) = cs.pr_number {
+            /*
+             * For pull requests, provide the same refs that GitHub Actions
+             * would provide for a "pull_request" event.
+             */
             buildenv.insert(
                 "GITHUB_REF".to_string(),
-                format!("refs/heads/{}", branch),
+                format!("refs/pull/{}/merge", number),
             );
+            buildenv.insert("GITHUB_REF_TYPE".to_string(), "branch".into());
+            if let Some(base) = cs.pr_base_ref.as_deref() {
+                buildenv.insert("GITHUB_BASE_REF".to_string(), base.into());
+            }
+            if let Some(head) = cs.pr_head_ref.as_deref() {
+                buildenv.insert("GITHUB_HEAD_REF".to_string(), head.into());
+            }
         }
 
         /*
@@ -724,12 +1023,15 @@ pub(crate) async fn run(
          */
         if let Some(toolchain) = c.rust_toolchain.as_deref() {
             let mut buildenv = buildenv.clone();
+            buildenv.extend(c.environment.clone());
             buildenv.insert("TOOLCHAIN".into(), toolchain.into());
 
             tasks.push(buildomat_client::types::TaskSubmit {
                 name: "rust-toolchain".into(),
                 env: buildenv,
                 env_clear: false,
+                always_run: false,
+                timeout_seconds: None,
                 gid: Some(12345),
                 uid: Some(12345),
                 workdir: Some("/home/build".into()),
@@ -764,6 +1066,8 @@ pub(crate) async fn run(
             name: "authentication".into(),
             env: buildenv.clone(),
             env_clear: false,
+            always_run: false,
+            timeout_seconds: None,
             gid: Some(12345),
             uid: Some(12345),
             workdir: Some("/home/build".into()),
@@ -802,6 +1106,8 @@ pub(crate) async fn run(
                 name: "clone repository".into(),
                 env: buildenv.clone(),
                 env_clear: false,
+                always_run: false,
+                timeout_seconds: None,
                 gid: Some(12345),
                 uid: Some(12345),
                 workdir: Some("/home/build".into()),
@@ -827,7 +1133,58 @@ pub(crate) async fn run(
             });
         }
 
+        /*
+         * If the job needs any secrets, wait for each of them to appear in the
+         * job store and write them to a file that only the build user can
+         * read.  The values are captured by the shell rather than printed, so
+         * they do not appear in the job output.  The build program is a bash
+         * program, so we can use BASH_ENV to load the values into the
+         * environment of the build task alone.  Once loaded, the file removes
+         * itself and unsets BASH_ENV, so that the values are not left on disk
+         * and are not loaded again by other bash programs run by the build.
+         */
+        if !c.secrets.is_empty() {
+            let mut buildenv = buildenv.clone();
+            buildenv.insert("BUILDOMAT_SECRETS".into(), c.secrets.join(" "));
+
+            tasks.push(buildomat_client::types::TaskSubmit {
+                name: "secrets".into(),
+                env: buildenv,
+                env_clear: false,
+                always_run: false,
+                timeout_seconds: None,
+                gid: Some(12345),
+                uid: Some(12345),
+                workdir: Some("/home/build".into()),
+                script: "\
+                    #!/bin/bash\n\
+                    \n\
+                    set -o errexit\n\
+                    set -o pipefail\n\
+                    \n\
+                    umask 077\n\
+                    file=\"$HOME/.buildomat-secrets\"\n\
+                    tmp=\"$HOME/.buildomat-secret\"\n\
+                    rm -f \"$file\" \"$tmp\"\n\
+                    for name in $BUILDOMAT_SECRETS; do\n\
+                        bmat store get -r \"$name\" >\"$tmp\"\n\
+                        IFS= read -r -d '' value <\"$tmp\" || true\n\
+                        printf 'export %s=%q\\n' \"$name\" \"$value\" \
+                            >>\"$file\"\n\
+                    done\n\
+                    rm -f \"$tmp\"\n\
+                    printf 'unset BASH_ENV\\nrm -f %q\\n' \"$file\" \
+                        >>\"$file\"\n\
+                    "
+                .into(),
+            });
+        }
+
         buildenv.insert("CI".to_string(), "true".to_string());
+        buildenv.extend(c.environment.clone());
+        if !c.secrets.is_empty() {
+            buildenv.insert("BASH_ENV".to_string(), SECRETS_FILE.to_string());
+        }
 
         let workdir = if !c.skip_clone {
             format!("/work/{}/{}", repo.owner, repo.name)
@@ -843,6 +1200,8 @@ pub(crate) async fn run(
             name: "build".into(),
             env: buildenv,
             env_clear: false,
+            always_run: false,
+            timeout_seconds: None,
             gid: Some(12345),
             uid: Some(12345),
             workdir: Some(workdir),
@@ -880,10 +1239,16 @@ pub(crate) async fn run(
         let body = buildomat_client::types::JobSubmit::builder()
             .name(format!("gong/{}", cr.id))
             .output_rules(c.output_rules.clone())
-            .target(c.target.as_deref().unwrap_or("default"))
+            .target(c.target.clone())
             .tasks(tasks)
             .tags(tags)
-            .depends(depends);
+            .depends(depends)
+            /*
+             * If the response to our submission is lost, we will try again
+             * the next time we process this check run.  Use the check run ID
+             * as the idempotency key so that we do not create a second job.
+             */
+            .idempotency_key(Some(cr.id.to_string()));
         let jsr = match b.job_submit().body(body).send().await {
             Ok(rv) => rv.into_inner(),
             Err(buildomat_client::Error::ErrorResponse(rv))
@@ -1098,10 +1463,17 @@ pub(crate) async fn artefact(
 ) -> Result<Option<hyper::Response<hyper::Body>>> {
     let p: BasicPrivate = cr.get_private()?;
 
-    let bunyan = match format {
-        Some("x-bunyan") => true,
-        None => false,
-        Some(other) => bail!("invalid format {:?}", other),
+    /*
+     * The user may ask us to render a bunyan log as HTML, or to override the
+     * content type we would otherwise guess for the file; e.g., to display a
+     * log file inline, or to force the browser to download it.  Any format we
+     * do not recognise is ignored, leaving the guessed content type intact.
+     */
+    let (bunyan, force_ct) = match format {
+        Some("x-bunyan") => (true, None),
+        Some("text" | "utf8") => (false, Some("text/plain; charset=utf-8")),
+        Some("raw") => (false, Some("application/octet-stream")),
+        _ => (false, None),
     };
 
     if let Some(id) = &p.buildomat_id {
@@ -1123,7 +1495,7 @@ pub(crate) async fn artefact(
          * escape hatch of sorts for unhelpful file extensions: put whatever you
          * want in the URL!
          */
-        let ct = guess_mime_type(name);
+        let ct = guess_mime_type_with(name, &app.config.mime_types);
 
         if bunyan {
             if ct != "text/plain" {
@@ -1182,12 +1554,16 @@ pub(crate) async fn artefact(
             ));
         }
 
+        let mut res = hyper::Response::builder()
+            .status(hyper::StatusCode::OK)
+            .header(hyper::header::CONTENT_TYPE, force_ct.unwrap_or(&ct))
+            .header(hyper::header::CONTENT_LENGTH, cl);
+        if format == Some("raw") {
+            res = res.header(hyper::header::CONTENT_DISPOSITION, "attachment");
+        }
+
         return Ok(Some(
-            hyper::Response::builder()
-                .status(hyper::StatusCode::OK)
-                .header(hyper::header::CONTENT_TYPE, ct)
-                .header(hyper::header::CONTENT_LENGTH, cl)
-                .body(hyper::Body::wrap_stream(backend.into_inner_stream()))?,
+            res.body(hyper::Body::wrap_stream(backend.into_inner_stream()))?,
         ));
     }
 
@@ -1206,7 +1582,7 @@ pub(crate) async fn details(
 
     out += &format!(
         "<pre>{}</pre>\n",
-        format!("{:#?}", c).replace('<', "&lt;").replace('>', "&gt;")
+        html_escape::encode_safe(&format!("{:#?}", c)),
     );
 
     let p: BasicPrivate = cr.get_private()?;
@@ -1236,6 +1612,28 @@ pub(crate) async fn details(
             out += "</ul>\n";
         }
 
+        if !job.stream_stats.is_empty() {
+            /*
+             * Show how much output the job produced on each stream, so that
+             * a particularly noisy job is easy to spot.
+             */
+            out += "<h3>Streams:</h3>\n";
+            out += "<table>\n";
+            out += "<tr><th>Stream</th><th>Events</th><th>Size</th></tr>\n";
+            let mut keys = job.stream_stats.keys().collect::<Vec<_>>();
+            keys.sort_unstable();
+            for &n in keys.iter() {
+                let ss = job.stream_stats.get(n).unwrap();
+                out += &format!(
+                    "<tr><td><b>{}</b></td><td>{}</td><td>{}</td></tr>\n",
+                    html_escape::encode_safe(n),
+                    ss.events,
+                    format_size(ss.bytes),
+                );
+            }
+            out += "</table>\n";
+        }
+
         if !outputs.is_empty() {
             out += "<h3>Artefacts:</h3>\n";
             out += "<ul>\n";
@@ -1243,7 +1641,7 @@ pub(crate) async fn details(
                 let bo = BasicOutput::new(app, cs, cr, &o);
                 out += &format!(
                     "<li><a href=\"{}\">{}</a> ({})\n",
-                    bo.href,
+                    html_escape::encode_double_quoted_attribute(&bo.href),
                     html_escape::encode_safe(&bo.path),
                     bo.size,
                 );
@@ -1254,13 +1652,52 @@ pub(crate) async fn details(
                      */
                     out += &format!(
                         " <a href=\"{}?format=x-bunyan\">[rendered]</a>\n",
-                        bo.href
+                        html_escape::encode_double_quoted_attribute(&bo.href),
                     );
                 }
             }
             out += "</ul>\n";
         }
 
+        let store = bm.job_store_get_all().job(jid).send().await?.into_inner();
+        if !store.is_empty() {
+            out += "<h3>Store:</h3>\n";
+            out += "<table>\n";
+            out += "<tr><th>Name</th><th>Value</th><th>Source</th>\
+                <th>Updated</th></tr>\n";
+            let mut keys = store.keys().collect::<Vec<_>>();
+            keys.sort_unstable();
+            for &n in keys.iter() {
+                let v = store.get(n).unwrap();
+                /*
+                 * The server does not return the value of secret entries, but
+                 * make sure we never display one regardless.  Values named in
+                 * the "secrets" option are hidden even if they were not
+                 * stored as secrets:
+                 */
+                let value = if v.secret || c.secrets.iter().any(|s| s == n) {
+                    "<i>(secret)</i>".to_string()
+                } else if let Some(value) = v.value.as_deref() {
+                    format!(
+                        "<span style=\"white-space: pre; \
+                        font-family: monospace;\">{}</span>",
+                        html_escape::encode_safe(value),
+                    )
+                } else {
+                    "-".to_string()
+                };
+                out += &format!(
+                    "<tr><td><b>{}</b></td><td>{}</td><td>{}</td>\
+                    <td>{}</td></tr>\n",
+                    html_escape::encode_safe(n),
+                    value,
+                    html_escape::encode_safe(&v.source),
+                    v.time_update.to_rfc3339_opts(SecondsFormat::Secs, true),
+                );
+            }
+            out += "</table>\n";
+        }
+
         out += "<h3>Output:</h3>\n";
         out += "<table style=\"border: none;\">\n";
 
@@ -1291,7 +1728,18 @@ pub(crate) async fn details(
             \">DETAILS</td>\n";
         out += "</tr>\n";
 
-        for ev in bm.job_events_get().job(jid).send().await?.into_inner() {
+        /*
+         * Chatty jobs may print the same progress line many times in a row.
+         * Collapse each run of identical lines into a single row, which keeps
+         * the permalink for the first event in the run, and note how many
+         * times the line was repeated.
+         */
+        let events = bm.job_events_get().job(jid).send().await?.into_inner();
+        let runs = compact_runs(events, |a, b| {
+            a.task == b.task && a.stream == b.stream && a.payload == b.payload
+        });
+
+        for (ev, count) in runs {
             if ev.task != last {
                 let cols = if local_time { 4 } else { 3 };
                 out += &format!("<tr><td colspan=\"{cols}\">&nbsp;</td></tr>");
@@ -1329,15 +1777,28 @@ pub(crate) async fn details(
             );
 
             /*
-             * The second column is the event timestamp.
+             * The second column is the event timestamp.  If we are not
+             * otherwise showing the remote time, but it differs substantially
+             * from the server time, include it here as well.
              */
+            let mut t = ev.time.to_rfc3339_opts(SecondsFormat::Millis, true);
+            if let Some(tr) = ev.time_remote.filter(|tr| {
+                !local_time
+                    && (ev.time - *tr).num_seconds().abs() >= CLOCK_SKEW_SECONDS
+            }) {
+                t += &format!(
+                    "<br><span style=\"color: #cc0000;\" \
+                    title=\"worker clock differs from server clock\">\
+                    {}</span>",
+                    tr.to_rfc3339_opts(SecondsFormat::Millis, true),
+                );
+            }
             out += &format!(
                 "<td style=\"vertical-align: top;\">\
                     <span style=\"white-space: pre; \
                     font-family: monospace; \
-                    \">{}</span>\
+                    \">{t}</span>\
                 </td>",
-                ev.time.to_rfc3339_opts(SecondsFormat::Millis, true),
             );
 
             if local_time {
@@ -1367,12 +1828,21 @@ pub(crate) async fn details(
             /*
              * The final column is the message payload for the event.
              */
+            let repeats = if count > 1 {
+                format!(
+                    " <span style=\"font-family: monospace; \
+                    color: #666666; \
+                    \">(&times;{count})</span>",
+                )
+            } else {
+                String::new()
+            };
             out += &format!(
                 "<td style=\"vertical-align: top;\">\
                     <span style=\"white-space: pre-wrap; \
                     white-space: -moz-pre-wrap !important; \
                     font-family: monospace; \
-                    \">{}</span>\
+                    \">{}</span>{repeats}\
                 </td>",
                 html_escape::encode_safe(&ev.payload),
             );
@@ -1385,10 +1855,39 @@ pub(crate) async fn details(
     Ok(out)
 }
 
+/*
+ * Collapse each run of consecutive items that are the same, according to the
+ * provided function, into the first item of the run and the length of the run.
+ */
+fn compact_runs<T>(
+    items: impl IntoIterator<Item = T>,
+    same: impl Fn(&T, &T) -> bool,
+) -> Vec<(T, usize)> {
+    let mut out: Vec<(T, usize)> = Vec::new();
+
+    for item in items {
+        match out.last_mut() {
+            Some((first, count)) if same(first, &item) => *count += 1,
+            _ => out.push((item, 1)),
+        }
+    }
+
+    out
+}
+
+/*
+ * A backend job only needs to be cancelled if it has not yet finished.  Note
+ * that the server reports a successful job as "completed", not "complete".
+ */
+fn needs_cancel(state: &JobState) -> bool {
+    !state.is_terminal()
+}
+
 pub(crate) async fn cancel(
     app: &Arc<App>,
     cs: &CheckSuite,
     cr: &mut CheckRun,
+    reason: &str,
 ) -> Result<()> {
     let db = &app.db;
     let repo = db.load_repository(cs.repo)?;
@@ -1406,7 +1905,7 @@ pub(crate) async fn cancel(
         let b = app.buildomat(&repo);
         let j = b.job_get().job(jid).send().await?;
 
-        if j.state == "complete" || j.state == "failed" {
+        if !needs_cancel(&j.state_parsed()) {
             /*
              * This job is already finished.
              */
@@ -1414,7 +1913,11 @@ pub(crate) async fn cancel(
         }
 
         info!(log, "cancelling backend buildomat job {}", jid);
-        b.job_cancel().job(jid).send().await?;
+        b.job_cancel()
+            .job(jid)
+            .body(JobCancel { reason: Some(reason.to_string()) })
+            .send()
+            .await?;
     } else {
         /*
          * Otherwise, report the failure and halt check run processing.
@@ -1430,3 +1933,35 @@ pub(crate) async fn cancel(
     db.update_check_run(cr)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::{compact_runs, needs_cancel};
+    use buildomat_client::ext::JobState;
+
+    #[test]
+    fn compact_repeated_lines() {
+        let lines = ["a", "b", "b", "b", "a", "c", "c"];
+        let runs = compact_runs(lines, |a, b| a == b);
+        assert_eq!(runs, vec![("a", 1), ("b", 3), ("a", 1), ("c", 2)]);
+
+        let runs = compact_runs(Vec::<&str>::new(), |a, b| a == b);
+        assert!(runs.is_empty());
+    }
+
+    #[test]
+    fn cancel_finished_job() {
+        for state in ["completed", "failed"] {
+            let state: JobState = state.parse().unwrap();
+            assert!(!needs_cancel(&state), "{} should not be cancelled", state);
+        }
+    }
+
+    #[test]
+    fn cancel_unfinished_job() {
+        for state in ["queued", "waiting", "running"] {
+            let state: JobState = state.parse().unwrap();
+            assert!(needs_cancel(&state), "{} should be cancelled", state);
+        }
+    }
+}
@@ -55,7 +55,8 @@ pub(crate) async fn details(
     out += &format!("<h3>Check Runs:</h3>\n");
     out += &format!("<ul>\n");
     for (set, runs) in &runs {
-        out += &format!("<li>check run \"{set}\"\n");
+        out +=
+            &format!("<li>check run \"{}\"\n", html_escape::encode_safe(set));
 
         out += &format!("<ul>\n");
         for run in runs {
@@ -279,3 +279,136 @@ ALTER TABLE worker ADD COLUMN
 -- v 43
 ALTER TABLE job ADD COLUMN
     time_archived   TEXT;
+
+-- v 44
+ALTER TABLE job ADD COLUMN
+    time_create     TEXT;
+
+-- v 45
+UPDATE job SET time_create = (
+    SELECT time FROM job_time
+    WHERE job_time.job = job.id AND job_time.name = 'submit')
+WHERE time_create IS NULL;
+
+-- v 46
+CREATE INDEX jobs_for_owner ON job (owner, id);
+
+-- v 47
+CREATE INDEX jobs_by_state ON job (complete, waiting, id);
+
+-- v 48
+CREATE TABLE audit_log (
+    id              TEXT    PRIMARY KEY,
+    time            TEXT    NOT NULL,
+    actor           TEXT    NOT NULL,
+    privilege       TEXT,
+    action          TEXT    NOT NULL,
+    target          TEXT,
+    source          TEXT
+);
+
+-- v 49
+CREATE INDEX audit_log_by_time ON audit_log (time);
+
+-- v 50
+ALTER TABLE user ADD COLUMN
+    token_expiry    TEXT;
+
+-- v 51
+ALTER TABLE job ADD COLUMN
+    event_bytes     INTEGER NOT NULL    DEFAULT 0;
+
+-- v 52
+CREATE TABLE job_metric (
+    job             TEXT    NOT NULL,
+    name            TEXT    NOT NULL,
+    value           INTEGER NOT NULL,
+    time_update     TEXT    NOT NULL,
+
+    PRIMARY KEY (job, name)
+);
+
+-- v 53
+ALTER TABLE job ADD COLUMN
+    cancel_reason   TEXT;
+
+-- v 54
+ALTER TABLE published_file ADD COLUMN
+    time_publish    TEXT;
+
+-- v 55
+ALTER TABLE job_file ADD COLUMN
+    sha256          TEXT;
+
+-- v 56
+ALTER TABLE job_output ADD COLUMN
+    content_type    TEXT;
+
+-- v 57
+ALTER TABLE job_depend ADD COLUMN
+    copy_outputs_globs  TEXT;
+
+-- v 58
+CREATE TABLE job_idempotency (
+    owner           TEXT    NOT NULL,
+    key             TEXT    NOT NULL,
+    job             TEXT    NOT NULL,
+    time_create     TEXT    NOT NULL,
+
+    PRIMARY KEY (owner, key)
+);
+
+-- v 59
+ALTER TABLE task ADD COLUMN
+    failure_code    INTEGER;
+
+-- v 60
+ALTER TABLE task ADD COLUMN
+    failure_signal  INTEGER;
+
+-- v 61
+ALTER TABLE task ADD COLUMN
+    failure_message TEXT;
+
+-- v 62
+ALTER TABLE job ADD COLUMN
+    failure_reason  TEXT;
+
+-- v 63
+ALTER TABLE task ADD COLUMN
+    always_run      INTEGER NOT NULL    DEFAULT 0;
+
+-- v 64
+ALTER TABLE task ADD COLUMN
+    timeout_seconds INTEGER;
+
+-- v 65
+CREATE TABLE job_share (
+    token           TEXT    PRIMARY KEY,
+    job             TEXT    NOT NULL,
+    time_create     TEXT    NOT NULL,
+    time_expiry     TEXT
+);
+
+-- v 66
+CREATE TABLE job_stream_stat (
+    job             TEXT    NOT NULL,
+    stream          TEXT    NOT NULL,
+    events          INTEGER NOT NULL,
+    bytes           INTEGER NOT NULL,
+
+    PRIMARY KEY (job, stream)
+);
+
+-- v 67
+CREATE TABLE job_stream (
+    job             TEXT    PRIMARY KEY,
+    seq             INTEGER NOT NULL,
+    time_update     TEXT    NOT NULL
+);
+
+-- v 68
+CREATE INDEX jobs_by_owner_name ON job (owner, name);
+
+-- v 69
+CREATE INDEX job_idempotency_by_time ON job_idempotency (time_create);
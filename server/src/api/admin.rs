@@ -4,11 +4,15 @@
 
 use super::prelude::*;
 
+use crate::ArchiveVerifyOutcome;
+use std::time::Duration;
+
 #[derive(Serialize, JsonSchema)]
 pub struct User {
     id: String,
     name: String,
     time_create: DateTime<Utc>,
+    token_expiry: Option<DateTime<Utc>>,
     privileges: Vec<String>,
 }
 
@@ -106,7 +110,7 @@ pub(crate) async fn user_create(
     let c = rqctx.context();
     let log = &rqctx.log;
 
-    c.require_admin(log, &rqctx.request, "user.create").await?;
+    c.require_admin(log, &rqctx.request, "user.create", None).await?;
 
     let new_user = new_user.into_inner();
     let u = c.db.user_create(&new_user.name).or_500()?;
@@ -135,7 +139,7 @@ pub(crate) async fn users_list(
     let c = rqctx.context();
     let log = &rqctx.log;
 
-    c.require_admin(log, &rqctx.request, "user.read").await?;
+    c.require_admin(log, &rqctx.request, "user.read", None).await?;
 
     let q = query.into_inner();
 
@@ -154,6 +158,7 @@ pub(crate) async fn users_list(
                     id: u.user.id.to_string(),
                     name: u.user.name,
                     time_create: u.user.time_create.into(),
+                    token_expiry: u.user.token_expiry.map(|t| t.0),
                     privileges: u.privileges,
                 })
             })
@@ -173,13 +178,16 @@ pub(crate) async fn user_get(
     let c = rqctx.context();
     let log = &rqctx.log;
 
-    c.require_admin(log, &rqctx.request, "user.read").await?;
+    let p = path.into_inner();
+    c.require_admin(log, &rqctx.request, "user.read", Some(p.user.clone()))
+        .await?;
 
-    if let Some(u) = c.db.user_get_by_id(path.into_inner().user()?).or_500()? {
+    if let Some(u) = c.db.user_get_by_id(p.user()?).or_500()? {
         Ok(HttpResponseOk(User {
             id: u.user.id.to_string(),
             name: u.user.name,
             time_create: u.user.time_create.into(),
+            token_expiry: u.user.token_expiry.map(|t| t.0),
             privileges: u.privileges,
         }))
     } else {
@@ -198,9 +206,15 @@ pub(crate) async fn user_privilege_grant(
     let c = rqctx.context();
     let log = &rqctx.log;
 
-    c.require_admin(log, &rqctx.request, "privilege.grant").await?;
-
     let path = path.into_inner();
+    c.require_admin(
+        log,
+        &rqctx.request,
+        "privilege.grant",
+        Some(path.user.clone()),
+    )
+    .await?;
+
     let u = path.user()?;
 
     c.db.user_privilege_grant(u, &path.privilege).or_500()?;
@@ -221,9 +235,15 @@ pub(crate) async fn user_privilege_revoke(
     let c = rqctx.context();
     let log = &rqctx.log;
 
-    c.require_admin(log, &rqctx.request, "privilege.revoke").await?;
-
     let path = path.into_inner();
+    c.require_admin(
+        log,
+        &rqctx.request,
+        "privilege.revoke",
+        Some(path.user.clone()),
+    )
+    .await?;
+
     let u = path.user()?;
 
     c.db.user_privilege_revoke(u, &path.privilege).or_500()?;
@@ -233,12 +253,155 @@ pub(crate) async fn user_privilege_revoke(
     Ok(HttpResponseDeleted())
 }
 
+#[derive(Serialize, JsonSchema)]
+pub struct UserTokenRotateResult {
+    id: String,
+    name: String,
+    token: String,
+}
+
+#[endpoint {
+    method = POST,
+    path = "/0/admin/user/{user}/rotate-token",
+}]
+pub(crate) async fn user_token_rotate(
+    rqctx: RequestContext<Arc<Central>>,
+    path: TypedPath<UserPath>,
+) -> DSResult<HttpResponseOk<UserTokenRotateResult>> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+
+    let p = path.into_inner();
+    c.require_admin(log, &rqctx.request, "user.token", Some(p.user.clone()))
+        .await?;
+
+    let id = p.user()?;
+    if c.db.user_get_by_id(id).or_500()?.is_none() {
+        return Err(HttpError::for_not_found(None, "user not found".into()));
+    }
+
+    /*
+     * The new token is returned only once, in this response.  It cannot be
+     * retrieved again later.
+     */
+    let u = c.db.user_token_rotate(id).or_500()?;
+    info!(log, "user {:?} token rotated", u.id);
+
+    Ok(HttpResponseOk(UserTokenRotateResult {
+        id: u.id.to_string(),
+        name: u.name,
+        token: u.token,
+    }))
+}
+
 #[derive(Deserialize, JsonSchema)]
+pub struct UserTokenExpiry {
+    expiry: Option<DateTime<Utc>>,
+}
+
+#[endpoint {
+    method = PUT,
+    path = "/0/admin/user/{user}/token-expiry",
+}]
+pub(crate) async fn user_token_expiry_set(
+    rqctx: RequestContext<Arc<Central>>,
+    path: TypedPath<UserPath>,
+    body: TypedBody<UserTokenExpiry>,
+) -> DSResult<HttpResponseUpdatedNoContent> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+
+    let p = path.into_inner();
+    c.require_admin(log, &rqctx.request, "user.token", Some(p.user.clone()))
+        .await?;
+
+    let id = p.user()?;
+    if c.db.user_get_by_id(id).or_500()?.is_none() {
+        return Err(HttpError::for_not_found(None, "user not found".into()));
+    }
+
+    let expiry = body.into_inner().expiry;
+    c.db.user_token_expiry_set(id, expiry).or_500()?;
+    info!(log, "user {:?} token expiry set to {:?}", id, expiry);
+
+    Ok(HttpResponseUpdatedNoContent())
+}
+
+#[derive(JsonSchema)]
 pub struct AdminJobsGetQuery {
     #[serde(default)]
     active: bool,
     #[serde(default)]
     completed: Option<u64>,
+    /*
+     * A "NAME=VALUE" tag filter.  The parameter may be repeated, in which case
+     * only jobs with all of the nominated tag values are included.
+     */
+    #[schemars(rename = "tag", with = "Option<String>")]
+    tags: Vec<String>,
+}
+
+/*
+ * The derived deserialiser does not allow a parameter to be repeated, so we
+ * collect the query parameters by hand in order to accept several tag filters.
+ */
+impl<'de> Deserialize<'de> for AdminJobsGetQuery {
+    fn deserialize<D>(d: D) -> SResult<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct QueryVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for QueryVisitor {
+            type Value = AdminJobsGetQuery;
+
+            fn expecting(
+                &self,
+                f: &mut std::fmt::Formatter<'_>,
+            ) -> std::fmt::Result {
+                f.write_str("admin job list query parameters")
+            }
+
+            fn visit_map<A>(
+                self,
+                mut map: A,
+            ) -> SResult<AdminJobsGetQuery, A::Error>
+            where
+                A: serde::de::MapAccess<'de>,
+            {
+                use serde::de::Error;
+
+                let mut q = AdminJobsGetQuery {
+                    active: false,
+                    completed: None,
+                    tags: Vec::new(),
+                };
+
+                while let Some((k, v)) = map.next_entry::<String, String>()? {
+                    match k.as_str() {
+                        "active" => {
+                            q.active = v.parse().map_err(|e| {
+                                A::Error::custom(format!("invalid active: {e}"))
+                            })?;
+                        }
+                        "completed" => {
+                            q.completed = Some(v.parse().map_err(|e| {
+                                A::Error::custom(format!(
+                                    "invalid completed: {e}"
+                                ))
+                            })?);
+                        }
+                        "tag" => q.tags.push(v),
+                        _ => (),
+                    }
+                }
+
+                Ok(q)
+            }
+        }
+
+        d.deserialize_map(QueryVisitor)
+    }
 }
 
 #[endpoint {
@@ -252,34 +415,30 @@ pub(crate) async fn admin_jobs_get(
     let c = rqctx.context();
     let log = &rqctx.log;
 
-    c.require_admin(log, &rqctx.request, "job.read").await?;
+    c.require_admin(log, &rqctx.request, "job.read", None).await?;
 
     let q = query.into_inner();
+    let tags = super::user::parse_tag_filters(&q.tags)?;
     let jobs = if q.active {
         /*
          * We have been asked to list only active jobs:
          */
-        let mut jobs = c.db.jobs_active().or_500()?;
-        jobs.extend(c.db.jobs_waiting().or_500()?);
-        jobs
+        c.db.jobs_incomplete(&tags).or_500()?
     } else if let Some(n) = &q.completed {
         /*
          * We have been asked to provide some number of recently completed jobs:
          */
-        c.db.jobs_completed((*n).try_into().unwrap()).or_500()?
+        c.db.jobs_completed((*n).try_into().unwrap(), &tags).or_500()?
     } else {
         /*
          * By default we list all jobs in the database.
          */
-        c.db.jobs_all().or_500()?
+        c.db.jobs_all(&tags).or_500()?
     };
 
-    let mut out = Vec::new();
-    for job in jobs {
-        out.push(super::user::Job::load(log, &c, &job).await.or_500()?);
-    }
-
-    Ok(HttpResponseOk(out))
+    Ok(HttpResponseOk(
+        super::user::Job::load_list(log, c, &jobs).await.or_500()?,
+    ))
 }
 
 #[endpoint {
@@ -293,9 +452,11 @@ pub(crate) async fn admin_job_get(
     let c = rqctx.context();
     let log = &rqctx.log;
 
-    c.require_admin(log, &rqctx.request, "job.read").await?;
+    let p = path.into_inner();
+    c.require_admin(log, &rqctx.request, "job.read", Some(p.job.clone()))
+        .await?;
 
-    let id = path.into_inner().job.parse::<db::JobId>().or_500()?;
+    let id = p.job.parse::<db::JobId>().or_500()?;
     let job = c.db.job_by_id(id).or_500()?;
 
     Ok(HttpResponseOk(super::user::Job::load(log, &c, &job).await.or_500()?))
@@ -312,9 +473,11 @@ pub(crate) async fn admin_job_archive_request(
     let c = rqctx.context();
     let log = &rqctx.log;
 
-    c.require_admin(log, &rqctx.request, "job.archive").await?;
+    let p = path.into_inner();
+    c.require_admin(log, &rqctx.request, "job.archive", Some(p.job.clone()))
+        .await?;
 
-    let id = path.into_inner().job.parse::<db::JobId>().or_500()?;
+    let id = p.job.parse::<db::JobId>().or_500()?;
     let job = c.db.job_by_id(id).or_500()?;
 
     if !job.complete {
@@ -330,6 +493,235 @@ pub(crate) async fn admin_job_archive_request(
     Ok(HttpResponseUpdatedNoContent())
 }
 
+/*
+ * Verification fetches each archive in full, so check only a limited number
+ * of archives in each request, and pause between them to avoid placing undue
+ * load on the object store.
+ */
+const ARCHIVE_VERIFY_MAX: usize = 100;
+const ARCHIVE_VERIFY_PACE: Duration = Duration::from_millis(100);
+
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct ArchiveVerify {
+    /*
+     * Check archived jobs with an ID after this one.  Archived jobs are
+     * checked in ID order, so the "last" value from one response can be
+     * passed here to resume with the next request.
+     */
+    after: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct ArchiveVerifyProblem {
+    job: String,
+    problem: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct ArchiveVerifyResult {
+    checked: usize,
+    last: Option<String>,
+    problems: Vec<ArchiveVerifyProblem>,
+    /*
+     * Jobs with archives that could not be checked because they are held in
+     * archival storage and must first be restored.
+     */
+    not_retrievable: Vec<String>,
+}
+
+#[endpoint {
+    method = POST,
+    path = "/0/admin/archive/verify",
+}]
+pub(crate) async fn admin_archive_verify(
+    rqctx: RequestContext<Arc<Central>>,
+    body: TypedBody<ArchiveVerify>,
+) -> DSResult<HttpResponseOk<ArchiveVerifyResult>> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+
+    c.require_admin(log, &rqctx.request, "job.archive", None).await?;
+
+    let b = body.into_inner();
+    let after = b
+        .after
+        .as_deref()
+        .map(|a| {
+            a.parse::<db::JobId>().map_err(|_| {
+                HttpError::for_bad_request(
+                    None,
+                    format!("invalid job ID {:?}", a),
+                )
+            })
+        })
+        .transpose()?;
+    let limit = b.limit.unwrap_or(ARCHIVE_VERIFY_MAX).min(ARCHIVE_VERIFY_MAX);
+
+    let jobs = c.db.jobs_archived(after, limit).or_500()?;
+
+    let mut problems = Vec::new();
+    let mut not_retrievable = Vec::new();
+    for (i, job) in jobs.iter().enumerate() {
+        if i > 0 {
+            tokio::time::sleep(ARCHIVE_VERIFY_PACE).await;
+        }
+
+        match c.archive_verify(log, job.id).await.or_500()? {
+            ArchiveVerifyOutcome::Intact => (),
+            ArchiveVerifyOutcome::NotRetrievable => {
+                warn!(log, "archive of job {} is not retrievable", job.id);
+                not_retrievable.push(job.id.to_string());
+            }
+            ArchiveVerifyOutcome::Problem(problem) => {
+                error!(
+                    log,
+                    "archive of job {} is damaged: {}", job.id, problem
+                );
+                problems.push(ArchiveVerifyProblem {
+                    job: job.id.to_string(),
+                    problem,
+                });
+            }
+        }
+    }
+
+    info!(log, "admin: verified {} job archives", jobs.len();
+        "problems" => problems.len(),
+        "not_retrievable" => not_retrievable.len());
+
+    Ok(HttpResponseOk(ArchiveVerifyResult {
+        checked: jobs.len(),
+        last: jobs.last().map(|j| j.id.to_string()),
+        problems,
+        not_retrievable,
+    }))
+}
+
+#[endpoint {
+    method = POST,
+    path = "/0/admin/job/{job}/requeue",
+}]
+pub(crate) async fn admin_job_requeue(
+    rqctx: RequestContext<Arc<Central>>,
+    path: TypedPath<JobPath>,
+) -> DSResult<HttpResponseUpdatedNoContent> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+
+    let p = path.into_inner();
+    c.require_admin(log, &rqctx.request, "job.requeue", Some(p.job.clone()))
+        .await?;
+
+    let id = p.job.parse::<db::JobId>().or_500()?;
+
+    /*
+     * If a worker dies without the agent reporting the job as complete, the
+     * job will appear to be running until the worker is cleaned up.  Allow an
+     * operator to return the job to the queue, provided the worker has
+     * stopped pinging.
+     */
+    let stale = c.config.worker.ping_stale();
+    let wid =
+        c.db.job_requeue(
+            id,
+            stale,
+            &format!(
+                "job returned to the queue by an administrator, as its \
+                worker has not responded for at least {} seconds",
+                stale.as_secs(),
+            ),
+        )
+        .or_500()?;
+
+    info!(log, "admin: requeued job {} from worker {}", id, wid);
+
+    Ok(HttpResponseUpdatedNoContent())
+}
+
+#[endpoint {
+    method = POST,
+    path = "/0/admin/job/{job}/reimport",
+}]
+pub(crate) async fn admin_job_reimport(
+    rqctx: RequestContext<Arc<Central>>,
+    path: TypedPath<JobPath>,
+) -> DSResult<HttpResponseUpdatedNoContent> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+
+    let p = path.into_inner();
+    c.require_admin(log, &rqctx.request, "job.archive", Some(p.job.clone()))
+        .await?;
+
+    let id = p.job.parse::<db::JobId>().or_500()?;
+
+    if c.db.job_by_id_opt(id).or_500()?.is_some() {
+        return Err(HttpError::for_client_error(
+            None,
+            StatusCode::CONFLICT,
+            "job already exists in the database".into(),
+        ));
+    }
+
+    /*
+     * If the database has been restored from a backup, it may not contain
+     * records for jobs that were archived after the backup was taken.  Load
+     * the archive from the object store and use it to repopulate the job.
+     */
+    let aj = c.archive_load(log, id).await.or_500()?;
+    let now = Utc::now();
+    let times = aj.times().or_500()?;
+
+    /*
+     * The job is marked as archived, so requests for its details, including
+     * the store values, continue to be served from the archive.  The values of
+     * secret store entries are not archived and are not restored, but as the
+     * job is complete they would never be used anyway.  The archive does not
+     * record when each metric was updated, so use the completion time.
+     */
+    let time_metrics = times.get("complete").copied().unwrap_or(now);
+
+    /*
+     * Event sequence numbers in the database begin at 1, but the archive
+     * only preserves the order of the events:
+     */
+    let events = aj
+        .job_events(0, None, None)
+        .or_500()?
+        .into_iter()
+        .map(|mut ev| {
+            ev.seq += 1;
+            ev
+        })
+        .collect::<Vec<_>>();
+
+    let ij = db::ImportJob {
+        job: aj.job(now).or_500()?,
+        tasks: aj.tasks().or_500()?,
+        output_rules: aj.output_rules().or_500()?,
+        inputs: aj.job_inputs().or_500()?,
+        outputs: aj.job_outputs().or_500()?,
+        events,
+        tags: aj.tags().or_500()?,
+        times,
+        depends: aj.depends().or_500()?,
+        store: aj.job_store().or_500()?,
+        metrics: aj.job_metrics(time_metrics).or_500()?,
+    };
+
+    c.db.job_import(&ij).or_500()?;
+
+    info!(log, "admin: reimported job {} from archive", id;
+        "tasks" => ij.tasks.len(),
+        "inputs" => ij.inputs.len(),
+        "outputs" => ij.outputs.len(),
+        "events" => ij.events.len(),
+        "store" => ij.store.len());
+
+    Ok(HttpResponseUpdatedNoContent())
+}
+
 #[endpoint {
     method = POST,
     path = "/0/control/hold",
@@ -340,7 +732,7 @@ pub(crate) async fn control_hold(
     let c = rqctx.context();
     let log = &rqctx.log;
 
-    c.require_admin(log, &rqctx.request, "control").await?;
+    c.require_admin(log, &rqctx.request, "control", None).await?;
 
     info!(log, "ADMIN: HOLD NEW VM CREATION");
     c.inner.lock().unwrap().hold = true;
@@ -358,7 +750,7 @@ pub(crate) async fn control_resume(
     let c = rqctx.context();
     let log = &rqctx.log;
 
-    c.require_admin(log, &rqctx.request, "control").await?;
+    c.require_admin(log, &rqctx.request, "control", None).await?;
 
     info!(log, "ADMIN: RESUME NEW VM CREATION");
     c.inner.lock().unwrap().hold = false;
@@ -385,6 +777,9 @@ struct Worker {
     pub deleted: bool,
     pub recycle: bool,
     pub lastping: Option<DateTime<Utc>>,
+    pub stale: bool,
+    pub age_seconds: u64,
+    pub job_count: usize,
     pub jobs: Vec<WorkerJob>,
 }
 
@@ -397,6 +792,8 @@ pub(crate) struct WorkersResult {
 pub struct WorkersListQuery {
     #[serde(default)]
     active: bool,
+    factory: Option<String>,
+    target: Option<String>,
 }
 
 #[endpoint {
@@ -410,19 +807,29 @@ pub(crate) async fn workers_list(
     let c = rqctx.context();
     let log = &rqctx.log;
 
-    c.require_admin(log, &rqctx.request, "worker.read").await?;
+    c.require_admin(log, &rqctx.request, "worker.read", None).await?;
 
-    let w = if query.into_inner().active {
-        /*
-         * List only active (i.e., not deleted) workers:
-         */
-        c.db.workers_active().or_500()?
-    } else {
-        /*
-         * List all workers in the database.
-         */
-        c.db.workers().or_500()?
-    };
+    let q = query.into_inner();
+
+    let factory = q
+        .factory
+        .as_deref()
+        .map(str::parse::<db::FactoryId>)
+        .transpose()
+        .or_500()?;
+    let target = q
+        .target
+        .as_deref()
+        .map(str::parse::<db::TargetId>)
+        .transpose()
+        .or_500()?;
+
+    /*
+     * If "active" is specified, list only workers that have not yet been
+     * deleted.
+     */
+    let w = c.db.workers(q.active, factory, target).or_500()?;
+    let now = Utc::now();
 
     let workers = w
         .iter()
@@ -448,6 +855,9 @@ pub(crate) async fn workers_list(
                 deleted: w.deleted,
                 recycle: w.recycle,
                 lastping: w.lastping.map(|x| x.into()),
+                stale: w.is_stale(now, c.config.worker.ping_stale()),
+                age_seconds: w.id.age().as_secs(),
+                job_count: jobs.len(),
                 jobs,
             }
         })
@@ -466,7 +876,7 @@ pub(crate) async fn workers_recycle(
     let c = rqctx.context();
     let log = &rqctx.log;
 
-    c.require_admin(log, &rqctx.request, "control").await?;
+    c.require_admin(log, &rqctx.request, "control", None).await?;
 
     c.db.worker_recycle_all().or_500()?;
     info!(log, "ADMIN: recycled all workers");
@@ -485,9 +895,11 @@ pub(crate) async fn worker_recycle(
     let c = rqctx.context();
     let log = &rqctx.log;
 
-    c.require_admin(log, &rqctx.request, "control").await?;
+    let p = path.into_inner();
+    c.require_admin(log, &rqctx.request, "control", Some(p.worker.clone()))
+        .await?;
 
-    let wid = path.into_inner().worker()?;
+    let wid = p.worker()?;
 
     c.db.worker_recycle(wid).or_500()?;
     info!(log, "ADMIN: recycled worker {}", wid);
@@ -518,7 +930,7 @@ pub(crate) async fn factory_create(
     let c = rqctx.context();
     let log = &rqctx.log;
 
-    c.require_admin(log, &rqctx.request, "factory.create").await?;
+    c.require_admin(log, &rqctx.request, "factory.create", None).await?;
 
     let new_fac = new_fac.into_inner();
     let f = c.db.factory_create(&new_fac.name).or_500()?;
@@ -559,7 +971,7 @@ pub(crate) async fn target_create(
     let c = rqctx.context();
     let log = &rqctx.log;
 
-    c.require_admin(log, &rqctx.request, "target.create").await?;
+    c.require_admin(log, &rqctx.request, "target.create", None).await?;
 
     let new_targ = new_targ.into_inner();
     let t = c.db.target_create(&new_targ.name, &new_targ.desc).or_500()?;
@@ -577,7 +989,7 @@ pub(crate) async fn targets_list(
     let c = rqctx.context();
     let log = &rqctx.log;
 
-    c.require_admin(log, &rqctx.request, "target.read").await?;
+    c.require_admin(log, &rqctx.request, "target.read", None).await?;
 
     let out =
         c.db.targets()
@@ -606,9 +1018,14 @@ pub(crate) async fn target_require_privilege(
     let c = rqctx.context();
     let log = &rqctx.log;
 
-    c.require_admin(log, &rqctx.request, "target.write").await?;
-
     let path = path.into_inner();
+    c.require_admin(
+        log,
+        &rqctx.request,
+        "target.write",
+        Some(path.target.clone()),
+    )
+    .await?;
     let t = c.db.target_get(path.target()?).or_500()?;
 
     c.db.target_require(t.id, Some(&path.privilege)).or_500()?;
@@ -627,9 +1044,14 @@ pub(crate) async fn target_require_no_privilege(
     let c = rqctx.context();
     let log = &rqctx.log;
 
-    c.require_admin(log, &rqctx.request, "target.write").await?;
-
     let path = path.into_inner();
+    c.require_admin(
+        log,
+        &rqctx.request,
+        "target.write",
+        Some(path.target.clone()),
+    )
+    .await?;
     let t = c.db.target_get(path.target()?).or_500()?;
 
     c.db.target_require(t.id, None).or_500()?;
@@ -663,9 +1085,14 @@ pub(crate) async fn target_redirect(
     let c = rqctx.context();
     let log = &rqctx.log;
 
-    c.require_admin(log, &rqctx.request, "target.write").await?;
-
     let path = path.into_inner();
+    c.require_admin(
+        log,
+        &rqctx.request,
+        "target.write",
+        Some(path.target.clone()),
+    )
+    .await?;
     let t = c.db.target_get(path.target()?).or_500()?;
 
     /*
@@ -701,9 +1128,14 @@ pub(crate) async fn target_rename(
     let c = rqctx.context();
     let log = &rqctx.log;
 
-    c.require_admin(log, &rqctx.request, "target.write").await?;
-
     let path = path.into_inner();
+    c.require_admin(
+        log,
+        &rqctx.request,
+        "target.write",
+        Some(path.target.clone()),
+    )
+    .await?;
     let t = c.db.target_get(path.target()?).or_500()?;
     let body = body.into_inner();
 
@@ -713,3 +1145,56 @@ pub(crate) async fn target_rename(
 
     Ok(HttpResponseCreated(TargetCreateResult::new(t.id)))
 }
+
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct AuditLogQuery {
+    after: Option<DateTime<Utc>>,
+    before: Option<DateTime<Utc>>,
+    actor: Option<String>,
+    limit: Option<u64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct AuditLogEntry {
+    id: String,
+    time: DateTime<Utc>,
+    actor: String,
+    privilege: Option<String>,
+    action: String,
+    target: Option<String>,
+    source: Option<String>,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/0/admin/audit",
+}]
+pub(crate) async fn admin_audit_get(
+    rqctx: RequestContext<Arc<Central>>,
+    query: TypedQuery<AuditLogQuery>,
+) -> DSResult<HttpResponseOk<Vec<AuditLogEntry>>> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+
+    c.require_admin(log, &rqctx.request, "audit.read", None).await?;
+
+    let q = query.into_inner();
+    let limit = q.limit.unwrap_or(100).min(1000).try_into().unwrap();
+
+    let entries =
+        c.db.audit_log(q.after, q.before, q.actor.as_deref(), limit)
+            .or_500()?
+            .into_iter()
+            .map(|e| AuditLogEntry {
+                id: e.id.to_string(),
+                time: e.time.0,
+                actor: e.actor,
+                privilege: e.privilege,
+                action: e.action,
+                target: e.target,
+                source: e.source,
+            })
+            .collect();
+
+    Ok(HttpResponseOk(entries))
+}
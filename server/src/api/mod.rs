@@ -3,15 +3,20 @@
  */
 
 mod prelude {
-    pub(crate) use crate::{db, unauth_response, Central, MakeInternalError};
+    pub(crate) use super::ErrorCode;
+    pub(crate) use crate::{
+        db, limits, unauth_response, Central, MakeInternalError,
+    };
     pub use anyhow::{anyhow, Result};
     pub use buildomat_types::metadata;
     pub use chrono::prelude::*;
     pub use dropshot::{
         endpoint, HttpError, HttpResponseCreated, HttpResponseDeleted,
         HttpResponseOk, HttpResponseUpdatedNoContent, Path as TypedPath,
-        Query as TypedQuery, RequestContext, TypedBody, UntypedBody,
+        Query as TypedQuery, RequestContext, StreamingBody, TypedBody,
+        UntypedBody,
     };
+    pub use futures::StreamExt;
     pub use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
     pub use hyper::StatusCode;
     pub use hyper::{Body, Response};
@@ -31,6 +36,77 @@ mod prelude {
     pub type DSResult<T> = std::result::Result<T, HttpError>;
 }
 
+/**
+ * Machine-readable error codes, reported to clients in the "error_code" field
+ * of an error response.  Clients may match on these values, so once a code has
+ * been published it must not be renamed.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ErrorCode {
+    Conflict,
+    Invalid,
+    TooManyRequests,
+    TooManyTasks,
+    TooManyInputs,
+    TooManyTags,
+    TooManyActiveJobs,
+    TagsTooLarge,
+    InvalidTagName,
+    InvalidTagValue,
+    InvalidOutputRule,
+    InvalidDepend,
+    InvalidName,
+    InvalidBatch,
+    InputTooLarge,
+    ChunkTooLarge,
+    UrlExpiryTooLong,
+    JobNotWaiting,
+    JobComplete,
+    TargetForbidden,
+    TargetUnknown,
+    NotRetrievable,
+    JobNameExists,
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ErrorCode::*;
+
+        let s = match self {
+            /*
+             * These two codes predate the others and are retained in their
+             * original form for existing clients.
+             */
+            Conflict => "conflict",
+            Invalid => "invalid",
+
+            TooManyRequests => "TooManyRequests",
+            TooManyTasks => "TooManyTasks",
+            TooManyInputs => "TooManyInputs",
+            TooManyTags => "TooManyTags",
+            TooManyActiveJobs => "TooManyActiveJobs",
+            TagsTooLarge => "TagsTooLarge",
+            InvalidTagName => "InvalidTagName",
+            InvalidTagValue => "InvalidTagValue",
+            InvalidOutputRule => "InvalidOutputRule",
+            InvalidDepend => "InvalidDepend",
+            InvalidName => "InvalidName",
+            InvalidBatch => "InvalidBatch",
+            InputTooLarge => "InputTooLarge",
+            ChunkTooLarge => "ChunkTooLarge",
+            UrlExpiryTooLong => "UrlExpiryTooLong",
+            JobNotWaiting => "JobNotWaiting",
+            JobComplete => "JobComplete",
+            TargetForbidden => "TargetForbidden",
+            TargetUnknown => "TargetUnknown",
+            NotRetrievable => "NotRetrievable",
+            JobNameExists => "JobNameExists",
+        };
+
+        f.write_str(s)
+    }
+}
+
 pub mod admin;
 pub mod factory;
 pub mod public;
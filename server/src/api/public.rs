@@ -4,27 +4,89 @@
 
 use super::prelude::*;
 
+/**
+ * This version name is reserved, and refers to the most recently published
+ * version of a file in a series.
+ */
+pub(crate) const VERSION_LATEST: &str = "latest";
+
 #[derive(Deserialize, JsonSchema)]
-pub(crate) struct PublicFilePath {
+pub(crate) struct PublicFileSeriesPath {
     username: String,
     series: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct PublicFileVersion {
     version: String,
-    name: String,
+    time_publish: DateTime<Utc>,
+    names: Vec<String>,
 }
 
 #[endpoint {
     method = GET,
-    path = "/0/public/file/{username}/{series}/{version}/{name}",
+    path = "/0/public/file/{username}/{series}",
 }]
-pub(crate) async fn public_file_download(
+pub(crate) async fn public_file_versions(
     rqctx: RequestContext<Arc<Central>>,
-    path: TypedPath<PublicFilePath>,
-) -> DSResult<Response<Body>> {
+    path: TypedPath<PublicFileSeriesPath>,
+) -> DSResult<HttpResponseOk<Vec<PublicFileVersion>>> {
     let c = rqctx.context();
-    let log = &rqctx.log;
 
     let p = path.into_inner();
 
+    let u = if let Some(au) = c.db.user_get_by_name(&p.username).or_500()? {
+        au.id
+    } else {
+        return Err(HttpError::for_client_error(
+            None,
+            StatusCode::NOT_FOUND,
+            "published file series not found".into(),
+        ));
+    };
+
+    /*
+     * The files are listed newest first.  A version is as new as the most
+     * recent file published within it, so the order of the versions follows
+     * from the first appearance of each in the list.
+     */
+    let mut out: Vec<PublicFileVersion> = Vec::new();
+    for pf in c.db.published_files_for_series(u, &p.series).or_500()? {
+        if let Some(v) = out.iter_mut().find(|v| v.version == pf.version) {
+            v.names.push(pf.name);
+            continue;
+        }
+
+        out.push(PublicFileVersion {
+            time_publish: pf.time_publish(),
+            version: pf.version,
+            names: vec![pf.name],
+        });
+    }
+
+    for v in out.iter_mut() {
+        v.names.sort();
+    }
+
+    Ok(HttpResponseOk(out))
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct PublicFilePath {
+    username: String,
+    series: String,
+    version: String,
+    name: String,
+}
+
+/**
+ * Locate the published file named in the request path, resolving the "latest"
+ * version if needed.
+ */
+fn public_file_lookup(
+    c: &Central,
+    p: &PublicFilePath,
+) -> DSResult<db::PublishedFile> {
     /*
      * Load the user from the database.
      */
@@ -38,23 +100,72 @@ pub(crate) async fn public_file_download(
         ));
     };
 
-    let pf = if let Some(pf) =
+    let pf = if p.version == VERSION_LATEST {
+        c.db.published_file_latest(u, &p.series, &p.name).or_500()?
+    } else {
         c.db.published_file_by_name(u, &p.series, &p.version, &p.name)
             .or_500()?
-    {
-        pf
-    } else {
-        return Err(HttpError::for_client_error(
+    };
+
+    pf.ok_or_else(|| {
+        HttpError::for_client_error(
             None,
             StatusCode::NOT_FOUND,
             "published file not found".into(),
-        ));
-    };
+        )
+    })
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct PublicFileInfo {
+    version: String,
+    file: String,
+    time_publish: DateTime<Utc>,
+}
+
+/**
+ * Report which file a published file name refers to, without fetching the
+ * file itself.  The file ID matches the entity tag returned when the file is
+ * downloaded, which allows a caching proxy to answer conditional requests
+ * without starting a download.
+ */
+#[endpoint {
+    method = GET,
+    path = "/0/public/info/{username}/{series}/{version}/{name}",
+}]
+pub(crate) async fn public_file_info(
+    rqctx: RequestContext<Arc<Central>>,
+    path: TypedPath<PublicFilePath>,
+) -> DSResult<HttpResponseOk<PublicFileInfo>> {
+    let c = rqctx.context();
+
+    let pf = public_file_lookup(c, &path.into_inner())?;
+
+    Ok(HttpResponseOk(PublicFileInfo {
+        time_publish: pf.time_publish(),
+        version: pf.version,
+        file: pf.file.to_string(),
+    }))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/0/public/file/{username}/{series}/{version}/{name}",
+}]
+pub(crate) async fn public_file_download(
+    rqctx: RequestContext<Arc<Central>>,
+    path: TypedPath<PublicFilePath>,
+) -> DSResult<Response<Body>> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+
+    let pf = public_file_lookup(c, &path.into_inner())?;
+    let u = pf.owner;
 
     let mut res = Response::builder();
     res = res.header(CONTENT_TYPE, "application/octet-stream");
 
-    let fr = c.file_response(pf.job, pf.file).await.or_500()?;
+    let fr = c.file_response(log, pf.job, pf.file).await.or_500()?;
     info!(
         log,
         "published file: user {} series {} version {} name {} is in the {}",
@@ -65,6 +176,13 @@ pub(crate) async fn public_file_download(
         fr.info,
     );
 
-    res = res.header(CONTENT_LENGTH, fr.size);
+    /*
+     * Published files never change, so the ID of the underlying file is a
+     * suitable entity tag.  Note that the "latest" version of a file may
+     * resolve to a different file over time, but will then have a different
+     * tag.
+     */
+    res = fr.headers(res);
+    res = res.header(hyper::header::ETAG, format!("\"{}\"", pf.file));
     Ok(res.body(fr.body)?)
 }
@@ -4,7 +4,26 @@
 
 use super::prelude::*;
 
-use super::worker::UploadedChunk;
+use super::worker::{FailureSummary, UploadedChunk};
+
+/*
+ * When rendering a job log, load this many events at a time:
+ */
+const JOB_LOG_PAGE_SIZE: usize = 1000;
+
+/*
+ * An event search returns at most this many matching events.  Clients may
+ * request the next page of matches using the sequence number of the last
+ * match they received.
+ */
+const MAX_SEARCH_MATCHES: usize = 1000;
+const MAX_SEARCH_PATTERN_BYTES: usize = 1024;
+/*
+ * The regex crate searches in time linear in the size of the input, but a
+ * pattern may still compile to a large program; e.g., through large repeat
+ * counts.  Refuse patterns that would need more than this many bytes.
+ */
+const MAX_SEARCH_REGEX_SIZE: usize = 1024 * 1024;
 
 #[derive(Serialize, JsonSchema)]
 pub(crate) struct JobEvent {
@@ -21,6 +40,15 @@ pub(crate) struct JobOutput {
     id: String,
     size: u64,
     path: String,
+    content_type: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct JobInput {
+    name: String,
+    id: Option<String>,
+    size: Option<u64>,
+    other_job: Option<String>,
 }
 
 #[derive(Deserialize, JsonSchema)]
@@ -34,6 +62,22 @@ impl JobPath {
     }
 }
 
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct JobInputCommitPath {
+    job: String,
+    commit_id: String,
+}
+
+impl JobInputCommitPath {
+    fn job(&self) -> DSResult<db::JobId> {
+        self.job.parse::<db::JobId>().or_500()
+    }
+
+    fn commit_id(&self) -> DSResult<Ulid> {
+        Ulid::from_str(self.commit_id.as_str()).or_500()
+    }
+}
+
 #[derive(Deserialize, JsonSchema)]
 pub(crate) struct JobStorePath {
     job: String,
@@ -46,6 +90,22 @@ impl JobStorePath {
     }
 }
 
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct JobsInputsPath {
+    job: String,
+    input: String,
+}
+
+impl JobsInputsPath {
+    fn job(&self) -> DSResult<db::JobId> {
+        self.job.parse::<db::JobId>().or_500()
+    }
+
+    fn input(&self) -> DSResult<db::JobFileId> {
+        self.input.parse::<db::JobFileId>().or_500()
+    }
+}
+
 #[derive(Deserialize, JsonSchema)]
 pub(crate) struct JobsOutputsPath {
     job: String,
@@ -65,6 +125,31 @@ impl JobsOutputsPath {
 #[derive(Deserialize, JsonSchema)]
 pub(crate) struct JobsEventsQuery {
     minseq: Option<usize>,
+    task: Option<u32>,
+    /*
+     * Either "server" (the default) to order events as they were received by
+     * the server, or "remote" to order them by the clock of the worker that
+     * produced them, where the worker provided a time.
+     */
+    clock: Option<String>,
+    /*
+     * A job share token.  The "X-Buildomat-Share" header should be used where
+     * possible, as the query string is recorded in request logs.
+     */
+    share: Option<String>,
+}
+
+/*
+ * The query parameters accepted by requests that may be authenticated with a
+ * job share token instead of a user token.
+ */
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct JobShareQuery {
+    /*
+     * A job share token.  The "X-Buildomat-Share" header should be used where
+     * possible, as the query string is recorded in request logs.
+     */
+    share: Option<String>,
 }
 
 #[endpoint {
@@ -82,24 +167,262 @@ pub(crate) async fn job_events_get(
     let p = path.into_inner();
     let q = query.into_inner();
 
+    let remote = match q.clock.as_deref() {
+        None | Some("server") => false,
+        Some("remote") => true,
+        Some(other) => {
+            return Err(HttpError::for_client_error(
+                Some(ErrorCode::Invalid.to_string()),
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "clock must be \"server\" or \"remote\", not {other:?}"
+                ),
+            ));
+        }
+    };
+
+    let j = c
+        .require_share_or_user(
+            log,
+            &rqctx.request,
+            q.share.as_deref(),
+            p.job()?,
+        )
+        .await?;
+
+    let jevs = c
+        .load_job_events(log, &j, q.minseq.unwrap_or(0), q.task, None)
+        .await
+        .or_500()?;
+
+    let mut out = jevs.iter().map(format_job_event).collect::<Vec<_>>();
+
+    if remote {
+        /*
+         * Events without a remote time (e.g., those generated by the server
+         * itself) are ordered by the server time instead.  The sort is stable,
+         * so events with the same time remain in sequence order.  Note that
+         * the ordering only applies within the events returned by this
+         * request; clients paging with "minseq" should still use the highest
+         * sequence number they have seen.
+         */
+        out.sort_by_key(|ev| ev.time_remote.unwrap_or(ev.time));
+    }
+
+    Ok(HttpResponseOk(out))
+}
+
+fn format_job_event(jev: &db::JobEvent) -> JobEvent {
+    JobEvent {
+        seq: jev.seq as usize,
+        task: jev.task.map(|n| n as u32),
+        stream: jev.stream.to_string(),
+        time: jev.time.into(),
+        time_remote: jev.time_remote.map(|t| t.into()),
+        payload: jev.payload.to_string(),
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct JobEventsSearchQuery {
+    q: String,
+    /*
+     * If true, "q" is a regular expression rather than a plain substring.
+     */
+    #[serde(default)]
+    regex: bool,
+    minseq: Option<usize>,
+    task: Option<u32>,
+    limit: Option<usize>,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/0/jobs/{job}/events/search",
+}]
+pub(crate) async fn job_events_search(
+    rqctx: RequestContext<Arc<Central>>,
+    path: TypedPath<JobPath>,
+    query: TypedQuery<JobEventsSearchQuery>,
+) -> DSResult<HttpResponseOk<Vec<JobEvent>>> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+
+    let p = path.into_inner();
+    let q = query.into_inner();
+
+    let invalid = |msg: String| {
+        HttpError::for_client_error(
+            Some(ErrorCode::Invalid.to_string()),
+            StatusCode::BAD_REQUEST,
+            msg,
+        )
+    };
+
+    if q.q.is_empty() || q.q.len() > MAX_SEARCH_PATTERN_BYTES {
+        return Err(invalid(format!(
+            "search pattern must be between 1 and {} bytes",
+            MAX_SEARCH_PATTERN_BYTES,
+        )));
+    }
+    let re = if q.regex {
+        Some(
+            regex::RegexBuilder::new(&q.q)
+                .size_limit(MAX_SEARCH_REGEX_SIZE)
+                .build()
+                .map_err(|e| invalid(format!("invalid regex: {e}")))?,
+        )
+    } else {
+        None
+    };
+    let limit = q.limit.unwrap_or(MAX_SEARCH_MATCHES).min(MAX_SEARCH_MATCHES);
+
     let owner = c.require_user(log, &rqctx.request).await?;
-    let j = c.load_job_for_user(log, &owner, p.job()?).await?;
+    let j = c.load_job_for_user(log, &rqctx.request, &owner, p.job()?).await?;
 
-    let jevs =
-        c.load_job_events(log, &j, q.minseq.unwrap_or(0)).await.or_500()?;
+    /*
+     * Scan the events a page at a time, so that we need not copy the entire
+     * log for a large job at once.  An archived job is loaded only once.
+     */
+    let pages = c.job_event_pages(log, &j).await.or_500()?;
+    let mut out = Vec::new();
+    let mut minseq = q.minseq.unwrap_or(0);
+    while out.len() < limit {
+        let evs =
+            pages.page(&c.db, minseq, q.task, JOB_LOG_PAGE_SIZE).or_500()?;
 
-    Ok(HttpResponseOk(
-        jevs.iter()
-            .map(|jev| JobEvent {
-                seq: jev.seq as usize,
-                task: jev.task.map(|n| n as u32),
-                stream: jev.stream.to_string(),
-                time: jev.time.into(),
-                time_remote: jev.time_remote.map(|t| t.into()),
-                payload: jev.payload.to_string(),
+        let Some(last) = evs.last() else {
+            break;
+        };
+        minseq = last.seq as usize + 1;
+
+        out.extend(
+            evs.iter()
+                .filter(|ev| {
+                    if let Some(re) = &re {
+                        re.is_match(&ev.payload)
+                    } else {
+                        ev.payload.contains(&q.q)
+                    }
+                })
+                .map(format_job_event),
+        );
+    }
+    out.truncate(limit);
+
+    Ok(HttpResponseOk(out))
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct JobLogQuery {
+    #[serde(default)]
+    prefix: bool,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/0/jobs/{job}/log",
+}]
+pub(crate) async fn job_log_get(
+    rqctx: RequestContext<Arc<Central>>,
+    path: TypedPath<JobPath>,
+    query: TypedQuery<JobLogQuery>,
+) -> DSResult<Response<Body>> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+
+    let p = path.into_inner();
+    let q = query.into_inner();
+
+    let owner = c.require_user(log, &rqctx.request).await?;
+    let j = c.load_job_for_user(log, &rqctx.request, &owner, p.job()?).await?;
+
+    /*
+     * Render the job events as plain text, one line per event.  The log for a
+     * large job may be quite large, so rather than assemble the entire log in
+     * memory we send the events a page at a time.
+     */
+    let pages = c.job_event_pages(log, &j).await.or_500()?;
+    let (mut tx, body) = Body::channel();
+    let c = Arc::clone(c);
+    let log = log.clone();
+    tokio::spawn(async move {
+        let mut minseq = 0;
+
+        loop {
+            let evs = match pages.page(&c.db, minseq, None, JOB_LOG_PAGE_SIZE) {
+                Ok(evs) => evs,
+                Err(e) => {
+                    error!(log, "job {} log events: {:?}", j.id, e);
+                    tx.abort();
+                    return;
+                }
+            };
+
+            let Some(last) = evs.last() else {
+                return;
+            };
+            minseq = last.seq as usize + 1;
+
+            let mut out = String::new();
+            for ev in evs.iter() {
+                if q.prefix {
+                    out += &ev.stream;
+                    if let Some(task) = ev.task {
+                        out += &format!("[{task}]");
+                    }
+                    out += ": ";
+                }
+                out += &ev.payload;
+                out += "\n";
+            }
+
+            if tx.send_data(out.into()).await.is_err() {
+                /*
+                 * The client has gone away.
+                 */
+                return;
+            }
+        }
+    });
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(body)?)
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct JobOutputsQuery {
+    /*
+     * If specified, return only outputs with an ID that sorts after this one.
+     * Outputs are returned in ID order, so the ID of the last output in one
+     * page can be used to request the next.
+     */
+    after: Option<String>,
+    limit: Option<usize>,
+    /*
+     * A job share token.  The "X-Buildomat-Share" header should be used where
+     * possible, as the query string is recorded in request logs.
+     */
+    share: Option<String>,
+}
+
+impl JobOutputsQuery {
+    fn after(&self) -> DSResult<Option<db::JobFileId>> {
+        self.after
+            .as_deref()
+            .map(|a| {
+                a.parse::<db::JobFileId>().map_err(|_| {
+                    HttpError::for_client_error(
+                        Some(ErrorCode::Invalid.to_string()),
+                        StatusCode::BAD_REQUEST,
+                        format!("invalid output ID {a:?}"),
+                    )
+                })
             })
-            .collect(),
-    ))
+            .transpose()
+    }
 }
 
 #[endpoint {
@@ -109,16 +432,25 @@ pub(crate) async fn job_events_get(
 pub(crate) async fn job_outputs_get(
     rqctx: RequestContext<Arc<Central>>,
     path: TypedPath<JobPath>,
+    query: TypedQuery<JobOutputsQuery>,
 ) -> DSResult<HttpResponseOk<Vec<JobOutput>>> {
     let c = rqctx.context();
     let log = &rqctx.log;
 
     let p = path.into_inner();
+    let q = query.into_inner();
+    let after = q.after()?;
+
+    let j = c
+        .require_share_or_user(
+            log,
+            &rqctx.request,
+            q.share.as_deref(),
+            p.job()?,
+        )
+        .await?;
 
-    let owner = c.require_user(log, &rqctx.request).await?;
-    let j = c.load_job_for_user(log, &owner, p.job()?).await?;
-
-    let jops = c.load_job_outputs(log, &j).await.or_500()?;
+    let jops = c.load_job_outputs(log, &j, after, q.limit).await.or_500()?;
 
     Ok(HttpResponseOk(
         jops.iter()
@@ -126,11 +458,97 @@ pub(crate) async fn job_outputs_get(
                 id: jop.id.to_string(),
                 size: jf.size.0,
                 path: jop.path.to_string(),
+                content_type: jop.content_type.clone(),
+            })
+            .collect(),
+    ))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/0/jobs/{job}/inputs",
+}]
+pub(crate) async fn job_inputs_get(
+    rqctx: RequestContext<Arc<Central>>,
+    path: TypedPath<JobPath>,
+) -> DSResult<HttpResponseOk<Vec<JobInput>>> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+
+    let p = path.into_inner();
+
+    let owner = c.require_user(log, &rqctx.request).await?;
+    let j = c.load_job_for_user(log, &rqctx.request, &owner, p.job()?).await?;
+
+    let jins = c.load_job_inputs(log, &j).await.or_500()?;
+
+    /*
+     * An input that has been declared but not yet uploaded will have neither
+     * an ID nor a size.
+     */
+    Ok(HttpResponseOk(
+        jins.iter()
+            .map(|(ji, jf)| JobInput {
+                name: ji.name.to_string(),
+                id: ji.id.map(|id| id.to_string()),
+                size: jf.as_ref().map(|jf| jf.size.0),
+                other_job: ji.other_job.map(|id| id.to_string()),
             })
             .collect(),
     ))
 }
 
+#[endpoint {
+    method = GET,
+    path = "/0/jobs/{job}/inputs/{input}",
+}]
+pub(crate) async fn job_input_download(
+    rqctx: RequestContext<Arc<Central>>,
+    path: TypedPath<JobsInputsPath>,
+) -> DSResult<Response<Body>> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+
+    let p = path.into_inner();
+    let input = p.input()?;
+
+    let owner = c.require_user(log, &rqctx.request).await?;
+    let t = c.load_job_for_user(log, &rqctx.request, &owner, p.job()?).await?;
+
+    let Some((i, _)) = c
+        .load_job_inputs(log, &t)
+        .await
+        .or_500()?
+        .into_iter()
+        .find(|(i, _)| i.id == Some(input))
+    else {
+        return Err(HttpError::for_client_error(
+            None,
+            StatusCode::NOT_FOUND,
+            format!("job {} has no uploaded input {}", t.id, input),
+        ));
+    };
+
+    let mut res = Response::builder();
+    res = res.header(CONTENT_TYPE, "application/octet-stream");
+
+    /*
+     * An input that was copied from the outputs of another job is stored with
+     * that other job.
+     */
+    let fr = c
+        .file_response(log, i.other_job.unwrap_or(i.job), input)
+        .await
+        .or_500()?;
+    info!(
+        log,
+        "job {} input {} name {:?} is in the {}", t.id, input, i.name, fr.info
+    );
+
+    res = fr.headers(res);
+    Ok(res.body(fr.body)?)
+}
+
 #[endpoint {
     method = GET,
     path = "/0/jobs/{job}/outputs/{output}",
@@ -138,27 +556,38 @@ pub(crate) async fn job_outputs_get(
 pub(crate) async fn job_output_download(
     rqctx: RequestContext<Arc<Central>>,
     path: TypedPath<JobsOutputsPath>,
+    query: TypedQuery<JobShareQuery>,
 ) -> DSResult<Response<Body>> {
     let c = rqctx.context();
     let log = &rqctx.log;
 
     let p = path.into_inner();
+    let q = query.into_inner();
 
-    let owner = c.require_user(log, &rqctx.request).await?;
-    let t = c.load_job_for_user(log, &owner, p.job()?).await?;
+    let t = c
+        .require_share_or_user(
+            log,
+            &rqctx.request,
+            q.share.as_deref(),
+            p.job()?,
+        )
+        .await?;
 
     let o = c.load_job_output(log, &t, p.output()?).await.or_500()?;
 
     let mut res = Response::builder();
-    res = res.header(CONTENT_TYPE, "application/octet-stream");
+    res = res.header(
+        CONTENT_TYPE,
+        o.content_type.as_deref().unwrap_or("application/octet-stream"),
+    );
 
-    let fr = c.file_response(t.id, o.id).await.or_500()?;
+    let fr = c.file_response(log, t.id, o.id).await.or_500()?;
     info!(
         log,
         "job {} output {} path {:?} is in the {}", t.id, o.id, o.path, fr.info
     );
 
-    res = res.header(CONTENT_LENGTH, fr.size);
+    res = fr.headers(res);
     Ok(res.body(fr.body)?)
 }
 
@@ -189,24 +618,27 @@ pub(crate) async fn job_output_signed_url(
     let p = path.into_inner();
     let b = body.into_inner();
 
-    if b.expiry_seconds > 3600 {
+    let max = c.config.job.max_signed_url_seconds;
+    if b.expiry_seconds > max {
         return Err(HttpError::for_client_error(
-            None,
+            Some(ErrorCode::UrlExpiryTooLong.to_string()),
             StatusCode::BAD_REQUEST,
-            "URLs can last at most one hour (3600 seconds)".into(),
+            format!("URLs can last at most {max} seconds"),
         ));
     }
 
     let owner = c.require_user(log, &rqctx.request).await?;
-    let t = c.load_job_for_user(log, &owner, p.job()?).await?;
+    let t = c.load_job_for_user(log, &rqctx.request, &owner, p.job()?).await?;
 
     let o = c.load_job_output(log, &t, p.output()?).await.or_500()?;
     let psu = c
         .file_presigned_url(
+            log,
             t.id,
             o.id,
+            &o.path,
             b.expiry_seconds,
-            b.content_type.as_deref(),
+            b.content_type.as_deref().or(o.content_type.as_deref()),
             b.content_disposition.as_deref(),
         )
         .await
@@ -234,6 +666,15 @@ impl JobOutputPublish {
         Self::one_safe(&series)?;
         Self::one_safe(&version)?;
         Self::one_safe(&name)?;
+
+        if version == super::public::VERSION_LATEST {
+            return Err(HttpError::for_client_error(
+                Some(ErrorCode::Invalid.to_string()),
+                StatusCode::BAD_REQUEST,
+                format!("version {:?} is reserved", version),
+            ));
+        }
+
         Ok(())
     }
 
@@ -250,7 +691,7 @@ impl JobOutputPublish {
             Ok(())
         } else {
             Err(HttpError::for_client_error(
-                None,
+                Some(ErrorCode::Invalid.to_string()),
                 StatusCode::BAD_REQUEST,
                 "invalid published file ID".into(),
             ))
@@ -276,7 +717,7 @@ pub(crate) async fn job_output_publish(
     b.safe()?;
 
     let owner = c.require_user(log, &rqctx.request).await?;
-    let t = c.load_job_for_user(log, &owner, p.job()?).await?;
+    let t = c.load_job_for_user(log, &rqctx.request, &owner, p.job()?).await?;
 
     let o = c.load_job_output(log, &t, p.output()?).await.or_500()?;
 
@@ -315,10 +756,28 @@ fn format_task(t: &db::Task) -> Task {
         uid: t.user_id.map(|x| x.0),
         gid: t.group_id.map(|x| x.0),
         workdir: t.workdir.clone(),
+        always_run: t.always_run,
+        timeout_seconds: t.timeout_seconds.map(|s| s.0),
         state,
+        failure: t.failure().map(FailureSummary::from),
     }
 }
 
+/**
+ * Describe the failure of a task, if the worker reported any details; e.g.,
+ * "task 'build' exited 101".
+ */
+fn format_task_failure(t: &db::Task) -> Option<String> {
+    let f = t.failure()?;
+    let desc = f.describe()?;
+
+    Some(if f.code.is_some() || f.signal.is_some() {
+        format!("task '{}' {desc}", t.name)
+    } else {
+        format!("task '{}' failed: {desc}", t.name)
+    })
+}
+
 pub(crate) fn format_job_state(j: &db::Job) -> String {
     if j.failed {
         "failed"
@@ -334,36 +793,56 @@ pub(crate) fn format_job_state(j: &db::Job) -> String {
     .to_string()
 }
 
+/**
+ * Job output rules are presently specified as strings with some prefix sigils
+ * based on behavioural directives.  Reconstruct the string version of a rule
+ * based on the structured version in the database.
+ */
+fn format_output_rule(jor: &db::JobOutputRule) -> String {
+    let mut out = String::with_capacity(jor.rule.capacity() + 3);
+    if jor.ignore {
+        out.push('!');
+    }
+    if jor.size_change_ok {
+        out.push('%');
+    }
+    if jor.require_match {
+        out.push('=');
+    }
+    out += &jor.rule;
+    out
+}
+
 pub(crate) fn format_job(
     j: &db::Job,
     t: &[db::Task],
     output_rules: Vec<db::JobOutputRule>,
     tags: HashMap<String, String>,
     target: &db::Target,
-    times: HashMap<String, DateTime<Utc>>,
+    mut times: HashMap<String, DateTime<Utc>>,
+    metrics: HashMap<String, u64>,
+    stream_stats: HashMap<String, db::StreamStats>,
+    worker: Option<JobWorker>,
+    queue_position: Option<u64>,
 ) -> Job {
+    let output_rules =
+        output_rules.iter().map(format_output_rule).collect::<Vec<_>>();
+
+    if let Some(t) = &j.time_create {
+        times.insert("create".to_string(), t.0);
+    }
+
     /*
-     * Job output rules are presently specified as strings with some prefix
-     * sigils based on behavioural directives.  We need to reconstruct the
-     * string version of this based on the structured version in the database.
+     * Prefer a reason reported for the job as a whole, but otherwise describe
+     * the first task that failed.
      */
-    let output_rules = output_rules
-        .iter()
-        .map(|jor| {
-            let mut out = String::with_capacity(jor.rule.capacity() + 3);
-            if jor.ignore {
-                out.push('!');
-            }
-            if jor.size_change_ok {
-                out.push('%');
-            }
-            if jor.require_match {
-                out.push('=');
-            }
-            out += &jor.rule;
-            out
+    let failure_reason = if j.failed {
+        j.failure_reason.clone().or_else(|| {
+            t.iter().filter(|t| t.failed).find_map(format_task_failure)
         })
-        .collect::<Vec<_>>();
+    } else {
+        None
+    };
 
     Job {
         id: j.id.to_string(),
@@ -376,7 +855,16 @@ pub(crate) fn format_job(
         state: format_job_state(j),
         tags,
         cancelled: j.cancelled,
+        cancel_reason: j.cancel_reason.clone(),
+        failure_reason,
         times,
+        metrics,
+        stream_stats: stream_stats
+            .into_iter()
+            .map(|(stream, ss)| (stream, ss.into()))
+            .collect(),
+        worker,
+        queue_position,
     }
 }
 
@@ -387,37 +875,262 @@ pub(crate) fn format_job(
 pub(crate) async fn job_get(
     rqctx: RequestContext<Arc<Central>>,
     path: TypedPath<JobPath>,
+    query: TypedQuery<JobShareQuery>,
 ) -> DSResult<HttpResponseOk<Job>> {
     let c = rqctx.context();
     let log = &rqctx.log;
     let p = path.into_inner();
+    let q = query.into_inner();
 
-    let owner = c.require_user(log, &rqctx.request).await?;
-    let job = c.load_job_for_user(log, &owner, p.job()?).await?;
+    let job = c
+        .require_share_or_user(
+            log,
+            &rqctx.request,
+            q.share.as_deref(),
+            p.job()?,
+        )
+        .await?;
 
     Ok(HttpResponseOk(Job::load(log, &c, &job).await.or_500()?))
 }
 
-#[endpoint {
-    method = GET,
-    path = "/0/jobs",
-}]
-pub(crate) async fn jobs_get(
-    rqctx: RequestContext<Arc<Central>>,
-) -> DSResult<HttpResponseOk<Vec<Job>>> {
-    let c = rqctx.context();
-    let log = &rqctx.log;
-
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct JobShare {
+    expiry_seconds: Option<u64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct JobShareResult {
+    token: String,
+    expiry: DateTime<Utc>,
+}
+
+/**
+ * Load a job that is to be shared, or no longer shared, by the user making
+ * the request.  Only the owner of a job may share it.  Users with the
+ * privilege to view the jobs of others may not extend that access to anybody
+ * else.
+ */
+async fn load_job_to_share(
+    rqctx: &RequestContext<Arc<Central>>,
+    job: db::JobId,
+) -> DSResult<(db::AuthUser, db::Job)> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+
     let owner = c.require_user(log, &rqctx.request).await?;
+    let job = c.load_job_for_user(log, &rqctx.request, &owner, job).await?;
+
+    if job.owner != owner.id {
+        return Err(HttpError::for_client_error(
+            None,
+            StatusCode::FORBIDDEN,
+            "not your job".into(),
+        ));
+    }
 
-    let jobs = c.db.user_jobs(owner.id).or_500()?;
+    Ok((owner, job))
+}
 
-    let mut out = Vec::new();
-    for job in jobs {
-        out.push(super::user::Job::load(log, &c, &job).await.or_500()?);
+/*
+ * Create a token that allows anybody who holds it to read this job, its
+ * events, and its outputs, without otherwise authenticating.  The token should
+ * be passed in the "X-Buildomat-Share" header; it is also accepted in the
+ * "share" query parameter for links that cannot carry a header.  Every token
+ * expires, after a week if no other expiry is requested.  The tokens for a job
+ * may be revoked with a DELETE request to the same path.
+ */
+#[endpoint {
+    method = POST,
+    path = "/0/jobs/{job}/share",
+}]
+pub(crate) async fn job_share(
+    rqctx: RequestContext<Arc<Central>>,
+    path: TypedPath<JobPath>,
+    body: TypedBody<JobShare>,
+) -> DSResult<HttpResponseCreated<JobShareResult>> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+    let p = path.into_inner();
+    let b = body.into_inner();
+
+    let (owner, job) = load_job_to_share(&rqctx, p.job()?).await?;
+
+    let secs = b.expiry_seconds.unwrap_or(limits::DEFAULT_SHARE_SECONDS);
+    if secs == 0 || secs > limits::MAX_SHARE_SECONDS {
+        return Err(HttpError::for_client_error(
+            Some(ErrorCode::Invalid.to_string()),
+            StatusCode::BAD_REQUEST,
+            format!(
+                "expiry_seconds must be between 1 and {}",
+                limits::MAX_SHARE_SECONDS,
+            ),
+        ));
     }
+    let expiry =
+        Utc::now() + chrono::Duration::seconds(secs.try_into().unwrap());
 
-    Ok(HttpResponseOk(out))
+    let js = c.db.job_share_create(job.id, expiry).or_500()?;
+    info!(log, "user {} shared job {} until {}", owner.id, job.id, expiry);
+
+    Ok(HttpResponseCreated(JobShareResult { token: js.token, expiry }))
+}
+
+/*
+ * Revoke every share token that has been created for this job.
+ */
+#[endpoint {
+    method = DELETE,
+    path = "/0/jobs/{job}/share",
+}]
+pub(crate) async fn job_share_revoke(
+    rqctx: RequestContext<Arc<Central>>,
+    path: TypedPath<JobPath>,
+) -> DSResult<HttpResponseDeleted> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+    let p = path.into_inner();
+
+    let (owner, job) = load_job_to_share(&rqctx, p.job()?).await?;
+
+    let count = c.db.job_share_revoke(job.id).or_500()?;
+    info!(log, "user {} revoked {} shares of job {}", owner.id, count, job.id);
+
+    Ok(HttpResponseDeleted())
+}
+
+#[derive(JsonSchema)]
+pub(crate) struct JobsGetQuery {
+    limit: Option<u64>,
+    before: Option<String>,
+    /*
+     * A "NAME=VALUE" tag filter.  The parameter may be repeated, in which case
+     * only jobs with all of the nominated tag values are included.
+     */
+    #[schemars(rename = "tag", with = "Option<String>")]
+    tags: Vec<String>,
+}
+
+/*
+ * The derived deserialiser does not allow a parameter to be repeated, so we
+ * collect the query parameters by hand in order to accept several tag filters.
+ */
+impl<'de> Deserialize<'de> for JobsGetQuery {
+    fn deserialize<D>(d: D) -> SResult<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct QueryVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for QueryVisitor {
+            type Value = JobsGetQuery;
+
+            fn expecting(
+                &self,
+                f: &mut std::fmt::Formatter<'_>,
+            ) -> std::fmt::Result {
+                f.write_str("job list query parameters")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> SResult<JobsGetQuery, A::Error>
+            where
+                A: serde::de::MapAccess<'de>,
+            {
+                use serde::de::Error;
+
+                let mut q = JobsGetQuery {
+                    limit: None,
+                    before: None,
+                    tags: Vec::new(),
+                };
+
+                while let Some((k, v)) = map.next_entry::<String, String>()? {
+                    match k.as_str() {
+                        "limit" => {
+                            q.limit = Some(v.parse().map_err(|e| {
+                                A::Error::custom(format!("invalid limit: {e}"))
+                            })?);
+                        }
+                        "before" => q.before = Some(v),
+                        "tag" => q.tags.push(v),
+                        _ => (),
+                    }
+                }
+
+                Ok(q)
+            }
+        }
+
+        d.deserialize_map(QueryVisitor)
+    }
+}
+
+impl JobsGetQuery {
+    fn before(&self) -> DSResult<Option<db::JobId>> {
+        self.before
+            .as_deref()
+            .map(|id| {
+                id.parse::<db::JobId>().map_err(|_| {
+                    HttpError::for_bad_request(
+                        None,
+                        format!("invalid job ID {:?}", id),
+                    )
+                })
+            })
+            .transpose()
+    }
+
+    fn tags(&self) -> DSResult<Vec<(String, String)>> {
+        parse_tag_filters(&self.tags)
+    }
+}
+
+/**
+ * Split a list of "NAME=VALUE" job tag filters into name and value pairs.
+ */
+pub(crate) fn parse_tag_filters(
+    tags: &[String],
+) -> DSResult<Vec<(String, String)>> {
+    tags.iter()
+        .map(|v| {
+            v.split_once('=')
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .ok_or_else(|| {
+                    HttpError::for_bad_request(
+                        None,
+                        format!("tag filter {:?} must be NAME=VALUE", v),
+                    )
+                })
+        })
+        .collect()
+}
+
+#[endpoint {
+    method = GET,
+    path = "/0/jobs",
+}]
+pub(crate) async fn jobs_get(
+    rqctx: RequestContext<Arc<Central>>,
+    query: TypedQuery<JobsGetQuery>,
+) -> DSResult<HttpResponseOk<Vec<Job>>> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+    let q = query.into_inner();
+
+    let owner = c.require_user(log, &rqctx.request).await?;
+
+    /*
+     * Jobs are listed newest first.  If the caller provides one or more
+     * "tag=NAME=VALUE" filters, only jobs with all of the nominated tag values
+     * are included.  The "before" job ID allows the caller to page through the
+     * list in chunks of "limit" jobs.
+     */
+    let tags = q.tags()?;
+    let limit = q.limit.map(|n| n.try_into().unwrap_or(usize::MAX));
+
+    let jobs = c.db.user_jobs(owner.id, &tags, q.before()?, limit).or_500()?;
+
+    Ok(HttpResponseOk(Job::load_list(log, c, &jobs).await.or_500()?))
 }
 
 #[derive(Serialize, JsonSchema)]
@@ -432,8 +1145,77 @@ pub(crate) struct Job {
     state: String,
     tags: HashMap<String, String>,
     cancelled: bool,
+    cancel_reason: Option<String>,
+    /*
+     * If the job failed, a short description of the failure as reported by
+     * the worker; e.g., "task 'build' exited 101".
+     */
+    failure_reason: Option<String>,
     #[serde(default)]
     times: HashMap<String, DateTime<Utc>>,
+    /*
+     * Peak resource usage for the job, as reported by the worker; e.g.,
+     * "rss_bytes", "cpu_seconds", and "disk_bytes".
+     */
+    #[serde(default)]
+    metrics: HashMap<String, u64>,
+    /*
+     * The number of events, and the total size of their payloads, recorded
+     * for each stream of the job; e.g., "stdout" or "stderr".
+     */
+    #[serde(default)]
+    stream_stats: HashMap<String, JobStreamStats>,
+    /*
+     * The worker to which the job is assigned, if any.  This is not
+     * available once the job has been archived.
+     */
+    worker: Option<JobWorker>,
+    /*
+     * For a queued job, an estimate of its position in the queue of jobs
+     * waiting for a worker of the same target; the job at the head of the
+     * queue is in position 1.
+     */
+    queue_position: Option<u64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct JobStreamStats {
+    events: u64,
+    bytes: u64,
+}
+
+impl From<db::StreamStats> for JobStreamStats {
+    fn from(input: db::StreamStats) -> JobStreamStats {
+        let db::StreamStats { events, bytes } = input;
+
+        JobStreamStats { events, bytes }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct JobWorker {
+    id: String,
+    factory: String,
+    target: Option<String>,
+    lastping: Option<DateTime<Utc>>,
+}
+
+impl JobWorker {
+    fn load(c: &Central, id: db::WorkerId) -> Result<Option<JobWorker>> {
+        let Some(w) = c.db.worker_get_opt(id)? else {
+            return Ok(None);
+        };
+
+        let factory = c.db.factory_get(w.factory())?;
+        let target = w.target.map(|t| c.db.target_get(t)).transpose()?;
+
+        Ok(Some(JobWorker {
+            id: w.id.to_string(),
+            factory: factory.name,
+            target: target.map(|t| t.name),
+            lastping: w.lastping.map(|t| t.0),
+        }))
+    }
 }
 
 impl Job {
@@ -442,7 +1224,52 @@ impl Job {
         c: &Central,
         job: &db::Job,
     ) -> Result<Job> {
-        let (tasks, output_rules, tags, target, times) = if job.is_archived() {
+        /*
+         * Jobs that are complete, including all archived jobs, are not in
+         * the queue.
+         */
+        let queue_position = c.db.job_queue_position(job).or_500()?;
+
+        Job::load_with_position(log, c, job, queue_position).await
+    }
+
+    /**
+     * Load a list of jobs, determining the queue position of any that are
+     * queued with a single query for the whole list.
+     */
+    pub(crate) async fn load_list(
+        log: &Logger,
+        c: &Central,
+        jobs: &[db::Job],
+    ) -> Result<Vec<Job>> {
+        let positions = c.db.job_queue_positions(jobs).or_500()?;
+
+        let mut out = Vec::new();
+        for job in jobs {
+            let queue_position = positions.get(&job.id).copied();
+            out.push(
+                Job::load_with_position(log, c, job, queue_position).await?,
+            );
+        }
+        Ok(out)
+    }
+
+    async fn load_with_position(
+        log: &Logger,
+        c: &Central,
+        job: &db::Job,
+        queue_position: Option<u64>,
+    ) -> Result<Job> {
+        let (
+            tasks,
+            output_rules,
+            tags,
+            target,
+            times,
+            metrics,
+            stream_stats,
+            worker,
+        ) = if job.is_archived() {
             let aj = c.archive_load(log, job.id).await.or_500()?;
 
             (
@@ -451,6 +1278,9 @@ impl Job {
                 aj.tags().or_500()?,
                 c.db.target_get(job.target()).or_500()?,
                 aj.times().or_500()?,
+                aj.metrics().or_500()?,
+                aj.stream_stats(),
+                None,
             )
         } else {
             (
@@ -459,10 +1289,28 @@ impl Job {
                 c.db.job_tags(job.id).or_500()?,
                 c.db.target_get(job.target()).or_500()?,
                 c.db.job_times(job.id).or_500()?,
+                c.db.job_metrics(job.id).or_500()?,
+                c.db.job_stream_stats(job.id).or_500()?,
+                job.worker
+                    .map(|w| JobWorker::load(c, w))
+                    .transpose()
+                    .or_500()?
+                    .flatten(),
             )
         };
 
-        Ok(format_job(&job, &tasks, output_rules, tags, &target, times))
+        Ok(format_job(
+            &job,
+            &tasks,
+            output_rules,
+            tags,
+            &target,
+            times,
+            metrics,
+            stream_stats,
+            worker,
+            queue_position,
+        ))
     }
 }
 
@@ -475,13 +1323,21 @@ pub(crate) struct Task {
     uid: Option<u32>,
     gid: Option<u32>,
     workdir: Option<String>,
+    always_run: bool,
+    timeout_seconds: Option<u64>,
     state: String,
+    failure: Option<FailureSummary>,
 }
 
 #[derive(Deserialize, JsonSchema)]
 pub(crate) struct JobSubmit {
     name: String,
-    target: String,
+    /*
+     * If no target is specified, the job uses the default target named in the
+     * server configuration.
+     */
+    #[serde(default)]
+    target: Option<String>,
     output_rules: Vec<String>,
     tasks: Vec<TaskSubmit>,
     #[serde(default)]
@@ -490,6 +1346,20 @@ pub(crate) struct JobSubmit {
     tags: HashMap<String, String>,
     #[serde(default)]
     depends: HashMap<String, DependSubmit>,
+    /*
+     * If specified, a repeat submission by the same user with the same key
+     * returns the job created by the original submission rather than creating
+     * another job.  Keys are remembered for a limited time.
+     */
+    #[serde(default)]
+    idempotency_key: Option<String>,
+    /*
+     * If set, the job is only created if the user does not already have a job
+     * with the same name.  Otherwise, the submission fails with a conflict
+     * that includes the ID of the existing job.
+     */
+    #[serde(default)]
+    unique_name: bool,
 }
 
 #[derive(Deserialize, JsonSchema)]
@@ -501,12 +1371,31 @@ pub(crate) struct TaskSubmit {
     uid: Option<u32>,
     gid: Option<u32>,
     workdir: Option<String>,
+    /*
+     * If set, the task is executed even if an earlier task in the job has
+     * failed; e.g., to collect diagnostic information.  The job is still
+     * marked as failed.
+     */
+    #[serde(default)]
+    always_run: bool,
+    /*
+     * If set, the task is killed and marked as failed if it runs for longer
+     * than this.  The timeout may not exceed the maximum run time for a job.
+     */
+    #[serde(default)]
+    timeout_seconds: Option<u64>,
 }
 
 #[derive(Deserialize, JsonSchema)]
 pub(crate) struct DependSubmit {
     prior_job: String,
     copy_outputs: bool,
+    /*
+     * If specified, only outputs of the prior job with a path that matches one
+     * of these glob patterns are copied.  All outputs are copied otherwise.
+     */
+    #[serde(default)]
+    copy_outputs_globs: Vec<String>,
     on_failed: bool,
     on_completed: bool,
 }
@@ -552,7 +1441,7 @@ fn parse_output_rule(input: &str) -> DSResult<db::CreateOutputRule> {
                 }
                 other => {
                     return Err(HttpError::for_client_error(
-                        None,
+                        Some(ErrorCode::InvalidOutputRule.to_string()),
                         StatusCode::BAD_REQUEST,
                         format!("wanted sigil/absolute path, not {:?}", other),
                     ));
@@ -569,7 +1458,7 @@ fn parse_output_rule(input: &str) -> DSResult<db::CreateOutputRule> {
                 }
                 other => {
                     return Err(HttpError::for_client_error(
-                        None,
+                        Some(ErrorCode::InvalidOutputRule.to_string()),
                         StatusCode::BAD_REQUEST,
                         format!("{:?} unexpected in output rule", other),
                     ));
@@ -586,7 +1475,7 @@ fn parse_output_rule(input: &str) -> DSResult<db::CreateOutputRule> {
                 }
                 other => {
                     return Err(HttpError::for_client_error(
-                        None,
+                        Some(ErrorCode::InvalidOutputRule.to_string()),
                         StatusCode::BAD_REQUEST,
                         format!("{:?} unexpected in output rule", other),
                     ));
@@ -599,7 +1488,7 @@ fn parse_output_rule(input: &str) -> DSResult<db::CreateOutputRule> {
                 }
                 other => {
                     return Err(HttpError::for_client_error(
-                        None,
+                        Some(ErrorCode::InvalidOutputRule.to_string()),
                         StatusCode::BAD_REQUEST,
                         format!("wanted '/', not {:?}, in output rule", other),
                     ));
@@ -609,203 +1498,898 @@ fn parse_output_rule(input: &str) -> DSResult<db::CreateOutputRule> {
         }
     }
 
-    if !rule.starts_with("/") {
+    if !rule.starts_with("/") {
+        return Err(HttpError::for_client_error(
+            Some(ErrorCode::InvalidOutputRule.to_string()),
+            StatusCode::BAD_REQUEST,
+            format!("output rule pattern must be absolute path"),
+        ));
+    }
+
+    if ignore {
+        assert!(!require_match && !size_change_ok);
+    }
+
+    Ok(db::CreateOutputRule { rule, ignore, require_match, size_change_ok })
+}
+
+/*
+ * Each rule is valid in isolation, but the set of rules for a job must also
+ * make sense as a whole.  A pattern that appears more than once is almost
+ * certainly a copy-paste mistake, and a pattern that is both ignored and
+ * required to match cannot be satisfied.
+ */
+fn check_output_rules(rules: &[db::CreateOutputRule]) -> DSResult<()> {
+    let mut seen: HashMap<&str, &db::CreateOutputRule> = HashMap::new();
+
+    for rule in rules {
+        if let Some(prior) = seen.insert(rule.rule.as_str(), rule) {
+            let msg = if (prior.ignore && rule.require_match)
+                || (prior.require_match && rule.ignore)
+            {
+                format!(
+                    "output rule pattern {:?} is both ignored and required",
+                    rule.rule,
+                )
+            } else {
+                format!("duplicate output rule pattern {:?}", rule.rule)
+            };
+
+            return Err(HttpError::for_client_error(
+                Some(ErrorCode::InvalidOutputRule.to_string()),
+                StatusCode::BAD_REQUEST,
+                msg,
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct Quota {
+    max_bytes_per_input: u64,
+    max_signed_url_seconds: u64,
+    max_tasks_per_job: u64,
+    max_inputs_per_job: u64,
+    max_tags_per_job: u64,
+    max_tag_bytes_per_job: u64,
+    max_depends_per_job: u64,
+    max_jobs_per_batch: u64,
+    max_bytes_per_sync_file: u64,
+    max_active_jobs_per_user: Option<u64>,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/0/quota",
+}]
+pub(crate) async fn quota(
+    rqctx: RequestContext<Arc<Central>>,
+) -> DSResult<HttpResponseOk<Quota>> {
+    let c = rqctx.context();
+
+    /*
+     * For now, this request just presents statically configured quota
+     * information.  These limits are enforced in requests, but we expose them
+     * here so that client tools can present better diagnostic information.
+     */
+    Ok(HttpResponseOk(Quota {
+        max_bytes_per_input: c.config.job.max_bytes_per_input(),
+        max_signed_url_seconds: c.config.job.max_signed_url_seconds,
+        max_tasks_per_job: c.config.job.max_tasks_per_job,
+        max_inputs_per_job: c.config.job.max_inputs_per_job,
+        max_tags_per_job: c.config.job.max_tags_per_job,
+        max_tag_bytes_per_job: c.config.job.max_tag_bytes_per_job,
+        max_depends_per_job: limits::MAX_DEPENDS_PER_JOB,
+        max_jobs_per_batch: limits::MAX_JOBS_PER_BATCH,
+        max_bytes_per_sync_file: limits::MAX_SYNC_FILE_BYTES,
+        max_active_jobs_per_user: c.config.job.max_active_per_user,
+    }))
+}
+
+/**
+ * Validate a job submission from a user and convert it into the form expected
+ * by the database.  If the job is part of a batch, "batch" contains the names
+ * of the jobs that appear earlier in that batch; a dependency may refer to one
+ * of those jobs by name instead of by ID.
+ */
+fn job_submit_prepare(
+    log: &Logger,
+    c: &Central,
+    owner: &db::AuthUser,
+    new_job: JobSubmit,
+    batch: &[String],
+) -> DSResult<db::CreateJob> {
+    let mut problems = Vec::new();
+
+    let cj = job_submit_check(log, c, owner, new_job, batch, &mut problems);
+    if let Some(e) = problems.into_iter().next() {
+        return Err(e);
+    }
+
+    /*
+     * The job can only be missing if a problem was recorded, but we would
+     * rather report an error than panic if that ever stops being true.
+     */
+    cj.ok_or_else(|| {
+        HttpError::for_internal_error(
+            "job submission check failed without a problem".into(),
+        )
+    })
+}
+
+/**
+ * Perform each of the checks required to accept a job submission, recording
+ * every problem we find rather than stopping at the first one.  Returns the
+ * converted job if it was possible to construct it, though it must not be used
+ * unless no problems were recorded.
+ */
+fn job_submit_check(
+    log: &Logger,
+    c: &Central,
+    owner: &db::AuthUser,
+    new_job: JobSubmit,
+    batch: &[String],
+    problems: &mut Vec<HttpError>,
+) -> Option<db::CreateJob> {
+    let cfg = &c.config.job;
+
+    if let Some(msg) =
+        limits::check_count("tasks", new_job.tasks.len(), cfg.max_tasks_per_job)
+    {
+        problems.push(HttpError::for_client_error(
+            Some(ErrorCode::TooManyTasks.to_string()),
+            StatusCode::BAD_REQUEST,
+            msg,
+        ));
+    }
+
+    if let Some(msg) = limits::check_count(
+        "inputs",
+        new_job.inputs.len(),
+        cfg.max_inputs_per_job,
+    ) {
+        problems.push(HttpError::for_client_error(
+            Some(ErrorCode::TooManyInputs.to_string()),
+            StatusCode::BAD_REQUEST,
+            msg,
+        ));
+    }
+
+    if let Some(msg) =
+        limits::check_count("tags", new_job.tags.len(), cfg.max_tags_per_job)
+    {
+        problems.push(HttpError::for_client_error(
+            Some(ErrorCode::TooManyTags.to_string()),
+            StatusCode::BAD_REQUEST,
+            msg,
+        ));
+    }
+
+    let tag_bytes =
+        new_job.tags.iter().map(|(n, v)| n.len() + v.len()).sum::<usize>();
+    if let Some(msg) =
+        limits::check_tag_bytes(tag_bytes, cfg.max_tag_bytes_per_job)
+    {
+        problems.push(HttpError::for_client_error(
+            Some(ErrorCode::TagsTooLarge.to_string()),
+            StatusCode::BAD_REQUEST,
+            msg,
+        ));
+    }
+
+    /*
+     * Tag names must not be a zero-length string, and all characters must be
+     * ASCII: numbers, lowercase letters, periods, hypens, or underscores:
+     */
+    if new_job.tags.keys().any(|n| {
+        n.is_empty()
+            || !n.chars().all(|c| {
+                c.is_ascii_digit()
+                    || c.is_ascii_lowercase()
+                    || c == '.'
+                    || c == '_'
+                    || c == '-'
+            })
+    }) {
+        problems.push(HttpError::for_client_error(
+            Some(ErrorCode::InvalidTagName.to_string()),
+            StatusCode::BAD_REQUEST,
+            "tag names must be [0-9a-z._-]+".into(),
+        ));
+    }
+
+    /*
+     * Tag values may contain any printable characters, but not control
+     * characters like newlines that would confuse the display of the tag.
+     */
+    let mut bad_values = new_job
+        .tags
+        .iter()
+        .filter(|(_, v)| v.chars().any(char::is_control))
+        .map(|(n, _)| n.as_str())
+        .collect::<Vec<_>>();
+    if !bad_values.is_empty() {
+        bad_values.sort_unstable();
+        problems.push(HttpError::for_client_error(
+            Some(ErrorCode::InvalidTagValue.to_string()),
+            StatusCode::BAD_REQUEST,
+            format!(
+                "tag values must not contain control characters: {}",
+                bad_values.join(", "),
+            ),
+        ));
+    }
+
+    /*
+     * A task may have a timeout shorter than the maximum run time for the job
+     * as a whole, but not longer.
+     */
+    for ts in new_job.tasks.iter() {
+        match ts.timeout_seconds {
+            Some(0) => {
+                problems.push(HttpError::for_client_error(
+                    Some(ErrorCode::Invalid.to_string()),
+                    StatusCode::BAD_REQUEST,
+                    format!(
+                        "task {:?} timeout must be at least 1 second",
+                        ts.name
+                    ),
+                ));
+            }
+            Some(secs) if secs > cfg.max_runtime => {
+                problems.push(HttpError::for_client_error(
+                    Some(ErrorCode::Invalid.to_string()),
+                    StatusCode::BAD_REQUEST,
+                    format!(
+                        "task {:?} timeout of {secs} seconds exceeds the \
+                        maximum job run time of {} seconds",
+                        ts.name, cfg.max_runtime,
+                    ),
+                ));
+            }
+            _ => (),
+        }
+    }
+
+    /*
+     * Resolve the target name to a specific target.  We store both so that it
+     * is subsequently clear what we were asked, and what we actually delivered.
+     * If the user did not ask for a particular target, use the configured
+     * default.
+     */
+    let target_name = new_job
+        .target
+        .clone()
+        .unwrap_or_else(|| cfg.default_target.to_string());
+    let target = match c.db.target_resolve(&target_name).or_500() {
+        Ok(Some(target)) => {
+            info!(
+                log,
+                "resolved target name {:?} to {:?}", target_name, target,
+            );
+            Some(target)
+        }
+        Ok(None) => {
+            info!(log, "could not resolve target name {:?}", target_name);
+            problems.push(HttpError::for_client_error(
+                Some(ErrorCode::TargetUnknown.to_string()),
+                StatusCode::BAD_REQUEST,
+                format!("could not resolve target name {:?}", target_name),
+            ));
+            None
+        }
+        Err(e) => {
+            problems.push(e);
+            None
+        }
+    };
+
+    /*
+     * Confirm that the authenticated user is allowed to create jobs using the
+     * resolved target.
+     */
+    if let Some(target) = &target {
+        if let Some(required) = target.privilege.as_deref() {
+            if !owner.has_privilege(required) {
+                warn!(
+                    log,
+                    "user {} denied the use of target {:?} ({:?})",
+                    owner.id,
+                    target.name,
+                    target_name,
+                );
+                problems.push(HttpError::for_client_error(
+                    Some(ErrorCode::TargetForbidden.to_string()),
+                    StatusCode::FORBIDDEN,
+                    "you are not allowed to use that target".into(),
+                ));
+            }
+        }
+    }
+
+    let tasks = new_job
+        .tasks
+        .iter()
+        .map(|ts| db::CreateTask {
+            name: ts.name.to_string(),
+            script: ts.script.to_string(),
+            env_clear: ts.env_clear,
+            env: ts.env.clone(),
+            user_id: ts.uid,
+            group_id: ts.gid,
+            workdir: ts.workdir.clone(),
+            always_run: ts.always_run,
+            timeout_seconds: ts.timeout_seconds,
+        })
+        .collect::<Vec<_>>();
+
+    /*
+     * Check every dependency before reporting any problems, so that a job with
+     * several invalid dependencies can be corrected in one go.  Dependencies
+     * are examined in name order so that the report is stable.
+     */
+    let mut depends = Vec::new();
+    let mut bad_depends = Vec::new();
+    if let Some(msg) = limits::check_count(
+        "depends",
+        new_job.depends.len(),
+        limits::MAX_DEPENDS_PER_JOB,
+    ) {
+        bad_depends.push(msg);
+    }
+    let mut names = new_job.depends.keys().collect::<Vec<_>>();
+    names.sort_unstable();
+    for name in names {
+        let ds = &new_job.depends[name];
+
+        if name.contains('/') || name.trim().is_empty() {
+            bad_depends.push(format!("depend {:?}: invalid name", name));
+        }
+
+        if !ds.copy_outputs_globs.is_empty() && !ds.copy_outputs {
+            bad_depends.push(format!(
+                "depend {:?}: \"copy_outputs_globs\" requires \
+                \"copy_outputs\"",
+                name,
+            ));
+        }
+        for g in ds.copy_outputs_globs.iter() {
+            if let Err(e) = glob::Pattern::new(g) {
+                bad_depends.push(format!(
+                    "depend {:?}: invalid glob {:?}: {}",
+                    name, g, e,
+                ));
+            }
+        }
+
+        if !ds.on_failed && !ds.on_completed {
+            bad_depends.push(format!(
+                "depend {:?}: at least one of \"on_failed\" or \
+                \"on_completed\" must be set",
+                name,
+            ));
+        }
+
+        /*
+         * The prior job is either the ID of a job that already exists, or the
+         * name of a job that appears earlier in the same batch.
+         */
+        let prior_job = if let Ok(id) = db::JobId::from_str(&ds.prior_job) {
+            /*
+             * Jobs may only depend on other jobs with the same owner.  Try not
+             * to leak information about the jobs of other users by reporting
+             * those as missing.
+             */
+            match c.db.job_by_id_opt(id).or_500() {
+                Ok(Some(pj)) if pj.owner == owner.id => {
+                    db::CreatePriorJob::Existing(id)
+                }
+                Ok(_) => {
+                    bad_depends.push(format!(
+                        "depend {:?}: prior job {} does not exist",
+                        name, id,
+                    ));
+                    continue;
+                }
+                Err(e) => {
+                    problems.push(e);
+                    continue;
+                }
+            }
+        } else {
+            let mut matches = batch
+                .iter()
+                .enumerate()
+                .filter(|(_, n)| *n == &ds.prior_job)
+                .map(|(i, _)| i);
+
+            match (matches.next(), matches.next()) {
+                (Some(i), None) => db::CreatePriorJob::Batch(i),
+                (Some(_), Some(_)) => {
+                    bad_depends.push(format!(
+                        "depend {:?}: prior job name {:?} is \
+                        ambiguous within the batch",
+                        name, ds.prior_job,
+                    ));
+                    continue;
+                }
+                (None, _) => {
+                    bad_depends.push(format!(
+                        "depend {:?}: prior job {:?} is neither a job \
+                        ID nor an earlier job in the batch",
+                        name, ds.prior_job,
+                    ));
+                    continue;
+                }
+            }
+        };
+
+        depends.push(db::CreateDepend {
+            name: name.to_string(),
+            prior_job,
+            copy_outputs: ds.copy_outputs,
+            copy_outputs_globs: ds.copy_outputs_globs.clone(),
+            on_failed: ds.on_failed,
+            on_completed: ds.on_completed,
+        });
+    }
+    if !bad_depends.is_empty() {
+        problems.push(HttpError::for_client_error(
+            Some(ErrorCode::InvalidDepend.to_string()),
+            StatusCode::BAD_REQUEST,
+            format!("invalid dependencies: {}", bad_depends.join("; ")),
+        ));
+    }
+
+    let mut output_rules = Vec::new();
+    for rule in new_job.output_rules.iter() {
+        match parse_output_rule(rule.as_str()) {
+            Ok(rule) => output_rules.push(rule),
+            Err(e) => problems.push(e),
+        }
+    }
+    if let Err(e) = check_output_rules(&output_rules) {
+        problems.push(e);
+    }
+
+    Some(db::CreateJob {
+        name: new_job.name,
+        target_name,
+        target: target?.id,
+        tasks,
+        output_rules,
+        inputs: new_job.inputs,
+        tags: new_job.tags,
+        depends,
+        max_active: if owner.has_privilege("admin.job.unlimited") {
+            None
+        } else {
+            cfg.max_active_per_user
+        },
+    })
+}
+
+fn unique_name_with_key() -> HttpError {
+    HttpError::for_client_error(
+        Some(ErrorCode::Invalid.to_string()),
+        StatusCode::BAD_REQUEST,
+        "a job may not have both an idempotency key and a unique name".into(),
+    )
+}
+
+#[endpoint {
+    method = POST,
+    path = "/0/jobs",
+}]
+pub(crate) async fn job_submit(
+    rqctx: RequestContext<Arc<Central>>,
+    new_job: TypedBody<JobSubmit>,
+) -> DSResult<HttpResponseCreated<JobSubmitResult>> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+
+    let owner = c.require_user(log, &rqctx.request).await?;
+    let mut new_job = new_job.into_inner();
+    let key = new_job.idempotency_key.take();
+    let unique_name = new_job.unique_name;
+
+    if key.is_some() && unique_name {
+        return Err(unique_name_with_key());
+    }
+
+    let since = chrono::Duration::from_std(std::time::Duration::from_secs(
+        c.config.job.idempotency_window_seconds,
+    ))
+    .ok()
+    .and_then(|d| Utc::now().checked_sub_signed(d))
+    .unwrap_or(DateTime::<Utc>::MIN_UTC);
+
+    if let Some(key) = &key {
+        if let Some(msg) = limits::check_idempotency_key(key) {
+            return Err(HttpError::for_client_error(
+                Some(ErrorCode::Invalid.to_string()),
+                StatusCode::BAD_REQUEST,
+                msg,
+            ));
+        }
+
+        /*
+         * If this is a repeat of an earlier submission, return the original
+         * job before we check anything else.  The original job may itself
+         * count against the limit on active jobs, which should not cause the
+         * retry to fail.
+         */
+        if let Some(t) =
+            c.db.job_by_idempotency_key(owner.id, key, since).or_500()?
+        {
+            info!(
+                log,
+                "user {} repeated submission with idempotency key {:?}; \
+                returning job {}",
+                owner.id,
+                key,
+                t.id,
+            );
+            return Ok(HttpResponseCreated(JobSubmitResult {
+                id: t.id.to_string(),
+            }));
+        }
+    }
+
+    let cj = job_submit_prepare(log, c, &owner, new_job, &[])?;
+
+    let t = if let Some(key) = &key {
+        c.db.job_create_idempotent(owner.id, cj, key, since).or_500()?
+    } else if unique_name {
+        match c.db.job_create_unique_name(owner.id, cj) {
+            Err(db::OperationError::Conflict(msg)) => {
+                return Err(HttpError::for_client_error(
+                    Some(ErrorCode::JobNameExists.to_string()),
+                    StatusCode::CONFLICT,
+                    msg,
+                ));
+            }
+            res => res.or_500()?,
+        }
+    } else {
+        c.db.job_create(owner.id, cj).or_500()?
+    };
+
+    Ok(HttpResponseCreated(JobSubmitResult { id: t.id.to_string() }))
+}
+
+/*
+ * Create a new job from the stored definition of an existing job, so that it
+ * can be run again.  Input files uploaded to the original job are reused
+ * rather than uploaded again, and store entries set by the user are copied.
+ */
+#[endpoint {
+    method = POST,
+    path = "/0/jobs/{job}/rerun",
+}]
+pub(crate) async fn job_rerun(
+    rqctx: RequestContext<Arc<Central>>,
+    path: TypedPath<JobPath>,
+) -> DSResult<HttpResponseCreated<JobSubmitResult>> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+    let p = path.into_inner();
+
+    let owner = c.require_user(log, &rqctx.request).await?;
+    let job =
+        c.load_job_for_user(log, &rqctx.request, &owner, p.job()?).await?;
+
+    /*
+     * The new job belongs to the owner of the original job.  Users with the
+     * privilege to view the jobs of others may not use it to submit jobs on
+     * their behalf.
+     */
+    if job.owner != owner.id {
         return Err(HttpError::for_client_error(
             None,
-            StatusCode::BAD_REQUEST,
-            format!("output rule pattern must be absolute path"),
+            StatusCode::FORBIDDEN,
+            "not your job".into(),
         ));
     }
 
-    if ignore {
-        assert!(!require_match && !size_change_ok);
-    }
+    let (tasks, output_rules, tags, depends, store) = if job.is_archived() {
+        let aj = c.archive_load(log, job.id).await.or_500()?;
 
-    Ok(db::CreateOutputRule { rule, ignore, require_match, size_change_ok })
+        /*
+         * Secret values are not kept in the archive, and archives made before
+         * that was true may also be missing other values.  We cannot run the
+         * job again without them.
+         */
+        let store = aj
+            .store()
+            .iter()
+            .filter(|(_, e)| e.source() == "user")
+            .map(|(name, e)| {
+                let Some(v) = e.value() else {
+                    return Err(HttpError::for_client_error(
+                        Some(ErrorCode::Conflict.to_string()),
+                        StatusCode::CONFLICT,
+                        format!(
+                            "the value of store entry {name:?} was not \
+                            archived, so this job cannot be run again; \
+                            submit it again instead",
+                        ),
+                    ));
+                };
+                Ok((name.to_string(), v.to_string(), e.secret()))
+            })
+            .collect::<DSResult<Vec<_>>>()?;
+
+        (
+            aj.tasks().or_500()?,
+            aj.output_rules().or_500()?,
+            aj.tags().or_500()?,
+            aj.depends().or_500()?,
+            store,
+        )
+    } else {
+        let store =
+            c.db.job_store(job.id)
+                .or_500()?
+                .into_values()
+                .filter(|e| e.source == "user")
+                .map(|e| (e.name, e.value, e.secret))
+                .collect::<Vec<_>>();
+
+        (
+            c.db.job_tasks(job.id).or_500()?,
+            c.db.job_output_rules(job.id).or_500()?,
+            c.db.job_tags(job.id).or_500()?,
+            c.db.job_depends(job.id).or_500()?,
+            store,
+        )
+    };
+
+    /*
+     * Inputs that were copied from the outputs of a dependency will be copied
+     * again when the dependency is satisfied for the new job.  Such an input
+     * refers to a file held by the prior job, and is named for the dependency.
+     * Only the inputs named in the original submission are carried over; if
+     * the original job was itself a re-run, those may also refer to files held
+     * by another job.
+     */
+    let from_depend = |ji: &db::JobInput| {
+        depends.iter().any(|d| {
+            ji.other_job == Some(d.prior_job)
+                && ji.name.starts_with(&format!("{}/", d.name.trim()))
+        })
+    };
+    let inputs = c
+        .load_job_inputs(log, &job)
+        .await
+        .or_500()?
+        .into_iter()
+        .filter(|(ji, _)| !from_depend(ji))
+        .collect::<Vec<_>>();
+
+    /*
+     * Reconstruct the submission for the original job and subject it to the
+     * same checks as any other submission, as limits and target availability
+     * may have changed since it was created.
+     */
+    let new_job = JobSubmit {
+        name: job.name.to_string(),
+        target: Some(job.target.to_string()),
+        output_rules: output_rules.iter().map(format_output_rule).collect(),
+        tasks: tasks
+            .into_iter()
+            .map(|t| TaskSubmit {
+                name: t.name,
+                script: t.script,
+                env_clear: t.env_clear,
+                env: t.env.0,
+                uid: t.user_id.map(|u| u.0),
+                gid: t.group_id.map(|g| g.0),
+                workdir: t.workdir,
+                always_run: t.always_run,
+                timeout_seconds: t.timeout_seconds.map(|s| s.0),
+            })
+            .collect(),
+        inputs: inputs.iter().map(|(ji, _)| ji.name.to_string()).collect(),
+        tags,
+        depends: depends
+            .into_iter()
+            .map(|d| {
+                (
+                    d.name,
+                    DependSubmit {
+                        prior_job: d.prior_job.to_string(),
+                        copy_outputs: d.copy_outputs,
+                        copy_outputs_globs: d
+                            .copy_outputs_globs
+                            .map(Into::into)
+                            .unwrap_or_default(),
+                        on_failed: d.on_failed,
+                        on_completed: d.on_completed,
+                    },
+                )
+            })
+            .collect(),
+        idempotency_key: None,
+        unique_name: false,
+    };
+
+    let cj = job_submit_prepare(log, c, &owner, new_job, &[])?;
+
+    let cr = db::CreateRerun {
+        original: job.id,
+        inputs: inputs
+            .into_iter()
+            .filter_map(|(ji, _)| {
+                ji.id.map(|id| (ji.name, ji.other_job.unwrap_or(ji.job), id))
+            })
+            .collect(),
+        store,
+    };
+
+    let t = c.db.job_rerun(owner.id, cj, cr).or_500()?;
+    info!(log, "user {} re-ran job {} as job {}", owner.id, job.id, t.id);
+
+    Ok(HttpResponseCreated(JobSubmitResult { id: t.id.to_string() }))
 }
 
 #[derive(Serialize, JsonSchema)]
-pub(crate) struct Quota {
-    max_bytes_per_input: u64,
+pub(crate) struct JobValidateProblem {
+    code: Option<String>,
+    message: String,
+    /*
+     * The ID of an existing job involved in the problem; e.g., the job that
+     * already has the name requested by a job with a unique name.
+     */
+    job: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct JobValidateResult {
+    problems: Vec<JobValidateProblem>,
 }
 
+/*
+ * Note that this cannot be "/0/jobs/validate", as the router does not allow a
+ * literal path component in the same position as the "{job}" variable in
+ * paths like "/0/jobs/{job}/events".
+ */
 #[endpoint {
-    method = GET,
-    path = "/0/quota",
+    method = POST,
+    path = "/0/validate/job",
 }]
-pub(crate) async fn quota(
+pub(crate) async fn job_validate(
     rqctx: RequestContext<Arc<Central>>,
-) -> DSResult<HttpResponseOk<Quota>> {
+    new_job: TypedBody<JobSubmit>,
+) -> DSResult<HttpResponseOk<JobValidateResult>> {
     let c = rqctx.context();
+    let log = &rqctx.log;
+
+    let owner = c.require_user(log, &rqctx.request).await?;
+    let new_job = new_job.into_inner();
+    let name = new_job.name.clone();
+    let unique_name = new_job.unique_name;
 
     /*
-     * For now, this request just presents statically configured quota
-     * information.  These limits are enforced in requests, but we expose them
-     * here so that client tools can present better diagnostic information.
+     * Perform all of the same checks we would perform for a real submission,
+     * but report every problem we find rather than creating the job.
      */
-    Ok(HttpResponseOk(Quota {
-        max_bytes_per_input: c.config.job.max_bytes_per_input(),
-    }))
+    let mut problems = Vec::new();
+    if new_job.idempotency_key.is_some() && unique_name {
+        problems.push(unique_name_with_key());
+    }
+    job_submit_check(log, c, &owner, new_job, &[], &mut problems);
+
+    let mut problems = problems
+        .into_iter()
+        .map(|e| JobValidateProblem {
+            code: e.error_code,
+            message: e.external_message,
+            job: None,
+        })
+        .collect::<Vec<_>>();
+
+    if unique_name {
+        if let Some(id) = c.db.job_by_name(owner.id, &name).or_500()? {
+            problems.push(JobValidateProblem {
+                code: Some(ErrorCode::JobNameExists.to_string()),
+                message: format!(
+                    "a job named {:?} already exists: {}",
+                    name, id
+                ),
+                job: Some(id.to_string()),
+            });
+        }
+    }
+
+    Ok(HttpResponseOk(JobValidateResult { problems }))
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct JobSubmitBatch {
+    jobs: Vec<JobSubmit>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct JobSubmitBatchResult {
+    jobs: Vec<JobSubmitResult>,
 }
 
 #[endpoint {
     method = POST,
-    path = "/0/jobs",
+    path = "/0/batch/jobs",
 }]
-pub(crate) async fn job_submit(
+pub(crate) async fn job_submit_batch(
     rqctx: RequestContext<Arc<Central>>,
-    new_job: TypedBody<JobSubmit>,
-) -> DSResult<HttpResponseCreated<JobSubmitResult>> {
+    batch: TypedBody<JobSubmitBatch>,
+) -> DSResult<HttpResponseCreated<JobSubmitBatchResult>> {
     let c = rqctx.context();
     let log = &rqctx.log;
 
     let owner = c.require_user(log, &rqctx.request).await?;
-    let new_job = new_job.into_inner();
+    let batch = batch.into_inner();
 
-    if new_job.tasks.len() > 100 {
+    if batch.jobs.is_empty() {
         return Err(HttpError::for_client_error(
-            None,
+            Some(ErrorCode::InvalidBatch.to_string()),
             StatusCode::BAD_REQUEST,
-            "too many tasks".into(),
+            "a batch must contain at least one job".into(),
         ));
     }
 
-    if new_job.inputs.len() > 25 {
+    if let Some(msg) = limits::check_count(
+        "jobs in batch",
+        batch.jobs.len(),
+        limits::MAX_JOBS_PER_BATCH,
+    ) {
         return Err(HttpError::for_client_error(
-            None,
+            Some(ErrorCode::InvalidBatch.to_string()),
             StatusCode::BAD_REQUEST,
-            "too many inputs".into(),
+            msg,
         ));
     }
 
-    if new_job.tags.len() > 100 {
+    if batch.jobs.iter().any(|j| j.idempotency_key.is_some()) {
         return Err(HttpError::for_client_error(
-            None,
+            Some(ErrorCode::InvalidBatch.to_string()),
             StatusCode::BAD_REQUEST,
-            "too many tags".into(),
+            "idempotency keys are not supported for jobs in a batch".into(),
         ));
     }
 
-    if new_job.tags.iter().map(|(n, v)| n.len() + v.len()).sum::<usize>()
-        > 131072
-    {
+    if batch.jobs.iter().any(|j| j.unique_name) {
         return Err(HttpError::for_client_error(
-            None,
+            Some(ErrorCode::InvalidBatch.to_string()),
             StatusCode::BAD_REQUEST,
-            "total size of all tags is larger than 128KB".into(),
+            "unique names are not supported for jobs in a batch".into(),
         ));
     }
 
-    for n in new_job.tags.keys() {
-        /*
-         * Tag names must not be a zero-length string, and all characters must
-         * be ASCII: numbers, lowercase letters, periods, hypens, or
-         * underscores:
-         */
-        if n.is_empty()
-            || !n.chars().all(|c| {
-                c.is_ascii_digit()
-                    || c.is_ascii_lowercase()
-                    || c == '.'
-                    || c == '_'
-                    || c == '-'
-            })
-        {
-            return Err(HttpError::for_client_error(
-                None,
-                StatusCode::BAD_REQUEST,
-                "tag names must be [0-9a-z._-]+".into(),
-            ));
-        }
-    }
-
-    /*
-     * Resolve the target name to a specific target.  We store both so that it
-     * is subsequently clear what we were asked, and what we actually delivered.
-     */
-    let target = match c.db.target_resolve(&new_job.target).or_500()? {
-        Some(target) => target,
-        None => {
-            info!(log, "could not resolve target name {:?}", new_job.target);
-            return Err(HttpError::for_client_error(
-                None,
-                StatusCode::BAD_REQUEST,
-                format!("could not resolve target name {:?}", new_job.target),
-            ));
-        }
-    };
-    info!(log, "resolved target name {:?} to {:?}", new_job.target, target,);
-
     /*
-     * Confirm that the authenticated user is allowed to create jobs using the
-     * resolved target.
+     * Jobs in the batch may depend on jobs that appear earlier in the batch,
+     * referring to them by name as the IDs have not yet been assigned.  The
+     * database will resolve these names to the freshly created job IDs when
+     * the depend records are inserted.
      */
-    if let Some(required) = target.privilege.as_deref() {
-        if !owner.has_privilege(required) {
-            warn!(
-                log,
-                "user {} denied the use of target {:?} ({:?})",
-                owner.id,
-                target.name,
-                new_job.target,
-            );
-            return Err(HttpError::for_client_error(
-                None,
-                StatusCode::FORBIDDEN,
-                "you are not allowed to use that target".into(),
-            ));
-        }
+    let mut names: Vec<String> = Vec::new();
+    let mut cjs = Vec::new();
+    for new_job in batch.jobs {
+        let name = new_job.name.to_string();
+        cjs.push(job_submit_prepare(log, c, &owner, new_job, &names)?);
+        names.push(name);
     }
 
-    let tasks = new_job
-        .tasks
-        .iter()
-        .map(|ts| db::CreateTask {
-            name: ts.name.to_string(),
-            script: ts.script.to_string(),
-            env_clear: ts.env_clear,
-            env: ts.env.clone(),
-            user_id: ts.uid,
-            group_id: ts.gid,
-            workdir: ts.workdir.clone(),
-        })
-        .collect::<Vec<_>>();
-
-    let depends = new_job
-        .depends
-        .iter()
-        .map(|(name, ds)| {
-            Ok(db::CreateDepend {
-                name: name.to_string(),
-                prior_job: db::JobId::from_str(&ds.prior_job).or_500()?,
-                copy_outputs: ds.copy_outputs,
-                on_failed: ds.on_failed,
-                on_completed: ds.on_completed,
-            })
-        })
-        .collect::<DSResult<Vec<_>>>()?;
-
-    let output_rules = new_job
-        .output_rules
-        .iter()
-        .map(|rule| parse_output_rule(rule.as_str()))
-        .collect::<DSResult<Vec<_>>>()?;
-
-    let t =
-        c.db.job_create(
-            owner.id,
-            &new_job.name,
-            &new_job.target,
-            target.id,
-            tasks,
-            output_rules,
-            &new_job.inputs,
-            new_job.tags,
-            depends,
-        )
-        .or_500()?;
+    let jobs = c.db.jobs_create(owner.id, cjs).or_500()?;
+    info!(log, "user {} submitted a batch of {} jobs", owner.id, jobs.len());
 
-    Ok(HttpResponseCreated(JobSubmitResult { id: t.id.to_string() }))
+    Ok(HttpResponseCreated(JobSubmitBatchResult {
+        jobs: jobs
+            .iter()
+            .map(|j| JobSubmitResult { id: j.id.to_string() })
+            .collect(),
+    }))
 }
 
 #[endpoint {
@@ -822,16 +2406,19 @@ pub(crate) async fn job_upload_chunk(
     let p = path.into_inner();
 
     let owner = c.require_user(log, &rqctx.request).await?;
-    let job = c.load_job_for_user(log, &owner, p.job()?).await?;
+    let job =
+        c.load_job_for_user(log, &rqctx.request, &owner, p.job()?).await?;
 
     if !job.waiting {
         return Err(HttpError::for_client_error(
-            None,
+            Some(ErrorCode::JobNotWaiting.to_string()),
             StatusCode::CONFLICT,
             "cannot upload chunks for job that is not waiting".into(),
         ));
     }
 
+    c.check_chunk_size(chunk.as_bytes())?;
+
     let cid = c.write_chunk(job.id, chunk.as_bytes()).or_500()?;
     info!(
         log,
@@ -878,7 +2465,7 @@ pub(crate) async fn job_add_input(
     let add = add.into_inner();
     if add.name.contains('/') {
         return Err(HttpError::for_client_error(
-            None,
+            Some(ErrorCode::InvalidName.to_string()),
             StatusCode::BAD_REQUEST,
             "name must not be a path".into(),
         ));
@@ -887,7 +2474,7 @@ pub(crate) async fn job_add_input(
     let max = c.config.job.max_bytes_per_input();
     if add.size > max {
         return Err(HttpError::for_client_error(
-            None,
+            Some(ErrorCode::InputTooLarge.to_string()),
             StatusCode::BAD_REQUEST,
             format!(
                 "input file size {} bigger than allowed maximum {max} bytes",
@@ -904,7 +2491,8 @@ pub(crate) async fn job_add_input(
         .or_500()?;
     let commit_id = Ulid::from_str(add.commit_id.as_str()).or_500()?;
 
-    let job = c.load_job_for_user(log, &owner, p.job()?).await?;
+    let job =
+        c.load_job_for_user(log, &rqctx.request, &owner, p.job()?).await?;
 
     /*
      * The transition from waiting to queued occurs as soon as the last input is
@@ -917,12 +2505,13 @@ pub(crate) async fn job_add_input(
      */
     if !job.waiting && !c.files.commit_file_exists(job.id, commit_id) {
         return Err(HttpError::for_client_error(
-            None,
+            Some(ErrorCode::JobNotWaiting.to_string()),
             StatusCode::CONFLICT,
             "cannot add inputs to a job that is not waiting".into(),
         ));
     }
 
+    c.touch_chunks(job.id, &chunks);
     let res = c.files.commit_file(
         job.id,
         commit_id,
@@ -966,7 +2555,7 @@ pub(crate) async fn job_add_input(
                 e,
             );
             Err(HttpError::for_client_error(
-                Some("invalid".to_string()),
+                Some(ErrorCode::Invalid.to_string()),
                 StatusCode::BAD_REQUEST,
                 format!("{}", e),
             ))
@@ -974,6 +2563,51 @@ pub(crate) async fn job_add_input(
     }
 }
 
+#[endpoint {
+    method = GET,
+    path = "/1/jobs/{job}/input/{commit_id}",
+}]
+pub(crate) async fn job_add_input_status(
+    rqctx: RequestContext<Arc<Central>>,
+    path: TypedPath<JobInputCommitPath>,
+) -> DSResult<HttpResponseOk<JobAddInputResult>> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+
+    let owner = c.require_user(log, &rqctx.request).await?;
+
+    let p = path.into_inner();
+    let commit_id = p.commit_id()?;
+
+    let job =
+        c.load_job_for_user(log, &rqctx.request, &owner, p.job()?).await?;
+
+    /*
+     * Report on the commit without enqueueing anything, so that a client that
+     * lost track of an upload can poll for the outcome without needing to
+     * resubmit the original request.
+     */
+    match c.files.commit_file_status(job.id, commit_id) {
+        Some(Some(Ok(()))) => Ok(HttpResponseOk(JobAddInputResult {
+            complete: true,
+            error: None,
+        })),
+        Some(Some(Err(msg))) => Ok(HttpResponseOk(JobAddInputResult {
+            complete: true,
+            error: Some(msg),
+        })),
+        Some(None) => Ok(HttpResponseOk(JobAddInputResult {
+            complete: false,
+            error: None,
+        })),
+        None => Err(HttpError::for_client_error(
+            None,
+            StatusCode::NOT_FOUND,
+            format!("job {} has no input commit {}", job.id, commit_id),
+        )),
+    }
+}
+
 #[derive(Deserialize, JsonSchema)]
 pub(crate) struct JobAddInputSync {
     name: String,
@@ -996,34 +2630,33 @@ pub(crate) async fn job_add_input_sync(
     let p = path.into_inner();
 
     let owner = c.require_user(log, &rqctx.request).await?;
-    let job = c.load_job_for_user(log, &owner, p.job()?).await?;
+    let job =
+        c.load_job_for_user(log, &rqctx.request, &owner, p.job()?).await?;
 
     if !job.waiting {
         return Err(HttpError::for_client_error(
-            None,
+            Some(ErrorCode::JobNotWaiting.to_string()),
             StatusCode::CONFLICT,
             "cannot add inputs to a job that is not waiting".into(),
         ));
     }
 
     /*
-     * Individual inputs using the old blocking entrypoint are capped at 1GB to
-     * avoid request timeouts.  Larger inputs are possible using the new
-     * asynchronous job mechanism.
+     * Individual inputs using the old blocking entrypoint are capped to avoid
+     * request timeouts.  Larger inputs are possible using the new asynchronous
+     * job mechanism.
      */
     let add = add.into_inner();
-    let addsize = if add.size < 0 || add.size > 1024 * 1024 * 1024 {
-        return Err(HttpError::for_client_error(
-            Some("invalid".to_string()),
+    let addsize = limits::check_sync_file_size(add.size).map_err(|msg| {
+        HttpError::for_client_error(
+            Some(ErrorCode::Invalid.to_string()),
             StatusCode::BAD_REQUEST,
-            format!("size {} must be between 0 and 1073741824", add.size),
-        ));
-    } else {
-        add.size as u64
-    };
+            msg,
+        )
+    })?;
     if add.name.contains('/') {
         return Err(HttpError::for_client_error(
-            None,
+            Some(ErrorCode::InvalidName.to_string()),
             StatusCode::BAD_REQUEST,
             "name must not be a path".into(),
         ));
@@ -1036,8 +2669,8 @@ pub(crate) async fn job_add_input_sync(
         .collect::<Result<Vec<_>>>()
         .or_500()?;
 
-    let fid = match c.commit_file(job.id, &chunks, addsize) {
-        Ok(fid) => fid,
+    let (fid, sha256) = match c.commit_file(job.id, &chunks, addsize) {
+        Ok(res) => res,
         Err(e) => {
             warn!(
                 log,
@@ -1049,7 +2682,7 @@ pub(crate) async fn job_add_input_sync(
                 e,
             );
             return Err(HttpError::for_client_error(
-                Some("invalid".to_string()),
+                Some(ErrorCode::Invalid.to_string()),
                 StatusCode::BAD_REQUEST,
                 format!("{:?}", e),
             ));
@@ -1059,11 +2692,19 @@ pub(crate) async fn job_add_input_sync(
     /*
      * Insert a record in the database for this input object and report success.
      */
-    c.db.job_add_input(job.id, &add.name, fid, addsize).or_500()?;
+    c.db.job_add_input(job.id, &add.name, fid, addsize, &sha256).or_500()?;
 
     Ok(HttpResponseUpdatedNoContent())
 }
 
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct JobCancel {
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+const MAX_CANCEL_REASON_BYTES: usize = 1024;
+
 #[endpoint {
     method = POST,
     path = "/0/jobs/{job}/cancel",
@@ -1071,24 +2712,51 @@ pub(crate) async fn job_add_input_sync(
 pub(crate) async fn job_cancel(
     rqctx: RequestContext<Arc<Central>>,
     path: TypedPath<JobPath>,
+    body: TypedBody<JobCancel>,
 ) -> DSResult<HttpResponseUpdatedNoContent> {
     let c = rqctx.context();
     let log = &rqctx.log;
     let p = path.into_inner();
+    let b = body.into_inner();
+
+    let reason = b.reason.as_deref().map(str::trim).filter(|r| !r.is_empty());
+    if reason.map(|r| r.len() > MAX_CANCEL_REASON_BYTES).unwrap_or(false) {
+        return Err(HttpError::for_client_error(
+            Some(ErrorCode::Invalid.to_string()),
+            StatusCode::BAD_REQUEST,
+            format!(
+                "cancel reason must be at most {} bytes",
+                MAX_CANCEL_REASON_BYTES,
+            ),
+        ));
+    }
 
     let owner = c.require_user(log, &rqctx.request).await?;
-    let job = c.load_job_for_user(log, &owner, p.job()?).await?;
+    let job =
+        c.load_job_for_user(log, &rqctx.request, &owner, p.job()?).await?;
 
     if job.complete {
         return Err(HttpError::for_client_error(
-            None,
+            Some(ErrorCode::JobComplete.to_string()),
             StatusCode::CONFLICT,
             "cannot cancel a job that is already complete".into(),
         ));
     }
 
-    c.db.job_cancel(job.id).or_500()?;
+    c.db.job_cancel(
+        job.id,
+        &format!("job cancelled by user {}", owner.name),
+        reason,
+    )
+    .or_500()?;
     info!(log, "user {} cancelled job {}", owner.id, job.id);
+    c.audit(
+        &rqctx.request,
+        &owner.id.to_string(),
+        None,
+        "job.cancel",
+        Some(job.id.to_string()),
+    )?;
 
     Ok(HttpResponseUpdatedNoContent())
 }
@@ -1114,11 +2782,12 @@ pub(crate) async fn job_store_put(
     let b = body.into_inner();
 
     let owner = c.require_user(log, &rqctx.request).await?;
-    let job = c.load_job_for_user(log, &owner, p.job()?).await?;
+    let job =
+        c.load_job_for_user(log, &rqctx.request, &owner, p.job()?).await?;
 
     if job.complete {
         return Err(HttpError::for_client_error(
-            None,
+            Some(ErrorCode::JobComplete.to_string()),
             StatusCode::CONFLICT,
             "cannot update the store for a job that is already complete".into(),
         ));
@@ -1154,7 +2823,8 @@ pub(crate) async fn job_store_get_all(
     let p = path.into_inner();
 
     let owner = c.require_user(log, &rqctx.request).await?;
-    let job = c.load_job_for_user(log, &owner, p.job()?).await?;
+    let job =
+        c.load_job_for_user(log, &rqctx.request, &owner, p.job()?).await?;
 
     info!(log, "user {} fetch job {} store, all values", owner.id, job.id);
 
@@ -1211,6 +2881,7 @@ pub(crate) async fn job_store_get_all(
 pub(crate) struct WhoamiResult {
     id: String,
     name: String,
+    privileges: Vec<String>,
 }
 
 #[endpoint {
@@ -1223,15 +2894,24 @@ pub(crate) async fn whoami(
     let c = rqctx.context();
     let log = &rqctx.log;
 
+    /*
+     * If the request uses delegated authentication, this will be the user on
+     * whose behalf the request is being made, so we report the privileges of
+     * that user rather than those of the delegating user.
+     */
     let u = c.require_user(log, &rqctx.request).await?;
 
-    Ok(HttpResponseOk(WhoamiResult { id: u.id.to_string(), name: u.user.name }))
+    Ok(HttpResponseOk(WhoamiResult {
+        id: u.id.to_string(),
+        name: u.user.name,
+        privileges: u.privileges,
+    }))
 }
 
 #[cfg(test)]
 mod test {
     use super::super::prelude::*;
-    use super::parse_output_rule;
+    use super::{check_output_rules, parse_output_rule};
 
     #[test]
     fn test_parse_output_rule() -> Result<()> {
@@ -1333,4 +3013,51 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_check_output_rules() -> Result<()> {
+        let ok = ["/var/log/*.log", "%/tmp/*.txt", "!/tmp/junk.txt"]
+            .into_iter()
+            .map(parse_output_rule)
+            .collect::<DSResult<Vec<_>>>()?;
+        assert!(check_output_rules(&ok).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_output_rules_duplicate() -> Result<()> {
+        let rules = ["/var/log/*.log", "%/tmp/*.txt", "%/var/log/*.log"]
+            .into_iter()
+            .map(parse_output_rule)
+            .collect::<DSResult<Vec<_>>>()?;
+
+        match check_output_rules(&rules) {
+            Err(e) => {
+                println!("  yes, fail! {:?}", e.external_message);
+                assert!(e.external_message.contains("duplicate"));
+            }
+            Ok(()) => panic!("wanted failure for duplicate pattern"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_output_rules_ignore_and_require() -> Result<()> {
+        let rules = ["=/var/log/*.log", "!/var/log/*.log"]
+            .into_iter()
+            .map(parse_output_rule)
+            .collect::<DSResult<Vec<_>>>()?;
+
+        match check_output_rules(&rules) {
+            Err(e) => {
+                println!("  yes, fail! {:?}", e.external_message);
+                assert!(e.external_message.contains("ignored and required"));
+            }
+            Ok(()) => panic!("wanted failure for ignore/require conflict"),
+        }
+
+        Ok(())
+    }
 }
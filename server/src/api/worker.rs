@@ -65,6 +65,8 @@ pub(crate) struct WorkerPingTask {
     uid: u32,
     gid: u32,
     workdir: String,
+    always_run: bool,
+    timeout_seconds: Option<u64>,
 }
 
 #[derive(Serialize, JsonSchema)]
@@ -93,6 +95,11 @@ pub(crate) struct WorkerPingJob {
 #[derive(Serialize, JsonSchema)]
 pub(crate) struct WorkerPingResult {
     poweroff: bool,
+    /*
+     * Set when the job assigned to this worker has been cancelled, so that the
+     * agent can abandon any remaining work without waiting to be recycled.
+     */
+    cancel: bool,
     job: Option<WorkerPingJob>,
     factory_metadata: Option<metadata::FactoryMetadata>,
 }
@@ -115,6 +122,7 @@ pub(crate) async fn worker_ping(
 
     let factory_metadata = w.factory_metadata().or_500()?;
 
+    let mut cancel = false;
     let job = if w.wait_for_flush {
         /*
          * The factory may have event records (e.g., boot time console logs or
@@ -124,7 +132,15 @@ pub(crate) async fn worker_ping(
         None
     } else {
         let job = c.db.worker_job(w.id).or_500()?;
-        if let Some(job) = job {
+        if let Some(job) = job.as_ref().filter(|job| job.cancelled) {
+            /*
+             * There is no sense in handing out a job that has been cancelled.
+             * If the agent has already adopted it, it should stop work now.
+             */
+            info!(log, "worker {} job {} was cancelled", w.id, job.id);
+            cancel = true;
+            None
+        } else if let Some(job) = job {
             Some(WorkerPingJob {
                 id: job.id.to_string(),
                 name: job.name,
@@ -159,6 +175,8 @@ pub(crate) async fn worker_ping(
                             .as_deref()
                             .unwrap_or("/")
                             .to_string(),
+                        always_run: t.always_run,
+                        timeout_seconds: t.timeout_seconds.map(|s| s.0),
                     })
                     .collect::<Vec<_>>(),
                 inputs: c
@@ -180,6 +198,7 @@ pub(crate) async fn worker_ping(
 
     let res = WorkerPingResult {
         poweroff: w.recycle || w.deleted,
+        cancel,
         job,
         factory_metadata,
     };
@@ -210,7 +229,7 @@ pub(crate) async fn worker_job_input_download(
     res = res.header(CONTENT_TYPE, "application/octet-stream");
 
     let fr = c
-        .file_response(i.other_job.unwrap_or(i.job), i.id.unwrap())
+        .file_response(log, i.other_job.unwrap_or(i.job), i.id.unwrap())
         .await
         .or_500()?;
     info!(
@@ -223,7 +242,7 @@ pub(crate) async fn worker_job_input_download(
         fr.info
     );
 
-    res = res.header(CONTENT_LENGTH, fr.size);
+    res = fr.headers(res);
     Ok(res.body(fr.body)?)
 }
 
@@ -254,19 +273,279 @@ pub(crate) async fn worker_job_append(
 
     info!(log, "worker {} append to job {} stream {}", w.id, j.id, a.stream);
 
-    c.db.job_append_event(
+    append_event(c, &j, None, &a)?;
+
+    Ok(HttpResponseUpdatedNoContent())
+}
+
+/*
+ * Output from the job itself counts against the per-job limit on the size of
+ * the event log.  Other streams (e.g., "worker" or "task") are generated by
+ * buildomat and are always recorded.
+ */
+fn append_event(
+    c: &Central,
+    j: &db::Job,
+    task: Option<u32>,
+    a: &WorkerAppendJob,
+) -> DSResult<()> {
+    if a.stream == "stdout" || a.stream == "stderr" {
+        c.db.job_append_output_event(
+            j.id,
+            task,
+            &a.stream,
+            Utc::now(),
+            Some(a.time),
+            &a.payload,
+            c.config.job.max_event_bytes,
+        )
+        .or_500()
+    } else {
+        c.db.job_append_event(
+            j.id,
+            task,
+            &a.stream,
+            Utc::now(),
+            Some(a.time),
+            &a.payload,
+        )
+        .or_500()
+    }
+}
+
+/*
+ * Limits on the size of a batch of events appended in a single request:
+ */
+const MAX_APPEND_BATCH_EVENTS: usize = 1000;
+const MAX_APPEND_BATCH_BYTES: usize = 1024 * 1024;
+
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct WorkerAppendJobBatchEvent {
+    task: Option<u32>,
+    stream: String,
+    time: DateTime<Utc>,
+    payload: String,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct WorkerAppendJobBatch {
+    events: Vec<WorkerAppendJobBatchEvent>,
+}
+
+#[endpoint {
+    method = POST,
+    path = "/0/worker/job/{job}/append-batch",
+}]
+pub(crate) async fn worker_job_append_batch(
+    rqctx: RequestContext<Arc<Central>>,
+    path: TypedPath<JobPath>,
+    append: TypedBody<WorkerAppendJobBatch>,
+) -> DSResult<HttpResponseUpdatedNoContent> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+
+    let w = c.require_worker(log, &rqctx.request).await?;
+
+    let a = append.into_inner();
+    let j = c.db.job_by_str(&path.into_inner().job).or_500()?; /* XXX */
+    w.owns(log, &j)?;
+
+    let ntasks = c.db.job_tasks(j.id).or_500()?.len();
+    let events = batch_events(a.events, ntasks)?;
+
+    info!(
+        log,
+        "worker {} append {} events to job {}",
+        w.id,
+        events.len(),
         j.id,
-        None,
-        &a.stream,
-        Utc::now(),
-        Some(a.time),
-        &a.payload,
-    )
-    .or_500()?;
+    );
+
+    c.db.job_append_events(j.id, &events, c.config.job.max_event_bytes)
+        .or_500()?;
 
     Ok(HttpResponseUpdatedNoContent())
 }
 
+/*
+ * Check a batch of events against the limits on the number of events and the
+ * total size of their payloads, and prepare them for the database.  Each event
+ * that belongs to a task must refer to one of the "ntasks" tasks in the job.
+ */
+fn batch_events(
+    events: Vec<WorkerAppendJobBatchEvent>,
+    ntasks: usize,
+) -> DSResult<Vec<db::CreateJobEvent>> {
+    if events.len() > MAX_APPEND_BATCH_EVENTS {
+        return Err(HttpError::for_client_error(
+            Some(ErrorCode::InvalidBatch.to_string()),
+            StatusCode::BAD_REQUEST,
+            format!(
+                "a batch may contain at most {MAX_APPEND_BATCH_EVENTS} events"
+            ),
+        ));
+    }
+
+    let bytes = events.iter().map(|e| e.payload.len()).sum::<usize>();
+    if bytes > MAX_APPEND_BATCH_BYTES {
+        return Err(HttpError::for_client_error(
+            Some(ErrorCode::InvalidBatch.to_string()),
+            StatusCode::BAD_REQUEST,
+            format!(
+                "a batch may contain at most {MAX_APPEND_BATCH_BYTES} bytes \
+                of event payload"
+            ),
+        ));
+    }
+
+    if let Some(task) = events
+        .iter()
+        .filter_map(|e| e.task)
+        .find(|t| usize::try_from(*t).map_or(true, |t| t >= ntasks))
+    {
+        return Err(HttpError::for_client_error(
+            Some(ErrorCode::InvalidBatch.to_string()),
+            StatusCode::BAD_REQUEST,
+            format!("event for task {task}, but the job has {ntasks} tasks"),
+        ));
+    }
+
+    Ok(events
+        .into_iter()
+        .map(|e| db::CreateJobEvent {
+            task: e.task,
+            stream: e.stream,
+            time: Utc::now(),
+            time_remote: Some(e.time),
+            payload: e.payload,
+        })
+        .collect())
+}
+
+/*
+ * A frame in an event stream may be at most this large, which allows for a
+ * batch of the largest size plus the overhead of encoding it.
+ */
+const MAX_STREAM_FRAME_BYTES: usize = 2 * MAX_APPEND_BATCH_BYTES;
+
+#[derive(Deserialize)]
+struct WorkerStreamFrame {
+    seq: u64,
+    events: Vec<WorkerAppendJobBatchEvent>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct WorkerStreamAck {
+    /*
+     * The sequence number of the last frame that has been committed.
+     */
+    seq: u64,
+}
+
+/*
+ * Remove the next frame from the front of the buffer, if we have received all
+ * of it.
+ */
+fn stream_frame(buf: &mut Vec<u8>) -> DSResult<Option<WorkerStreamFrame>> {
+    let Some(len) = buf.get(0..4) else {
+        return Ok(None);
+    };
+    let len = u32::from_be_bytes(len.try_into().unwrap()) as usize;
+
+    if len > MAX_STREAM_FRAME_BYTES {
+        return Err(HttpError::for_client_error(
+            Some(ErrorCode::InvalidBatch.to_string()),
+            StatusCode::BAD_REQUEST,
+            format!(
+                "a stream frame may be at most {MAX_STREAM_FRAME_BYTES} bytes"
+            ),
+        ));
+    }
+    if buf.len() < 4 + len {
+        return Ok(None);
+    }
+
+    let frame = buf.drain(0..4 + len).skip(4).collect::<Vec<_>>();
+    serde_json::from_slice(&frame).map(Some).map_err(|e| {
+        HttpError::for_client_error(
+            Some(ErrorCode::InvalidBatch.to_string()),
+            StatusCode::BAD_REQUEST,
+            format!("invalid stream frame: {e}"),
+        )
+    })
+}
+
+/*
+ * Rather than make a request for each event or batch of events, a worker may
+ * send a stream of frames in the body of a single long-lived request.  Each
+ * frame is a 32-bit big-endian length, followed by that many bytes of JSON: a
+ * sequence number assigned by the worker, and a batch of events.  Frames are
+ * committed as they arrive, and when the worker ends the request the response
+ * acknowledges the sequence number of the last committed frame.  Frames that
+ * were already committed are ignored, so a worker that loses its connection
+ * can safely send again any frame that was not acknowledged.  A request with
+ * an empty body just reports the current acknowledgement.
+ */
+#[endpoint {
+    method = POST,
+    path = "/0/worker/job/{job}/stream",
+}]
+pub(crate) async fn worker_job_stream(
+    rqctx: RequestContext<Arc<Central>>,
+    path: TypedPath<JobPath>,
+    body: StreamingBody,
+) -> DSResult<HttpResponseOk<WorkerStreamAck>> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+
+    let w = c.require_worker(log, &rqctx.request).await?;
+
+    let j = c.db.job_by_str(&path.into_inner().job).or_500()?; /* XXX */
+    w.owns(log, &j)?;
+
+    let ntasks = c.db.job_tasks(j.id).or_500()?.len();
+
+    let mut body = Box::pin(body.into_stream());
+    let mut buf = Vec::new();
+    let mut committed = 0;
+    let mut ignored = 0;
+    while let Some(data) = body.next().await {
+        buf.extend_from_slice(&data?);
+
+        while let Some(frame) = stream_frame(&mut buf)? {
+            let events = batch_events(frame.events, ntasks)?;
+
+            if c.db
+                .job_append_stream_events(
+                    j.id,
+                    frame.seq,
+                    &events,
+                    c.config.job.max_event_bytes,
+                )
+                .or_500()?
+            {
+                committed += 1;
+            } else {
+                ignored += 1;
+            }
+        }
+    }
+
+    if !buf.is_empty() {
+        return Err(HttpError::for_client_error(
+            Some(ErrorCode::InvalidBatch.to_string()),
+            StatusCode::BAD_REQUEST,
+            "event stream ended part way through a frame".into(),
+        ));
+    }
+
+    let seq = c.db.job_stream_seq(j.id).or_500()?;
+    info!(log, "worker {} streamed events to job {}", w.id, j.id;
+        "committed" => committed, "ignored" => ignored, "seq" => seq);
+
+    Ok(HttpResponseOk(WorkerStreamAck { seq }))
+}
+
 #[endpoint {
     method = POST,
     path = "/0/worker/job/{job}/task/{task}/append",
@@ -295,22 +574,51 @@ pub(crate) async fn worker_task_append(
         a.stream
     );
 
-    c.db.job_append_event(
-        j.id,
-        Some(p.task),
-        &a.stream,
-        Utc::now(),
-        Some(a.time),
-        &a.payload,
-    )
-    .or_500()?;
+    append_event(c, &j, Some(p.task), &a)?;
 
     Ok(HttpResponseUpdatedNoContent())
 }
 
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub(crate) struct FailureSummary {
+    /*
+     * The exit status of the process, if it exited normally.
+     */
+    pub code: Option<i32>,
+    /*
+     * The signal that terminated the process, if any.
+     */
+    pub signal: Option<i32>,
+    /*
+     * A short human-readable description of the failure.
+     */
+    pub message: Option<String>,
+}
+
+impl From<FailureSummary> for db::FailureSummary {
+    fn from(f: FailureSummary) -> Self {
+        db::FailureSummary {
+            code: f.code,
+            signal: f.signal,
+            message: f.message,
+        }
+    }
+}
+
+impl From<db::FailureSummary> for FailureSummary {
+    fn from(f: db::FailureSummary) -> Self {
+        FailureSummary { code: f.code, signal: f.signal, message: f.message }
+    }
+}
+
 #[derive(Deserialize, JsonSchema)]
 pub(crate) struct WorkerCompleteTask {
     failed: bool,
+    /*
+     * If the task failed, details about the failure.
+     */
+    #[serde(default)]
+    failure: Option<FailureSummary>,
 }
 
 #[endpoint {
@@ -333,11 +641,115 @@ pub(crate) async fn worker_task_complete(
     w.owns(log, &j)?;
 
     info!(log, "worker {} complete job {} task {}", w.id, j.id, p.task);
-    c.db.task_complete(j.id, p.task, b.failed).or_500()?;
+    let failure = b.failure.map(db::FailureSummary::from);
+    c.db.task_complete(j.id, p.task, b.failed, failure.as_ref()).or_500()?;
 
     Ok(HttpResponseUpdatedNoContent())
 }
 
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct WorkerTaskEnv {
+    env: HashMap<String, String>,
+    /*
+     * The names of job store entries referred to by the task environment that
+     * do not exist.  If any are missing, the task must fail without running.
+     */
+    missing: Vec<String>,
+    /*
+     * The values of secret job store entries that were substituted into the
+     * environment.  These must not be echoed into the event log.
+     */
+    secrets: Vec<String>,
+}
+
+/*
+ * Secret values are redacted from task output by replacing every occurrence
+ * of the value.  A value of only a character or two would also match a great
+ * deal of unrelated output, and would not be much of a secret anyway, so such
+ * values are not redacted.
+ */
+const MIN_REDACT_BYTES: usize = 4;
+
+fn store_ref_regex() -> &'static regex::Regex {
+    static RE: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+
+    RE.get_or_init(|| {
+        regex::Regex::new(r"\$\{store:([A-Za-z0-9_]+)\}").unwrap()
+    })
+}
+
+/*
+ * The value of a task environment variable may refer to an entry in the job
+ * store with the syntax "${store:NAME}".  These references are resolved when
+ * the agent is about to start the task, so that an earlier task in the job may
+ * store a value for a later task to use.
+ */
+#[endpoint {
+    method = GET,
+    path = "/0/worker/job/{job}/task/{task}/env",
+}]
+pub(crate) async fn worker_task_env(
+    rqctx: RequestContext<Arc<Central>>,
+    path: TypedPath<JobTaskPath>,
+) -> DSResult<HttpResponseOk<WorkerTaskEnv>> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+
+    let w = c.require_worker(log, &rqctx.request).await?;
+
+    let p = path.into_inner();
+    let j = c.db.job_by_str(&p.job).or_500()?; /* XXX */
+    w.owns(log, &j)?;
+
+    let tasks = c.db.job_tasks(j.id).or_500()?;
+    let Some(t) = usize::try_from(p.task).ok().and_then(|i| tasks.get(i))
+    else {
+        return Err(HttpError::for_client_error(
+            None,
+            StatusCode::NOT_FOUND,
+            format!("task {} not found", p.task),
+        ));
+    };
+
+    let store = c.db.job_store(j.id).or_500()?;
+    let re = store_ref_regex();
+
+    let mut missing = Vec::new();
+    let mut secrets = Vec::new();
+    let env = t
+        .env
+        .0
+        .iter()
+        .map(|(k, v)| {
+            let v = re.replace_all(v, |cap: &regex::Captures| {
+                let name = &cap[1];
+                if let Some(e) = store.get(name) {
+                    if e.secret
+                        && e.value.len() >= MIN_REDACT_BYTES
+                        && !secrets.contains(&e.value)
+                    {
+                        secrets.push(e.value.to_string());
+                    }
+                    e.value.to_string()
+                } else {
+                    if !missing.iter().any(|m| m == name) {
+                        missing.push(name.to_string());
+                    }
+                    String::new()
+                }
+            });
+            (k.to_string(), v.into_owned())
+        })
+        .collect::<HashMap<_, _>>();
+    missing.sort();
+
+    info!(log, "worker {} job {} task {} environment", w.id, j.id, p.task;
+        "missing" => missing.len(),
+        "secrets" => secrets.len());
+
+    Ok(HttpResponseOk(WorkerTaskEnv { env, missing, secrets }))
+}
+
 #[derive(Serialize, JsonSchema)]
 pub(crate) struct WorkerJobStoreGet {
     value: Option<WorkerJobStoreValue>,
@@ -398,9 +810,60 @@ pub(crate) async fn worker_job_store_put(
     Ok(HttpResponseUpdatedNoContent())
 }
 
+/*
+ * A sample of resource usage for a job, reported periodically by the agent.
+ * Each value is the highest observed so far; any value the agent is unable to
+ * measure may be omitted.
+ */
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct WorkerJobMetrics {
+    rss_bytes: Option<u64>,
+    cpu_seconds: Option<u64>,
+    disk_bytes: Option<u64>,
+}
+
+#[endpoint {
+    method = POST,
+    path = "/0/worker/job/{job}/metrics",
+}]
+pub(crate) async fn worker_job_metrics(
+    rqctx: RequestContext<Arc<Central>>,
+    path: TypedPath<JobPath>,
+    body: TypedBody<WorkerJobMetrics>,
+) -> DSResult<HttpResponseUpdatedNoContent> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+
+    let w = c.require_worker(log, &rqctx.request).await?;
+
+    let b = body.into_inner();
+    let p = path.into_inner();
+    let j = c.db.job_by_str(&p.job).or_500()?; /* XXX */
+    w.owns(log, &j)?;
+
+    let samples = [
+        ("rss_bytes", b.rss_bytes),
+        ("cpu_seconds", b.cpu_seconds),
+        ("disk_bytes", b.disk_bytes),
+    ]
+    .into_iter()
+    .filter_map(|(n, v)| v.map(|v| (n, v)))
+    .collect::<Vec<_>>();
+
+    c.db.job_metrics_update(j.id, &samples).or_500()?;
+
+    Ok(HttpResponseUpdatedNoContent())
+}
+
 #[derive(Deserialize, JsonSchema)]
 pub(crate) struct WorkerCompleteJob {
     failed: bool,
+    /*
+     * If the job failed for a reason other than the failure of a task, details
+     * about the failure.
+     */
+    #[serde(default)]
+    failure: Option<FailureSummary>,
 }
 
 #[endpoint {
@@ -422,7 +885,11 @@ pub(crate) async fn worker_job_complete(
     let j = c.db.job_by_str(&p.job).or_500()?; /* XXX */
     w.owns(log, &j)?;
 
-    if let Err(e) = c.complete_job(log, j.id, b.failed) {
+    let failure_reason =
+        b.failure.map(db::FailureSummary::from).and_then(|f| f.describe());
+    if let Err(e) =
+        c.complete_job(log, j.id, b.failed, failure_reason.as_deref())
+    {
         error!(log, "worker {} cannot complete job {}: {e}", w.id, j.id);
         return Err(HttpError::for_client_error(
             None,
@@ -457,6 +924,8 @@ pub(crate) async fn worker_job_upload_chunk(
     let j = c.db.job_by_str(&path.into_inner().job).or_500()?; /* XXX */
     w.owns(log, &j)?;
 
+    c.check_chunk_size(chunk.as_bytes())?;
+
     let cid = c.write_chunk(j.id, chunk.as_bytes()).or_500()?;
     info!(
         log,
@@ -473,6 +942,14 @@ pub(crate) async fn worker_job_upload_chunk(
 #[derive(Serialize, JsonSchema)]
 pub(crate) struct WorkerJobQuota {
     max_bytes_per_output: u64,
+    /*
+     * Outputs are uploaded as a sequence of chunks, each no larger than this
+     * size, which are then committed together with the total size; see
+     * "worker_job_add_output".  Any output larger than a single chunk must be
+     * split, so that neither the agent nor the server need to hold an entire
+     * file in memory.
+     */
+    max_bytes_per_chunk: u64,
 }
 
 #[endpoint {
@@ -493,6 +970,7 @@ pub(crate) async fn worker_job_quota(
      */
     Ok(HttpResponseOk(WorkerJobQuota {
         max_bytes_per_output: c.config.job.max_bytes_per_output(),
+        max_bytes_per_chunk: c.config.job.max_chunk_bytes,
     }))
 }
 
@@ -502,6 +980,30 @@ pub(crate) struct WorkerAddOutput {
     size: u64,
     chunks: Vec<String>,
     commit_id: String,
+    /*
+     * The MIME type of the output, if known; e.g., "application/json".  This
+     * is used as the default content type when the output is downloaded.  The
+     * buildomat agent does not presently determine a type for the files it
+     * uploads, so this is only provided by other workers.
+     */
+    content_type: Option<String>,
+}
+
+fn check_content_type(ct: Option<&str>) -> DSResult<()> {
+    if let Some(ct) = ct {
+        if ct.len() > 255
+            || !ct.contains('/')
+            || hyper::header::HeaderValue::from_str(ct).is_err()
+        {
+            return Err(HttpError::for_client_error(
+                Some(ErrorCode::Invalid.to_string()),
+                StatusCode::BAD_REQUEST,
+                format!("invalid content type {ct:?}"),
+            ));
+        }
+    }
+
+    Ok(())
 }
 
 #[derive(Serialize, JsonSchema)]
@@ -547,10 +1049,16 @@ pub(crate) async fn worker_job_add_output(
         ));
     }
 
+    check_content_type(add.content_type.as_deref())?;
+
+    c.touch_chunks(j.id, &chunks);
     let res = c.files.commit_file(
         j.id,
         commit_id,
-        crate::files::FileKind::Output { path: add.path.to_string() },
+        crate::files::FileKind::Output {
+            path: add.path.to_string(),
+            content_type: add.content_type.clone(),
+        },
         add.size,
         chunks,
     );
@@ -603,6 +1111,11 @@ pub(crate) struct WorkerAddOutputSync {
     path: String,
     size: i64,
     chunks: Vec<String>,
+    /*
+     * As for WorkerAddOutput.
+     */
+    #[serde(default)]
+    content_type: Option<String>,
 }
 
 #[endpoint {
@@ -619,20 +1132,19 @@ pub(crate) async fn worker_job_add_output_sync(
     let log = &rqctx.log;
 
     /*
-     * Individual outputs using the old blocking entrypoint are capped at 1GB to
-     * avoid request timeouts.  Larger outputs are possible using the new
+     * Individual outputs using the old blocking entrypoint are capped to avoid
+     * request timeouts.  Larger outputs are possible using the new
      * asynchronous job mechanism.
      */
     let add = add.into_inner();
-    let addsize = if add.size < 0 || add.size > 1024 * 1024 * 1024 {
-        return Err(HttpError::for_client_error(
+    let addsize = limits::check_sync_file_size(add.size).map_err(|msg| {
+        HttpError::for_client_error(
             Some("invalid".to_string()),
             StatusCode::BAD_REQUEST,
-            format!("size {} must be between 0 and 1073741824", add.size),
-        ));
-    } else {
-        add.size as u64
-    };
+            msg,
+        )
+    })?;
+    check_content_type(add.content_type.as_deref())?;
     let w = c.require_worker(log, &rqctx.request).await?;
     let j = c.db.job_by_str(&path.into_inner().job).or_500()?; /* XXX */
     w.owns(log, &j)?;
@@ -644,8 +1156,8 @@ pub(crate) async fn worker_job_add_output_sync(
         .collect::<Result<Vec<_>>>()
         .or_500()?;
 
-    let fid = match c.commit_file(j.id, &chunks, addsize) {
-        Ok(fid) => fid,
+    let (fid, sha256) = match c.commit_file(j.id, &chunks, addsize) {
+        Ok(res) => res,
         Err(e) => {
             warn!(
                 log,
@@ -668,7 +1180,15 @@ pub(crate) async fn worker_job_add_output_sync(
      * Insert a record in the database for this output object and report
      * success.
      */
-    c.db.job_add_output(j.id, &add.path, fid, addsize).or_500()?;
+    c.db.job_add_output(
+        j.id,
+        &add.path,
+        fid,
+        addsize,
+        &sha256,
+        add.content_type.as_deref(),
+    )
+    .or_500()?;
 
     Ok(HttpResponseUpdatedNoContent())
 }
@@ -704,3 +1224,61 @@ pub(crate) async fn worker_bootstrap(
         unauth_response()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::super::prelude::*;
+    use super::{stream_frame, MAX_STREAM_FRAME_BYTES};
+
+    fn frame(json: &str) -> Vec<u8> {
+        let mut buf = (json.len() as u32).to_be_bytes().to_vec();
+        buf.extend_from_slice(json.as_bytes());
+        buf
+    }
+
+    #[test]
+    fn stream_frame_partial() -> Result<()> {
+        let full = frame(
+            r#"{"seq":3,"events":[{"task":null,"stream":"stdout",
+            "time":"2024-01-01T00:00:00Z","payload":"hi"}]}"#,
+        );
+
+        /*
+         * Until the whole frame has arrived, nothing is removed from the
+         * buffer.
+         */
+        for n in [0, 2, 4, full.len() - 1] {
+            let mut buf = full[..n].to_vec();
+            assert!(stream_frame(&mut buf).unwrap().is_none());
+            assert_eq!(buf.len(), n);
+        }
+
+        let mut buf = full.clone();
+        buf.extend_from_slice(&full[..6]);
+        let f = stream_frame(&mut buf).unwrap().expect("frame");
+        assert_eq!(f.seq, 3);
+        assert_eq!(f.events.len(), 1);
+        assert_eq!(f.events[0].payload, "hi");
+        assert_eq!(buf, full[..6]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn stream_frame_too_large() {
+        let len = u32::try_from(MAX_STREAM_FRAME_BYTES + 1).unwrap();
+        let mut buf = len.to_be_bytes().to_vec();
+
+        let e = stream_frame(&mut buf).err().expect("error");
+        assert_eq!(e.status_code, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn stream_frame_bad_json() {
+        for json in ["{", r#"{"seq":1}"#, r#"{"seq":-1,"events":[]}"#] {
+            let mut buf = frame(json);
+            let e = stream_frame(&mut buf).err().expect("error");
+            assert_eq!(e.status_code, StatusCode::BAD_REQUEST);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+/*
+ * Copyright 2023 Oxide Computer Company
+ */
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+#[allow(unused_imports)]
+use slog::{error, info, warn, Logger};
+
+use crate::Central;
+
+struct CachedArchive {
+    path: std::path::PathBuf,
+    size: u64,
+    accessed: SystemTime,
+}
+
+async fn archive_cache_clean_one(
+    log: &Logger,
+    c: &Central,
+    max_bytes: u64,
+) -> Result<()> {
+    /*
+     * Get a list of the archive files we have cached locally.  The
+     * modification time of each file is updated whenever it is loaded, so it
+     * reflects the time of last access.
+     */
+    let mut cached = Vec::new();
+    let mut dir = std::fs::read_dir(c.archive_dir()?)?;
+
+    while let Some(ent) = dir.next().transpose()? {
+        if !ent.file_type()?.is_file() {
+            error!(log, "unexpected item in archive dir: {:?}", ent.path());
+            continue;
+        }
+
+        /*
+         * Skip any temporary files that are in the process of being written.
+         */
+        if ent.file_name().to_str().map(|n| n.starts_with('.')).unwrap_or(true)
+        {
+            continue;
+        }
+
+        let md = ent.metadata()?;
+        cached.push(CachedArchive {
+            path: ent.path(),
+            size: md.len(),
+            accessed: md.modified()?,
+        });
+    }
+
+    let mut total = cached.iter().map(|ca| ca.size).sum::<u64>();
+    if total <= max_bytes {
+        return Ok(());
+    }
+
+    /*
+     * The cache is over budget.  Evict the least recently accessed files
+     * first, until we are back under the limit.  Archives can always be
+     * fetched again from the object store if they are needed later.
+     */
+    cached.sort_by(|a, b| a.accessed.cmp(&b.accessed));
+
+    for ca in cached {
+        if total <= max_bytes {
+            break;
+        }
+
+        match std::fs::remove_file(&ca.path) {
+            Ok(()) => {
+                info!(log, "evicted archive cache file {:?}", ca.path;
+                    "size" => ca.size);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => {
+                error!(log, "could not evict {:?}: {:?}", ca.path, e);
+                continue;
+            }
+        }
+
+        total = total.saturating_sub(ca.size);
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn archive_cache_clean(
+    log: Logger,
+    c: Arc<Central>,
+) -> Result<()> {
+    let delay = Duration::from_secs(61);
+
+    info!(log, "start archive cache clean task");
+
+    loop {
+        if let Some(max_bytes) = c.config.archive.cache_max_bytes {
+            if let Err(e) = archive_cache_clean_one(&log, &c, max_bytes).await {
+                error!(log, "archive cache clean task error: {:?}", e);
+            }
+        }
+
+        if !c.idle(delay).await {
+            info!(log, "stop archive cache clean task");
+            return Ok(());
+        }
+    }
+}
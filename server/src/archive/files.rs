@@ -7,11 +7,13 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{bail, Result};
+use aws_smithy_http::byte_stream::ByteStream;
+use aws_smithy_http::result::SdkError;
 use chrono::prelude::*;
 #[allow(unused_imports)]
 use slog::{debug, error, info, warn, Logger};
 
-use crate::{db, Central};
+use crate::{db, metrics, Central};
 
 async fn archive_files_one(
     log: &Logger,
@@ -36,8 +38,7 @@ async fn archive_files_one(
          */
         let p = c.file_path(jf.job, jf.id)?;
 
-        let f = tokio::fs::File::open(&p).await?;
-        let file_size = f.metadata().await?.len();
+        let file_size = tokio::fs::metadata(&p).await?.len();
         if file_size != jf.size.0 {
             bail!(
                 "local file {:?} size {} != database size {}",
@@ -47,19 +48,58 @@ async fn archive_files_one(
             );
         }
 
-        let stream = aws_smithy_http::byte_stream::ByteStream::read_from()
-            .file(f)
-            .build()
+        if file_size > c.config.storage.multipart_threshold {
+            /*
+             * Large files are uploaded in several parts.
+             */
+            crate::s3::upload_multipart(
+                log,
+                s3,
+                &c.config.storage,
+                &key,
+                &p,
+                file_size,
+                c.config.storage.output_storage_class(),
+            )
             .await?;
 
-        let res = s3
-            .put_object()
-            .bucket(&c.config.storage.bucket)
-            .key(&key)
-            .content_length(file_size.try_into()?)
-            .body(stream)
-            .send()
-            .await?;
+            info!(
+                log,
+                "uploaded file {} from job {} at {}:{}",
+                jf.id, jf.job, c.config.storage.bucket, key;
+                "multipart" => true,
+            );
+
+            c.db.job_file_mark_archived(&jf, Utc::now())?;
+            continue;
+        }
+
+        /*
+         * The request body is consumed by each attempt, so we must open the
+         * file again if the upload needs to be retried.
+         */
+        let res = crate::s3::retry(
+            log,
+            &c.config.storage.retry,
+            "file upload",
+            || async {
+                let stream = ByteStream::read_from()
+                    .path(&p)
+                    .build()
+                    .await
+                    .map_err(SdkError::construction_failure)?;
+
+                s3.put_object()
+                    .bucket(&c.config.storage.bucket)
+                    .key(&key)
+                    .set_storage_class(c.config.storage.output_storage_class())
+                    .content_length(file_size.try_into().unwrap())
+                    .body(stream)
+                    .send()
+                    .await
+            },
+        )
+        .await?;
 
         info!(
             log,
@@ -207,6 +247,8 @@ async fn clean_files_one(log: &Logger, c: &Central) -> Result<()> {
         }
     }
 
+    metrics::FILE_BYTES.set(metrics::measure_dir(&c.file_dir()?)?);
+
     Ok(())
 }
 
@@ -224,6 +266,9 @@ pub(crate) async fn archive_files(log: Logger, c: Arc<Central>) -> Result<()> {
             error!(log, "file clean task error: {:?}", e);
         }
 
-        tokio::time::sleep(delay).await;
+        if !c.idle(delay).await {
+            info!(log, "stop file archive task");
+            return Ok(());
+        }
     }
 }
@@ -6,7 +6,7 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{bail, Result};
 use chrono::prelude::*;
 use serde::{Deserialize, Serialize};
 #[allow(unused_imports)]
@@ -52,6 +52,16 @@ struct ArchivedTask {
     pub workdir: Option<String>,
     pub complete: bool,
     pub failed: bool,
+    #[serde(default)]
+    pub failure_code: Option<i32>,
+    #[serde(default)]
+    pub failure_signal: Option<i32>,
+    #[serde(default)]
+    pub failure_message: Option<String>,
+    #[serde(default)]
+    pub always_run: bool,
+    #[serde(default)]
+    pub timeout_seconds: Option<u64>,
 }
 
 impl From<db::Task> for ArchivedTask {
@@ -75,6 +85,11 @@ impl From<db::Task> for ArchivedTask {
             workdir,
             complete,
             failed,
+            failure_code,
+            failure_signal,
+            failure_message,
+            always_run,
+            timeout_seconds,
         } = input;
 
         ArchivedTask {
@@ -88,6 +103,11 @@ impl From<db::Task> for ArchivedTask {
             workdir,
             complete,
             failed,
+            failure_code,
+            failure_signal,
+            failure_message,
+            always_run,
+            timeout_seconds: timeout_seconds.map(|s| s.0),
         }
     }
 }
@@ -142,15 +162,17 @@ impl From<db::JobEvent> for ArchivedEvent {
 struct ArchivedOutput {
     pub path: String,
     pub file: ArchivedFile,
+    #[serde(default)]
+    pub content_type: Option<String>,
 }
 
 impl TryFrom<(db::JobOutput, db::JobFile)> for ArchivedOutput {
     type Error = anyhow::Error;
 
     fn try_from(input: (db::JobOutput, db::JobFile)) -> Result<Self> {
-        let db::JobOutput { job: _, id: _, path } = input.0;
+        let db::JobOutput { job: _, id: _, path, content_type } = input.0;
 
-        Ok(ArchivedOutput { path, file: input.1.try_into()? })
+        Ok(ArchivedOutput { path, file: input.1.try_into()?, content_type })
     }
 }
 
@@ -181,6 +203,8 @@ struct ArchivedFile {
     id: String,
     size: u64,
     time_archived: String,
+    #[serde(default)]
+    sha256: Option<String>,
 }
 
 impl ArchivedFile {
@@ -201,7 +225,7 @@ impl TryFrom<db::JobFile> for ArchivedFile {
     type Error = anyhow::Error;
 
     fn try_from(input: db::JobFile) -> Result<Self> {
-        let db::JobFile { job, id, size, time_archived } = input;
+        let db::JobFile { job, id, size, time_archived, sha256 } = input;
 
         let Some(time_archived) = time_archived else {
             bail!("job file not yet archived");
@@ -212,6 +236,7 @@ impl TryFrom<db::JobFile> for ArchivedFile {
             id: id.to_string(),
             size: size.0,
             time_archived: time_archived.to_archive(),
+            sha256,
         })
     }
 }
@@ -283,7 +308,7 @@ impl From<db::JobStore> for ArchivedStoreEntry {
             /*
              * Elide secret values from the archive:
              */
-            value: if secret { Some(value) } else { None },
+            value: if secret { None } else { Some(value) },
             secret,
             source,
             time_update: time_update.to_archive(),
@@ -298,6 +323,8 @@ pub struct ArchivedDepend {
     on_failed: bool,
     on_completed: bool,
     satisfied: bool,
+    #[serde(default)]
+    copy_outputs_globs: Option<Vec<String>>,
 }
 
 impl From<db::JobDepend> for ArchivedDepend {
@@ -310,6 +337,7 @@ impl From<db::JobDepend> for ArchivedDepend {
             on_failed,
             on_completed,
             satisfied,
+            copy_outputs_globs,
         } = input;
 
         ArchivedDepend {
@@ -318,6 +346,7 @@ impl From<db::JobDepend> for ArchivedDepend {
             on_failed,
             on_completed,
             satisfied,
+            copy_outputs_globs: copy_outputs_globs.map(Into::into),
         }
     }
 }
@@ -388,6 +417,10 @@ pub struct ArchivedJob {
      */
     failed: bool,
     cancelled: bool,
+    #[serde(default)]
+    cancel_reason: Option<String>,
+    #[serde(default)]
+    failure_reason: Option<String>,
 
     /*
      * Store both the user ID and the login name for the user at the time the
@@ -421,28 +454,106 @@ pub struct ArchivedJob {
     inputs: Vec<ArchivedInput>,
     outputs: Vec<ArchivedOutput>,
     times: HashMap<String, String>,
+    #[serde(default)]
+    time_create: Option<String>,
+    #[serde(default)]
+    metrics: HashMap<String, u64>,
     events: Vec<ArchivedEvent>,
     store: HashMap<String, ArchivedStoreEntry>,
     depends: HashMap<String, ArchivedDepend>,
 }
 
+/*
+ * Archives are compressed with gzip before they are stored.  Archives created
+ * before compression was introduced are plain JSON, so we use the gzip magic
+ * number to determine whether we need to decompress the data we read back.
+ */
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+pub fn is_gzip(buf: &[u8]) -> bool {
+    buf.starts_with(&GZIP_MAGIC)
+}
+
 impl ArchivedJob {
     pub fn is_valid(&self) -> bool {
         self.v == "1"
     }
 
+    /**
+     * Parse an archive from either a gzip-compressed or a plain JSON buffer.
+     */
+    pub fn from_bytes(buf: &[u8]) -> Result<ArchivedJob> {
+        if is_gzip(buf) {
+            let gz = flate2::read::GzDecoder::new(buf);
+            Ok(serde_json::from_reader(std::io::BufReader::new(gz))?)
+        } else {
+            Ok(serde_json::from_slice(buf)?)
+        }
+    }
+
+    /**
+     * Serialise the archive as gzip-compressed JSON, either pretty-printed or
+     * in the compact form.  Returns the compressed bytes, and the size of the
+     * JSON before it was compressed.
+     */
+    pub fn to_gzip_bytes(&self, pretty: bool) -> Result<(Vec<u8>, u64)> {
+        /*
+         * Count the bytes of JSON on their way into the compressor.
+         */
+        struct Counted<W>(W, u64);
+
+        impl<W: std::io::Write> std::io::Write for Counted<W> {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                let n = self.0.write(buf)?;
+                self.1 += n as u64;
+                Ok(n)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                self.0.flush()
+            }
+        }
+
+        let mut w = Counted(
+            flate2::write::GzEncoder::new(
+                Vec::new(),
+                flate2::Compression::default(),
+            ),
+            0,
+        );
+        if pretty {
+            serde_json::to_writer_pretty(&mut w, self)?;
+        } else {
+            serde_json::to_writer(&mut w, self)?;
+        }
+
+        let Counted(gz, size) = w;
+        Ok((gz.finish()?, size))
+    }
+
     pub fn version(&self) -> &str {
         &self.v
     }
 
-    pub fn job_events(&self, minseq: usize) -> Result<Vec<db::JobEvent>> {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn job_events(
+        &self,
+        minseq: usize,
+        task: Option<u32>,
+        limit: Option<usize>,
+    ) -> Result<Vec<db::JobEvent>> {
         let job: db::JobId = self.id.parse()?;
 
         Ok(self
             .events
             .iter()
             .enumerate()
-            .filter(|(seq, _)| *seq >= minseq)
+            .skip(minseq)
+            .filter(|(_, ev)| task.is_none() || ev.task == task)
+            .take(limit.unwrap_or(usize::MAX))
             .map(|(seq, ev)| {
                 Ok(db::JobEvent {
                     job,
@@ -472,6 +583,7 @@ impl ArchivedJob {
                     job,
                     path: f.path.clone(),
                     id: f.file.id()?,
+                    content_type: f.content_type.clone(),
                 };
 
                 let file = db::JobFile {
@@ -479,6 +591,7 @@ impl ArchivedJob {
                     id: f.file.id()?,
                     size: db::DataSize(f.file.size),
                     time_archived: Some(f.file.time_archived()?),
+                    sha256: f.file.sha256.clone(),
                 };
 
                 Ok((output, file))
@@ -486,7 +599,49 @@ impl ArchivedJob {
             .collect::<Result<Vec<_>>>()?)
     }
 
-    pub fn job_output(&self, id: db::JobFileId) -> Result<db::JobOutput> {
+    pub fn job_inputs(
+        &self,
+    ) -> Result<Vec<(db::JobInput, Option<db::JobFile>)>> {
+        let job: db::JobId = self.id.parse()?;
+
+        Ok(self
+            .inputs
+            .iter()
+            .map(|i| {
+                let input = db::JobInput {
+                    job,
+                    name: i.name.clone(),
+                    id: i.file.as_ref().map(|f| f.id()).transpose()?,
+                    other_job: i
+                        .other_job_id
+                        .as_deref()
+                        .map(str::parse::<db::JobId>)
+                        .transpose()?,
+                };
+
+                let file = i
+                    .file
+                    .as_ref()
+                    .map(|f| {
+                        Ok(db::JobFile {
+                            job: f.job()?,
+                            id: f.id()?,
+                            size: db::DataSize(f.size),
+                            time_archived: Some(f.time_archived()?),
+                            sha256: f.sha256.clone(),
+                        })
+                    })
+                    .transpose()?;
+
+                Ok((input, file))
+            })
+            .collect::<Result<Vec<_>>>()?)
+    }
+
+    pub fn job_output(
+        &self,
+        id: db::JobFileId,
+    ) -> Result<Option<db::JobOutput>> {
         let job: db::JobId = self.id.parse()?;
 
         self.outputs
@@ -497,9 +652,10 @@ impl ArchivedJob {
                     job,
                     path: f.path.clone(),
                     id: f.file.id()?,
+                    content_type: f.content_type.clone(),
                 })
             })
-            .ok_or_else(|| anyhow!("file {id} for job {job} not in archive"))?
+            .transpose()
     }
 
     pub fn times(&self) -> Result<HashMap<String, DateTime<Utc>>> {
@@ -510,6 +666,47 @@ impl ArchivedJob {
             .collect::<Result<HashMap<_, _>>>()?)
     }
 
+    pub fn metrics(&self) -> Result<HashMap<String, u64>> {
+        Ok(self.metrics.clone())
+    }
+
+    /**
+     * Reconstruct the metric records for this job.  The archive does not keep
+     * the time at which each metric was last updated, so the caller must
+     * provide one.
+     */
+    pub fn job_metrics(
+        &self,
+        time_update: DateTime<Utc>,
+    ) -> Result<Vec<db::JobMetric>> {
+        let job: db::JobId = self.id.parse()?;
+
+        Ok(self
+            .metrics
+            .iter()
+            .map(|(name, value)| db::JobMetric {
+                job,
+                name: name.clone(),
+                value: db::DataSize(*value),
+                time_update: db::IsoDate(time_update),
+            })
+            .collect())
+    }
+
+    /**
+     * Stream statistics are not stored in the archive, but are cheap enough
+     * to compute from the events when needed.
+     */
+    pub fn stream_stats(&self) -> HashMap<String, db::StreamStats> {
+        let mut out: HashMap<String, db::StreamStats> = HashMap::new();
+
+        for ev in self.events.iter() {
+            out.entry(ev.stream.clone()).or_default().add(&ev.payload);
+        }
+
+        out
+    }
+
     pub fn tags(&self) -> Result<HashMap<String, String>> {
         Ok(self.tags.clone())
     }
@@ -559,6 +756,11 @@ impl ArchivedJob {
                     workdir,
                     complete,
                     failed,
+                    failure_code,
+                    failure_signal,
+                    failure_message,
+                    always_run,
+                    timeout_seconds,
                 } = t;
 
                 Ok(db::Task {
@@ -573,6 +775,11 @@ impl ArchivedJob {
                     workdir: workdir.clone(),
                     failed: *failed,
                     complete: *complete,
+                    failure_code: *failure_code,
+                    failure_signal: *failure_signal,
+                    failure_message: failure_message.clone(),
+                    always_run: *always_run,
+                    timeout_seconds: timeout_seconds.map(db::Seconds),
                 })
             })
             .collect::<Result<Vec<_>>>()?)
@@ -581,6 +788,120 @@ impl ArchivedJob {
     pub fn store(&self) -> &HashMap<String, ArchivedStoreEntry> {
         &self.store
     }
+
+    /**
+     * Archives written by older versions of the server included the values of
+     * secret store entries.  Remove any such values from this archive,
+     * returning the number of values that were removed.
+     */
+    pub fn scrub_secrets(&mut self) -> usize {
+        let mut n = 0;
+        for ent in self.store.values_mut() {
+            if ent.secret && ent.value.take().is_some() {
+                n += 1;
+            }
+        }
+        n
+    }
+
+    /**
+     * Reconstruct the store records for this job.  The values of secret
+     * entries are not kept in the archive, so those entries cannot be
+     * restored and are omitted.
+     */
+    pub fn job_store(&self) -> Result<Vec<db::JobStore>> {
+        let job: db::JobId = self.id.parse()?;
+
+        self.store
+            .iter()
+            .filter_map(|(name, e)| Some((name, e, e.value.as_ref()?)))
+            .map(|(name, e, value)| {
+                Ok(db::JobStore {
+                    job,
+                    name: name.clone(),
+                    value: value.clone(),
+                    secret: e.secret,
+                    source: e.source.clone(),
+                    time_update: e.time_update()?,
+                })
+            })
+            .collect()
+    }
+
+    pub fn depends(&self) -> Result<Vec<db::JobDepend>> {
+        let job: db::JobId = self.id.parse()?;
+
+        Ok(self
+            .depends
+            .iter()
+            .map(|(name, d)| {
+                let ArchivedDepend {
+                    prior_job,
+                    copy_outputs,
+                    on_failed,
+                    on_completed,
+                    satisfied,
+                    copy_outputs_globs,
+                } = d;
+
+                Ok(db::JobDepend {
+                    job,
+                    name: name.clone(),
+                    prior_job: prior_job.parse()?,
+                    copy_outputs: *copy_outputs,
+                    on_failed: *on_failed,
+                    on_completed: *on_completed,
+                    satisfied: *satisfied,
+                    copy_outputs_globs: copy_outputs_globs
+                        .clone()
+                        .map(Into::into),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?)
+    }
+
+    /**
+     * Reconstruct the database record for this job, for use when importing an
+     * archived job back into the database.  The job is necessarily complete,
+     * as it would otherwise not have been archived.
+     */
+    pub fn job(&self, time_archived: DateTime<Utc>) -> Result<db::Job> {
+        Ok(db::Job {
+            id: self.id.parse()?,
+            owner: self.owner_id.parse()?,
+            name: self.name.clone(),
+            target: self.target_name.clone(),
+            complete: true,
+            failed: self.failed,
+            worker: self
+                .worker_id
+                .as_deref()
+                .map(str::parse::<db::WorkerId>)
+                .transpose()?,
+            waiting: false,
+            target_id: Some(self.target_id.parse()?),
+            cancelled: self.cancelled,
+            time_archived: Some(db::IsoDate(time_archived)),
+            time_create: self
+                .time_create
+                .as_ref()
+                .map(|t| t.from_archive())
+                .transpose()?,
+            /*
+             * Only output from the job itself counts against the limit on the
+             * size of the event log.
+             */
+            event_bytes: db::DataSize(
+                self.events
+                    .iter()
+                    .filter(|ev| ev.stream == "stdout" || ev.stream == "stderr")
+                    .map(|ev| ev.payload.len() as u64)
+                    .sum(),
+            ),
+            cancel_reason: self.cancel_reason.clone(),
+            failure_reason: self.failure_reason.clone(),
+        })
+    }
 }
 
 async fn archive_jobs_one(log: &Logger, c: &Central) -> Result<bool> {
@@ -606,9 +927,14 @@ async fn archive_jobs_one(log: &Logger, c: &Central) -> Result<bool> {
     } else if c.config.job.auto_archive {
         /*
          * Otherwise, if auto-archiving is enabled, archive the next as-yet
-         * unarchived job.
+         * unarchived job that completed long enough ago.
          */
-        if let Some(job) = c.db.job_next_unarchived()? {
+        let delay = Duration::from_secs(c.config.archive.delay_seconds);
+        let completed_before = chrono::Duration::from_std(delay)
+            .ok()
+            .and_then(|d| Utc::now().checked_sub_signed(d))
+            .unwrap_or(DateTime::<Utc>::MIN_UTC);
+        if let Some(job) = c.db.job_next_unarchived(completed_before)? {
             ("automatic", job)
         } else {
             return Ok(false);
@@ -627,7 +953,7 @@ async fn archive_jobs_one(log: &Logger, c: &Central) -> Result<bool> {
      * archive of the job.
      */
     let events =
-        c.db.job_events(job.id, 0)?
+        c.db.job_events(job.id, 0, None, None)?
             .into_iter()
             .map(ArchivedEvent::from)
             .collect::<Vec<_>>();
@@ -657,6 +983,7 @@ async fn archive_jobs_one(log: &Logger, c: &Central) -> Result<bool> {
             .into_iter()
             .map(|(name, time)| (name, time.to_archive()))
             .collect::<HashMap<String, String>>();
+    let metrics = c.db.job_metrics(job.id)?;
     let target_id = job.target().to_string();
     let (target_resolved_name, target_resolved_desc) = {
         let t = c.db.target_get(job.target())?;
@@ -692,6 +1019,10 @@ async fn archive_jobs_one(log: &Logger, c: &Central) -> Result<bool> {
         complete: _,
         waiting: _,
         time_archived: _,
+        time_create,
+        event_bytes: _,
+        cancel_reason,
+        failure_reason,
 
         /*
          * We use the target_id value we already fetched above, so ignore it
@@ -710,6 +1041,8 @@ async fn archive_jobs_one(log: &Logger, c: &Central) -> Result<bool> {
         name,
         failed,
         cancelled,
+        cancel_reason,
+        failure_reason,
 
         owner_id: owner.id.to_string(),
         owner_name: owner.name.to_string(),
@@ -729,11 +1062,13 @@ async fn archive_jobs_one(log: &Logger, c: &Central) -> Result<bool> {
         inputs,
         outputs,
         times,
+        time_create: time_create.map(|t| t.to_archive()),
+        metrics,
         store,
         depends,
     };
 
-    c.archive_store(log, id, aj).await?;
+    c.archive_store(log, id, &aj).await?;
 
     c.db.job_mark_archived(id, Utc::now())?;
 
@@ -750,11 +1085,14 @@ pub(crate) async fn archive_jobs(log: Logger, c: Arc<Central>) -> Result<()> {
 
     loop {
         match archive_jobs_one(&log, &c).await {
-            Ok(true) => continue,
-            Ok(false) => (),
+            Ok(true) if !c.shutdown.is_cancelled() => continue,
+            Ok(_) => (),
             Err(e) => error!(log, "job archive task error: {:?}", e),
         }
 
-        tokio::time::sleep(delay).await;
+        if !c.idle(delay).await {
+            info!(log, "stop job archive task");
+            return Ok(());
+        }
     }
 }
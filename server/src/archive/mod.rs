@@ -1,2 +1,3 @@
+pub(crate) mod cache;
 pub(crate) mod files;
 pub(crate) mod jobs;
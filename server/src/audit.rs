@@ -0,0 +1,48 @@
+/*
+ * Copyright 2026 Oxide Computer Company
+ */
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+#[allow(unused_imports)]
+use slog::{error, info, warn, Logger};
+
+use super::Central;
+
+async fn audit_cleanup_one(log: &Logger, c: &Central) -> Result<()> {
+    /*
+     * Discard entries that are older than the retention period.
+     */
+    let before = chrono::Duration::from_std(c.config.audit.retention())
+        .ok()
+        .and_then(|d| chrono::Utc::now().checked_sub_signed(d))
+        .unwrap_or(chrono::DateTime::<chrono::Utc>::MIN_UTC);
+
+    let count = c.db.audit_log_prune(before)?;
+    if count > 0 {
+        info!(
+            log,
+            "removed {} audit log entries from before {}", count, before
+        );
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn audit_cleanup(log: Logger, c: Arc<Central>) -> Result<()> {
+    let delay = Duration::from_secs(3607);
+    info!(log, "start audit cleanup task");
+
+    loop {
+        if let Err(e) = audit_cleanup_one(&log, &c).await {
+            error!(log, "audit cleanup task error: {:?}", e);
+        }
+
+        if !c.idle(delay).await {
+            info!(log, "stop audit cleanup task");
+            return Ok(());
+        }
+    }
+}
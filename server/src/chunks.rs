@@ -2,9 +2,10 @@
  * Copyright 2021 Oxide Computer Company
  */
 
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use anyhow::Result;
 #[allow(unused_imports)]
@@ -12,6 +13,34 @@ use slog::{error, info, warn, Logger};
 
 use super::Central;
 
+/*
+ * Find the files in a chunk directory that have not been modified within the
+ * retention period.  This includes both chunks and the content hash index
+ * entries that refer to them.
+ */
+fn expired_chunks(
+    dir: &Path,
+    now: SystemTime,
+    ttl: Duration,
+) -> Result<Vec<PathBuf>> {
+    let mut out = Vec::new();
+
+    for ent in std::fs::read_dir(dir)? {
+        let ent = ent?;
+        let md = ent.metadata()?;
+        if !md.is_file() {
+            continue;
+        }
+
+        let age = now.duration_since(md.modified()?).unwrap_or_default();
+        if age > ttl {
+            out.push(ent.path());
+        }
+    }
+
+    Ok(out)
+}
+
 async fn chunk_cleanup_one(log: &Logger, c: &Central) -> Result<()> {
     /*
      * Get a list of chunk directories.  Each directory will be the ID of a job.
@@ -51,6 +80,20 @@ async fn chunk_cleanup_one(log: &Logger, c: &Central) -> Result<()> {
         match c.db.job_by_id_opt(id) {
             Ok(Some(job)) => {
                 if !job.complete {
+                    /*
+                     * The job is still running, but we can remove any chunks
+                     * that were uploaded and then not committed within the
+                     * retention period.
+                     */
+                    let now = SystemTime::now();
+                    let ttl = c.config.chunk.ttl();
+                    for p in expired_chunks(&ent.path(), now, ttl)? {
+                        info!(
+                            log,
+                            "job {} chunk {:?} expired; removing", id, p
+                        );
+                        std::fs::remove_file(&p)?;
+                    }
                     continue;
                 }
 
@@ -68,6 +111,9 @@ async fn chunk_cleanup_one(log: &Logger, c: &Central) -> Result<()> {
         std::fs::remove_dir_all(ent.path())?;
     }
 
+    super::metrics::CHUNK_BYTES
+        .set(super::metrics::measure_dir(&c.chunk_dir()?)?);
+
     Ok(())
 }
 
@@ -80,6 +126,37 @@ pub(crate) async fn chunk_cleanup(log: Logger, c: Arc<Central>) -> Result<()> {
             error!(log, "chunk cleanup task error: {:?}", e);
         }
 
-        tokio::time::sleep(delay).await;
+        if !c.idle(delay).await {
+            info!(log, "stop chunk cleanup task");
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::expired_chunks;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn expire_old_chunk() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let now = SystemTime::now();
+        let ttl = Duration::from_secs(3600);
+
+        let old = dir.path().join("old");
+        std::fs::write(&old, b"old chunk")?;
+        std::fs::File::options()
+            .write(true)
+            .open(&old)?
+            .set_modified(now - Duration::from_secs(2 * 3600))?;
+
+        let fresh = dir.path().join("fresh");
+        std::fs::write(&fresh, b"fresh chunk")?;
+
+        let expired = expired_chunks(dir.path(), now, ttl)?;
+        assert_eq!(expired, vec![old]);
+
+        Ok(())
     }
 }
@@ -3,8 +3,10 @@
  */
 
 use std::path::Path;
+use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use aws_sdk_s3::types::StorageClass;
 use buildomat_common::*;
 use serde::Deserialize;
 #[allow(unused_imports)]
@@ -18,6 +20,237 @@ pub struct ConfigFile {
     pub storage: ConfigFileStorage,
     pub sqlite: ConfigFileSqlite,
     pub job: ConfigFileJob,
+    #[serde(default)]
+    pub archive: ConfigFileArchive,
+    #[serde(default)]
+    pub limits: ConfigFileLimits,
+    #[serde(default)]
+    pub worker: ConfigFileWorker,
+    #[serde(default)]
+    pub chunk: ConfigFileChunk,
+    #[serde(default)]
+    pub files: ConfigFileFiles,
+    #[serde(default)]
+    pub shutdown: ConfigFileShutdown,
+    #[serde(default)]
+    pub agent: ConfigFileAgent,
+    #[serde(default)]
+    pub audit: ConfigFileAudit,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ConfigFileAudit {
+    /**
+     * Audit log entries are kept for this many days.
+     */
+    #[serde(default = "default_audit_retention_days")]
+    pub retention_days: u64,
+}
+
+impl Default for ConfigFileAudit {
+    fn default() -> Self {
+        ConfigFileAudit { retention_days: default_audit_retention_days() }
+    }
+}
+
+impl ConfigFileAudit {
+    pub fn retention(&self) -> Duration {
+        Duration::from_secs(self.retention_days.saturating_mul(24 * 60 * 60))
+    }
+}
+
+fn default_audit_retention_days() -> u64 {
+    /*
+     * Keep a little over a year of entries, so that a full year is always
+     * available for review:
+     */
+    400
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ConfigFileAgent {
+    /**
+     * Agent binaries for particular platforms, in order of preference.  The
+     * first entry that matches the platform of the requesting worker is used.
+     * If no entries are specified, "buildomat-agent-linux" is used for Linux
+     * systems and "buildomat-agent" for everything else.
+     */
+    #[serde(default)]
+    pub files: Vec<ConfigFileAgentFile>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ConfigFileAgentFile {
+    /**
+     * The operating system name, as reported by "uname -s"; e.g., "Linux",
+     * "SunOS", or "FreeBSD".  If not specified, any system matches.
+     */
+    #[serde(default)]
+    pub kernel: Option<String>,
+    /**
+     * The machine hardware name, as reported by "uname -m"; e.g., "x86_64",
+     * "aarch64", or "i86pc".  If not specified, any machine matches.
+     */
+    #[serde(default)]
+    pub mach: Option<String>,
+    /**
+     * The processor type, as reported by "uname -p".  If not specified, any
+     * processor matches.
+     */
+    #[serde(default)]
+    pub proc: Option<String>,
+    pub file: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ConfigFileShutdown {
+    /**
+     * When asked to shut down, allow this many seconds for in-flight requests,
+     * background tasks, and file commits to finish before exiting.
+     */
+    #[serde(default = "default_shutdown_grace_seconds")]
+    pub grace_seconds: u64,
+}
+
+impl Default for ConfigFileShutdown {
+    fn default() -> Self {
+        ConfigFileShutdown { grace_seconds: default_shutdown_grace_seconds() }
+    }
+}
+
+impl ConfigFileShutdown {
+    pub fn grace(&self) -> Duration {
+        Duration::from_secs(self.grace_seconds)
+    }
+}
+
+fn default_shutdown_grace_seconds() -> u64 {
+    30
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ConfigFileChunk {
+    /**
+     * An uploaded chunk that has not been used by a file commit for this many
+     * seconds may be removed, even if the job is not yet complete.
+     */
+    #[serde(default = "default_chunk_ttl_seconds")]
+    pub ttl_seconds: u64,
+}
+
+impl Default for ConfigFileChunk {
+    fn default() -> Self {
+        ConfigFileChunk { ttl_seconds: default_chunk_ttl_seconds() }
+    }
+}
+
+impl ConfigFileChunk {
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_seconds)
+    }
+}
+
+fn default_chunk_ttl_seconds() -> u64 {
+    /*
+     * Large files are uploaded in many chunks, and the client may take some
+     * time to upload them all before committing the file, so be generous:
+     */
+    6 * 60 * 60
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ConfigFileFiles {
+    /**
+     * The number of threads that assemble uploaded chunks into input and
+     * output files.  Hosts with fast storage may benefit from more; small
+     * hosts may need fewer.
+     */
+    #[serde(default = "default_files_workers")]
+    pub workers: usize,
+}
+
+impl Default for ConfigFileFiles {
+    fn default() -> Self {
+        ConfigFileFiles { workers: default_files_workers() }
+    }
+}
+
+fn default_files_workers() -> usize {
+    4
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ConfigFileWorker {
+    /**
+     * A worker that has not pinged the server for this many seconds is
+     * considered stale, and will be recycled.
+     */
+    #[serde(default = "default_ping_stale_seconds")]
+    pub ping_stale_seconds: u64,
+}
+
+impl Default for ConfigFileWorker {
+    fn default() -> Self {
+        ConfigFileWorker { ping_stale_seconds: default_ping_stale_seconds() }
+    }
+}
+
+impl ConfigFileWorker {
+    pub fn ping_stale(&self) -> Duration {
+        Duration::from_secs(self.ping_stale_seconds)
+    }
+}
+
+fn default_ping_stale_seconds() -> u64 {
+    /*
+     * Agents ping the server every few seconds, so a worker that has been
+     * silent for ten minutes is almost certainly wedged:
+     */
+    10 * 60
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ConfigFileLimits {
+    /**
+     * The sustained number of requests per second that each authenticated
+     * principal (user, worker, factory, or admin token) may make.  If not
+     * specified, requests are not rate limited.
+     */
+    #[serde(default)]
+    pub requests_per_second: Option<u32>,
+    /**
+     * The number of requests that may be made in a burst before the rate
+     * limit applies.  Defaults to one second worth of requests.
+     */
+    #[serde(default)]
+    pub burst: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ConfigFileArchive {
+    /**
+     * The maximum total size of the local cache of archived jobs that have
+     * been fetched from the object store.  If not specified, the cache is not
+     * limited.
+     */
+    #[serde(default)]
+    pub cache_max_bytes: Option<u64>,
+    /**
+     * The minimum number of seconds that must have elapsed since a job
+     * completed before it is automatically archived.  Keeping recently
+     * completed jobs in the database allows their results to be viewed
+     * without fetching the archive from the object store.  Jobs are archived
+     * as soon as possible by default.
+     */
+    #[serde(default)]
+    pub delay_seconds: u64,
+    /**
+     * Store archives as pretty-printed JSON, which is easier to read by hand
+     * but larger.  Archives are stored in the compact form by default.  Both
+     * forms can be loaded regardless of this setting.
+     */
+    #[serde(default)]
+    pub pretty: bool,
 }
 
 #[derive(Deserialize, Debug)]
@@ -33,6 +266,41 @@ pub struct ConfigFileJob {
     pub max_size_per_file_mb: u64,
     #[serde(default)]
     pub auto_archive: bool,
+    #[serde(default = "default_max_signed_url_seconds")]
+    pub max_signed_url_seconds: u64,
+    #[serde(default = "default_max_tasks_per_job")]
+    pub max_tasks_per_job: u64,
+    #[serde(default = "default_max_inputs_per_job")]
+    pub max_inputs_per_job: u64,
+    #[serde(default = "default_max_tags_per_job")]
+    pub max_tags_per_job: u64,
+    #[serde(default = "default_max_tag_bytes_per_job")]
+    pub max_tag_bytes_per_job: u64,
+    #[serde(default = "default_max_event_bytes")]
+    pub max_event_bytes: u64,
+    #[serde(default = "default_max_chunk_bytes")]
+    pub max_chunk_bytes: u64,
+    /**
+     * The number of jobs that each user may have that have not yet completed.
+     * Submissions beyond this limit are rejected, unless the user holds the
+     * "admin.job.unlimited" privilege.  There is no limit by default.
+     */
+    #[serde(default)]
+    pub max_active_per_user: Option<u64>,
+    /**
+     * The number of seconds for which the idempotency key provided with a job
+     * submission is remembered.  A repeat submission by the same user with
+     * the same key within this window returns the original job rather than
+     * creating a new one.
+     */
+    #[serde(default = "default_idempotency_window_seconds")]
+    pub idempotency_window_seconds: u64,
+    /**
+     * The name of the target used by jobs submitted without one.  The server
+     * will not start unless this name resolves to a target.
+     */
+    #[serde(default = "default_default_target")]
+    pub default_target: String,
 }
 
 impl ConfigFileJob {
@@ -43,6 +311,19 @@ impl ConfigFileJob {
     pub fn max_bytes_per_input(&self) -> u64 {
         self.max_size_per_file_mb.saturating_mul(1024 * 1024)
     }
+
+    /**
+     * Chunks are uploaded as the entire body of a request, so the server
+     * must accept request bodies of at least the maximum chunk size.  Other
+     * requests are small, but we retain the historical limit of 10MB as a
+     * floor so that lowering the chunk size does not affect them.
+     */
+    pub fn request_body_max_bytes(&self) -> usize {
+        self.max_chunk_bytes
+            .max(10 * 1024 * 1024)
+            .try_into()
+            .unwrap_or(usize::MAX)
+    }
 }
 
 fn default_max_size_per_file_mb() -> u64 {
@@ -52,6 +333,56 @@ fn default_max_size_per_file_mb() -> u64 {
     1 * 1024
 }
 
+fn default_max_signed_url_seconds() -> u64 {
+    /*
+     * By default, presigned URLs may last at most one hour:
+     */
+    3600
+}
+
+fn default_max_tasks_per_job() -> u64 {
+    crate::limits::DEFAULT_MAX_TASKS_PER_JOB
+}
+
+fn default_max_inputs_per_job() -> u64 {
+    crate::limits::DEFAULT_MAX_INPUTS_PER_JOB
+}
+
+fn default_max_tags_per_job() -> u64 {
+    crate::limits::DEFAULT_MAX_TAGS_PER_JOB
+}
+
+fn default_max_tag_bytes_per_job() -> u64 {
+    crate::limits::DEFAULT_MAX_TAG_BYTES_PER_JOB
+}
+
+fn default_max_event_bytes() -> u64 {
+    /*
+     * By default, a job may produce at most 256MB of stdout and stderr output
+     * before we stop recording it:
+     */
+    256 * 1024 * 1024
+}
+
+fn default_idempotency_window_seconds() -> u64 {
+    /*
+     * By default, remember idempotency keys for one day:
+     */
+    24 * 3600
+}
+
+fn default_default_target() -> String {
+    "default".to_string()
+}
+
+fn default_max_chunk_bytes() -> u64 {
+    /*
+     * By default, allow chunks up to the size of the historical request body
+     * limit of 10MB:
+     */
+    10 * 1024 * 1024
+}
+
 #[derive(Deserialize, Debug)]
 pub struct ConfigFileSqlite {
     #[serde(default)]
@@ -60,20 +391,135 @@ pub struct ConfigFileSqlite {
 
 #[derive(Deserialize, Debug)]
 pub struct ConfigFileAdmin {
-    pub token: String,
+    /**
+     * The legacy single global admin token.  This is deprecated in favour of
+     * the list of named tokens in "tokens", and will be removed in a future
+     * release.
+     */
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub tokens: Vec<ConfigFileAdminToken>,
+    /**
+     * A token that grants access only to the metrics endpoint, for use by the
+     * monitoring system.  Requests made with this token are not audited.
+     */
+    #[serde(default)]
+    pub metrics_token: Option<String>,
     /**
      * Should we hold off on new VM creation by default at startup?
      */
     pub hold: bool,
 }
 
+impl ConfigFileAdmin {
+    /**
+     * If this bearer token matches one of the configured admin tokens, return
+     * the name of that token.
+     */
+    pub fn token_name(&self, token: &str) -> Option<&str> {
+        if let Some(t) = self.tokens.iter().find(|t| t.token == token) {
+            return Some(&t.name);
+        }
+
+        if self.token.as_deref() == Some(token) {
+            return Some("legacy");
+        }
+
+        None
+    }
+}
+
 #[derive(Deserialize, Debug)]
+pub struct ConfigFileAdminToken {
+    pub name: String,
+    pub token: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
 pub struct ConfigFileStorage {
     pub access_key_id: String,
     pub secret_access_key: String,
     pub bucket: String,
     pub prefix: String,
     pub region: String,
+    #[serde(default)]
+    pub retry: ConfigFileStorageRetry,
+    /**
+     * Files larger than this many bytes are uploaded to the object store in
+     * several parts, rather than with a single request.
+     */
+    #[serde(default = "default_multipart_threshold")]
+    pub multipart_threshold: u64,
+    /**
+     * The URL of an S3-compatible object store to use instead of AWS; e.g.,
+     * "https://minio.example.com:9000".
+     */
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    /**
+     * Address buckets as part of the request path, rather than as part of the
+     * host name.  Most object stores other than AWS require this.
+     */
+    #[serde(default)]
+    pub path_style: bool,
+    /**
+     * The storage class (e.g., "STANDARD_IA" or "GLACIER_IR") to use for job
+     * archives.  If not specified, the default class for the bucket is used.
+     * An archive in a class that is not immediately retrievable, like
+     * "GLACIER", must be restored before the job can be viewed, and is
+     * skipped by archive verification.
+     */
+    #[serde(default)]
+    pub archive_storage_class: Option<String>,
+    /**
+     * The storage class to use for job output files.  If not specified, the
+     * default class for the bucket is used.  An output in a class that is not
+     * immediately retrievable must be restored before it can be downloaded,
+     * or before a signed URL can be issued for it.
+     */
+    #[serde(default)]
+    pub output_storage_class: Option<String>,
+}
+
+fn default_multipart_threshold() -> u64 {
+    /*
+     * By default, use multipart uploads for files larger than 100MB:
+     */
+    100 * 1024 * 1024
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ConfigFileStorageRetry {
+    /**
+     * How many times should we attempt an object store request before giving
+     * up?  This includes the initial attempt.
+     */
+    #[serde(default = "default_retry_max_attempts")]
+    pub max_attempts: u32,
+    /**
+     * The delay before the first retry, in milliseconds.  The delay doubles
+     * with each subsequent attempt.
+     */
+    #[serde(default = "default_retry_base_delay_ms")]
+    pub base_delay_ms: u64,
+}
+
+impl Default for ConfigFileStorageRetry {
+    fn default() -> Self {
+        ConfigFileStorageRetry {
+            max_attempts: default_retry_max_attempts(),
+            base_delay_ms: default_retry_base_delay_ms(),
+        }
+    }
+}
+
+fn default_retry_max_attempts() -> u32 {
+    5
+}
+
+fn default_retry_base_delay_ms() -> u64 {
+    250
 }
 
 impl ConfigFileStorage {
@@ -90,8 +536,64 @@ impl ConfigFileStorage {
     pub fn region(&self) -> aws_types::region::Region {
         aws_types::region::Region::new(self.region.to_string())
     }
+
+    pub fn archive_storage_class(&self) -> Option<StorageClass> {
+        self.archive_storage_class.as_deref().map(StorageClass::from)
+    }
+
+    pub fn output_storage_class(&self) -> Option<StorageClass> {
+        self.output_storage_class.as_deref().map(StorageClass::from)
+    }
 }
 
 pub fn load<P: AsRef<Path>>(path: P) -> Result<ConfigFile> {
-    read_toml(path.as_ref())
+    let c: ConfigFile = read_toml(path.as_ref())?;
+
+    if c.admin.token.is_none() && c.admin.tokens.is_empty() {
+        bail!("at least one admin token must be configured");
+    }
+
+    let mut names = std::collections::HashSet::new();
+    for t in c.admin.tokens.iter() {
+        if !names.insert(t.name.as_str()) {
+            bail!("admin token name {:?} is used more than once", t.name);
+        }
+    }
+
+    if c.files.workers < 1 {
+        bail!("at least one file commit worker must be configured");
+    }
+
+    for (what, class) in [
+        ("archive", c.storage.archive_storage_class.as_deref()),
+        ("output", c.storage.output_storage_class.as_deref()),
+    ] {
+        if let Some(class) = class {
+            if !StorageClass::values().contains(&class) {
+                bail!(
+                    "{what} storage class {class:?} must be one of: {}",
+                    StorageClass::values().join(", "),
+                );
+            }
+        }
+    }
+
+    if let Some(endpoint) = c.storage.endpoint.as_deref() {
+        /*
+         * Presigned URLs are constructed from the endpoint and handed to
+         * clients, so it must be a complete absolute URL.
+         */
+        let ok = endpoint.parse::<hyper::Uri>().ok().map_or(false, |u| {
+            matches!(u.scheme_str(), Some("http" | "https"))
+                && u.authority().is_some()
+        });
+        if !ok {
+            bail!(
+                "storage endpoint {:?} must be an http or https URL",
+                endpoint
+            );
+        }
+    }
+
+    Ok(c)
 }
@@ -2,7 +2,7 @@
  * Copyright 2023 Oxide Computer Company
  */
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use std::str::FromStr;
 use std::sync::Mutex;
@@ -27,6 +27,8 @@ pub use models::*;
 pub enum OperationError {
     #[error("conflict: {0}")]
     Conflict(String),
+    #[error("not found: {0}")]
+    NotFound(String),
     #[error(transparent)]
     Sql(#[from] diesel::result::Error),
     #[error(transparent)]
@@ -37,6 +39,11 @@ pub enum OperationError {
 
 pub type OResult<T> = std::result::Result<T, OperationError>;
 
+/*
+ * The maximum number of redirects we will follow when resolving a target name.
+ */
+const MAX_TARGET_REDIRECTS: usize = 16;
+
 macro_rules! conflict {
     ($msg:expr) => {
         return Err(OperationError::Conflict($msg.to_string()))
@@ -60,16 +67,166 @@ pub struct CreateTask {
     pub user_id: Option<u32>,
     pub group_id: Option<u32>,
     pub workdir: Option<String>,
+    pub always_run: bool,
+    pub timeout_seconds: Option<u64>,
+}
+
+/**
+ * The records of an archived job, as reconstructed from the archive in order to
+ * import the job back into the database.
+ */
+pub struct ImportJob {
+    pub job: Job,
+    pub tasks: Vec<Task>,
+    pub output_rules: Vec<JobOutputRule>,
+    pub inputs: Vec<(JobInput, Option<JobFile>)>,
+    pub outputs: Vec<(JobOutput, JobFile)>,
+    pub events: Vec<JobEvent>,
+    pub tags: HashMap<String, String>,
+    pub times: HashMap<String, DateTime<Utc>>,
+    pub depends: Vec<JobDepend>,
+    pub store: Vec<JobStore>,
+    pub metrics: Vec<JobMetric>,
+}
+
+pub struct CreateJob {
+    pub name: String,
+    pub target_name: String,
+    pub target: TargetId,
+    pub tasks: Vec<CreateTask>,
+    pub output_rules: Vec<CreateOutputRule>,
+    pub inputs: Vec<String>,
+    pub tags: HashMap<String, String>,
+    pub depends: Vec<CreateDepend>,
+    /**
+     * If specified, the job is not created if the owner would then have more
+     * than this many jobs that have not completed.
+     */
+    pub max_active: Option<u64>,
+}
+
+/**
+ * A job was not created because the owner has too many jobs that have not yet
+ * completed.
+ */
+#[derive(Error, Debug)]
+#[error(
+    "you may have at most {0} jobs that have not completed; wait for some to \
+    finish before submitting more"
+)]
+pub struct TooManyActiveJobs(pub u64);
+
+/**
+ * Details carried over from an existing job when creating a new job that runs
+ * it again.
+ */
+#[derive(Debug)]
+pub struct CreateRerun {
+    pub original: JobId,
+    /**
+     * Input files that were provided for the original job, by input name,
+     * along with the ID of the job that holds each file.  That is the original
+     * job, unless the original job was itself created to run an earlier job
+     * again.  The new job refers to these files rather than requiring that
+     * they be uploaded again.
+     */
+    pub inputs: Vec<(String, JobId, JobFileId)>,
+    /**
+     * Store entries set by the user on the original job, as (name, value,
+     * secret) tuples.
+     */
+    pub store: Vec<(String, String, bool)>,
+}
+
+/**
+ * The number of events recorded for a stream, and the total size in bytes of
+ * their payloads.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StreamStats {
+    pub events: u64,
+    pub bytes: u64,
+}
+
+impl StreamStats {
+    pub fn add(&mut self, payload: &str) {
+        self.events = self.events.saturating_add(1);
+        self.bytes = self.bytes.saturating_add(payload.len() as u64);
+    }
+}
+
+/**
+ * Structured details about the failure of a task or job, as reported by the
+ * worker.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct FailureSummary {
+    pub code: Option<i32>,
+    pub signal: Option<i32>,
+    pub message: Option<String>,
+}
+
+impl FailureSummary {
+    /**
+     * Produce a short description of the failure; e.g., "exited 101", or
+     * "killed by signal 9: out of memory".
+     */
+    pub fn describe(&self) -> Option<String> {
+        let status = if let Some(signal) = self.signal {
+            Some(format!("killed by signal {signal}"))
+        } else {
+            self.code.map(|code| format!("exited {code}"))
+        };
+
+        match (status, self.message.as_deref()) {
+            (Some(status), Some(msg)) => Some(format!("{status}: {msg}")),
+            (Some(status), None) => Some(status),
+            (None, Some(msg)) => Some(msg.to_string()),
+            (None, None) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum CreatePriorJob {
+    /**
+     * A job that already exists in the database.
+     */
+    Existing(JobId),
+    /**
+     * A job that appears earlier in the same batch of jobs, by its index in
+     * that batch.
+     */
+    Batch(usize),
 }
 
 pub struct CreateDepend {
     pub name: String,
-    pub prior_job: JobId,
+    pub prior_job: CreatePriorJob,
     pub copy_outputs: bool,
+    pub copy_outputs_globs: Vec<String>,
     pub on_failed: bool,
     pub on_completed: bool,
 }
 
+pub struct CreateJobEvent {
+    pub task: Option<u32>,
+    pub stream: String,
+    pub time: DateTime<Utc>,
+    pub time_remote: Option<DateTime<Utc>>,
+    pub payload: String,
+}
+
+impl CreateJobEvent {
+    /**
+     * Output from the job itself counts against the per-job limit on the size
+     * of the event log.
+     */
+    fn is_output(&self) -> bool {
+        self.stream == "stdout" || self.stream == "stderr"
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct CreateOutputRule {
     pub rule: String,
@@ -94,12 +251,35 @@ impl Database {
         Ok(Database(log, Mutex::new(Inner { conn })))
     }
 
-    pub fn workers(&self) -> Result<Vec<Worker>> {
+    /**
+     * List workers, optionally restricted to those that have not been deleted,
+     * and to those created by a particular factory or for a particular target.
+     */
+    pub fn workers(
+        &self,
+        active: bool,
+        factory: Option<FactoryId>,
+        target: Option<TargetId>,
+    ) -> Result<Vec<Worker>> {
         let c = &mut self.1.lock().unwrap().conn;
 
         use schema::worker::dsl;
 
-        Ok(dsl::worker.order_by(dsl::id.asc()).get_results(c)?)
+        let mut q = dsl::worker.order_by(dsl::id.asc()).into_boxed();
+
+        if active {
+            q = q.filter(dsl::deleted.eq(false));
+        }
+
+        if let Some(factory) = factory {
+            q = q.filter(dsl::factory.eq(factory));
+        }
+
+        if let Some(target) = target {
+            q = q.filter(dsl::target.eq(target));
+        }
+
+        Ok(q.get_results(c)?)
     }
 
     pub fn workers_active(&self) -> Result<Vec<Worker>> {
@@ -523,11 +703,181 @@ impl Database {
     /**
      * Enumerate all jobs.
      */
-    pub fn jobs_all(&self) -> Result<Vec<Job>> {
+    /**
+     * Enumerate all jobs that have every nominated tag name and value.
+     */
+    pub fn jobs_all(&self, tags: &[(String, String)]) -> Result<Vec<Job>> {
         use schema::job::dsl;
 
         let c = &mut self.1.lock().unwrap().conn;
-        Ok(dsl::job.order_by(dsl::id.asc()).get_results(c)?)
+        let q = dsl::job.order_by(dsl::id.asc()).into_boxed();
+        Ok(Self::i_jobs_with_tags(q, tags).get_results(c)?)
+    }
+
+    /**
+     * Restrict a job query to jobs that have every nominated tag name and
+     * value.
+     */
+    fn i_jobs_with_tags<'a>(
+        mut q: schema::job::BoxedQuery<'a, diesel::sqlite::Sqlite>,
+        tags: &'a [(String, String)],
+    ) -> schema::job::BoxedQuery<'a, diesel::sqlite::Sqlite> {
+        use schema::{job, job_tag};
+
+        for (name, value) in tags {
+            q = q.filter(
+                job::dsl::id.eq_any(
+                    job_tag::dsl::job_tag
+                        .select(job_tag::dsl::job)
+                        .filter(job_tag::dsl::name.eq(name))
+                        .filter(job_tag::dsl::value.eq(value)),
+                ),
+            );
+        }
+
+        q
+    }
+
+    /**
+     * Enumerate archived jobs in ID order, beginning after the specified job
+     * if one is provided.
+     */
+    pub fn jobs_archived(
+        &self,
+        after: Option<JobId>,
+        limit: usize,
+    ) -> Result<Vec<Job>> {
+        use schema::job::dsl;
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        let mut q = dsl::job
+            .filter(dsl::time_archived.is_not_null())
+            .order_by(dsl::id.asc())
+            .limit(limit.try_into().unwrap_or(i64::MAX))
+            .into_boxed();
+
+        if let Some(after) = after {
+            q = q.filter(dsl::id.gt(after));
+        }
+
+        Ok(q.get_results(c)?)
+    }
+
+    /**
+     * Count the jobs in each state, as reported in the "state" field of a job.
+     */
+    pub fn job_state_counts(&self) -> Result<Vec<(&'static str, u64)>> {
+        use schema::job::dsl;
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        let n = |v: i64| -> u64 { v.try_into().unwrap_or(0) };
+
+        let failed: i64 =
+            dsl::job.filter(dsl::failed.eq(true)).count().get_result(c)?;
+        let completed: i64 = dsl::job
+            .filter(dsl::failed.eq(false))
+            .filter(dsl::complete.eq(true))
+            .count()
+            .get_result(c)?;
+        let running: i64 = dsl::job
+            .filter(dsl::failed.eq(false))
+            .filter(dsl::complete.eq(false))
+            .filter(dsl::worker.is_not_null())
+            .count()
+            .get_result(c)?;
+        let waiting: i64 = dsl::job
+            .filter(dsl::failed.eq(false))
+            .filter(dsl::complete.eq(false))
+            .filter(dsl::worker.is_null())
+            .filter(dsl::waiting.eq(true))
+            .count()
+            .get_result(c)?;
+        let queued: i64 = dsl::job
+            .filter(dsl::failed.eq(false))
+            .filter(dsl::complete.eq(false))
+            .filter(dsl::worker.is_null())
+            .filter(dsl::waiting.eq(false))
+            .count()
+            .get_result(c)?;
+
+        Ok(vec![
+            ("waiting", n(waiting)),
+            ("queued", n(queued)),
+            ("running", n(running)),
+            ("completed", n(completed)),
+            ("failed", n(failed)),
+        ])
+    }
+
+    /**
+     * Estimate the position of a queued job in the queue for its target, where
+     * the job at the head of the queue is in position 1.  Jobs are assigned to
+     * workers in the order in which they were created, so this is the number
+     * of unassigned jobs for the same target that were created before this
+     * one.  Returns None if the job is not presently queued.
+     */
+    pub fn job_queue_position(&self, job: &Job) -> Result<Option<u64>> {
+        Ok(self.job_queue_positions(std::slice::from_ref(job))?.remove(&job.id))
+    }
+
+    /**
+     * Estimate the queue position of each of a list of jobs, as for
+     * job_queue_position(), using a single query.  Jobs that are not presently
+     * queued do not appear in the result.
+     */
+    pub fn job_queue_positions(
+        &self,
+        jobs: &[Job],
+    ) -> Result<HashMap<JobId, u64>> {
+        use schema::job::dsl;
+
+        let queued = jobs
+            .iter()
+            .filter(|j| {
+                !(j.complete || j.waiting || j.cancelled || j.worker.is_some())
+            })
+            .collect::<Vec<_>>();
+        if queued.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let targets = queued
+            .iter()
+            .map(|j| j.target())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        let c = &mut self.1.lock().unwrap().conn;
+        let all: Vec<(JobId, Option<TargetId>)> = dsl::job
+            .select((dsl::id, dsl::target_id))
+            .filter(dsl::complete.eq(false))
+            .filter(dsl::waiting.eq(false))
+            .filter(dsl::cancelled.eq(false))
+            .filter(dsl::worker.is_null())
+            .filter(dsl::target_id.eq_any(targets))
+            .order_by(dsl::id.asc())
+            .get_results(c)?;
+
+        let mut queues: HashMap<TargetId, Vec<JobId>> = HashMap::new();
+        for (id, target) in all {
+            if let Some(target) = target {
+                queues.entry(target).or_default().push(id);
+            }
+        }
+
+        Ok(queued
+            .into_iter()
+            .map(|j| {
+                let ahead = queues
+                    .get(&j.target())
+                    .map(|q| q.partition_point(|id| *id < j.id))
+                    .unwrap_or(0);
+                (j.id, u64::try_from(ahead).unwrap().saturating_add(1))
+            })
+            .collect())
     }
 
     /**
@@ -559,17 +909,42 @@ impl Database {
     }
 
     /**
-     * Enumerate some number of the most recently complete jobs.
+     * Enumerate jobs that are not yet complete, and that have every nominated
+     * tag name and value.  Active jobs are listed first, followed by jobs that
+     * are waiting.
+     */
+    pub fn jobs_incomplete(
+        &self,
+        tags: &[(String, String)],
+    ) -> Result<Vec<Job>> {
+        use schema::job::dsl;
+
+        let c = &mut self.1.lock().unwrap().conn;
+        let q = dsl::job
+            .filter(dsl::complete.eq(false))
+            .order_by((dsl::waiting.asc(), dsl::id.asc()))
+            .into_boxed();
+        Ok(Self::i_jobs_with_tags(q, tags).get_results(c)?)
+    }
+
+    /**
+     * Enumerate some number of the most recently complete jobs that have every
+     * nominated tag name and value.
      */
-    pub fn jobs_completed(&self, limit: usize) -> Result<Vec<Job>> {
+    pub fn jobs_completed(
+        &self,
+        limit: usize,
+        tags: &[(String, String)],
+    ) -> Result<Vec<Job>> {
         use schema::job::dsl;
 
         let c = &mut self.1.lock().unwrap().conn;
-        let mut res = dsl::job
+        let q = dsl::job
             .filter(dsl::complete.eq(true))
             .order_by(dsl::id.desc())
             .limit(limit.try_into().unwrap())
-            .get_results(c)?;
+            .into_boxed();
+        let mut res = Self::i_jobs_with_tags(q, tags).get_results(c)?;
         res.reverse();
         Ok(res)
     }
@@ -660,7 +1035,21 @@ impl Database {
                 /*
                  * Resolve the list of output files.
                  */
-                let pjouts = self.i_job_outputs(tx, pj.id)?;
+                let mut pjouts = self.i_job_outputs(tx, pj.id)?;
+
+                /*
+                 * If the dependency nominated particular outputs, copy only
+                 * those that match at least one of the patterns.
+                 */
+                if let Some(globs) = &d.copy_outputs_globs {
+                    let pats = globs
+                        .iter()
+                        .map(|g| glob::Pattern::new(g))
+                        .collect::<std::result::Result<Vec<_>, _>>()?;
+                    pjouts.retain(|(pjo, _)| {
+                        pats.iter().any(|pat| pat.matches(&pjo.path))
+                    });
+                }
 
                 /*
                  * For each output file produced by the dependency, create an
@@ -768,12 +1157,86 @@ impl Database {
             .get_results(tx)?)
     }
 
+    /**
+     * Locate any output rules for this job that required a match, but which do
+     * not match any of the outputs that have been uploaded.
+     */
+    fn i_job_unmatched_output_rules(
+        &self,
+        tx: &mut SqliteConnection,
+        job: JobId,
+    ) -> Result<Vec<String>> {
+        use schema::job_output_rule;
+
+        let rules: Vec<JobOutputRule> = job_output_rule::dsl::job_output_rule
+            .filter(job_output_rule::dsl::job.eq(job))
+            .filter(job_output_rule::dsl::require_match.eq(true))
+            .order_by(job_output_rule::dsl::seq.asc())
+            .get_results(tx)?;
+        if rules.is_empty() {
+            return Ok(Default::default());
+        }
+
+        let outputs = self.i_job_outputs(tx, job)?;
+
+        Ok(rules
+            .into_iter()
+            .filter(|r| {
+                /*
+                 * A pattern that cannot be parsed cannot have matched
+                 * anything.
+                 */
+                let Ok(pat) = glob::Pattern::new(&r.rule) else {
+                    return true;
+                };
+                !outputs.iter().any(|(o, _)| pat.matches(&o.path))
+            })
+            .map(|r| r.rule)
+            .collect())
+    }
+
     pub fn job_outputs(&self, job: JobId) -> Result<Vec<(JobOutput, JobFile)>> {
         let c = &mut self.1.lock().unwrap().conn;
 
         self.i_job_outputs(c, job)
     }
 
+    /**
+     * Load a page of the outputs for a job, sorted by output ID.  Only outputs
+     * with an ID greater than "after" are returned, if it is specified.  If a
+     * limit is specified, at most that many outputs will be returned.
+     */
+    pub fn job_outputs_page(
+        &self,
+        job: JobId,
+        after: Option<JobFileId>,
+        limit: Option<usize>,
+    ) -> Result<Vec<(JobOutput, JobFile)>> {
+        use schema::{job_file, job_output};
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        let mut q = job_output::dsl::job_output
+            .inner_join(
+                job_file::table.on(job_file::dsl::job
+                    .eq(job_output::dsl::job)
+                    .and(job_file::dsl::id.eq(job_output::dsl::id))),
+            )
+            .filter(job_file::dsl::job.eq(job))
+            .order_by(job_file::dsl::id.asc())
+            .into_boxed();
+
+        if let Some(after) = after {
+            q = q.filter(job_file::dsl::id.gt(after));
+        }
+
+        if let Some(limit) = limit {
+            q = q.limit(limit.try_into().unwrap_or(i64::MAX));
+        }
+
+        Ok(q.get_results(c)?)
+    }
+
     pub fn job_file_by_id_opt(
         &self,
         job: JobId,
@@ -792,15 +1255,32 @@ impl Database {
         &self,
         job: JobId,
         minseq: usize,
+        task: Option<u32>,
+        limit: Option<usize>,
     ) -> Result<Vec<JobEvent>> {
         use schema::job_event::dsl;
 
         let c = &mut self.1.lock().unwrap().conn;
-        Ok(dsl::job_event
+
+        let mut q = dsl::job_event
             .filter(dsl::job.eq(job))
             .filter(dsl::seq.ge(minseq as i32))
             .order_by(dsl::seq.asc())
-            .get_results(c)?)
+            .into_boxed();
+
+        if let Some(task) = task {
+            /*
+             * Events that are not associated with a particular task are
+             * excluded by this filter.
+             */
+            q = q.filter(dsl::task.eq(task as i32));
+        }
+
+        if let Some(limit) = limit {
+            q = q.limit(limit.try_into().unwrap_or(i64::MAX));
+        }
+
+        Ok(q.get_results(c)?)
     }
 
     pub fn job_by_str(&self, job: &str) -> Result<Job> {
@@ -810,10 +1290,14 @@ impl Database {
         Ok(dsl::job.filter(dsl::id.eq(id)).get_result(c)?)
     }
 
-    pub fn job_by_id(&self, job: JobId) -> Result<Job> {
+    pub fn job_by_id(&self, job: JobId) -> OResult<Job> {
         let c = &mut self.1.lock().unwrap().conn;
         use schema::job::dsl;
-        Ok(dsl::job.filter(dsl::id.eq(job)).get_result(c)?)
+        dsl::job
+            .filter(dsl::id.eq(job))
+            .get_result(c)
+            .optional()?
+            .ok_or_else(|| OperationError::NotFound(format!("job {job}")))
     }
 
     pub fn job_by_id_opt(&self, job: JobId) -> Result<Option<Job>> {
@@ -822,157 +1306,414 @@ impl Database {
         Ok(dsl::job.filter(dsl::id.eq(job)).get_result(c).optional()?)
     }
 
-    pub fn job_create<I>(
+    pub fn job_create(&self, owner: UserId, cj: CreateJob) -> Result<Job> {
+        let c = &mut self.1.lock().unwrap().conn;
+
+        c.immediate_transaction(|tx| self.i_job_create(tx, owner, cj, &[]))
+    }
+
+    /**
+     * Create a job, unless this user already has a job with the same name.  The
+     * conflict reported in that case includes the ID of the existing job.
+     */
+    pub fn job_create_unique_name(
         &self,
         owner: UserId,
-        name: &str,
-        target_name: &str,
-        target: TargetId,
-        tasks: Vec<CreateTask>,
-        output_rules: Vec<CreateOutputRule>,
-        inputs: &[String],
-        tags: I,
-        depends: Vec<CreateDepend>,
-    ) -> Result<Job>
-    where
-        I: IntoIterator<Item = (String, String)>,
-    {
-        use schema::{
-            job, job_depend, job_input, job_output_rule, job_tag, task,
-        };
-
-        if tasks.is_empty() {
-            bail!("a job must have at least one task");
-        }
-        if tasks.len() > 64 {
-            bail!("a job must have 64 or fewer tasks");
-        }
+        cj: CreateJob,
+    ) -> OResult<Job> {
+        let c = &mut self.1.lock().unwrap().conn;
 
-        if depends.len() > 8 {
-            bail!("a job must depend on 8 or fewer other jobs");
-        }
-        for cd in depends.iter() {
-            if cd.name.contains('/') || cd.name.trim().is_empty() {
-                bail!("invalid depend name");
+        c.immediate_transaction(|tx| {
+            if let Some(id) = self.i_job_by_name(tx, owner, &cj.name)? {
+                conflict!("a job named {:?} already exists: {}", cj.name, id);
             }
 
-            if !cd.on_failed && !cd.on_completed {
-                bail!("depend must have at least one trigger condition");
-            }
-        }
+            Ok(self.i_job_create(tx, owner, cj, &[])?)
+        })
+    }
 
-        if inputs.len() > 32 {
-            bail!("a job must have 32 or fewer input files");
-        }
-        for ci in inputs.iter() {
-            if ci.contains('/') || ci.trim().is_empty() {
-                bail!("invalid input name");
-            }
-        }
+    /**
+     * Locate the most recent job with this name created by this user, if
+     * there is one.
+     */
+    pub fn job_by_name(
+        &self,
+        owner: UserId,
+        name: &str,
+    ) -> Result<Option<JobId>> {
+        let c = &mut self.1.lock().unwrap().conn;
 
-        /*
-         * If the job has any input files or any jobs on which it depends, it
-         * begins in the "waiting" state.  Otherwise it can begin immediately.
-         */
-        let waiting = !inputs.is_empty() || !depends.is_empty();
+        self.i_job_by_name(c, owner, name)
+    }
 
-        let j = Job {
-            id: JobId::generate(),
-            owner,
-            name: name.to_string(),
-            target: target_name.to_string(),
-            target_id: Some(target),
-            waiting,
-            complete: false,
-            failed: false,
-            worker: None,
-            cancelled: false,
-            time_archived: None,
-        };
+    fn i_job_by_name(
+        &self,
+        tx: &mut SqliteConnection,
+        owner: UserId,
+        name: &str,
+    ) -> Result<Option<JobId>> {
+        use schema::job;
 
-        /*
-         * Use the timestamp from the generated ID as the submit time.
-         */
-        let start = j.id.datetime();
+        Ok(job::dsl::job
+            .select(job::dsl::id)
+            .filter(job::dsl::owner.eq(owner))
+            .filter(job::dsl::name.eq(name))
+            .order_by(job::dsl::id.desc())
+            .limit(1)
+            .get_result(tx)
+            .optional()?)
+    }
+
+    /**
+     * Create a job that runs an existing job again.  The new job is created,
+     * its inputs are pointed at the files uploaded to the original job, and
+     * the user-provided store entries are copied, all in one transaction so
+     * that the new job cannot begin running before it is complete.
+     */
+    pub fn job_rerun(
+        &self,
+        owner: UserId,
+        cj: CreateJob,
+        cr: CreateRerun,
+    ) -> Result<Job> {
+        use schema::{job_input, job_store};
 
         let c = &mut self.1.lock().unwrap().conn;
 
         c.immediate_transaction(|tx| {
-            let ic =
-                diesel::insert_into(job::dsl::job).values(&j).execute(tx)?;
-            assert_eq!(ic, 1);
+            let j = self.i_job_create(tx, owner, cj, &[])?;
 
-            self.i_job_time_record(tx, j.id, "submit", start)?;
-            if !waiting {
-                /*
-                 * If the job is not waiting, record the submit time as the time
-                 * at which dependencies are satisfied and the job is ready to
-                 * run.
-                 */
-                self.i_job_time_record(tx, j.id, "ready", start)?;
+            for (name, holder, id) in cr.inputs.iter() {
+                let uc = diesel::update(job_input::dsl::job_input)
+                    .filter(job_input::dsl::job.eq(j.id))
+                    .filter(job_input::dsl::name.eq(name))
+                    .set((
+                        job_input::dsl::id.eq(*id),
+                        job_input::dsl::other_job.eq(*holder),
+                    ))
+                    .execute(tx)?;
+                assert_eq!(uc, 1);
             }
 
-            for (i, ct) in tasks.iter().enumerate() {
-                let ic = diesel::insert_into(task::dsl::task)
-                    .values(Task::from_create(ct, j.id, i))
+            for (name, value, secret) in cr.store.iter() {
+                let ic = diesel::insert_into(job_store::dsl::job_store)
+                    .values(JobStore {
+                        job: j.id,
+                        name: name.to_string(),
+                        value: value.to_string(),
+                        secret: *secret,
+                        source: "user".to_string(),
+                        time_update: IsoDate::now(),
+                    })
                     .execute(tx)?;
                 assert_eq!(ic, 1);
             }
 
-            for cd in depends.iter() {
-                /*
-                 * Make sure that this job exists in the system and that its
-                 * owner matches the owner of this job.  By requiring prior jobs
-                 * to exist already at the time of dependency specification we
-                 * can avoid the mess of cycles in the dependency graph.
-                 */
-                let pj: Option<Job> = job::dsl::job
-                    .find(cd.prior_job)
-                    .get_result(tx)
-                    .optional()?;
+            self.i_job_event_insert(
+                tx,
+                j.id,
+                None,
+                "control",
+                Utc::now(),
+                None,
+                &format!("job created to run job {} again", cr.original),
+            )?;
 
-                if !pj.map(|j| j.owner == owner).unwrap_or(false) {
-                    /*
-                     * Try not to leak information about job IDs from other
-                     * users in the process.
-                     */
-                    bail!("prior job does not exist");
-                }
+            Ok(j)
+        })
+    }
 
-                let ic = diesel::insert_into(job_depend::dsl::job_depend)
-                    .values(JobDepend::from_create(cd, j.id))
-                    .execute(tx)?;
-                assert_eq!(ic, 1);
+    /**
+     * Locate the job created by an earlier submission from this user that
+     * carried the same idempotency key, if that submission was made at or
+     * after the specified time.
+     */
+    pub fn job_by_idempotency_key(
+        &self,
+        owner: UserId,
+        key: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Option<Job>> {
+        let c = &mut self.1.lock().unwrap().conn;
+
+        self.i_job_by_idempotency_key(c, owner, key, since)
+    }
+
+    fn i_job_by_idempotency_key(
+        &self,
+        tx: &mut SqliteConnection,
+        owner: UserId,
+        key: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Option<Job>> {
+        use schema::{job, job_idempotency};
+
+        let ji: Option<JobIdempotency> = job_idempotency::dsl::job_idempotency
+            .find((owner, key))
+            .filter(job_idempotency::dsl::time_create.ge(IsoDate(since)))
+            .get_result(tx)
+            .optional()?;
+
+        Ok(ji
+            .map(|ji| job::dsl::job.find(ji.job).get_result(tx))
+            .transpose()?)
+    }
+
+    /**
+     * Create a job, unless this user has already created a job with the same
+     * idempotency key at or after the specified time, in which case that job
+     * is returned instead.
+     */
+    pub fn job_create_idempotent(
+        &self,
+        owner: UserId,
+        cj: CreateJob,
+        key: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Job> {
+        use schema::job_idempotency;
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        c.immediate_transaction(|tx| {
+            if let Some(j) =
+                self.i_job_by_idempotency_key(tx, owner, key, since)?
+            {
+                return Ok(j);
             }
 
-            for ci in inputs.iter() {
-                let ic = diesel::insert_into(job_input::dsl::job_input)
-                    .values(JobInput::from_create(ci.as_str(), j.id))
-                    .execute(tx)?;
-                assert_eq!(ic, 1);
+            /*
+             * Discard any keys that have expired, for every user, including
+             * any earlier use of this key, before we record the new job.
+             */
+            diesel::delete(job_idempotency::dsl::job_idempotency)
+                .filter(job_idempotency::dsl::time_create.lt(IsoDate(since)))
+                .execute(tx)?;
+
+            let j = self.i_job_create(tx, owner, cj, &[])?;
+
+            let ic = diesel::insert_into(job_idempotency::dsl::job_idempotency)
+                .values(JobIdempotency {
+                    owner,
+                    key: key.to_string(),
+                    job: j.id,
+                    time_create: IsoDate::now(),
+                })
+                .execute(tx)?;
+            assert_eq!(ic, 1);
+
+            Ok(j)
+        })
+    }
+
+    /**
+     * Create a batch of jobs in a single transaction.  A job in the batch may
+     * depend on a job that appears earlier in the same batch; by only allowing
+     * references to earlier jobs we avoid cycles in the dependency graph.
+     * Either all of the jobs are created, or none of them are.
+     */
+    pub fn jobs_create(
+        &self,
+        owner: UserId,
+        cjs: Vec<CreateJob>,
+    ) -> Result<Vec<Job>> {
+        let c = &mut self.1.lock().unwrap().conn;
+
+        c.immediate_transaction(|tx| {
+            let mut jobs: Vec<Job> = Vec::new();
+            for cj in cjs {
+                let j = self.i_job_create(tx, owner, cj, &jobs)?;
+                jobs.push(j);
             }
+            Ok(jobs)
+        })
+    }
 
-            for (i, rule) in output_rules.iter().enumerate() {
-                let ic =
-                    diesel::insert_into(job_output_rule::dsl::job_output_rule)
-                        .values(JobOutputRule::from_create(rule, j.id, i))
-                        .execute(tx)?;
-                assert_eq!(ic, 1);
+    fn i_job_create(
+        &self,
+        tx: &mut SqliteConnection,
+        owner: UserId,
+        cj: CreateJob,
+        batch: &[Job],
+    ) -> Result<Job> {
+        use schema::{
+            job, job_depend, job_input, job_output_rule, job_tag, task,
+        };
+
+        let CreateJob {
+            name,
+            target_name,
+            target,
+            tasks,
+            output_rules,
+            inputs,
+            tags,
+            depends,
+            max_active,
+        } = cj;
+
+        /*
+         * The limit is checked in the same transaction that creates the job,
+         * so that concurrent submissions cannot together exceed it.  Jobs
+         * created earlier in the same batch are included in the count.
+         */
+        if let Some(max) = max_active {
+            let active: i64 = job::dsl::job
+                .filter(job::dsl::owner.eq(owner))
+                .filter(job::dsl::complete.eq(false))
+                .count()
+                .get_result(tx)?;
+            if u64::try_from(active).unwrap_or(0) >= max {
+                return Err(TooManyActiveJobs(max).into());
             }
+        }
 
-            for (n, v) in tags {
-                let ic = diesel::insert_into(job_tag::dsl::job_tag)
-                    .values((
-                        job_tag::dsl::job.eq(j.id),
-                        job_tag::dsl::name.eq(n),
-                        job_tag::dsl::value.eq(v),
-                    ))
-                    .execute(tx)?;
-                assert_eq!(ic, 1);
+        if tasks.is_empty() {
+            bail!("a job must have at least one task");
+        }
+
+        if let Some(msg) = crate::limits::check_count(
+            "depends",
+            depends.len(),
+            crate::limits::MAX_DEPENDS_PER_JOB,
+        ) {
+            bail!(msg);
+        }
+        for cd in depends.iter() {
+            if cd.name.contains('/') || cd.name.trim().is_empty() {
+                bail!("invalid depend name");
             }
 
-            Ok(j)
-        })
+            if !cd.on_failed && !cd.on_completed {
+                bail!("depend must have at least one trigger condition");
+            }
+        }
+
+        if inputs.len() > 32 {
+            bail!("a job must have 32 or fewer input files");
+        }
+        for ci in inputs.iter() {
+            if ci.contains('/') || ci.trim().is_empty() {
+                bail!("invalid input name");
+            }
+        }
+
+        /*
+         * If the job has any input files or any jobs on which it depends, it
+         * begins in the "waiting" state.  Otherwise it can begin immediately.
+         */
+        let waiting = !inputs.is_empty() || !depends.is_empty();
+
+        let j = Job {
+            id: JobId::generate(),
+            owner,
+            name,
+            target: target_name,
+            target_id: Some(target),
+            waiting,
+            complete: false,
+            failed: false,
+            worker: None,
+            cancelled: false,
+            time_archived: None,
+            time_create: Some(IsoDate::now()),
+            event_bytes: DataSize(0),
+            cancel_reason: None,
+            failure_reason: None,
+        };
+
+        /*
+         * Use the timestamp from the generated ID as the submit time.
+         */
+        let start = j.id.datetime();
+
+        let ic = diesel::insert_into(job::dsl::job).values(&j).execute(tx)?;
+        assert_eq!(ic, 1);
+
+        self.i_job_time_record(tx, j.id, "submit", start)?;
+        if !waiting {
+            /*
+             * If the job is not waiting, record the submit time as the time at
+             * which dependencies are satisfied and the job is ready to run.
+             */
+            self.i_job_time_record(tx, j.id, "ready", start)?;
+        }
+
+        for (i, ct) in tasks.iter().enumerate() {
+            let ic = diesel::insert_into(task::dsl::task)
+                .values(Task::from_create(ct, j.id, i))
+                .execute(tx)?;
+            assert_eq!(ic, 1);
+        }
+
+        for cd in depends.iter() {
+            let prior_job = match cd.prior_job {
+                CreatePriorJob::Existing(id) => {
+                    /*
+                     * Make sure that this job exists in the system and that
+                     * its owner matches the owner of this job.  By requiring
+                     * prior jobs to exist already at the time of dependency
+                     * specification we can avoid the mess of cycles in the
+                     * dependency graph.
+                     */
+                    let pj: Option<Job> =
+                        job::dsl::job.find(id).get_result(tx).optional()?;
+
+                    if !pj.map(|j| j.owner == owner).unwrap_or(false) {
+                        /*
+                         * Try not to leak information about job IDs from
+                         * other users in the process.
+                         */
+                        bail!("prior job does not exist");
+                    }
+
+                    id
+                }
+                CreatePriorJob::Batch(idx) => {
+                    /*
+                     * The prior job was created earlier in this same batch,
+                     * and thus in this same transaction.
+                     */
+                    let Some(pj) = batch.get(idx) else {
+                        bail!("prior job is not an earlier job in this batch");
+                    };
+
+                    pj.id
+                }
+            };
+
+            let ic = diesel::insert_into(job_depend::dsl::job_depend)
+                .values(JobDepend::from_create(cd, j.id, prior_job))
+                .execute(tx)?;
+            assert_eq!(ic, 1);
+        }
+
+        for ci in inputs.iter() {
+            let ic = diesel::insert_into(job_input::dsl::job_input)
+                .values(JobInput::from_create(ci.as_str(), j.id))
+                .execute(tx)?;
+            assert_eq!(ic, 1);
+        }
+
+        for (i, rule) in output_rules.iter().enumerate() {
+            let ic = diesel::insert_into(job_output_rule::dsl::job_output_rule)
+                .values(JobOutputRule::from_create(rule, j.id, i))
+                .execute(tx)?;
+            assert_eq!(ic, 1);
+        }
+
+        for (n, v) in tags {
+            let ic = diesel::insert_into(job_tag::dsl::job_tag)
+                .values((
+                    job_tag::dsl::job.eq(j.id),
+                    job_tag::dsl::name.eq(n),
+                    job_tag::dsl::value.eq(v),
+                ))
+                .execute(tx)?;
+            assert_eq!(ic, 1);
+        }
+
+        Ok(j)
     }
 
     pub fn job_input_by_str(&self, job: &str, file: &str) -> Result<JobInput> {
@@ -989,15 +1730,23 @@ impl Database {
             .get_result(c)?)
     }
 
-    pub fn job_output(&self, job: JobId, file: JobFileId) -> Result<JobOutput> {
+    pub fn job_output(
+        &self,
+        job: JobId,
+        file: JobFileId,
+    ) -> OResult<JobOutput> {
         use schema::job_output;
 
         let c = &mut self.1.lock().unwrap().conn;
 
-        Ok(job_output::dsl::job_output
+        job_output::dsl::job_output
             .filter(job_output::dsl::job.eq(job))
             .filter(job_output::dsl::id.eq(file))
-            .get_result(c)?)
+            .get_result(c)
+            .optional()?
+            .ok_or_else(|| {
+                OperationError::NotFound(format!("output {file} for job {job}"))
+            })
     }
 
     pub fn published_file_by_name(
@@ -1017,6 +1766,59 @@ impl Database {
             .optional()?)
     }
 
+    /**
+     * List the files published in a series, newest first.
+     */
+    pub fn published_files_for_series(
+        &self,
+        owner: UserId,
+        series: &str,
+    ) -> Result<Vec<PublishedFile>> {
+        use schema::published_file;
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        let mut out: Vec<PublishedFile> = published_file::dsl::published_file
+            .filter(published_file::dsl::owner.eq(owner))
+            .filter(published_file::dsl::series.eq(series))
+            .get_results(c)?;
+
+        out.sort_by(|a, b| {
+            b.time_publish()
+                .cmp(&a.time_publish())
+                .then_with(|| b.version.cmp(&a.version))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+
+        Ok(out)
+    }
+
+    /**
+     * Locate the most recently published file with this name in a series.
+     */
+    pub fn published_file_latest(
+        &self,
+        owner: UserId,
+        series: &str,
+        name: &str,
+    ) -> Result<Option<PublishedFile>> {
+        use schema::published_file;
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        Ok(published_file::dsl::published_file
+            .filter(published_file::dsl::owner.eq(owner))
+            .filter(published_file::dsl::series.eq(series))
+            .filter(published_file::dsl::name.eq(name))
+            .get_results::<PublishedFile>(c)?
+            .into_iter()
+            .max_by(|a, b| {
+                a.time_publish()
+                    .cmp(&b.time_publish())
+                    .then_with(|| a.version.cmp(&b.version))
+            }))
+    }
+
     pub fn job_publish_output(
         &self,
         job: JobId,
@@ -1073,6 +1875,7 @@ impl Database {
                     series: series.to_string(),
                     version: version.to_string(),
                     name: name.to_string(),
+                    time_publish: Some(IsoDate::now()),
                 })
                 .execute(tx)?;
             assert!(ic == 1);
@@ -1087,6 +1890,8 @@ impl Database {
         path: &str,
         id: JobFileId,
         size: u64,
+        sha256: &str,
+        content_type: Option<&str>,
     ) -> OResult<()> {
         use schema::{job, job_file, job_output};
 
@@ -1104,12 +1909,18 @@ impl Database {
                     id,
                     size: DataSize(size),
                     time_archived: None,
+                    sha256: Some(sha256.to_string()),
                 })
                 .execute(tx)?;
             assert_eq!(ic, 1);
 
             let ic = diesel::insert_into(job_output::dsl::job_output)
-                .values(JobOutput { job, path: path.to_string(), id })
+                .values(JobOutput {
+                    job,
+                    path: path.to_string(),
+                    id,
+                    content_type: content_type.map(str::to_string),
+                })
                 .execute(tx)?;
             assert_eq!(ic, 1);
 
@@ -1123,6 +1934,7 @@ impl Database {
         name: &str,
         id: JobFileId,
         size: u64,
+        sha256: &str,
     ) -> OResult<()> {
         use schema::{job, job_file, job_input};
 
@@ -1144,6 +1956,7 @@ impl Database {
                     id,
                     size: DataSize(size),
                     time_archived: None,
+                    sha256: Some(sha256.to_string()),
                 })
                 .execute(tx)?;
             assert_eq!(ic, 1);
@@ -1159,18 +1972,33 @@ impl Database {
         })
     }
 
-    pub fn job_next_unarchived(&self) -> OResult<Option<Job>> {
-        use schema::job;
+    pub fn job_next_unarchived(
+        &self,
+        completed_before: DateTime<Utc>,
+    ) -> OResult<Option<Job>> {
+        use schema::{job, job_time};
 
         let c = &mut self.1.lock().unwrap().conn;
 
         /*
          * Find the oldest completed job that has not yet been archived to long
-         * term storage.
+         * term storage.  Jobs that completed at or after the specified time are
+         * skipped; jobs that completed before we recorded completion times are
+         * always eligible.
          */
         let res: Option<Job> = job::dsl::job
             .filter(job::dsl::complete.eq(true))
             .filter(job::dsl::time_archived.is_null())
+            .filter(
+                job::dsl::id.ne_all(
+                    job_time::dsl::job_time
+                        .select(job_time::dsl::job)
+                        .filter(job_time::dsl::name.eq("complete"))
+                        .filter(
+                            job_time::dsl::time.ge(IsoDate(completed_before)),
+                        ),
+                ),
+            )
             .order_by(job::dsl::id.asc())
             .limit(1)
             .get_result(c)
@@ -1198,6 +2026,152 @@ impl Database {
         Ok(())
     }
 
+    /**
+     * Recreate the records for an archived job that is missing from the
+     * database; e.g., because the database was restored from a backup that
+     * predates the job.  The job must not already exist.
+     */
+    pub fn job_import(&self, ij: &ImportJob) -> OResult<()> {
+        use schema::{
+            job, job_depend, job_event, job_file, job_input, job_metric,
+            job_output, job_output_rule, job_store, job_tag, task, user,
+        };
+
+        let ImportJob {
+            job: j,
+            tasks,
+            output_rules,
+            inputs,
+            outputs,
+            events,
+            tags,
+            times,
+            depends,
+            store,
+            metrics,
+        } = ij;
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        c.immediate_transaction(|tx| {
+            let ej: Option<Job> =
+                job::dsl::job.find(j.id).get_result(tx).optional()?;
+            if ej.is_some() {
+                conflict!("job {} already exists", j.id);
+            }
+
+            let owner: Option<User> =
+                user::dsl::user.find(j.owner).get_result(tx).optional()?;
+            if owner.is_none() {
+                conflict!("owner {} of job {} does not exist", j.owner, j.id);
+            }
+
+            let ic =
+                diesel::insert_into(job::dsl::job).values(j).execute(tx)?;
+            assert_eq!(ic, 1);
+
+            for t in tasks {
+                let ic = diesel::insert_into(task::dsl::task)
+                    .values(t)
+                    .execute(tx)?;
+                assert_eq!(ic, 1);
+            }
+
+            for r in output_rules {
+                let ic =
+                    diesel::insert_into(job_output_rule::dsl::job_output_rule)
+                        .values(r)
+                        .execute(tx)?;
+                assert_eq!(ic, 1);
+            }
+
+            for (i, f) in inputs {
+                /*
+                 * Inputs may be files uploaded for this job, or outputs of a
+                 * prior job, and in either case the file record may already
+                 * exist.
+                 */
+                if let Some(f) = f {
+                    diesel::insert_into(job_file::dsl::job_file)
+                        .values(f)
+                        .on_conflict_do_nothing()
+                        .execute(tx)?;
+                }
+
+                let ic = diesel::insert_into(job_input::dsl::job_input)
+                    .values(i)
+                    .execute(tx)?;
+                assert_eq!(ic, 1);
+            }
+
+            for (o, f) in outputs {
+                /*
+                 * The file may have been copied from a prior job, in which
+                 * case the file record may already exist.
+                 */
+                diesel::insert_into(job_file::dsl::job_file)
+                    .values(f)
+                    .on_conflict_do_nothing()
+                    .execute(tx)?;
+
+                let ic = diesel::insert_into(job_output::dsl::job_output)
+                    .values(o)
+                    .execute(tx)?;
+                assert_eq!(ic, 1);
+            }
+
+            let mut stats: HashMap<String, StreamStats> = HashMap::new();
+            for ev in events {
+                let ic = diesel::insert_into(job_event::dsl::job_event)
+                    .values(ev)
+                    .execute(tx)?;
+                assert_eq!(ic, 1);
+                stats.entry(ev.stream.clone()).or_default().add(&ev.payload);
+            }
+            for (stream, ss) in stats {
+                self.i_job_stream_stat_add(tx, j.id, &stream, ss)?;
+            }
+
+            for d in depends {
+                let ic = diesel::insert_into(job_depend::dsl::job_depend)
+                    .values(d)
+                    .execute(tx)?;
+                assert_eq!(ic, 1);
+            }
+
+            for s in store {
+                let ic = diesel::insert_into(job_store::dsl::job_store)
+                    .values(s)
+                    .execute(tx)?;
+                assert_eq!(ic, 1);
+            }
+
+            for m in metrics {
+                let ic = diesel::insert_into(job_metric::dsl::job_metric)
+                    .values(m)
+                    .execute(tx)?;
+                assert_eq!(ic, 1);
+            }
+
+            for (n, v) in tags {
+                let ic = diesel::insert_into(job_tag::dsl::job_tag)
+                    .values((
+                        job_tag::dsl::job.eq(j.id),
+                        job_tag::dsl::name.eq(n),
+                        job_tag::dsl::value.eq(v),
+                    ))
+                    .execute(tx)?;
+                assert_eq!(ic, 1);
+            }
+
+            for (n, t) in times {
+                self.i_job_time_record(tx, j.id, n, *t)?;
+            }
+
+            Ok(())
+        })
+    }
+
     pub fn job_file_next_unarchived(&self) -> OResult<Option<JobFile>> {
         use schema::{job, job_file};
 
@@ -1250,24 +2224,215 @@ impl Database {
     ) -> OResult<()> {
         use schema::job;
 
-        let c = &mut self.1.lock().unwrap().conn;
+        let c = &mut self.1.lock().unwrap().conn;
+
+        c.immediate_transaction(|tx| {
+            let j: Job = job::dsl::job.find(job).get_result(tx)?;
+            if j.complete {
+                conflict!("job already complete, cannot append");
+            }
+
+            Ok(self.i_job_event_insert(
+                tx,
+                j.id,
+                task,
+                stream,
+                time,
+                time_remote,
+                payload,
+            )?)
+        })
+    }
+
+    /**
+     * Append a stdout or stderr event from a worker, subject to a limit on the
+     * total size of such output for the job.  Once the limit is exceeded, a
+     * single control event is recorded and further output is discarded.  The
+     * job otherwise continues unimpeded.
+     */
+    #[allow(clippy::too_many_arguments)]
+    pub fn job_append_output_event(
+        &self,
+        job: JobId,
+        task: Option<u32>,
+        stream: &str,
+        time: DateTime<Utc>,
+        time_remote: Option<DateTime<Utc>>,
+        payload: &str,
+        max_bytes: u64,
+    ) -> OResult<()> {
+        let ev = CreateJobEvent {
+            task,
+            stream: stream.to_string(),
+            time,
+            time_remote,
+            payload: payload.to_string(),
+        };
+
+        self.job_append_events(job, &[ev], max_bytes)
+    }
+
+    /**
+     * Append a batch of events from a worker in a single transaction, so that
+     * the events are assigned contiguous sequence numbers.  Output events are
+     * subject to the same limit on total size as in job_append_output_event();
+     * other events are always recorded.
+     */
+    pub fn job_append_events(
+        &self,
+        job: JobId,
+        events: &[CreateJobEvent],
+        max_bytes: u64,
+    ) -> OResult<()> {
+        use schema::job;
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        c.immediate_transaction(|tx| {
+            let j: Job = job::dsl::job.find(job).get_result(tx)?;
+            if j.complete {
+                conflict!("job already complete, cannot append");
+            }
+
+            Ok(self.i_job_append_events(tx, &j, events, max_bytes)?)
+        })
+    }
+
+    /**
+     * Get the sequence number of the last frame committed from the event
+     * stream for this job, or zero if there has not been one.
+     */
+    pub fn job_stream_seq(&self, job: JobId) -> Result<u64> {
+        use schema::job_stream;
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        let js: Option<JobStream> =
+            job_stream::dsl::job_stream.find(job).get_result(c).optional()?;
+
+        Ok(js.map(|js| js.seq.try_into()).transpose()?.unwrap_or(0))
+    }
+
+    /**
+     * Append a frame of events from the event stream for this job.  Frames
+     * have sequence numbers assigned by the worker, and we record the
+     * sequence number of the last frame in the same transaction as the events
+     * themselves.  A frame we have already committed is ignored, so that a
+     * worker may safely send a frame again if it did not see our
+     * acknowledgement.  Returns false if the frame was ignored.
+     */
+    pub fn job_append_stream_events(
+        &self,
+        job: JobId,
+        seq: u64,
+        events: &[CreateJobEvent],
+        max_bytes: u64,
+    ) -> OResult<bool> {
+        use schema::{job, job_stream};
+
+        let seq = i64::try_from(seq)
+            .map_err(|_| anyhow!("stream sequence {seq} out of range"))?;
+        let c = &mut self.1.lock().unwrap().conn;
+
+        c.immediate_transaction(|tx| {
+            let j: Job = job::dsl::job.find(job).get_result(tx)?;
+            if j.complete {
+                conflict!("job already complete, cannot append");
+            }
+
+            let pre: Option<JobStream> = job_stream::dsl::job_stream
+                .find(j.id)
+                .get_result(tx)
+                .optional()?;
+            if pre.map(|pre| seq <= pre.seq).unwrap_or(false) {
+                return Ok(false);
+            }
+
+            self.i_job_append_events(tx, &j, events, max_bytes)?;
+
+            diesel::replace_into(job_stream::dsl::job_stream)
+                .values(JobStream {
+                    job: j.id,
+                    seq,
+                    time_update: IsoDate::now(),
+                })
+                .execute(tx)?;
+
+            Ok(true)
+        })
+    }
+
+    fn i_job_append_events(
+        &self,
+        tx: &mut SqliteConnection,
+        j: &Job,
+        events: &[CreateJobEvent],
+        max_bytes: u64,
+    ) -> Result<()> {
+        use schema::job;
+
+        /*
+         * Rather than update the stream statistics for each event, collect
+         * them for the whole batch and update each stream once at the end.
+         */
+        let mut stats: HashMap<String, StreamStats> = HashMap::new();
+
+        let mut bytes = j.event_bytes.0;
+        for ev in events {
+            if !ev.is_output() {
+                self.i_job_event_row_insert(
+                    tx,
+                    j.id,
+                    ev.task,
+                    &ev.stream,
+                    ev.time,
+                    ev.time_remote,
+                    &ev.payload,
+                )?;
+                stats.entry(ev.stream.clone()).or_default().add(&ev.payload);
+                continue;
+            }
 
-        c.immediate_transaction(|tx| {
-            let j: Job = job::dsl::job.find(job).get_result(tx)?;
-            if j.complete {
-                conflict!("job already complete, cannot append");
+            let before = bytes;
+            bytes = before.saturating_add(ev.payload.len() as u64);
+
+            if bytes <= max_bytes {
+                self.i_job_event_row_insert(
+                    tx,
+                    j.id,
+                    ev.task,
+                    &ev.stream,
+                    ev.time,
+                    ev.time_remote,
+                    &ev.payload,
+                )?;
+                stats.entry(ev.stream.clone()).or_default().add(&ev.payload);
+            } else if before <= max_bytes {
+                let msg = format!(
+                    "job output exceeded {} bytes; \
+                    further output will be discarded",
+                    max_bytes,
+                );
+                self.i_job_event_row_insert(
+                    tx, j.id, ev.task, "control", ev.time, None, &msg,
+                )?;
+                stats.entry("control".into()).or_default().add(&msg);
             }
+        }
 
-            Ok(self.i_job_event_insert(
-                tx,
-                j.id,
-                task,
-                stream,
-                time,
-                time_remote,
-                payload,
-            )?)
-        })
+        for (stream, ss) in stats {
+            self.i_job_stream_stat_add(tx, j.id, &stream, ss)?;
+        }
+
+        if bytes != j.event_bytes.0 {
+            let uc = diesel::update(job::dsl::job)
+                .filter(job::dsl::id.eq(j.id))
+                .set(job::dsl::event_bytes.eq(DataSize(bytes)))
+                .execute(tx)?;
+            assert_eq!(uc, 1);
+        }
+
+        Ok(())
     }
 
     pub fn job_wakeup(&self, job: JobId) -> OResult<()> {
@@ -1318,7 +2483,127 @@ impl Database {
         })
     }
 
-    pub fn job_cancel(&self, job: JobId) -> OResult<bool> {
+    /**
+     * Detach a running job from its worker and return it to the queue, so that
+     * it can be assigned to another worker.  This is only possible if the
+     * worker has not pinged within the specified threshold, as a worker that
+     * is still alive may yet be running the job.  The worker is marked for
+     * recycling, and its ID is returned.
+     */
+    pub fn job_requeue(
+        &self,
+        job: JobId,
+        stale: std::time::Duration,
+        message: &str,
+    ) -> OResult<WorkerId> {
+        use schema::{job, job_file, job_output, job_time, task, worker};
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        c.immediate_transaction(|tx| {
+            let j: Job = job::dsl::job.find(job).get_result(tx)?;
+            if j.complete {
+                conflict!("job {} is already complete", j.id);
+            }
+            if j.cancelled {
+                conflict!("job {} has been cancelled", j.id);
+            }
+            let Some(wid) = j.worker else {
+                conflict!("job {} is not assigned to a worker", j.id);
+            };
+
+            let w: Worker = worker::dsl::worker.find(wid).get_result(tx)?;
+            if !w.deleted {
+                /*
+                 * A worker that has not yet pinged may still be starting up,
+                 * so we measure from the time it was created instead.
+                 */
+                let last = w
+                    .lastping
+                    .as_ref()
+                    .map(|t| t.0)
+                    .unwrap_or_else(|| w.id.datetime());
+                let since = Utc::now()
+                    .signed_duration_since(last)
+                    .to_std()
+                    .unwrap_or_default();
+                if since < stale {
+                    conflict!(
+                        "worker {} was last heard from {} seconds ago and may \
+                        still be running job {}",
+                        w.id,
+                        since.as_secs(),
+                        j.id,
+                    );
+                }
+            }
+
+            /*
+             * Discard any progress the job made on the previous worker, so
+             * that it starts afresh on the next one.
+             */
+            diesel::update(task::dsl::task)
+                .filter(task::dsl::job.eq(j.id))
+                .set((
+                    task::dsl::complete.eq(false),
+                    task::dsl::failed.eq(false),
+                    task::dsl::failure_code.eq(None::<i32>),
+                    task::dsl::failure_signal.eq(None::<i32>),
+                    task::dsl::failure_message.eq(None::<String>),
+                ))
+                .execute(tx)?;
+            let files: Vec<JobFileId> = job_output::dsl::job_output
+                .select(job_output::dsl::id)
+                .filter(job_output::dsl::job.eq(j.id))
+                .get_results(tx)?;
+            diesel::delete(job_output::dsl::job_output)
+                .filter(job_output::dsl::job.eq(j.id))
+                .execute(tx)?;
+            diesel::delete(job_file::dsl::job_file)
+                .filter(job_file::dsl::job.eq(j.id))
+                .filter(job_file::dsl::id.eq_any(files))
+                .execute(tx)?;
+            diesel::delete(job_time::dsl::job_time)
+                .filter(job_time::dsl::job.eq(j.id))
+                .filter(job_time::dsl::name.eq("assigned"))
+                .execute(tx)?;
+
+            let uc = diesel::update(job::dsl::job)
+                .filter(job::dsl::id.eq(j.id))
+                .filter(job::dsl::worker.eq(wid))
+                .set(job::dsl::worker.eq(None::<WorkerId>))
+                .execute(tx)?;
+            assert_eq!(uc, 1);
+
+            diesel::update(worker::dsl::worker)
+                .filter(worker::dsl::id.eq(wid))
+                .set(worker::dsl::recycle.eq(true))
+                .execute(tx)?;
+
+            self.i_job_event_insert(
+                tx,
+                j.id,
+                None,
+                "control",
+                Utc::now(),
+                None,
+                message,
+            )?;
+
+            Ok(wid)
+        })
+    }
+
+    /**
+     * Cancel a job.  The message is appended to the job as a control event,
+     * so that anybody watching the job can see why it stopped.
+     */
+    pub fn job_cancel(
+        &self,
+        job: JobId,
+        message: &str,
+        reason: Option<&str>,
+    ) -> OResult<bool> {
         use schema::job;
 
         let c = &mut self.1.lock().unwrap().conn;
@@ -1336,6 +2621,12 @@ impl Database {
                 return Ok(false);
             }
 
+            let message = if let Some(reason) = reason {
+                format!("{message}: {reason}")
+            } else {
+                message.to_string()
+            };
+
             self.i_job_event_insert(
                 tx,
                 j.id,
@@ -1343,13 +2634,16 @@ impl Database {
                 "control",
                 Utc::now(),
                 None,
-                "job cancelled",
+                &message,
             )?;
 
             let uc = diesel::update(job::dsl::job)
                 .filter(job::dsl::id.eq(j.id))
                 .filter(job::dsl::complete.eq(false))
-                .set((job::dsl::cancelled.eq(true),))
+                .set((
+                    job::dsl::cancelled.eq(true),
+                    job::dsl::cancel_reason.eq(reason),
+                ))
                 .execute(tx)?;
             assert_eq!(uc, 1);
 
@@ -1357,7 +2651,12 @@ impl Database {
         })
     }
 
-    pub fn job_complete(&self, job: JobId, failed: bool) -> Result<bool> {
+    pub fn job_complete(
+        &self,
+        job: JobId,
+        failed: bool,
+        failure_reason: Option<&str>,
+    ) -> Result<bool> {
         use schema::{job, task};
 
         let c = &mut self.1.lock().unwrap().conn;
@@ -1432,13 +2731,40 @@ impl Database {
                 )?;
                 true
             } else {
-                false
+                /*
+                 * The agent checks that every output rule that requires a
+                 * match was matched by at least one uploaded file, but we
+                 * confirm it here so that a missing artefact always fails the
+                 * job.
+                 */
+                let unmatched = self.i_job_unmatched_output_rules(tx, j.id)?;
+                for rule in unmatched.iter() {
+                    self.i_job_event_insert(
+                        tx,
+                        j.id,
+                        None,
+                        "control",
+                        Utc::now(),
+                        None,
+                        &format!(
+                            "job failed because output rule {:?} required a \
+                            match, but no output file matched",
+                            rule,
+                        ),
+                    )?;
+                }
+                !unmatched.is_empty()
             };
 
             let uc = diesel::update(job::dsl::job)
                 .filter(job::dsl::id.eq(j.id))
                 .filter(job::dsl::complete.eq(false))
-                .set((job::dsl::failed.eq(failed), job::dsl::complete.eq(true)))
+                .set((
+                    job::dsl::failed.eq(failed),
+                    job::dsl::complete.eq(true),
+                    job::dsl::failure_reason
+                        .eq(failure_reason.filter(|_| failed)),
+                ))
                 .execute(tx)?;
             assert_eq!(uc, 1);
 
@@ -1631,11 +2957,68 @@ impl Database {
         })
     }
 
+    /**
+     * Record a set of resource usage samples from the worker for a job.  We
+     * keep only the highest value seen for each metric.
+     */
+    pub fn job_metrics_update(
+        &self,
+        job: JobId,
+        samples: &[(&str, u64)],
+    ) -> OResult<()> {
+        use schema::{job, job_metric};
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        c.immediate_transaction(|tx| {
+            let j: Job = job::dsl::job.find(job).get_result(tx)?;
+            if j.complete {
+                conflict!("job {job} already complete; cannot update metrics");
+            }
+
+            for (name, value) in samples {
+                let pre: Option<JobMetric> = job_metric::dsl::job_metric
+                    .find((job, *name))
+                    .get_result(tx)
+                    .optional()?;
+
+                if pre.as_ref().map(|m| m.value.0 >= *value).unwrap_or(false) {
+                    continue;
+                }
+
+                diesel::replace_into(job_metric::dsl::job_metric)
+                    .values(JobMetric {
+                        job,
+                        name: name.to_string(),
+                        value: DataSize(*value),
+                        time_update: IsoDate::now(),
+                    })
+                    .execute(tx)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    pub fn job_metrics(&self, job: JobId) -> Result<HashMap<String, u64>> {
+        use schema::job_metric;
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        Ok(job_metric::dsl::job_metric
+            .filter(job_metric::dsl::job.eq(job))
+            .get_results::<JobMetric>(c)?
+            .into_iter()
+            .map(|jm| (jm.name, jm.value.0))
+            .collect())
+    }
+
     pub fn task_complete(
         &self,
         job: JobId,
         seq: u32,
         failed: bool,
+        failure: Option<&FailureSummary>,
     ) -> Result<bool> {
         use schema::task;
 
@@ -1655,6 +3038,11 @@ impl Database {
                 .set((
                     task::dsl::complete.eq(true),
                     task::dsl::failed.eq(failed),
+                    task::dsl::failure_code.eq(failure.and_then(|f| f.code)),
+                    task::dsl::failure_signal
+                        .eq(failure.and_then(|f| f.signal)),
+                    task::dsl::failure_message
+                        .eq(failure.and_then(|f| f.message.as_deref())),
                 ))
                 .execute(tx)?;
             assert_eq!(uc, 1);
@@ -1673,6 +3061,36 @@ impl Database {
         time: DateTime<Utc>,
         time_remote: Option<DateTime<Utc>>,
         payload: &str,
+    ) -> Result<()> {
+        self.i_job_event_row_insert(
+            tx,
+            job,
+            task,
+            stream,
+            time,
+            time_remote,
+            payload,
+        )?;
+
+        let mut ss = StreamStats::default();
+        ss.add(payload);
+        self.i_job_stream_stat_add(tx, job, stream, ss)
+    }
+
+    /**
+     * Insert an event without updating the statistics for its stream.  The
+     * caller is responsible for doing so with i_job_stream_stat_add().
+     */
+    #[allow(clippy::too_many_arguments)]
+    fn i_job_event_row_insert(
+        &self,
+        tx: &mut SqliteConnection,
+        job: JobId,
+        task: Option<u32>,
+        stream: &str,
+        time: DateTime<Utc>,
+        time_remote: Option<DateTime<Utc>>,
+        payload: &str,
     ) -> Result<()> {
         use schema::job_event;
 
@@ -1697,12 +3115,91 @@ impl Database {
         Ok(())
     }
 
-    pub fn user_jobs(&self, owner: UserId) -> Result<Vec<Job>> {
+    /**
+     * Keep a running count of the events and bytes recorded for each stream,
+     * so that a summary is available without scanning the log.
+     */
+    fn i_job_stream_stat_add(
+        &self,
+        tx: &mut SqliteConnection,
+        job: JobId,
+        stream: &str,
+        add: StreamStats,
+    ) -> Result<()> {
+        use schema::job_stream_stat;
+
+        let pre: Option<JobStreamStat> = job_stream_stat::dsl::job_stream_stat
+            .find((job, stream))
+            .get_result(tx)
+            .optional()?;
+        let ss = pre
+            .map(|pre| StreamStats { events: pre.events.0, bytes: pre.bytes.0 })
+            .unwrap_or_default();
+
+        diesel::replace_into(job_stream_stat::dsl::job_stream_stat)
+            .values(JobStreamStat {
+                job,
+                stream: stream.to_string(),
+                events: EventCount(ss.events.saturating_add(add.events)),
+                bytes: DataSize(ss.bytes.saturating_add(add.bytes)),
+            })
+            .execute(tx)?;
+
+        Ok(())
+    }
+
+    pub fn job_stream_stats(
+        &self,
+        job: JobId,
+    ) -> Result<HashMap<String, StreamStats>> {
+        use schema::job_stream_stat;
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        Ok(job_stream_stat::dsl::job_stream_stat
+            .filter(job_stream_stat::dsl::job.eq(job))
+            .get_results::<JobStreamStat>(c)?
+            .into_iter()
+            .map(|ss| {
+                let stats =
+                    StreamStats { events: ss.events.0, bytes: ss.bytes.0 };
+                (ss.stream, stats)
+            })
+            .collect())
+    }
+
+    /**
+     * Enumerate the jobs owned by a user, most recently created first.  Only
+     * jobs that have every nominated tag name and value will be included.  If
+     * "before" is specified, only jobs with an ID lower than that job will be
+     * included, which allows the caller to page through the list.
+     */
+    pub fn user_jobs(
+        &self,
+        owner: UserId,
+        tags: &[(String, String)],
+        before: Option<JobId>,
+        limit: Option<usize>,
+    ) -> Result<Vec<Job>> {
         use schema::job;
 
         let c = &mut self.1.lock().unwrap().conn;
 
-        Ok(job::dsl::job.filter(job::dsl::owner.eq(owner)).get_results(c)?)
+        let q = job::dsl::job
+            .filter(job::dsl::owner.eq(owner))
+            .order_by(job::dsl::id.desc())
+            .into_boxed();
+        let mut q = Self::i_jobs_with_tags(q, tags);
+
+        if let Some(before) = before {
+            q = q.filter(job::dsl::id.lt(before));
+        }
+
+        if let Some(limit) = limit {
+            q = q.limit(limit.try_into().unwrap_or(i64::MAX));
+        }
+
+        Ok(q.get_results(c)?)
     }
 
     pub fn worker_job(&self, worker: WorkerId) -> Result<Option<Job>> {
@@ -1862,6 +3359,7 @@ impl Database {
             name: name.to_string(),
             token: genkey(48),
             time_create: Utc::now().into(),
+            token_expiry: None,
         };
 
         diesel::insert_into(dsl::user).values(&u).execute(tx)?;
@@ -1883,6 +3381,108 @@ impl Database {
         self.i_user_create(name, c)
     }
 
+    /**
+     * Replace the token for this user with a freshly generated one.  Any
+     * expiry time set on the old token is cleared.
+     */
+    pub fn user_token_rotate(&self, id: UserId) -> Result<User> {
+        use schema::user::dsl;
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        c.immediate_transaction(|tx| {
+            let uc = diesel::update(dsl::user)
+                .filter(dsl::id.eq(id))
+                .set((
+                    dsl::token.eq(genkey(48)),
+                    dsl::token_expiry.eq(None::<IsoDate>),
+                ))
+                .execute(tx)?;
+            if uc != 1 {
+                bail!("user {} not found", id);
+            }
+
+            Ok(dsl::user.find(id).get_result(tx)?)
+        })
+    }
+
+    pub fn user_token_expiry_set(
+        &self,
+        id: UserId,
+        expiry: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        use schema::user::dsl;
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        let uc = diesel::update(dsl::user)
+            .filter(dsl::id.eq(id))
+            .set(dsl::token_expiry.eq(expiry.map(IsoDate)))
+            .execute(c)?;
+        if uc != 1 {
+            bail!("user {} not found", id);
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Create a token that allows read-only access to a single job, which
+     * remains valid until the expiry time.
+     */
+    pub fn job_share_create(
+        &self,
+        job: JobId,
+        expiry: DateTime<Utc>,
+    ) -> Result<JobShare> {
+        use schema::job_share::dsl;
+
+        let js = JobShare {
+            token: genkey(64),
+            job,
+            time_create: IsoDate::now(),
+            time_expiry: Some(IsoDate(expiry)),
+        };
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        let ic = diesel::insert_into(dsl::job_share).values(&js).execute(c)?;
+        assert_eq!(ic, 1);
+
+        Ok(js)
+    }
+
+    pub fn job_share_auth(&self, token: &str) -> Result<JobShare> {
+        use schema::job_share::dsl;
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        let Some(js) =
+            dsl::job_share.find(token).get_result::<JobShare>(c).optional()?
+        else {
+            bail!("share token invalid");
+        };
+
+        if js.is_expired(Utc::now()) {
+            bail!("share token for job {} expired", js.job);
+        }
+
+        Ok(js)
+    }
+
+    /**
+     * Remove every share token for this job, returning the number removed.
+     */
+    pub fn job_share_revoke(&self, job: JobId) -> Result<usize> {
+        use schema::job_share::dsl;
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        Ok(diesel::delete(dsl::job_share)
+            .filter(dsl::job.eq(job))
+            .execute(c)?)
+    }
+
     pub fn user_ensure(&self, name: &str) -> Result<AuthUser> {
         use schema::user::dsl;
 
@@ -1920,10 +3520,19 @@ impl Database {
             .get_results(c)?;
 
         match (users.pop(), users.pop()) {
-            (None, _) => bail!("auth failure"),
+            (None, _) => bail!("token invalid"),
             (Some(u), Some(x)) => bail!("token error ({}, {})", u.id, x.id),
             (Some(u), None) => {
                 assert_eq!(&u.token, token);
+                if let Some(expiry) = &u.token_expiry {
+                    if expiry.0 <= Utc::now() {
+                        bail!(
+                            "token expired for user {} at {}",
+                            u.id,
+                            expiry.0
+                        );
+                    }
+                }
                 Ok(AuthUser {
                     privileges: self.user_privileges(u.id, c)?,
                     user: u,
@@ -2050,7 +3659,13 @@ impl Database {
         Ok(t)
     }
 
-    pub fn target_resolve(&self, name: &str) -> Result<Option<Target>> {
+    /**
+     * Resolve a target name to a target, following any redirects.  Operators
+     * may accidentally construct a redirect cycle, or an excessively long
+     * chain of redirects, while migrating targets; either is reported as a
+     * conflict rather than resolved.
+     */
+    pub fn target_resolve(&self, name: &str) -> OResult<Option<Target>> {
         use schema::target::dsl;
 
         let c = &mut self.1.lock().unwrap().conn;
@@ -2066,25 +3681,34 @@ impl Database {
             return Ok(None);
         };
 
-        let mut count = 0;
-        loop {
-            if count > 32 {
-                bail!("too many target redirects starting from {:?}", name);
+        let mut chain = vec![target.name.clone()];
+        let mut seen = HashSet::new();
+        seen.insert(target.id);
+
+        while let Some(redirect) = target.redirect {
+            if chain.len() > MAX_TARGET_REDIRECTS {
+                conflict!(
+                    "target {:?} has more than {} redirects",
+                    name,
+                    MAX_TARGET_REDIRECTS,
+                );
             }
-            count += 1;
 
-            if let Some(redirect) = &target.redirect {
-                target = if let Some(target) =
-                    dsl::target.find(redirect).get_result(c).optional()?
-                {
-                    target
-                } else {
-                    return Ok(None);
-                };
+            target = if let Some(target) =
+                dsl::target.find(redirect).get_result(c).optional()?
+            {
+                target
             } else {
-                return Ok(Some(target));
+                return Ok(None);
+            };
+
+            chain.push(target.name.clone());
+            if !seen.insert(target.id) {
+                conflict!("target redirect cycle: {}", chain.join(" -> "));
             }
         }
+
+        Ok(Some(target))
     }
 
     pub fn target_require(
@@ -2196,4 +3820,703 @@ impl Database {
             Ok(nt)
         })
     }
+
+    /**
+     * Record an audit log entry.
+     */
+    pub fn audit_log_record(&self, entry: &AuditLog) -> Result<()> {
+        use schema::audit_log::dsl;
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        let ic =
+            diesel::insert_into(dsl::audit_log).values(entry).execute(c)?;
+        assert_eq!(ic, 1);
+
+        Ok(())
+    }
+
+    /**
+     * Remove audit log entries recorded before the nominated time, returning
+     * the number removed.
+     */
+    pub fn audit_log_prune(&self, before: DateTime<Utc>) -> Result<usize> {
+        use schema::audit_log::dsl;
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        Ok(diesel::delete(dsl::audit_log)
+            .filter(dsl::time.lt(IsoDate(before)))
+            .execute(c)?)
+    }
+
+    /**
+     * List audit log entries, most recent first, optionally restricted to a
+     * particular time range or actor.
+     */
+    pub fn audit_log(
+        &self,
+        after: Option<DateTime<Utc>>,
+        before: Option<DateTime<Utc>>,
+        actor: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<AuditLog>> {
+        use schema::audit_log::dsl;
+
+        let c = &mut self.1.lock().unwrap().conn;
+
+        let mut q = dsl::audit_log
+            .order_by(dsl::time.desc())
+            .limit(limit.try_into().unwrap_or(i64::MAX))
+            .into_boxed();
+
+        if let Some(after) = after {
+            q = q.filter(dsl::time.ge(IsoDate(after)));
+        }
+        if let Some(before) = before {
+            q = q.filter(dsl::time.lt(IsoDate(before)));
+        }
+        if let Some(actor) = actor {
+            q = q.filter(dsl::actor.eq(actor));
+        }
+
+        Ok(q.get_results(c)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        CreateJob, CreateJobEvent, CreateOutputRule, CreateTask, DataSize,
+        Database, FailureSummary, ImportJob, IsoDate, Job, JobFileId,
+        JobMetric, OperationError, StreamStats, Target, TooManyActiveJobs,
+        User,
+    };
+    use anyhow::Result;
+
+    #[test]
+    fn target_redirect_cycle() -> Result<()> {
+        let (_dir, db) = test_db()?;
+
+        let a = db.target_create("a", "target a")?;
+        let b = db.target_create("b", "target b")?;
+        db.target_redirect(a.id, Some(b.id))?;
+
+        let t = db.target_resolve("a")?.expect("target a should resolve");
+        assert_eq!(t.id, b.id);
+
+        db.target_redirect(b.id, Some(a.id))?;
+
+        match db.target_resolve("a") {
+            Err(OperationError::Conflict(msg)) => {
+                assert!(msg.contains("cycle"), "unexpected message: {}", msg);
+            }
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(t) => panic!("wanted failure, got {:?}", t),
+        }
+
+        Ok(())
+    }
+
+    fn job_def(t: &Target, name: &str) -> CreateJob {
+        CreateJob {
+            name: name.into(),
+            target_name: t.name.clone(),
+            target: t.id,
+            tasks: vec![CreateTask {
+                name: "task".into(),
+                script: "true".into(),
+                env_clear: false,
+                env: Default::default(),
+                user_id: None,
+                group_id: None,
+                workdir: None,
+                always_run: false,
+                timeout_seconds: None,
+            }],
+            output_rules: Default::default(),
+            inputs: Default::default(),
+            tags: Default::default(),
+            depends: Default::default(),
+            max_active: None,
+        }
+    }
+
+    /**
+     * Create an empty database in a temporary directory.  The directory is
+     * removed when the returned handle is dropped, so the caller must keep it
+     * for as long as the database is in use.
+     */
+    fn test_db() -> Result<(tempfile::TempDir, Database)> {
+        let dir = tempfile::tempdir()?;
+        let log = slog::Logger::root(slog::Discard, slog::o!());
+        let db = Database::new(log, dir.path().join("data.sqlite3"), None)?;
+
+        Ok((dir, db))
+    }
+
+    /**
+     * Create a user and a target for test jobs.  The database already contains
+     * a target named "default", so we use another name.
+     */
+    fn user_and_target(db: &Database) -> Result<(User, Target)> {
+        let u = db.user_create("user")?;
+        let t = db.target_create("test", "test target")?;
+
+        Ok((u, t))
+    }
+
+    fn create_job(db: &Database) -> Result<(Target, Job)> {
+        let (u, t) = user_and_target(db)?;
+
+        let j = db.job_create(u.id, job_def(&t, "job"))?;
+
+        Ok((t, j))
+    }
+
+    #[test]
+    fn job_cancel_while_running() -> Result<()> {
+        let (_dir, db) = test_db()?;
+
+        let (t, j) = create_job(&db)?;
+        let f = db.factory_create("factory")?;
+        let w = db.worker_create(&f, &t, None, false)?;
+        db.worker_assign_job(w.id, j.id)?;
+
+        let wj = db.worker_job(w.id)?.expect("worker should have a job");
+        assert_eq!(wj.id, j.id);
+        assert!(!wj.cancelled);
+
+        /*
+         * Cancel the job while it is running.  The worker must be able to see
+         * that the job it is working on has been cancelled, and the job may
+         * no longer complete successfully.
+         */
+        assert!(db.job_cancel(j.id, "job cancelled", Some("test"))?);
+        assert!(!db.job_cancel(j.id, "job cancelled", None)?);
+
+        let wj = db.worker_job(w.id)?.expect("worker should have a job");
+        assert!(wj.cancelled);
+        assert!(!wj.complete);
+        assert_eq!(wj.cancel_reason.as_deref(), Some("test"));
+
+        assert!(db.job_complete(j.id, false, None).is_err());
+        assert!(db.job_complete(j.id, true, None)?);
+
+        match db.job_cancel(j.id, "job cancelled", None) {
+            Err(OperationError::Conflict(_)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn job_import_restores_records() -> Result<()> {
+        let (_dir, db) = test_db()?;
+
+        let (_, j) = create_job(&db)?;
+        db.job_store_put(j.id, "key", "value", false, "user")?;
+        db.job_metrics_update(j.id, &[("memory", 100)])?;
+        db.job_append_event(
+            j.id,
+            Some(0),
+            "stdout",
+            chrono::Utc::now(),
+            None,
+            "hi",
+        )?;
+        assert!(db.job_complete(j.id, false, None)?);
+        let j = db.job_by_id(j.id)?;
+
+        /*
+         * Import the job into a fresh database, as if it had been restored
+         * from a backup taken before the job was created.
+         */
+        let (_dir2, db2) = test_db()?;
+        let (u2, t2) = user_and_target(&db2)?;
+        let mut job = db.job_by_id(j.id)?;
+        job.owner = u2.id;
+        job.target_id = Some(t2.id);
+        let ij = ImportJob {
+            job,
+            tasks: db.job_tasks(j.id)?,
+            output_rules: db.job_output_rules(j.id)?,
+            inputs: db.job_inputs(j.id)?,
+            outputs: db.job_outputs(j.id)?,
+            events: db.job_events(j.id, 0, None, None)?,
+            tags: db.job_tags(j.id)?,
+            times: db.job_times(j.id)?,
+            depends: db.job_depends(j.id)?,
+            store: db.job_store(j.id)?.into_values().collect(),
+            metrics: db
+                .job_metrics(j.id)?
+                .into_iter()
+                .map(|(name, value)| JobMetric {
+                    job: j.id,
+                    name,
+                    value: DataSize(value),
+                    time_update: IsoDate(chrono::Utc::now()),
+                })
+                .collect(),
+        };
+        db2.job_import(&ij)?;
+
+        let j2 = db2.job_by_id(j.id)?;
+        assert!(j2.complete);
+        assert_eq!(j2.event_bytes.0, j.event_bytes.0);
+        assert_eq!(db2.job_store(j.id)?["key"].value, "value");
+        assert_eq!(db2.job_metrics(j.id)?["memory"], 100);
+        assert_eq!(
+            db2.job_stream_stats(j.id)?["stdout"].events,
+            db.job_stream_stats(j.id)?["stdout"].events,
+        );
+
+        /*
+         * A job can only be imported once.
+         */
+        match db2.job_import(&ij) {
+            Err(OperationError::Conflict(_)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn job_create_idempotent_keys() -> Result<()> {
+        let (_dir, db) = test_db()?;
+
+        let (u, t) = user_and_target(&db)?;
+        let u2 = db.user_create("other")?;
+        let long_ago = chrono::Utc::now() - chrono::Duration::hours(1);
+
+        /*
+         * Submitting again with the same key returns the original job.
+         */
+        let j1 =
+            db.job_create_idempotent(u.id, job_def(&t, "a"), "k", long_ago)?;
+        let j2 =
+            db.job_create_idempotent(u.id, job_def(&t, "b"), "k", long_ago)?;
+        assert_eq!(j1.id, j2.id);
+        assert_eq!(j2.name, "a");
+
+        /*
+         * Keys belong to the user that submitted the job; another user may use
+         * the same key without seeing the first user's job.
+         */
+        let j3 =
+            db.job_create_idempotent(u2.id, job_def(&t, "c"), "k", long_ago)?;
+        assert_ne!(j3.id, j1.id);
+
+        /*
+         * Once a key has expired, a new job is created.  Expired keys are
+         * discarded for every user, so the other user's key is gone too.
+         */
+        let now = chrono::Utc::now() + chrono::Duration::seconds(1);
+        let j4 = db.job_create_idempotent(u.id, job_def(&t, "d"), "k", now)?;
+        assert_ne!(j4.id, j1.id);
+        let j5 =
+            db.job_create_idempotent(u2.id, job_def(&t, "e"), "k", long_ago)?;
+        assert_ne!(j5.id, j3.id);
+
+        Ok(())
+    }
+
+    #[test]
+    fn jobs_filtered_by_tag() -> Result<()> {
+        let (_dir, db) = test_db()?;
+
+        let (u, t) = user_and_target(&db)?;
+        let mut jobs = Vec::new();
+        for (name, repo) in [("a", "x"), ("b", "y"), ("c", "x"), ("d", "x")] {
+            let mut cj = job_def(&t, name);
+            cj.tags.insert("repo".into(), repo.into());
+            jobs.push(db.job_create(u.id, cj)?);
+        }
+        for j in &jobs[0..3] {
+            assert!(db.job_complete(j.id, false, None)?);
+        }
+
+        let x = vec![("repo".to_string(), "x".to_string())];
+        let ids =
+            |jobs: Vec<Job>| jobs.iter().map(|j| j.id).collect::<Vec<_>>();
+
+        /*
+         * The limit on completed jobs applies after the filter, so that the
+         * caller gets as many matching jobs as it asked for.
+         */
+        assert_eq!(ids(db.jobs_completed(2, &x)?), [jobs[0].id, jobs[2].id]);
+        assert_eq!(ids(db.jobs_completed(1, &x)?), [jobs[2].id]);
+        assert_eq!(ids(db.jobs_completed(1, &[])?), [jobs[2].id]);
+        assert_eq!(ids(db.jobs_incomplete(&x)?), [jobs[3].id]);
+        assert_eq!(ids(db.jobs_all(&x)?), [jobs[0].id, jobs[2].id, jobs[3].id]);
+
+        let both = vec![
+            ("repo".to_string(), "x".to_string()),
+            ("repo".to_string(), "y".to_string()),
+        ];
+        assert!(db.jobs_all(&both)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn job_requeue_from_dead_worker() -> Result<()> {
+        let (_dir, db) = test_db()?;
+
+        let (t, j) = create_job(&db)?;
+        let f = db.factory_create("factory")?;
+        let w = db.worker_create(&f, &t, None, false)?;
+        db.worker_assign_job(w.id, j.id)?;
+
+        /*
+         * A worker that was only just created, or has pinged recently, may
+         * still be running the job, so the job must not be requeued.
+         */
+        let stale = std::time::Duration::from_secs(600);
+        match db.job_requeue(j.id, stale, "requeued") {
+            Err(OperationError::Conflict(_)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        db.worker_ping(w.id)?;
+        match db.job_requeue(j.id, stale, "requeued") {
+            Err(OperationError::Conflict(_)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        /*
+         * Outputs uploaded by the previous worker are discarded, along with
+         * the files behind them.
+         */
+        let fid = JobFileId::generate();
+        db.job_add_output(j.id, "/work/out.txt", fid, 1, "00", None)?;
+
+        /*
+         * Once the worker has been silent for long enough, the job can be
+         * detached and assigned to another worker.
+         */
+        let wid =
+            db.job_requeue(j.id, std::time::Duration::ZERO, "requeued")?;
+        assert_eq!(wid, w.id);
+        assert!(db.worker_job(w.id)?.is_none());
+        assert!(db.worker_get(w.id)?.recycle);
+        assert!(db.job_file_by_id_opt(j.id, fid)?.is_none());
+
+        let w2 = db.worker_create(&f, &t, None, false)?;
+        db.worker_assign_job(w2.id, j.id)?;
+        let wj = db.worker_job(w2.id)?.expect("worker should have a job");
+        assert_eq!(wj.id, j.id);
+
+        Ok(())
+    }
+
+    #[test]
+    fn job_append_events_interleaved() -> Result<()> {
+        let (_dir, db) = test_db()?;
+
+        let (_, j) = create_job(&db)?;
+
+        const BATCHES: usize = 20;
+        const EVENTS: usize = 5;
+
+        /*
+         * Append batches of events from two threads at once.  Each batch must
+         * be assigned a contiguous range of sequence numbers, and there must
+         * be no gaps in the sequence overall.
+         */
+        std::thread::scope(|s| {
+            for name in ["a", "b"] {
+                let db = &db;
+                s.spawn(move || {
+                    for batch in 0..BATCHES {
+                        let events = (0..EVENTS)
+                            .map(|n| CreateJobEvent {
+                                task: None,
+                                stream: "stdout".into(),
+                                time: chrono::Utc::now(),
+                                time_remote: None,
+                                payload: format!("{name} {batch} {n}"),
+                            })
+                            .collect::<Vec<_>>();
+                        db.job_append_events(j.id, &events, u64::MAX).unwrap();
+                    }
+                });
+            }
+        });
+
+        let events = db.job_events(j.id, 0, None, None)?;
+        assert_eq!(events.len(), 2 * BATCHES * EVENTS);
+
+        for (i, ev) in events.iter().enumerate() {
+            assert_eq!(usize::try_from(ev.seq)?, i + 1);
+        }
+
+        for batch in events.chunks(EVENTS) {
+            let first = batch[0].payload.rsplit_once(' ').unwrap().0;
+            for (n, ev) in batch.iter().enumerate() {
+                assert_eq!(ev.payload, format!("{first} {n}"));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn job_stream_stats_count_recorded_events() -> Result<()> {
+        let (_dir, db) = test_db()?;
+
+        let (_, j) = create_job(&db)?;
+
+        let mk = |evs: &[(&str, &str)]| {
+            evs.iter()
+                .map(|(stream, payload)| CreateJobEvent {
+                    task: None,
+                    stream: stream.to_string(),
+                    time: chrono::Utc::now(),
+                    time_remote: None,
+                    payload: payload.to_string(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        /*
+         * The last event exceeds the output limit, so it is replaced by a
+         * control event and does not count towards the "stdout" stream.
+         */
+        let events =
+            mk(&[("stdout", "abc"), ("stderr", "x"), ("stdout", "de")]);
+        db.job_append_events(j.id, &events, 4)?;
+
+        let stats = db.job_stream_stats(j.id)?;
+        assert_eq!(stats["stdout"], StreamStats { events: 1, bytes: 3 });
+        assert_eq!(stats["stderr"], StreamStats { events: 1, bytes: 1 });
+        assert_eq!(stats["control"].events, 1);
+
+        /*
+         * A later batch adds to the counts already recorded.
+         */
+        db.job_append_events(
+            j.id,
+            &mk(&[("stderr", "yz"), ("stderr", "w")]),
+            100,
+        )?;
+
+        let stats = db.job_stream_stats(j.id)?;
+        assert_eq!(stats["stderr"], StreamStats { events: 3, bytes: 4 });
+
+        Ok(())
+    }
+
+    #[test]
+    fn job_append_stream_events_resume() -> Result<()> {
+        let (_dir, db) = test_db()?;
+
+        let (_, j) = create_job(&db)?;
+        assert_eq!(db.job_stream_seq(j.id)?, 0);
+
+        let ev = |payload: &str| CreateJobEvent {
+            task: None,
+            stream: "stdout".into(),
+            time: chrono::Utc::now(),
+            time_remote: None,
+            payload: payload.into(),
+        };
+
+        assert!(db.job_append_stream_events(j.id, 1, &[ev("a")], 1000)?);
+        assert!(db.job_append_stream_events(j.id, 2, &[ev("b")], 1000)?);
+        assert_eq!(db.job_stream_seq(j.id)?, 2);
+
+        /*
+         * Frames that repeat or precede the last committed frame are ignored,
+         * and do not move the sequence backwards.
+         */
+        assert!(!db.job_append_stream_events(j.id, 2, &[ev("b")], 1000)?);
+        assert!(!db.job_append_stream_events(j.id, 1, &[ev("a")], 1000)?);
+        assert_eq!(db.job_stream_seq(j.id)?, 2);
+
+        assert!(db.job_append_stream_events(j.id, 3, &[ev("c")], 1000)?);
+        assert_eq!(db.job_stream_seq(j.id)?, 3);
+
+        let payloads = db
+            .job_events(j.id, 0, None, None)?
+            .into_iter()
+            .filter(|ev| ev.stream == "stdout")
+            .map(|ev| ev.payload)
+            .collect::<Vec<_>>();
+        assert_eq!(payloads, vec!["a", "b", "c"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn job_complete_required_output_missing() -> Result<()> {
+        let (_dir, db) = test_db()?;
+
+        let (u, t) = user_and_target(&db)?;
+        let j = db.job_create(
+            u.id,
+            CreateJob {
+                output_rules: vec![CreateOutputRule {
+                    rule: "/work/*.txt".into(),
+                    ignore: false,
+                    size_change_ok: false,
+                    require_match: true,
+                }],
+                ..job_def(&t, "job")
+            },
+        )?;
+
+        /*
+         * The job produced no outputs at all, so even though the worker
+         * reports success the job must fail.
+         */
+        assert!(db.task_complete(j.id, 0, false, None)?);
+        assert!(db.job_complete(j.id, false, None)?);
+
+        let j = db.job_by_id(j.id)?;
+        assert!(j.complete);
+        assert!(j.failed);
+
+        let events = db.job_events(j.id, 0, None, None)?;
+        assert!(
+            events.iter().any(|ev| ev.stream == "control"
+                && ev.payload.contains("\"/work/*.txt\" required a match")),
+            "missing control event: {:?}",
+            events,
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn task_complete_failure_summary() -> Result<()> {
+        let (_dir, db) = test_db()?;
+
+        let (_, j) = create_job(&db)?;
+
+        let failure =
+            FailureSummary { code: Some(101), signal: None, message: None };
+        assert!(db.task_complete(j.id, 0, true, Some(&failure))?);
+        assert!(db.job_complete(j.id, true, None)?);
+
+        let tasks = db.job_tasks(j.id)?;
+        let f = tasks[0].failure().expect("task failure details");
+        assert_eq!(f.code, Some(101));
+        assert_eq!(f.describe().as_deref(), Some("exited 101"));
+
+        let j = db.job_by_id(j.id)?;
+        assert!(j.failed);
+        assert_eq!(j.failure_reason, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn job_share_token() -> Result<()> {
+        let (_dir, db) = test_db()?;
+
+        let (_, j) = create_job(&db)?;
+
+        let future = chrono::Utc::now() + chrono::Duration::hours(1);
+        let js = db.job_share_create(j.id, future)?;
+        assert_eq!(db.job_share_auth(&js.token)?.job, j.id);
+        assert!(db.job_share_auth("nonexistent").is_err());
+
+        let past = chrono::Utc::now() - chrono::Duration::seconds(1);
+        let old = db.job_share_create(j.id, past)?;
+        assert!(db.job_share_auth(&old.token).is_err());
+
+        assert_eq!(db.job_share_revoke(j.id)?, 2);
+        assert!(db.job_share_auth(&js.token).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn job_create_unique_name() -> Result<()> {
+        let (_dir, db) = test_db()?;
+
+        let (t, j) = create_job(&db)?;
+
+        match db.job_create_unique_name(j.owner, job_def(&t, "job")) {
+            Err(OperationError::Conflict(msg)) => {
+                assert!(msg.contains(&j.id.to_string()));
+            }
+            other => panic!("expected conflict, got {other:?}"),
+        }
+
+        let other = db.job_create_unique_name(j.owner, job_def(&t, "other"))?;
+        assert_ne!(other.id, j.id);
+
+        assert_eq!(db.job_by_name(j.owner, "other")?, Some(other.id));
+        assert_eq!(db.job_by_name(j.owner, "missing")?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn job_queue_positions() -> Result<()> {
+        let (_dir, db) = test_db()?;
+
+        /*
+         * Job IDs are only ordered by creation time at the granularity of a
+         * millisecond.
+         */
+        let (t, a) = create_job(&db)?;
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let b = db.job_create(a.owner, job_def(&t, "b"))?;
+        let other = db.target_create("other", "other target")?;
+        let c = db.job_create(a.owner, job_def(&other, "c"))?;
+
+        let pos = db.job_queue_positions(&[c.clone(), b.clone(), a.clone()])?;
+        assert_eq!(pos.get(&a.id), Some(&1));
+        assert_eq!(pos.get(&b.id), Some(&2));
+        assert_eq!(pos.get(&c.id), Some(&1));
+        assert_eq!(db.job_queue_position(&b)?, Some(2));
+
+        /*
+         * Once the job at the head of the queue is assigned to a worker, it
+         * is no longer queued and the next job moves up.
+         */
+        let f = db.factory_create("factory")?;
+        let w = db.worker_create(&f, &t, None, false)?;
+        db.worker_assign_job(w.id, a.id)?;
+        let a = db.job_by_id(a.id)?;
+        let pos = db.job_queue_positions(&[a.clone(), b.clone()])?;
+        assert_eq!(pos.get(&a.id), None);
+        assert_eq!(pos.get(&b.id), Some(&1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn job_create_max_active() -> Result<()> {
+        let (_dir, db) = test_db()?;
+
+        let (t, j) = create_job(&db)?;
+        let limited =
+            |name: &str| CreateJob { max_active: Some(2), ..job_def(&t, name) };
+
+        /*
+         * A batch is counted as a whole, so a batch that would take the user
+         * over the limit creates none of its jobs.
+         */
+        let e = db
+            .jobs_create(j.owner, vec![limited("a"), limited("b")])
+            .unwrap_err();
+        assert!(e.downcast_ref::<TooManyActiveJobs>().is_some());
+
+        db.job_create(j.owner, limited("a"))?;
+        let e = db.job_create(j.owner, limited("b")).unwrap_err();
+        assert!(e.downcast_ref::<TooManyActiveJobs>().is_some());
+
+        /*
+         * Once a job completes, there is room for another.
+         */
+        assert!(db.job_complete(j.id, true, None)?);
+        db.job_create(j.owner, limited("b"))?;
+
+        Ok(())
+    }
 }
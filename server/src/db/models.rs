@@ -17,6 +17,10 @@ pub use buildomat_database::{Dictionary, IsoDate, JsonValue};
 integer_new_type!(UnixUid, u32, i32, Integer, diesel::sql_types::Integer);
 integer_new_type!(UnixGid, u32, i32, Integer, diesel::sql_types::Integer);
 integer_new_type!(DataSize, u64, i64, BigInt, diesel::sql_types::BigInt);
+integer_new_type!(Seconds, u64, i64, BigInt, diesel::sql_types::BigInt);
+integer_new_type!(EventCount, u64, i64, BigInt, diesel::sql_types::BigInt);
+
+json_new_type!(StringList, Vec<String>);
 
 ulid_new_type!(UserId);
 ulid_new_type!(JobId);
@@ -25,6 +29,7 @@ ulid_new_type!(TaskId);
 ulid_new_type!(WorkerId);
 ulid_new_type!(FactoryId);
 ulid_new_type!(TargetId);
+ulid_new_type!(AuditId);
 
 #[derive(Debug, Queryable, Insertable, Identifiable)]
 #[diesel(table_name = user)]
@@ -34,6 +39,10 @@ pub struct User {
     pub name: String,
     pub token: String,
     pub time_create: IsoDate,
+    /**
+     * If set, the token may not be used to authenticate after this time.
+     */
+    pub token_expiry: Option<IsoDate>,
 }
 
 #[derive(Debug, Queryable, Insertable, Identifiable)]
@@ -79,6 +88,19 @@ pub struct Task {
     pub workdir: Option<String>,
     pub complete: bool,
     pub failed: bool,
+    pub failure_code: Option<i32>,
+    pub failure_signal: Option<i32>,
+    pub failure_message: Option<String>,
+    /**
+     * Should this task be executed even if an earlier task in the job has
+     * failed?
+     */
+    pub always_run: bool,
+    /**
+     * If set, the worker kills the task and marks it as failed if it runs for
+     * longer than this.
+     */
+    pub timeout_seconds: Option<Seconds>,
 }
 
 impl Task {
@@ -95,8 +117,32 @@ impl Task {
             workdir: ct.workdir.clone(),
             complete: false,
             failed: false,
+            failure_code: None,
+            failure_signal: None,
+            failure_message: None,
+            always_run: ct.always_run,
+            timeout_seconds: ct.timeout_seconds.map(Seconds),
         }
     }
+
+    /**
+     * If the worker reported details about the failure of this task, return
+     * them.
+     */
+    pub fn failure(&self) -> Option<super::FailureSummary> {
+        if self.failure_code.is_none()
+            && self.failure_signal.is_none()
+            && self.failure_message.is_none()
+        {
+            return None;
+        }
+
+        Some(super::FailureSummary {
+            code: self.failure_code,
+            signal: self.failure_signal,
+            message: self.failure_message.clone(),
+        })
+    }
 }
 
 #[derive(Debug, Clone, Queryable, Insertable, Identifiable)]
@@ -163,6 +209,11 @@ pub struct JobOutput {
     pub job: JobId,
     pub path: String,
     pub id: JobFileId,
+    /**
+     * The MIME type of the output, if the worker provided one when the output
+     * was uploaded.
+     */
+    pub content_type: Option<String>,
 }
 
 #[derive(Debug, Queryable, Insertable, Identifiable)]
@@ -199,6 +250,11 @@ pub struct JobFile {
      * When was this file successfully uploaded to the object store?
      */
     pub time_archived: Option<IsoDate>,
+    /**
+     * The SHA-256 digest of the file contents, as a hex string.  Files
+     * committed before this column was added do not have a value.
+     */
+    pub sha256: Option<String>,
 }
 
 #[derive(Debug, Queryable, Insertable, Identifiable)]
@@ -211,6 +267,21 @@ pub struct PublishedFile {
     pub name: String,
     pub job: JobId,
     pub file: JobFileId,
+    /**
+     * When was this file published?  Files published before this column was
+     * added do not have a value; see time_publish().
+     */
+    pub time_publish: Option<IsoDate>,
+}
+
+impl PublishedFile {
+    /**
+     * Files published before we began recording the time of publication are
+     * treated as if they were published when the job was created.
+     */
+    pub fn time_publish(&self) -> DateTime<Utc> {
+        self.time_publish.map(|t| t.0).unwrap_or_else(|| self.job.datetime())
+    }
 }
 
 #[derive(Debug, Clone, Queryable, Insertable, Identifiable)]
@@ -255,6 +326,25 @@ impl Worker {
             .unwrap_or_else(|_| Duration::from_secs(0))
     }
 
+    /**
+     * A worker is stale if the agent has started, but has not pinged the
+     * server for longer than the threshold.
+     */
+    pub fn is_stale(&self, now: DateTime<Utc>, threshold: Duration) -> bool {
+        if !self.agent_ok() {
+            return false;
+        }
+
+        let Some(lastping) = &self.lastping else {
+            return false;
+        };
+
+        now.signed_duration_since(lastping.0)
+            .to_std()
+            .map(|since| since > threshold)
+            .unwrap_or(false)
+    }
+
     pub fn factory(&self) -> FactoryId {
         self.factory.unwrap_or_else(|| Worker::legacy_default_factory_id())
     }
@@ -305,6 +395,25 @@ pub struct Job {
      * When was this job successfully uploaded to the object store?
      */
     pub time_archived: Option<IsoDate>,
+    /**
+     * When was this job record created in the database?  Jobs created before
+     * this column was added may not have a value.
+     */
+    pub time_create: Option<IsoDate>,
+    /**
+     * How many bytes of stdout and stderr output have been appended to the
+     * event log for this job, including output that was discarded?
+     */
+    pub event_bytes: DataSize,
+    /**
+     * If the job was cancelled, the reason given by whoever cancelled it.
+     */
+    pub cancel_reason: Option<String>,
+    /**
+     * If the job failed, a short description of the failure reported by the
+     * worker.
+     */
+    pub failure_reason: Option<String>,
 }
 
 impl Job {
@@ -361,18 +470,33 @@ pub struct JobDepend {
     pub on_failed: bool,
     pub on_completed: bool,
     pub satisfied: bool,
+    /**
+     * If the outputs of the prior job are to be copied, and this list is
+     * present, only outputs with a path that matches one of these glob
+     * patterns are copied.
+     */
+    pub copy_outputs_globs: Option<StringList>,
 }
 
 impl JobDepend {
-    pub fn from_create(cd: &super::CreateDepend, job: JobId) -> JobDepend {
+    pub fn from_create(
+        cd: &super::CreateDepend,
+        job: JobId,
+        prior_job: JobId,
+    ) -> JobDepend {
         JobDepend {
             job,
             name: cd.name.to_string(),
-            prior_job: cd.prior_job,
+            prior_job,
             copy_outputs: cd.copy_outputs,
             on_failed: cd.on_failed,
             on_completed: cd.on_completed,
             satisfied: false,
+            copy_outputs_globs: if cd.copy_outputs_globs.is_empty() {
+                None
+            } else {
+                Some(cd.copy_outputs_globs.clone().into())
+            },
         }
     }
 }
@@ -397,3 +521,157 @@ pub struct JobStore {
     pub source: String,
     pub time_update: IsoDate,
 }
+
+/**
+ * The highest value reported by the worker for a particular resource usage
+ * metric while the job was running.
+ */
+#[derive(Debug, Clone, Queryable, Insertable, Identifiable)]
+#[diesel(table_name = job_metric)]
+#[diesel(primary_key(job, name))]
+pub struct JobMetric {
+    pub job: JobId,
+    pub name: String,
+    pub value: DataSize,
+    pub time_update: IsoDate,
+}
+
+/**
+ * The number of events, and the total size of their payloads, recorded for
+ * each stream (e.g., "stdout" or "worker") of a job.
+ */
+#[derive(Debug, Clone, Queryable, Insertable, Identifiable)]
+#[diesel(table_name = job_stream_stat)]
+#[diesel(primary_key(job, stream))]
+pub struct JobStreamStat {
+    pub job: JobId,
+    pub stream: String,
+    pub events: EventCount,
+    pub bytes: DataSize,
+}
+
+/**
+ * The sequence number of the last frame of events committed from the stream
+ * of events sent by the worker for a job.
+ */
+#[derive(Debug, Clone, Queryable, Insertable, Identifiable)]
+#[diesel(table_name = job_stream)]
+#[diesel(primary_key(job))]
+pub struct JobStream {
+    pub job: JobId,
+    pub seq: i64,
+    pub time_update: IsoDate,
+}
+
+/**
+ * Records the job that was created by a submission that carried an
+ * idempotency key, so that a repeat of the same submission can be given the
+ * original job rather than creating another.
+ */
+#[derive(Debug, Clone, Queryable, Insertable, Identifiable)]
+#[diesel(table_name = job_idempotency)]
+#[diesel(primary_key(owner, key))]
+pub struct JobIdempotency {
+    pub owner: UserId,
+    pub key: String,
+    pub job: JobId,
+    pub time_create: IsoDate,
+}
+
+#[derive(Debug, Queryable, Insertable, Identifiable)]
+#[diesel(table_name = audit_log)]
+#[diesel(primary_key(id))]
+pub struct AuditLog {
+    pub id: AuditId,
+    pub time: IsoDate,
+    /**
+     * Either the ID of the user that performed the action, or the name of
+     * the admin token that was used, in the form "token:NAME".
+     */
+    pub actor: String,
+    pub privilege: Option<String>,
+    pub action: String,
+    /**
+     * The ID of the job or worker on which the action was performed, if any.
+     */
+    pub target: Option<String>,
+    /**
+     * The remote address from which the request was made.
+     */
+    pub source: Option<String>,
+}
+
+/**
+ * A token that allows anybody who holds it to read a single job, without
+ * otherwise authenticating.  The token does not allow the job to be changed.
+ */
+#[derive(Debug, Clone, Queryable, Insertable, Identifiable)]
+#[diesel(table_name = job_share)]
+#[diesel(primary_key(token))]
+pub struct JobShare {
+    pub token: String,
+    pub job: JobId,
+    pub time_create: IsoDate,
+    pub time_expiry: Option<IsoDate>,
+}
+
+impl JobShare {
+    /**
+     * Every token now has an expiry time.  Tokens created before that was
+     * required are treated as expired.
+     */
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.time_expiry.as_ref().map(|t| t.0 <= now).unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn worker(token: Option<&str>, lastping: Option<DateTime<Utc>>) -> Worker {
+        Worker {
+            id: WorkerId::generate(),
+            bootstrap: "bootstrap".into(),
+            token: token.map(str::to_string),
+            factory_private: None,
+            deleted: false,
+            recycle: false,
+            lastping: lastping.map(IsoDate),
+            factory: None,
+            target: None,
+            wait_for_flush: false,
+            factory_metadata: None,
+        }
+    }
+
+    #[test]
+    fn test_worker_is_stale() {
+        let now = Utc::now();
+        let threshold = Duration::from_secs(300);
+        let ago = |ms: i64| now - chrono::Duration::milliseconds(ms);
+
+        /*
+         * A worker that has not yet started its agent is never stale:
+         */
+        assert!(!worker(None, None).is_stale(now, threshold));
+        assert!(!worker(None, Some(ago(3_600_000))).is_stale(now, threshold));
+        assert!(!worker(Some("token"), None).is_stale(now, threshold));
+
+        /*
+         * Check either side of the threshold:
+         */
+        let w = worker(Some("token"), Some(ago(299_999)));
+        assert!(!w.is_stale(now, threshold));
+        let w = worker(Some("token"), Some(ago(300_000)));
+        assert!(!w.is_stale(now, threshold));
+        let w = worker(Some("token"), Some(ago(300_001)));
+        assert!(w.is_stale(now, threshold));
+
+        /*
+         * A ping from the future, e.g., due to clock adjustment, is not stale:
+         */
+        let w = worker(Some("token"), Some(ago(-1000)));
+        assert!(!w.is_stale(now, threshold));
+    }
+}
@@ -8,6 +8,7 @@ table! {
         name -> Text,
         token -> Text,
         time_create -> Text,
+        token_expiry -> Nullable<Text>,
     }
 }
 
@@ -24,6 +25,10 @@ table! {
         target_id -> Nullable<Text>,
         cancelled -> Bool,
         time_archived -> Nullable<Text>,
+        time_create -> Nullable<Text>,
+        event_bytes -> BigInt,
+        cancel_reason -> Nullable<Text>,
+        failure_reason -> Nullable<Text>,
     }
 }
 
@@ -48,6 +53,11 @@ table! {
         workdir -> Nullable<Text>,
         complete -> Bool,
         failed -> Bool,
+        failure_code -> Nullable<Integer>,
+        failure_signal -> Nullable<Integer>,
+        failure_message -> Nullable<Text>,
+        always_run -> Bool,
+        timeout_seconds -> Nullable<BigInt>,
     }
 }
 
@@ -76,6 +86,7 @@ table! {
         job -> Text,
         path -> Text,
         id -> Text,
+        content_type -> Nullable<Text>,
     }
 }
 
@@ -85,6 +96,7 @@ table! {
         id -> Text,
         size -> BigInt,
         time_archived -> Nullable<Text>,
+        sha256 -> Nullable<Text>,
     }
 }
 
@@ -159,6 +171,7 @@ table! {
         name -> Text,
         job -> Text,
         file -> Text,
+        time_publish -> Nullable<Text>,
     }
 }
 
@@ -173,6 +186,7 @@ table! {
         on_failed -> Bool,
         on_completed -> Bool,
         satisfied -> Bool,
+        copy_outputs_globs -> Nullable<Text>,
     }
 }
 
@@ -194,3 +208,59 @@ table! {
         time_update -> Text,
     }
 }
+
+table! {
+    job_metric (job, name) {
+        job -> Text,
+        name -> Text,
+        value -> BigInt,
+        time_update -> Text,
+    }
+}
+
+table! {
+    audit_log (id) {
+        id -> Text,
+        time -> Text,
+        actor -> Text,
+        privilege -> Nullable<Text>,
+        action -> Text,
+        target -> Nullable<Text>,
+        source -> Nullable<Text>,
+    }
+}
+
+table! {
+    job_idempotency (owner, key) {
+        owner -> Text,
+        key -> Text,
+        job -> Text,
+        time_create -> Text,
+    }
+}
+
+table! {
+    job_share (token) {
+        token -> Text,
+        job -> Text,
+        time_create -> Text,
+        time_expiry -> Nullable<Text>,
+    }
+}
+
+table! {
+    job_stream_stat (job, stream) {
+        job -> Text,
+        stream -> Text,
+        events -> BigInt,
+        bytes -> BigInt,
+    }
+}
+
+table! {
+    job_stream (job) {
+        job -> Text,
+        seq -> BigInt,
+        time_update -> Text,
+    }
+}
@@ -39,7 +39,7 @@ struct FileCommit {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum FileKind {
     Input { name: String },
-    Output { path: String },
+    Output { path: String, content_type: Option<String> },
 }
 
 #[derive(Debug)]
@@ -68,6 +68,12 @@ impl FileCommit {
         !matches!(&*fcs, State::Complete(..))
     }
 
+    fn active(&self) -> bool {
+        let fcs = self.state.lock().unwrap();
+
+        matches!(&*fcs, State::Active(..))
+    }
+
     fn mark_active(&self) {
         let mut fcs = self.state.lock().unwrap();
         assert!(matches!(&*fcs, State::Queued));
@@ -148,8 +154,9 @@ fn thread_file_commit(
             "chunks" => fc.chunks.len(),
             "expected_size" => fc.expected_size);
 
-        let fid = match c.commit_file(bgid.0, &fc.chunks, fc.expected_size) {
-            Ok(fid) => fid,
+        let res = c.commit_file(bgid.0, &fc.chunks, fc.expected_size);
+        let (fid, sha256) = match res {
+            Ok(res) => res,
             Err(e) => {
                 error!(log, "{bgid} failed: {e}");
 
@@ -163,12 +170,21 @@ fn thread_file_commit(
          * database as either an input or an output:
          */
         let res = match &fc.kind {
-            FileKind::Input { name } => {
-                c.db.job_add_input(bgid.0, &name, fid, fc.expected_size)
-            }
-            FileKind::Output { path } => {
-                c.db.job_add_output(bgid.0, &path, fid, fc.expected_size)
-            }
+            FileKind::Input { name } => c.db.job_add_input(
+                bgid.0,
+                &name,
+                fid,
+                fc.expected_size,
+                &sha256,
+            ),
+            FileKind::Output { path, content_type } => c.db.job_add_output(
+                bgid.0,
+                &path,
+                fid,
+                fc.expected_size,
+                &sha256,
+                content_type.as_deref(),
+            ),
         };
 
         let dur = Instant::now().saturating_duration_since(start).as_millis();
@@ -210,6 +226,31 @@ impl Files {
         }
     }
 
+    /**
+     * Are there any file commits either waiting in the queue or in progress?
+     */
+    pub fn busy(&self) -> bool {
+        let g = self.inner.lock().unwrap();
+
+        !g.queue.is_empty() || g.commits.values().any(|fc| fc.pending())
+    }
+
+    /**
+     * The number of file commits waiting in the queue for a worker thread.
+     */
+    pub fn queue_depth(&self) -> usize {
+        self.inner.lock().unwrap().queue.len()
+    }
+
+    /**
+     * The number of file commits currently being processed by a worker thread.
+     */
+    pub fn active(&self) -> usize {
+        let g = self.inner.lock().unwrap();
+
+        g.commits.values().filter(|fc| fc.active()).count()
+    }
+
     /**
      * Enqueue a file commit job.  This routine is intended to be idempotent.
      * If called with the same arguments over and over, it will eventually
@@ -266,6 +307,21 @@ impl Files {
         }
     }
 
+    /**
+     * Report the status of a previously enqueued file commit, without
+     * enqueueing anything.  Returns None if we have no record of the commit;
+     * otherwise, the result is as for commit_file().
+     */
+    pub fn commit_file_status(
+        &self,
+        job: JobId,
+        commit_id: Ulid,
+    ) -> Option<Option<std::result::Result<(), String>>> {
+        let fi = self.inner.lock().unwrap();
+
+        fi.commits.get(&BackgroundId(job, commit_id)).map(|fc| fc.observe())
+    }
+
     /**
      * Check to see if a job has been submitted with this commit ID for this job
      * already.
@@ -100,7 +100,7 @@ async fn job_assignment_one(log: &Logger, c: &Central) -> Result<()> {
                  */
                 info!(log, "failing job {}, cancelled before assignment", j.id);
             }
-            c.complete_job(log, j.id, true)?;
+            c.complete_job(log, j.id, true, None)?;
             continue;
         }
 
@@ -124,7 +124,7 @@ async fn job_assignment_one(log: &Logger, c: &Central) -> Result<()> {
                     None,
                     "worker failed without completing job",
                 )?;
-                c.complete_job(log, j.id, true)?;
+                c.complete_job(log, j.id, true, None)?;
             }
             continue;
         }
@@ -160,7 +160,7 @@ async fn job_waiters_one(log: &Logger, c: &Central) -> Result<()> {
              * This job was cancelled before it was ready to run.
              */
             info!(log, "failing job {}, cancelled while waiting", j.id);
-            c.complete_job(log, j.id, true)?;
+            c.complete_job(log, j.id, true, None)?;
             continue 'job;
         }
 
@@ -231,7 +231,7 @@ async fn job_waiters_one(log: &Logger, c: &Central) -> Result<()> {
                 None,
                 &failmsg,
             )?;
-            c.complete_job(log, j.id, true)?;
+            c.complete_job(log, j.id, true, None)?;
             continue 'job;
         }
 
@@ -365,6 +365,9 @@ pub(crate) async fn job_assignment(log: Logger, c: Arc<Central>) -> Result<()> {
             error!(log, "job assignment task error: {:?}", e);
         }
 
-        tokio::time::sleep(delay).await;
+        if !c.idle(delay).await {
+            info!(log, "stop job assignment task");
+            return Ok(());
+        }
     }
 }
@@ -0,0 +1,119 @@
+/*
+ * Copyright 2026 Oxide Computer Company
+ */
+
+/*
+ * Limits applied to the requests made by users and workers.  Where a limit may
+ * be changed in the "[job]" section of the configuration file, the constant
+ * here is the default value.  The checks below produce the message reported to
+ * the client, so that the value we enforce is always the value we describe.
+ */
+
+pub const DEFAULT_MAX_TASKS_PER_JOB: u64 = 100;
+pub const DEFAULT_MAX_INPUTS_PER_JOB: u64 = 25;
+pub const DEFAULT_MAX_TAGS_PER_JOB: u64 = 100;
+
+/*
+ * By default, the total size of all tag names and values may be at most 128KB:
+ */
+pub const DEFAULT_MAX_TAG_BYTES_PER_JOB: u64 = 128 * 1024;
+
+/*
+ * The number of jobs that may be submitted at once in a batch.
+ */
+pub const MAX_JOBS_PER_BATCH: u64 = 100;
+
+/*
+ * The number of other jobs on which a single job may depend.
+ */
+pub const MAX_DEPENDS_PER_JOB: u64 = 8;
+
+pub const MAX_IDEMPOTENCY_KEY_BYTES: u64 = 256;
+
+/*
+ * A job share token expires after a week unless the user asks for a shorter
+ * period, and may not be made to last longer than 30 days.
+ */
+pub const DEFAULT_SHARE_SECONDS: u64 = 7 * 24 * 3600;
+pub const MAX_SHARE_SECONDS: u64 = 30 * 24 * 3600;
+
+/*
+ * Individual files uploaded through the old blocking entrypoints are capped at
+ * 1GB to avoid request timeouts.  Larger files are possible using the new
+ * asynchronous commit mechanism.
+ */
+pub const MAX_SYNC_FILE_BYTES: u64 = 1024 * 1024 * 1024;
+
+fn to_u64(n: usize) -> u64 {
+    u64::try_from(n).unwrap_or(u64::MAX)
+}
+
+/**
+ * Check the number of some kind of object (e.g., "tasks") in a request against
+ * the maximum, returning a message for the client if there are too many.
+ */
+pub fn check_count(what: &str, count: usize, max: u64) -> Option<String> {
+    let count = to_u64(count);
+
+    (count > max).then(|| {
+        format!("too many {what}: {count} exceeds the maximum of {max}")
+    })
+}
+
+pub fn check_tag_bytes(bytes: usize, max: u64) -> Option<String> {
+    (to_u64(bytes) > max)
+        .then(|| format!("total size of all tags is larger than {max} bytes"))
+}
+
+pub fn check_idempotency_key(key: &str) -> Option<String> {
+    if key.is_empty() || to_u64(key.len()) > MAX_IDEMPOTENCY_KEY_BYTES {
+        Some(format!(
+            "idempotency key must be between 1 and \
+            {MAX_IDEMPOTENCY_KEY_BYTES} bytes"
+        ))
+    } else {
+        None
+    }
+}
+
+/**
+ * Check the size of a file uploaded through one of the old blocking
+ * entrypoints, returning the size as an unsigned value if it is acceptable.
+ */
+pub fn check_sync_file_size(size: i64) -> Result<u64, String> {
+    match u64::try_from(size) {
+        Ok(size) if size <= MAX_SYNC_FILE_BYTES => Ok(size),
+        _ => Err(format!(
+            "size {size} must be between 0 and {MAX_SYNC_FILE_BYTES}"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn messages_match_limits() {
+        assert_eq!(check_count("tasks", 100, DEFAULT_MAX_TASKS_PER_JOB), None);
+        let msg = check_count("tasks", 101, DEFAULT_MAX_TASKS_PER_JOB).unwrap();
+        assert!(msg.contains(&DEFAULT_MAX_TASKS_PER_JOB.to_string()));
+
+        let max = DEFAULT_MAX_TAG_BYTES_PER_JOB;
+        assert_eq!(check_tag_bytes(max as usize, max), None);
+        let msg = check_tag_bytes(max as usize + 1, max).unwrap();
+        assert!(msg.contains(&max.to_string()));
+
+        let max = MAX_IDEMPOTENCY_KEY_BYTES as usize;
+        assert_eq!(check_idempotency_key(&"k".repeat(max)), None);
+        let msg = check_idempotency_key(&"k".repeat(max + 1)).unwrap();
+        assert!(msg.contains(&MAX_IDEMPOTENCY_KEY_BYTES.to_string()));
+        assert!(check_idempotency_key("").is_some());
+
+        let max = MAX_SYNC_FILE_BYTES as i64;
+        assert_eq!(check_sync_file_size(max), Ok(MAX_SYNC_FILE_BYTES));
+        let msg = check_sync_file_size(max + 1).unwrap_err();
+        assert!(msg.contains(&MAX_SYNC_FILE_BYTES.to_string()));
+        assert!(check_sync_file_size(-1).is_err());
+    }
+}
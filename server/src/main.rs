@@ -5,13 +5,13 @@
 #![allow(clippy::many_single_char_names)]
 #![allow(clippy::too_many_arguments)]
 
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::io::Write;
 use std::path::PathBuf;
 use std::process::exit;
 use std::result::Result as SResult;
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::{anyhow, bail, Context, Result};
 use dropshot::{
@@ -29,31 +29,62 @@ use schemars::JsonSchema;
 use serde::Deserialize;
 #[allow(unused_imports)]
 use slog::{error, info, o, warn, Logger};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_util::sync::CancellationToken;
 #[macro_use]
 extern crate diesel;
 use buildomat_common::*;
 
 mod api;
 mod archive;
+mod audit;
 mod chunks;
 mod config;
 mod db;
 mod files;
 mod jobs;
+mod limits;
+mod metrics;
+mod ratelimit;
+mod s3;
 mod workers;
 
-use db::{AuthUser, Job, JobEvent, JobFile, JobFileId, JobId, JobOutput};
+use db::{
+    AuthUser, Job, JobEvent, JobFile, JobFileId, JobId, JobInput, JobOutput,
+    OResult, OperationError,
+};
 
 pub(crate) trait MakeInternalError<T> {
     fn or_500(self) -> SResult<T, HttpError>;
 }
 
+fn anyhow_to_http(e: anyhow::Error) -> HttpError {
+    /*
+     * An object in archival storage is not an internal failure, and the client
+     * should be told why the request cannot succeed yet.
+     */
+    if let Some(nr) = e.downcast_ref::<s3::NotRetrievable>() {
+        return HttpError::for_unavail(
+            Some(api::ErrorCode::NotRetrievable.to_string()),
+            nr.to_string(),
+        );
+    }
+
+    if let Some(tm) = e.downcast_ref::<db::TooManyActiveJobs>() {
+        return HttpError::for_client_error(
+            Some(api::ErrorCode::TooManyActiveJobs.to_string()),
+            StatusCode::TOO_MANY_REQUESTS,
+            tm.to_string(),
+        );
+    }
+
+    let msg = format!("internal error: {:?}", e);
+    HttpError::for_internal_error(msg)
+}
+
 impl<T> MakeInternalError<T> for std::result::Result<T, anyhow::Error> {
     fn or_500(self) -> SResult<T, HttpError> {
-        self.map_err(|e| {
-            let msg = format!("internal error: {:?}", e);
-            HttpError::for_internal_error(msg)
-        })
+        self.map_err(anyhow_to_http)
     }
 }
 
@@ -68,19 +99,19 @@ impl<T> MakeInternalError<T> for std::io::Result<T> {
 
 impl<T> MakeInternalError<T> for db::OResult<T> {
     fn or_500(self) -> SResult<T, HttpError> {
-        self.map_err(|e| {
-            use db::OperationError;
-
-            match e {
-                OperationError::Conflict(msg) => HttpError::for_client_error(
-                    Some("conflict".to_string()),
-                    StatusCode::CONFLICT,
-                    msg,
-                ),
-                _ => {
-                    let msg = format!("internal error: {:?}", e);
-                    HttpError::for_internal_error(msg)
-                }
+        self.map_err(|e| match e {
+            OperationError::Conflict(msg) => HttpError::for_client_error(
+                Some(api::ErrorCode::Conflict.to_string()),
+                StatusCode::CONFLICT,
+                msg,
+            ),
+            OperationError::NotFound(msg) => {
+                HttpError::for_not_found(None, format!("{msg} not found"))
+            }
+            OperationError::Other(e) => anyhow_to_http(e),
+            _ => {
+                let msg = format!("internal error: {:?}", e);
+                HttpError::for_internal_error(msg)
             }
         })
     }
@@ -91,8 +122,15 @@ impl<T> MakeInternalError<T>
 {
     fn or_500(self) -> SResult<T, HttpError> {
         self.map_err(|e| {
-            let msg = format!("ID decode error: {:?}", e);
-            HttpError::for_internal_error(msg)
+            /*
+             * Identifiers are provided by the client, so one that cannot be
+             * decoded is a client error rather than an internal one.
+             */
+            HttpError::for_client_error(
+                Some(api::ErrorCode::Invalid.to_string()),
+                StatusCode::BAD_REQUEST,
+                format!("invalid ID: {e}"),
+            )
         })
     }
 }
@@ -119,6 +157,32 @@ struct FileResponse {
     pub info: String,
     pub body: Body,
     pub size: u64,
+    pub sha256: Option<String>,
+}
+
+/**
+ * Clients may use this header to check the integrity of a downloaded file.
+ */
+const SHA256_HEADER: &str = "x-buildomat-sha256";
+
+impl FileResponse {
+    /**
+     * Add the headers that describe the file to a response.
+     */
+    fn headers(
+        &self,
+        mut res: hyper::http::response::Builder,
+    ) -> hyper::http::response::Builder {
+        res = res.header(CONTENT_LENGTH, self.size);
+        if let Some(sha256) = &self.sha256 {
+            res = res.header(SHA256_HEADER, sha256);
+        }
+        res
+    }
+}
+
+fn hex(buf: &[u8]) -> String {
+    buf.iter().map(|b| format!("{:02x}", b)).collect()
 }
 
 struct FilePresignedUrl {
@@ -126,10 +190,49 @@ struct FilePresignedUrl {
     pub url: String,
 }
 
+/**
+ * The outcome of checking a job archive in the object store.
+ */
+enum ArchiveVerifyOutcome {
+    Intact,
+    /**
+     * The archive is held in an archival storage class and must be restored
+     * before it can be read, so it could not be checked.
+     */
+    NotRetrievable,
+    Problem(String),
+}
+
+/**
+ * Produce a Content-Disposition header value that suggests the final component
+ * of the provided path as the name of the downloaded file.  Characters that
+ * cannot appear in the quoted file name are replaced with an underscore.  If
+ * the path has no final component (e.g., it ends in a slash), the file ID is
+ * used instead.
+ */
+fn content_disposition_attachment(path: &str, file: JobFileId) -> String {
+    let name = match path.rsplit('/').next() {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => file.to_string(),
+    };
+    let name = name
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c.is_control() || !c.is_ascii() => '_',
+            c => c,
+        })
+        .collect::<String>();
+
+    format!("attachment; filename=\"{name}\"")
+}
+
 struct CentralInner {
     hold: bool,
     leases: jobs::Leases,
     archive_queue: VecDeque<JobId>,
+    ratelimit: ratelimit::RateLimiter,
+    agent_sha256: HashMap<String, ((u64, SystemTime), String)>,
 }
 
 struct Central {
@@ -139,6 +242,7 @@ struct Central {
     files: files::Files,
     inner: Mutex<CentralInner>,
     s3: aws_sdk_s3::Client,
+    shutdown: CancellationToken,
 }
 
 pub(crate) fn unauth_response<T>() -> SResult<T, HttpError> {
@@ -202,20 +306,136 @@ impl Central {
         unauth_response()
     }
 
+    /**
+     * Apply the configured request rate limit, if any, to this authenticated
+     * principal.  Endpoints that do not require authentication are not
+     * subject to the limit.
+     */
+    fn _int_rate_limit(
+        &self,
+        log: &Logger,
+        principal: &str,
+    ) -> SResult<(), HttpError> {
+        let limits = &self.config.limits;
+        let Some(rate) = limits.requests_per_second else {
+            return Ok(());
+        };
+        let burst = limits.burst.unwrap_or(rate);
+
+        let res =
+            self.inner.lock().unwrap().ratelimit.take(principal, rate, burst);
+
+        if let Err(wait) = res {
+            /*
+             * Round up so that a client waiting for this many seconds will
+             * find a token available.
+             */
+            let secs = wait.as_secs() + 1;
+            warn!(log, "rate limit exceeded for {}", principal);
+            return Err(HttpError::for_client_error(
+                Some(api::ErrorCode::TooManyRequests.to_string()),
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("rate limit exceeded; retry after {} seconds", secs),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Record the use of a privilege, or some other sensitive action, in the
+     * audit log.  If the record cannot be written, the request must fail.
+     */
+    fn audit(
+        &self,
+        req: &RequestInfo,
+        actor: &str,
+        privilege: Option<&str>,
+        action: &str,
+        target: Option<String>,
+    ) -> SResult<(), HttpError> {
+        /*
+         * Entries older than the retention period are discarded by the audit
+         * cleanup task.
+         */
+        self.db
+            .audit_log_record(&db::AuditLog {
+                id: db::AuditId::generate(),
+                time: db::IsoDate::now(),
+                actor: actor.to_string(),
+                privilege: privilege.map(str::to_string),
+                action: action.to_string(),
+                target,
+                source: Some(req.remote_addr().ip().to_string()),
+            })
+            .or_500()
+    }
+
+    /**
+     * Authenticate a request that requires an administrative privilege.  Each
+     * use of the privilege is recorded in the audit log here, so the endpoints
+     * themselves need not do so.  If the request acts on a particular object
+     * (e.g., a job or a worker), its ID should be provided as the target.
+     */
     async fn require_admin(
         &self,
         log: &Logger,
         req: &RequestInfo,
         privname: &str,
+        target: Option<String>,
     ) -> SResult<(), HttpError> {
         let t = self._int_auth_token(log, req)?;
+        let (actor, want) = self._int_admin_privilege(log, &t, privname)?;
+        let action = format!("{} {}", req.method(), req.uri().path());
+
+        self.audit(req, &actor, Some(&want), &action, target)
+    }
 
-        if t == self.config.admin.token {
+    /**
+     * Authenticate a request from the monitoring system that collects metrics.
+     * Scrapes arrive frequently and do not change anything, so unlike other
+     * administrative requests they are not recorded in the audit log.
+     */
+    async fn require_metrics(
+        &self,
+        log: &Logger,
+        req: &RequestInfo,
+    ) -> SResult<(), HttpError> {
+        let t = self._int_auth_token(log, req)?;
+
+        if self.config.admin.metrics_token.as_deref() == Some(t.as_str()) {
+            return Ok(());
+        }
+
+        self._int_admin_privilege(log, &t, "metrics.read")?;
+        Ok(())
+    }
+
+    /**
+     * Check that the bearer token is either a global admin token, or belongs
+     * to a user that has been delegated the specific administrative privilege.
+     * Returns the actor and privilege to record in the audit log.
+     */
+    fn _int_admin_privilege(
+        &self,
+        log: &Logger,
+        t: &str,
+        privname: &str,
+    ) -> SResult<(String, String), HttpError> {
+        if let Some(name) = self.config.admin.token_name(t) {
             /*
-             * If the bearer token matches the configured global admin token, we
-             * can proceed immediately.
+             * If the bearer token matches one of the configured global admin
+             * tokens, we can proceed immediately.
              */
-            return Ok(());
+            info!(
+                log,
+                "admin token {:?} used for privilege {}", name, privname
+            );
+            self._int_rate_limit(log, &format!("admin:{}", name))?;
+
+            let actor = format!("token:{}", name);
+            let want = format!("admin.{}", privname);
+            return Ok((actor, want));
         }
 
         /*
@@ -225,7 +445,7 @@ impl Central {
          */
         assert!(!privname.starts_with("admin."));
         let want = format!("admin.{}", privname);
-        let u = match self.db.user_auth(&t) {
+        let u = match self.db.user_auth(t) {
             Ok(u) => u,
             Err(e) => {
                 warn!(log, "admin auth failure: {:?}", e);
@@ -239,7 +459,9 @@ impl Central {
         }
 
         info!(log, "user {} used delegated admin privilege {}", u.name, want);
-        Ok(())
+        self._int_rate_limit(log, &format!("user:{}", u.id))?;
+
+        Ok((u.id.to_string(), want))
     }
 
     async fn require_user(
@@ -264,7 +486,7 @@ impl Central {
          * Now check to see if the authenticated user is requesting delegated
          * authentication to act as another user:
          */
-        if let Some(delegate) = self._int_delegate_username(log, req)? {
+        let u = if let Some(delegate) = self._int_delegate_username(log, req)? {
             if u.has_privilege("delegate") {
                 /*
                  * The authenticated user is allowed to impersonate other users
@@ -274,7 +496,7 @@ impl Central {
                  * that repository.
                  */
                 info!(log, "user {} delegated auth as {:?}", u.name, delegate);
-                Ok(self.db.user_ensure(&delegate).or_500()?)
+                self.db.user_ensure(&delegate).or_500()?
             } else {
                 /*
                  * This user is not allowed to act as another user.
@@ -285,11 +507,20 @@ impl Central {
                     u.name,
                     delegate
                 );
-                unauth_response()
+                return unauth_response();
             }
         } else {
-            Ok(u)
-        }
+            u
+        };
+
+        /*
+         * Requests made through delegated authentication are limited as the
+         * user being impersonated, so that each GitHub repository, for
+         * example, has its own allowance.
+         */
+        self._int_rate_limit(log, &format!("user:{}", u.id))?;
+
+        Ok(u)
     }
 
     async fn require_worker(
@@ -298,13 +529,17 @@ impl Central {
         req: &RequestInfo,
     ) -> SResult<db::Worker, HttpError> {
         let t = self._int_auth_token(log, req)?;
-        match self.db.worker_auth(&t) {
-            Ok(u) => Ok(u),
+        let w = match self.db.worker_auth(&t) {
+            Ok(w) => w,
             Err(e) => {
                 warn!(log, "worker auth failure: {:?}", e);
-                unauth_response()
+                return unauth_response();
             }
-        }
+        };
+
+        self._int_rate_limit(log, &format!("worker:{}", w.id))?;
+
+        Ok(w)
     }
 
     async fn require_factory(
@@ -313,13 +548,17 @@ impl Central {
         req: &RequestInfo,
     ) -> SResult<db::Factory, HttpError> {
         let t = self._int_auth_token(log, req)?;
-        match self.db.factory_auth(&t) {
-            Ok(u) => Ok(u),
+        let f = match self.db.factory_auth(&t) {
+            Ok(f) => f,
             Err(e) => {
                 warn!(log, "factory auth failure: {:?}", e);
-                unauth_response()
+                return unauth_response();
             }
-        }
+        };
+
+        self._int_rate_limit(log, &format!("factory:{}", f.id))?;
+
+        Ok(f)
     }
 
     fn archive_dir(&self) -> Result<PathBuf> {
@@ -330,6 +569,16 @@ impl Central {
     }
 
     fn archive_path(&self, job: JobId) -> Result<PathBuf> {
+        let mut p = self.archive_dir()?;
+        p.push(format!("{job}.json.gz"));
+        Ok(p)
+    }
+
+    /**
+     * Archives were once cached locally as uncompressed JSON files.  Those
+     * files are no longer used, and are removed when they are encountered.
+     */
+    fn archive_path_legacy(&self, job: JobId) -> Result<PathBuf> {
         let mut p = self.archive_dir()?;
         p.push(format!("{job}.json"));
         Ok(p)
@@ -364,25 +613,34 @@ impl Central {
         &self,
         log: &Logger,
         job: JobId,
-        archive: archive::jobs::ArchivedJob,
+        archive: &archive::jobs::ArchivedJob,
     ) -> Result<()> {
         let start = Instant::now();
-        let akey = self.archive_object_key(job, &archive);
+        let akey = self.archive_object_key(job, archive);
         let bucket = &self.config.storage.bucket;
-        let body = serde_json::to_vec_pretty(&archive)?;
-
-        self.s3
-            .put_object()
-            .bucket(bucket)
-            .key(&akey)
-            .content_length(body.len().try_into().unwrap())
-            .body(body.into())
-            .send()
-            .await?;
+        let pretty = self.config.archive.pretty;
+        let (body, json_bytes) = archive.to_gzip_bytes(pretty)?;
+
+        s3::retry(log, &self.config.storage.retry, "archive upload", || {
+            self.s3
+                .put_object()
+                .bucket(bucket)
+                .key(&akey)
+                .set_storage_class(self.config.storage.archive_storage_class())
+                .content_encoding("gzip")
+                .content_length(body.len().try_into().unwrap())
+                .body(body.clone().into())
+                .send()
+        })
+        .await?;
 
         let dur = Instant::now().saturating_duration_since(start);
+        metrics::ARCHIVE_UPLOAD.observe(dur);
         info!(log, "uploaded job archive from job {job} at {bucket}:{akey}";
-            "duration_msec" => dur.as_millis());
+            "duration_msec" => dur.as_millis(),
+            "pretty" => pretty,
+            "bytes" => body.len(),
+            "json_bytes" => json_bytes);
 
         Ok(())
     }
@@ -397,13 +655,42 @@ impl Central {
          * it from the object store we do not need to do so again.
          */
         let apath = self.archive_path(job)?;
-        match std::fs::File::open(&apath) {
-            Ok(f) => {
-                let br = std::io::BufReader::new(f);
-                let aj: archive::jobs::ArchivedJob =
-                    serde_json::from_reader(br)?;
+        match std::fs::read(&apath) {
+            Ok(buf) => {
+                let mut aj = archive::jobs::ArchivedJob::from_bytes(&buf)?;
                 if aj.is_valid() {
                     info!(log, "loaded archive of job {job} from {apath:?}");
+
+                    let n = aj.scrub_secrets();
+                    if n > 0 {
+                        /*
+                         * This cached copy predates the removal of secret
+                         * values from archives.  Rewrite it, and the copy in
+                         * the object store, without them.
+                         */
+                        warn!(
+                            log,
+                            "archive of job {job} contained {n} \
+                            secret values; rewriting"
+                        );
+                        self.archive_store(log, job, &aj).await?;
+                        self.archive_cache(job, &aj)?;
+                        return Ok(aj);
+                    }
+
+                    /*
+                     * Update the modification time of the cached file so that
+                     * the cache cleaning task can evict the least recently
+                     * used archives first.
+                     */
+                    if let Err(e) = std::fs::File::options()
+                        .write(true)
+                        .open(&apath)
+                        .and_then(|f| f.set_modified(SystemTime::now()))
+                    {
+                        warn!(log, "could not touch {apath:?}: {e}");
+                    }
+
                     return Ok(aj);
                 }
                 error!(
@@ -415,8 +702,17 @@ impl Central {
             Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
                 /*
                  * The file does not exist locally; we need to fetch it from the
-                 * object store.
+                 * object store.  If there is a cached copy in the old
+                 * uncompressed format, discard it.
                  */
+                let lpath = self.archive_path_legacy(job)?;
+                match std::fs::remove_file(&lpath) {
+                    Ok(()) => {
+                        info!(log, "removed legacy archive cache {lpath:?}");
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                    Err(e) => bail!("removing {lpath:?}: {e}"),
+                }
             }
             Err(e) => bail!("archived job {job} path {apath:?} error: {e}"),
         };
@@ -425,30 +721,155 @@ impl Central {
         let akey = self.archive_object_key_with_version(job, "1");
         let bucket = &self.config.storage.bucket;
 
-        let res = self.s3.get_object().bucket(bucket).key(&akey).send().await?;
+        let res =
+            s3::retry(log, &self.config.storage.retry, "archive fetch", || {
+                self.s3.get_object().bucket(bucket).key(&akey).send()
+            })
+            .await
+            .map_err(|e| s3::fetch_error(e, &akey))?;
         let body = res.body.collect().await?.to_vec();
 
         /*
          * First, make sure the data we read from S3 is valid:
          */
-        let aj: archive::jobs::ArchivedJob = serde_json::from_slice(&body)?;
+        let mut aj = archive::jobs::ArchivedJob::from_bytes(&body)?;
         if !aj.is_valid() {
             bail!("archive of job {job} at {bucket}:{akey} is invalid");
         }
         let dur = Instant::now().saturating_duration_since(start);
+        metrics::ARCHIVE_DOWNLOAD.observe(dur);
         info!(log, "loaded archive of job {job} from {bucket}:{akey}";
             "duration_msec" => dur.as_millis());
 
         /*
-         * Cache the loaded data in the local file system:
+         * Archives written by older versions of the server included the values
+         * of secret store entries.  If this is one of those, replace it in the
+         * object store with a copy that does not.
          */
+        let n = aj.scrub_secrets();
+        if n > 0 {
+            warn!(
+                log,
+                "archive of job {job} at {bucket}:{akey} contained \
+                {n} secret values; rewriting"
+            );
+            self.archive_store(log, job, &aj).await?;
+        }
+
+        /*
+         * Cache the loaded data in the local file system.  The local cache is
+         * always compressed, even if the archive in the object store predates
+         * the use of compression.
+         */
+        if n == 0 && archive::jobs::is_gzip(&body) {
+            self.archive_cache_bytes(job, &body)?;
+        } else {
+            self.archive_cache(job, &aj)?;
+        }
+
+        Ok(aj)
+    }
+
+    fn archive_cache(
+        &self,
+        job: JobId,
+        aj: &archive::jobs::ArchivedJob,
+    ) -> Result<()> {
+        let (body, _) = aj.to_gzip_bytes(self.config.archive.pretty)?;
+        self.archive_cache_bytes(job, &body)
+    }
+
+    fn archive_cache_bytes(&self, job: JobId, body: &[u8]) -> Result<()> {
         let mut tf = tempfile::NamedTempFile::new_in(self.archive_dir()?)?;
-        tf.write_all(&body)?;
+        tf.write_all(body)?;
         tf.flush()?;
         tf.as_file_mut().sync_all()?;
         tf.persist(self.archive_path(job)?)?;
+        Ok(())
+    }
 
-        Ok(aj)
+    /**
+     * Fetch the archive of a job from the object store, bypassing the local
+     * cache, and check that it is intact.  Reports a description of the
+     * problem if the archive is missing or invalid, or that it could not be
+     * checked because it is in archival storage.  Other failures, such as
+     * an object store that cannot be reached, are reported as errors.  An
+     * archive that still holds secret store values is rewritten without them,
+     * and reported as a problem.
+     */
+    async fn archive_verify(
+        &self,
+        log: &Logger,
+        job: JobId,
+    ) -> Result<ArchiveVerifyOutcome> {
+        let akey = self.archive_object_key_with_version(job, "1");
+        let bucket = &self.config.storage.bucket;
+
+        let res = s3::retry(
+            log,
+            &self.config.storage.retry,
+            "archive verify",
+            || self.s3.get_object().bucket(bucket).key(&akey).send(),
+        )
+        .await;
+        let body = match res {
+            Ok(res) => res.body.collect().await?.to_vec(),
+            Err(e) if s3::is_not_found(&e) => {
+                return Ok(ArchiveVerifyOutcome::Problem(format!(
+                    "{bucket}:{akey} does not exist"
+                )));
+            }
+            Err(e) => {
+                let e = s3::fetch_error(e, &akey);
+                if e.is::<s3::NotRetrievable>() {
+                    return Ok(ArchiveVerifyOutcome::NotRetrievable);
+                }
+                bail!("fetching {bucket}:{akey}: {e}");
+            }
+        };
+
+        let mut aj = match archive::jobs::ArchivedJob::from_bytes(&body) {
+            Ok(aj) => aj,
+            Err(e) => {
+                return Ok(ArchiveVerifyOutcome::Problem(format!(
+                    "{bucket}:{akey} is corrupt: {e}"
+                )));
+            }
+        };
+        if !aj.is_valid() {
+            return Ok(ArchiveVerifyOutcome::Problem(format!(
+                "{bucket}:{akey} has unexpected version {:?}",
+                aj.version(),
+            )));
+        }
+        if aj.id() != job.to_string() {
+            return Ok(ArchiveVerifyOutcome::Problem(format!(
+                "{bucket}:{akey} is for the wrong job {:?}",
+                aj.id(),
+            )));
+        }
+
+        /*
+         * Archives written by older versions of the server included the values
+         * of secret store entries.  Replace any such archive with a copy that
+         * does not, and discard any cached copy.
+         */
+        let n = aj.scrub_secrets();
+        if n > 0 {
+            self.archive_store(log, job, &aj).await?;
+            let apath = self.archive_path(job)?;
+            match std::fs::remove_file(&apath) {
+                Ok(()) => (),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                Err(e) => bail!("removing {apath:?}: {e}"),
+            }
+            return Ok(ArchiveVerifyOutcome::Problem(format!(
+                "{bucket}:{akey} contained {n} secret values, which have \
+                been removed",
+            )));
+        }
+
+        Ok(ArchiveVerifyOutcome::Intact)
     }
 
     fn chunk_dir(&self) -> Result<PathBuf> {
@@ -490,7 +911,99 @@ impl Central {
         self.object_key("output", &format!("{job}/{file}"))
     }
 
+    fn chunk_index_path(&self, job: JobId, hash: &[u8]) -> Result<PathBuf> {
+        let mut p = self.chunk_dir()?;
+        p.push(job.to_string());
+        std::fs::create_dir_all(&p)?;
+        p.push(format!("sha256-{}", hex(hash)));
+        Ok(p)
+    }
+
+    /**
+     * Background tasks sleep between each pass through their work.  Returns
+     * false if the server is shutting down, in which case the task should
+     * exit rather than begin another pass.
+     */
+    async fn idle(&self, delay: Duration) -> bool {
+        tokio::select! {
+            _ = self.shutdown.cancelled() => false,
+            _ = tokio::time::sleep(delay) => true,
+        }
+    }
+
+    /**
+     * Determine the SHA-256 digest of an agent binary.  The digest is cached,
+     * and computed again only if the size or modification time of the file
+     * changes.
+     */
+    async fn agent_sha256(&self, filename: &str) -> Result<String> {
+        let md = tokio::fs::metadata(filename).await?;
+        let key = (md.len(), md.modified()?);
+
+        if let Some((k, digest)) =
+            self.inner.lock().unwrap().agent_sha256.get(filename)
+        {
+            if *k == key {
+                return Ok(digest.to_string());
+            }
+        }
+
+        let f = filename.to_string();
+        let digest = tokio::task::spawn_blocking(move || -> Result<String> {
+            Ok(hex(&hmac_sha256::Hash::hash(&std::fs::read(f)?)))
+        })
+        .await??;
+
+        self.inner
+            .lock()
+            .unwrap()
+            .agent_sha256
+            .insert(filename.to_string(), (key, digest.to_string()));
+
+        Ok(digest)
+    }
+
+    /**
+     * Check an uploaded chunk against the configured maximum chunk size.
+     */
+    fn check_chunk_size(&self, chunk: &[u8]) -> SResult<(), HttpError> {
+        let max = self.config.job.max_chunk_bytes;
+        let size = u64::try_from(chunk.len()).unwrap_or(u64::MAX);
+
+        if size > max {
+            return Err(HttpError::for_client_error(
+                Some(api::ErrorCode::ChunkTooLarge.to_string()),
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("chunk size {size} exceeds maximum of {max} bytes"),
+            ));
+        }
+
+        Ok(())
+    }
+
     fn write_chunk(&self, job: JobId, chunk: &[u8]) -> Result<Ulid> {
+        /*
+         * Clients often upload the same data more than once; e.g., a cache
+         * archive where large parts are unchanged.  We keep an index, by
+         * content hash, of the chunks already uploaded for this job.  If we
+         * have an identical chunk, return its ID rather than storing another
+         * copy.  The index is only an optimisation: if it is missing or out of
+         * date, we just store the chunk again.
+         */
+        let hash = hmac_sha256::Hash::hash(chunk);
+        let idx = self.chunk_index_path(job, &hash)?;
+        if let Ok(existing) = std::fs::read_to_string(&idx) {
+            if let Ok(cid) = existing.trim().parse::<Ulid>() {
+                let p = self.chunk_path(job, cid)?;
+                if p.metadata().map(|md| md.len()).ok()
+                    == Some(chunk.len() as u64)
+                {
+                    self.touch_chunks(job, &[cid]);
+                    return Ok(cid);
+                }
+            }
+        }
+
         /*
          * Assign an ID for this chunk and determine where will store it in the
          * file system.
@@ -505,15 +1018,41 @@ impl Central {
         bw.write_all(chunk).or_500()?;
         bw.flush()?;
 
+        std::fs::write(&idx, cid.to_string())?;
+
+        metrics::CHUNK_BYTES.add(chunk.len() as u64);
+
         Ok(cid)
     }
 
+    /**
+     * Chunks that have not been used for some time are removed by the chunk
+     * cleanup task.  Refresh the modification time of chunks that are about to
+     * be used so that they are not removed out from under a pending commit.
+     * Errors are ignored: a missing chunk will be reported by the commit.
+     */
+    fn touch_chunks(&self, job: JobId, chunks: &[Ulid]) {
+        let now = SystemTime::now();
+
+        for cid in chunks {
+            if let Ok(p) = self.chunk_path(job, *cid) {
+                std::fs::File::options()
+                    .write(true)
+                    .open(p)
+                    .and_then(|f| f.set_modified(now))
+                    .ok();
+            }
+        }
+    }
+
     fn commit_file(
         &self,
         job: JobId,
         chunks: &[Ulid],
         expected_size: u64,
-    ) -> Result<JobFileId> {
+    ) -> Result<(JobFileId, String)> {
+        self.touch_chunks(job, chunks);
+
         /*
          * Check that all of the chunks the client wants to use exist, and that
          * the sum of their sizes matches the total size.
@@ -547,13 +1086,13 @@ impl Central {
             .create_new(true)
             .write(true)
             .open(&fp)?;
+        let mut hash = hmac_sha256::Hash::new();
         {
             let mut bw = std::io::BufWriter::new(&mut fout);
             for (ip, _) in files.iter() {
-                let fin = std::fs::File::open(&ip).or_500()?;
-                let mut br = std::io::BufReader::new(fin);
-
-                std::io::copy(&mut br, &mut bw).or_500()?;
+                let buf = std::fs::read(&ip)?;
+                hash.update(&buf);
+                bw.write_all(&buf)?;
             }
             bw.flush()?;
         }
@@ -574,19 +1113,24 @@ impl Central {
             );
         }
 
-        Ok(fid)
+        metrics::FILE_BYTES.add(expected_size);
+
+        Ok((fid, hex(&hash.finalize())))
     }
 
     async fn file_presigned_url(
         &self,
+        log: &Logger,
         job: JobId,
         file: JobFileId,
+        path: &str,
         expiry_seconds: u64,
         content_type: Option<&str>,
         content_disposition: Option<&str>,
     ) -> Result<FilePresignedUrl> {
-        if expiry_seconds > 3600 {
-            bail!("expiry too long");
+        let max = self.config.job.max_signed_url_seconds;
+        if expiry_seconds > max {
+            bail!("expiry too long; URLs can last at most {max} seconds");
         }
 
         /*
@@ -594,9 +1138,22 @@ impl Central {
          */
         let key = self.file_object_key(job, file);
         let info = format!("object store at {}", key);
+        let bucket = &self.config.storage.bucket;
 
-        let mut obj =
-            self.s3.get_object().bucket(&self.config.storage.bucket).key(key);
+        /*
+         * A URL for an object in archival storage would produce only an error
+         * for whoever tries to use it, so check the storage class first.
+         */
+        let head =
+            s3::retry(log, &self.config.storage.retry, "file head", || {
+                self.s3.head_object().bucket(bucket).key(&key).send()
+            })
+            .await?;
+        if s3::is_cold(head.storage_class(), head.restore()) {
+            return Err(s3::NotRetrievable(key).into());
+        }
+
+        let mut obj = self.s3.get_object().bucket(bucket).key(key);
 
         /*
          * We may be asked to override some of the headers that S3 provides in
@@ -607,6 +1164,16 @@ impl Central {
         }
         if let Some(val) = content_disposition {
             obj = obj.response_content_disposition(val);
+        } else {
+            /*
+             * If the caller did not specify a disposition, suggest the final
+             * component of the output path as the file name.  Otherwise, the
+             * browser will use the final component of the object key, which is
+             * just the file ID.
+             */
+            obj = obj.response_content_disposition(
+                content_disposition_attachment(path, file),
+            );
         }
 
         let obj = obj
@@ -622,10 +1189,13 @@ impl Central {
 
     async fn file_response(
         &self,
+        log: &Logger,
         job: JobId,
         file: JobFileId,
     ) -> Result<FileResponse> {
         let op = self.file_path(job, file)?;
+        let sha256 =
+            self.db.job_file_by_id_opt(job, file)?.and_then(|f| f.sha256);
 
         Ok(if op.is_file() {
             /*
@@ -637,25 +1207,33 @@ impl Central {
             assert!(md.is_file());
             let fbs = FileBytesStream::new(f);
 
-            FileResponse { info, body: fbs.into_body(), size: md.len() }
+            FileResponse { info, body: fbs.into_body(), size: md.len(), sha256 }
         } else {
             /*
              * Otherwise, try to get it from the object store.
              */
             let key = self.file_object_key(job, file);
             let info = format!("object store at {}", key);
-            let obj = self
-                .s3
-                .get_object()
-                .bucket(&self.config.storage.bucket)
-                .key(key)
-                .send()
-                .await?;
+            let obj = s3::retry(
+                log,
+                &self.config.storage.retry,
+                "file fetch",
+                || {
+                    self.s3
+                        .get_object()
+                        .bucket(&self.config.storage.bucket)
+                        .key(&key)
+                        .send()
+                },
+            )
+            .await
+            .map_err(|e| s3::fetch_error(e, &key))?;
 
             FileResponse {
                 info,
                 size: obj.content_length.try_into().unwrap(),
                 body: Body::wrap_stream(obj.body),
+                sha256,
             }
         })
     }
@@ -665,13 +1243,14 @@ impl Central {
         log: &Logger,
         job: JobId,
         failed: bool,
+        failure_reason: Option<&str>,
     ) -> Result<bool> {
         if let Err(e) = self.files.mark_job_completed(job) {
             warn!(log, "job {job} cannot be completed yet: {e}");
             bail!("{}", e);
         }
 
-        let res = self.db.job_complete(job, failed)?;
+        let res = self.db.job_complete(job, failed, failure_reason)?;
 
         self.files.forget_job(job);
 
@@ -686,6 +1265,7 @@ impl Central {
     async fn load_job_for_user(
         &self,
         log: &Logger,
+        req: &RequestInfo,
         user: &AuthUser,
         id: JobId,
     ) -> SResult<Job, HttpError> {
@@ -702,7 +1282,7 @@ impl Central {
              * Users are always allowed to see their own job records.
              */
             Ok(job)
-        } else if user.has_privilege("admin.job.read") {
+        } else if user.has_privilege(readpriv) {
             /*
              * Users may be granted the right to view all jobs in the system,
              * regardless of who owns them.
@@ -711,6 +1291,13 @@ impl Central {
                 log,
                 "user {} used delegated admin privilege {readpriv}", user.name,
             );
+            self.audit(
+                req,
+                &user.id.to_string(),
+                Some(readpriv),
+                "job.read",
+                Some(job.id.to_string()),
+            )?;
             Ok(job)
         } else {
             Err(HttpError::for_client_error(
@@ -721,6 +1308,63 @@ impl Central {
         }
     }
 
+    /**
+     * Look for a job share token, either in the "X-Buildomat-Share" header or
+     * in the "share" query parameter passed in by the endpoint.  The header is
+     * preferred, as query strings appear in request logs.
+     */
+    fn _int_share_token(
+        &self,
+        _log: &Logger,
+        req: &RequestInfo,
+        query: Option<&str>,
+    ) -> SResult<Option<String>, HttpError> {
+        if let Some(h) = req.headers().get("x-buildomat-share") {
+            return match h.to_str() {
+                Ok(v) => Ok(Some(v.trim().to_string())),
+                Err(_) => unauth_response(),
+            };
+        }
+
+        Ok(query.map(str::to_string))
+    }
+
+    /**
+     * Load a job for a read-only request.  If the request carries a share
+     * token, that token must have been created for this specific job and no
+     * other authentication is required.  Otherwise, the request must be made
+     * by a user who is allowed to see the job.
+     */
+    async fn require_share_or_user(
+        &self,
+        log: &Logger,
+        req: &RequestInfo,
+        share: Option<&str>,
+        id: JobId,
+    ) -> SResult<Job, HttpError> {
+        let Some(t) = self._int_share_token(log, req, share)? else {
+            let user = self.require_user(log, req).await?;
+            return self.load_job_for_user(log, req, &user, id).await;
+        };
+
+        let share = match self.db.job_share_auth(&t) {
+            Ok(share) => share,
+            Err(e) => {
+                warn!(log, "share auth failure: {:?}", e);
+                return unauth_response();
+            }
+        };
+
+        if share.job != id {
+            warn!(log, "share token for job {} used for job {}", share.job, id);
+            return unauth_response();
+        }
+
+        self._int_rate_limit(log, &format!("share:{}", share.job))?;
+
+        self.db.job_by_id(id).or_500()
+    }
+
     /**
      * Load a job output record, either from the live database or the
      * archive.
@@ -730,31 +1374,69 @@ impl Central {
         log: &Logger,
         job: &Job,
         output: JobFileId,
-    ) -> Result<JobOutput> {
+    ) -> OResult<JobOutput> {
         if job.is_archived() {
             let aj = self.archive_load(log, job.id).await?;
 
-            aj.job_output(output)
+            aj.job_output(output)?.ok_or_else(|| {
+                OperationError::NotFound(format!(
+                    "output {output} for job {}",
+                    job.id
+                ))
+            })
         } else {
             self.db.job_output(job.id, output)
         }
     }
 
     /**
-     * Load all job output records for a particular job, either from the live
-     * database or the archive.
+     * Load all job input records for a particular job, either from the live
+     * database or the archive.  Inputs that have not yet been uploaded have no
+     * associated file.
+     */
+    async fn load_job_inputs(
+        &self,
+        log: &Logger,
+        job: &Job,
+    ) -> Result<Vec<(JobInput, Option<JobFile>)>> {
+        if job.is_archived() {
+            let aj = self.archive_load(log, job.id).await?;
+
+            aj.job_inputs()
+        } else {
+            self.db.job_inputs(job.id)
+        }
+    }
+
+    /**
+     * Load output records for a particular job, either from the live database
+     * or the archive.  Records are sorted by output ID in ascending order.
+     *
+     * If "after" is specified, only outputs with a greater ID are returned.
+     * If a limit is specified, at most that many records will be returned.
      */
     async fn load_job_outputs(
         &self,
         log: &Logger,
         job: &Job,
+        after: Option<JobFileId>,
+        limit: Option<usize>,
     ) -> Result<Vec<(JobOutput, JobFile)>> {
         if job.is_archived() {
             let aj = self.archive_load(log, job.id).await?;
 
-            aj.job_outputs()
+            let mut outs = aj
+                .job_outputs()?
+                .into_iter()
+                .filter(|(jop, _)| after.map(|a| jop.id > a).unwrap_or(true))
+                .collect::<Vec<_>>();
+            outs.sort_by_key(|(jop, _)| jop.id);
+            if let Some(limit) = limit {
+                outs.truncate(limit);
+            }
+            Ok(outs)
         } else {
-            self.db.job_outputs(job.id)
+            self.db.job_outputs_page(job.id, after, limit)
         }
     }
 
@@ -767,19 +1449,67 @@ impl Central {
      * be returned.  Event sequence numbers begin at 0 and increase
      * monotonically without holes.  If a number higher than that of the most
      * recently stored event is specified, an empty list is returned.
+     *
+     * If a task is specified, only events emitted by that task are returned.
+     * The sequence numbers of the returned events are not renumbered, so they
+     * may have holes, but they remain suitable for use as a minseq value in a
+     * subsequent call.  If a limit is specified, at most that many records
+     * will be returned.
      */
     async fn load_job_events(
         &self,
         log: &Logger,
         job: &Job,
         minseq: usize,
+        task: Option<u32>,
+        limit: Option<usize>,
     ) -> Result<Vec<JobEvent>> {
         if job.is_archived() {
             let aj = self.archive_load(log, job.id).await?;
 
-            aj.job_events(minseq)
+            aj.job_events(minseq, task, limit)
         } else {
-            self.db.job_events(job.id, minseq)
+            self.db.job_events(job.id, minseq, task, limit)
+        }
+    }
+
+    /**
+     * Prepare to read the events of a job a page at a time.  The archive of an
+     * archived job is loaded once here, rather than again for each page.
+     */
+    async fn job_event_pages(
+        &self,
+        log: &Logger,
+        job: &Job,
+    ) -> Result<JobEventPages> {
+        Ok(if job.is_archived() {
+            JobEventPages::Archive(self.archive_load(log, job.id).await?)
+        } else {
+            JobEventPages::Database(job.id)
+        })
+    }
+}
+
+pub(crate) enum JobEventPages {
+    Database(JobId),
+    Archive(archive::jobs::ArchivedJob),
+}
+
+impl JobEventPages {
+    fn page(
+        &self,
+        db: &db::Database,
+        minseq: usize,
+        task: Option<u32>,
+        limit: usize,
+    ) -> Result<Vec<JobEvent>> {
+        match self {
+            JobEventPages::Database(job) => {
+                db.job_events(*job, minseq, task, Some(limit))
+            }
+            JobEventPages::Archive(aj) => {
+                aj.job_events(minseq, task, Some(limit))
+            }
         }
     }
 }
@@ -803,6 +1533,64 @@ impl FileAgentQuery {
             Some(_) | None => false,
         }
     }
+
+    /**
+     * Select the agent binary for the platform described in this query.  Each
+     * property of a configured entry must match the value provided by the
+     * worker; properties that are not specified in the entry match anything.
+     */
+    fn agent_file(&self, cfg: &config::ConfigFileAgent) -> Option<String> {
+        if cfg.files.is_empty() {
+            return Some(
+                if self.is_linux() {
+                    "buildomat-agent-linux"
+                } else {
+                    "buildomat-agent"
+                }
+                .to_string(),
+            );
+        }
+
+        let matches = |want: &Option<String>, have: &Option<String>| {
+            want.is_none() || want == have
+        };
+
+        cfg.files
+            .iter()
+            .find(|f| {
+                matches(&f.kernel, &self.kernel)
+                    && matches(&f.mach, &self.mach)
+                    && matches(&f.proc, &self.proc)
+            })
+            .map(|f| f.file.to_string())
+    }
+}
+
+/*
+ * Metrics are presented in the Prometheus text format for collection by a
+ * monitoring system, which must authenticate with the configured metrics
+ * token, an admin token, or as a user with the "admin.metrics.read" privilege.
+ */
+#[endpoint {
+    method = GET,
+    path = "/metrics",
+    unpublished = true,
+}]
+async fn metrics_get(
+    rqctx: RequestContext<Arc<Central>>,
+) -> SResult<Response<Body>, HttpError> {
+    let c = rqctx.context();
+    let log = &rqctx.log;
+
+    c.require_metrics(log, &rqctx.request).await?;
+
+    let body = metrics::render(c).or_500()?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+        .header(CONTENT_LENGTH, body.len())
+        .body(Body::from(body))?)
 }
 
 #[endpoint {
@@ -814,19 +1602,35 @@ async fn file_agent(
     rqctx: RequestContext<Arc<Central>>,
     query: TypedQuery<FileAgentQuery>,
 ) -> SResult<Response<Body>, HttpError> {
+    let c = rqctx.context();
     let log = &rqctx.log;
     let q = query.into_inner();
 
     info!(log, "agent request; query = {:?}", q);
 
-    let filename =
-        if q.is_linux() { "buildomat-agent-linux" } else { "buildomat-agent" };
+    let Some(filename) = q.agent_file(&c.config.agent) else {
+        warn!(log, "no agent available for query {:?}", q);
+        return Err(HttpError::for_client_error(
+            None,
+            StatusCode::NOT_FOUND,
+            format!(
+                "no agent available for kernel {:?} machine {:?} \
+                processor {:?}",
+                q.kernel.as_deref().unwrap_or("unknown"),
+                q.mach.as_deref().unwrap_or("unknown"),
+                q.proc.as_deref().unwrap_or("unknown"),
+            ),
+        ));
+    };
     info!(log, "using agent file {:?}", filename);
 
+    let sha256 = c.agent_sha256(&filename).await.or_500()?;
     let f = tokio::fs::File::open(filename).await.or_500()?;
     let fbs = FileBytesStream::new(f);
 
-    Ok(Response::builder().body(fbs.into_body())?)
+    Ok(Response::builder()
+        .header(SHA256_HEADER, sha256)
+        .body(fbs.into_body())?)
 }
 
 #[tokio::main]
@@ -854,11 +1658,17 @@ async fn main() -> Result<()> {
     ad.register(api::admin::user_create).api_check()?;
     ad.register(api::admin::user_privilege_grant).api_check()?;
     ad.register(api::admin::user_privilege_revoke).api_check()?;
+    ad.register(api::admin::user_token_rotate).api_check()?;
+    ad.register(api::admin::user_token_expiry_set).api_check()?;
     ad.register(api::admin::workers_list).api_check()?;
     ad.register(api::admin::workers_recycle).api_check()?;
     ad.register(api::admin::worker_recycle).api_check()?;
+    ad.register(api::admin::admin_audit_get).api_check()?;
     ad.register(api::admin::admin_job_get).api_check()?;
     ad.register(api::admin::admin_job_archive_request).api_check()?;
+    ad.register(api::admin::admin_job_reimport).api_check()?;
+    ad.register(api::admin::admin_job_requeue).api_check()?;
+    ad.register(api::admin::admin_archive_verify).api_check()?;
     ad.register(api::admin::admin_jobs_get).api_check()?;
     ad.register(api::admin::factory_create).api_check()?;
     ad.register(api::admin::target_create).api_check()?;
@@ -868,7 +1678,11 @@ async fn main() -> Result<()> {
     ad.register(api::admin::target_redirect).api_check()?;
     ad.register(api::admin::target_rename).api_check()?;
     ad.register(api::user::job_events_get).api_check()?;
+    ad.register(api::user::job_events_search).api_check()?;
+    ad.register(api::user::job_log_get).api_check()?;
     ad.register(api::user::job_outputs_get).api_check()?;
+    ad.register(api::user::job_inputs_get).api_check()?;
+    ad.register(api::user::job_input_download).api_check()?;
     ad.register(api::user::job_output_download).api_check()?;
     ad.register(api::user::job_output_signed_url).api_check()?;
     ad.register(api::user::job_output_publish).api_check()?;
@@ -876,8 +1690,14 @@ async fn main() -> Result<()> {
     ad.register(api::user::job_store_get_all).api_check()?;
     ad.register(api::user::job_store_put).api_check()?;
     ad.register(api::user::job_submit).api_check()?;
+    ad.register(api::user::job_rerun).api_check()?;
+    ad.register(api::user::job_share).api_check()?;
+    ad.register(api::user::job_share_revoke).api_check()?;
+    ad.register(api::user::job_validate).api_check()?;
+    ad.register(api::user::job_submit_batch).api_check()?;
     ad.register(api::user::job_upload_chunk).api_check()?;
     ad.register(api::user::job_add_input).api_check()?;
+    ad.register(api::user::job_add_input_status).api_check()?;
     ad.register(api::user::job_add_input_sync).api_check()?;
     ad.register(api::user::job_cancel).api_check()?;
     ad.register(api::user::jobs_get).api_check()?;
@@ -886,7 +1706,10 @@ async fn main() -> Result<()> {
     ad.register(api::worker::worker_bootstrap).api_check()?;
     ad.register(api::worker::worker_ping).api_check()?;
     ad.register(api::worker::worker_job_append).api_check()?;
+    ad.register(api::worker::worker_job_append_batch).api_check()?;
+    ad.register(api::worker::worker_job_stream).api_check()?;
     ad.register(api::worker::worker_job_complete).api_check()?;
+    ad.register(api::worker::worker_job_metrics).api_check()?;
     ad.register(api::worker::worker_job_upload_chunk).api_check()?;
     ad.register(api::worker::worker_job_quota).api_check()?;
     ad.register(api::worker::worker_job_add_output).api_check()?;
@@ -896,6 +1719,7 @@ async fn main() -> Result<()> {
     ad.register(api::worker::worker_job_store_put).api_check()?;
     ad.register(api::worker::worker_task_append).api_check()?;
     ad.register(api::worker::worker_task_complete).api_check()?;
+    ad.register(api::worker::worker_task_env).api_check()?;
     ad.register(api::factory::factory_workers).api_check()?;
     ad.register(api::factory::factory_worker_get).api_check()?;
     ad.register(api::factory::factory_ping).api_check()?;
@@ -907,7 +1731,10 @@ async fn main() -> Result<()> {
     ad.register(api::factory::factory_lease).api_check()?;
     ad.register(api::factory::factory_lease_renew).api_check()?;
     ad.register(api::public::public_file_download).api_check()?;
+    ad.register(api::public::public_file_info).api_check()?;
+    ad.register(api::public::public_file_versions).api_check()?;
     ad.register(file_agent).api_check()?;
+    ad.register(metrics_get).api_check()?;
 
     if let Some(s) = p.opt_str("S") {
         let mut f = std::fs::OpenOptions::new()
@@ -929,6 +1756,14 @@ async fn main() -> Result<()> {
 
     let log = make_log("buildomat");
 
+    if config.admin.token.is_some() {
+        warn!(
+            log,
+            "the \"admin.token\" setting is deprecated; \
+            use \"[[admin.tokens]]\" instead"
+        );
+    }
+
     let mut datadir = std::env::current_dir()?;
     datadir.push("data");
     if !datadir.is_dir() {
@@ -939,12 +1774,49 @@ async fn main() -> Result<()> {
     dbfile.push("data.sqlite3");
     let db = db::Database::new(log.clone(), dbfile, config.sqlite.cache_kb)?;
 
+    /*
+     * Jobs submitted without a target use the configured default target, so
+     * make sure it exists before we accept any submissions.
+     */
+    let default_target = &config.job.default_target;
+    match db.target_resolve(default_target)? {
+        Some(t) => {
+            info!(
+                log,
+                "default target {:?} resolves to {:?} ({})",
+                default_target,
+                t.name,
+                t.id,
+            );
+        }
+        None => {
+            bail!(
+                "default target {:?} (\"job.default_target\" in the \
+                configuration file) does not resolve to a target",
+                default_target,
+            );
+        }
+    }
+
     let awscfg = aws_config::ConfigLoader::default()
         .region(config.storage.region())
         .credentials_provider(config.storage.creds())
         .load()
         .await;
-    let s3 = aws_sdk_s3::Client::new(&awscfg);
+    let mut s3cfg = aws_sdk_s3::config::Builder::from(&awscfg);
+    if let Some(endpoint) = config.storage.endpoint.as_deref() {
+        /*
+         * An S3-compatible object store other than AWS.  Presigned URLs are
+         * generated from the same configuration, so they will refer to this
+         * endpoint as well.
+         */
+        info!(log, "using object store endpoint {:?}", endpoint);
+        s3cfg = s3cfg.endpoint_url(endpoint);
+    }
+    if config.storage.path_style {
+        s3cfg = s3cfg.force_path_style(true);
+    }
+    let s3 = aws_sdk_s3::Client::from_conf(s3cfg.build());
 
     let files = files::Files::new(log.new(o!("component" => "files")));
 
@@ -953,19 +1825,22 @@ async fn main() -> Result<()> {
             hold: config.admin.hold,
             leases: Default::default(),
             archive_queue: Default::default(),
+            ratelimit: Default::default(),
+            agent_sha256: Default::default(),
         }),
         config,
         datadir,
         db,
         s3,
         files,
+        shutdown: CancellationToken::new(),
     });
 
-    c.files.start(&c, 4);
+    c.files.start(&c, c.config.files.workers);
 
     let c0 = Arc::clone(&c);
     let log0 = log.new(o!("component" => "job_assignment"));
-    let t_assign = tokio::task::spawn(async move {
+    let mut t_assign = tokio::task::spawn(async move {
         jobs::job_assignment(log0, c0)
             .await
             .context("job assignment task failure")
@@ -973,7 +1848,7 @@ async fn main() -> Result<()> {
 
     let c0 = Arc::clone(&c);
     let log0 = log.new(o!("component" => "chunk_cleanup"));
-    let t_chunks = tokio::task::spawn(async move {
+    let mut t_chunks = tokio::task::spawn(async move {
         chunks::chunk_cleanup(log0, c0)
             .await
             .context("chunk cleanup task failure")
@@ -981,7 +1856,7 @@ async fn main() -> Result<()> {
 
     let c0 = Arc::clone(&c);
     let log0 = log.new(o!("component" => "archive_files"));
-    let t_archive_files = tokio::task::spawn(async move {
+    let mut t_archive_files = tokio::task::spawn(async move {
         archive::files::archive_files(log0, c0)
             .await
             .context("archive files task failure")
@@ -989,24 +1864,44 @@ async fn main() -> Result<()> {
 
     let c0 = Arc::clone(&c);
     let log0 = log.new(o!("component" => "archive_jobs"));
-    let t_archive_jobs = tokio::task::spawn(async move {
+    let mut t_archive_jobs = tokio::task::spawn(async move {
         archive::jobs::archive_jobs(log0, c0)
             .await
             .context("archive jobs task failure")
     });
 
+    let c0 = Arc::clone(&c);
+    let log0 = log.new(o!("component" => "archive_cache"));
+    let mut t_archive_cache = tokio::task::spawn(async move {
+        archive::cache::archive_cache_clean(log0, c0)
+            .await
+            .context("archive cache clean task failure")
+    });
+
     let c0 = Arc::clone(&c);
     let log0 = log.new(o!("component" => "worker_cleanup"));
-    let t_workers = tokio::task::spawn(async move {
+    let mut t_workers = tokio::task::spawn(async move {
         workers::worker_cleanup(log0, c0)
             .await
             .context("worker cleanup task failure")
     });
 
+    let c0 = Arc::clone(&c);
+    let log0 = log.new(o!("component" => "audit_cleanup"));
+    let mut t_audit = tokio::task::spawn(async move {
+        audit::audit_cleanup(log0, c0)
+            .await
+            .context("audit cleanup task failure")
+    });
+
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sigint = signal(SignalKind::interrupt())?;
+
+    let c0 = Arc::clone(&c);
     let server = HttpServerStarter::new(
         #[allow(clippy::needless_update)]
         &ConfigDropshot {
-            request_body_max_bytes: 10 * 1024 * 1024,
+            request_body_max_bytes: c.config.job.request_body_max_bytes(),
             bind_address,
             ..Default::default()
         },
@@ -1016,16 +1911,70 @@ async fn main() -> Result<()> {
     )
     .map_err(|e| anyhow!("server startup failure: {:?}", e))?;
 
-    let server_task = server.start();
+    let mut server_task = server.start();
+
+    tokio::select! {
+        _ = &mut t_assign => bail!("task assignment task stopped early"),
+        _ = &mut t_chunks => bail!("chunk cleanup task stopped early"),
+        _ = &mut t_archive_files => bail!("archive files task stopped early"),
+        _ = &mut t_archive_jobs => bail!("archive jobs task stopped early"),
+        _ = &mut t_archive_cache => bail!("archive cache task stopped early"),
+        _ = &mut t_workers => bail!("worker cleanup task stopped early"),
+        _ = &mut t_audit => bail!("audit cleanup task stopped early"),
+        _ = &mut server_task => bail!("server stopped early"),
+        _ = sigterm.recv() => info!(log, "SIGTERM received; shutting down"),
+        _ = sigint.recv() => info!(log, "SIGINT received; shutting down"),
+    }
 
-    loop {
-        tokio::select! {
-            _ = t_assign => bail!("task assignment task stopped early"),
-            _ = t_chunks => bail!("chunk cleanup task stopped early"),
-            _ = t_archive_files => bail!("archive files task stopped early"),
-            _ = t_archive_jobs => bail!("archive jobs task stopped early"),
-            _ = t_workers => bail!("worker cleanup task stopped early"),
-            _ = server_task => bail!("server stopped early"),
+    let c = c0;
+    let grace = c.config.shutdown.grace();
+    let deadline = Instant::now() + grace;
+
+    /*
+     * Ask the background tasks to stop once they finish what they are doing
+     * now, and stop accepting new connections.  In-flight requests are allowed
+     * to complete, within the grace period.
+     */
+    c.shutdown.cancel();
+    match tokio::time::timeout(grace, server_task.close()).await {
+        Ok(Ok(())) => (),
+        Ok(Err(e)) => warn!(log, "server shutdown error: {e}"),
+        Err(_) => warn!(log, "in-flight requests did not complete in time"),
+    }
+
+    for t in [
+        t_assign,
+        t_chunks,
+        t_archive_files,
+        t_archive_jobs,
+        t_archive_cache,
+        t_workers,
+        t_audit,
+    ] {
+        let rem = deadline.saturating_duration_since(Instant::now());
+        match tokio::time::timeout(rem, t).await {
+            Ok(Ok(Ok(()))) => (),
+            Ok(Ok(Err(e))) => warn!(log, "background task error: {e:?}"),
+            Ok(Err(e)) => warn!(log, "background task panicked: {e}"),
+            Err(_) => {
+                warn!(log, "background tasks did not stop in time");
+                break;
+            }
         }
     }
+
+    /*
+     * Wait for any file commits that are queued or in progress, so that we do
+     * not leave partially assembled files behind.
+     */
+    while c.files.busy() {
+        if Instant::now() >= deadline {
+            warn!(log, "file commits did not complete in time");
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    info!(log, "shutdown complete");
+    Ok(())
 }
@@ -0,0 +1,246 @@
+/*
+ * Copyright 2023 Oxide Computer Company
+ */
+
+use std::fmt::Write;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::Central;
+
+/**
+ * A monotonically increasing count of events, or of bytes.
+ */
+pub(crate) struct Counter(AtomicU64);
+
+impl Counter {
+    const fn new() -> Counter {
+        Counter(AtomicU64::new(0))
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/**
+ * A quantity that may go up or down, like the number of bytes stored.
+ */
+pub(crate) struct Gauge(AtomicU64);
+
+impl Gauge {
+    const fn new() -> Gauge {
+        Gauge(AtomicU64::new(0))
+    }
+
+    pub fn set(&self, n: u64) {
+        self.0.store(n, Ordering::Relaxed);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/**
+ * The number of times an operation was performed, and the total time it took.
+ * This is presented to Prometheus as a summary without quantiles.
+ */
+pub(crate) struct Timing {
+    count: Counter,
+    sum_msec: Counter,
+}
+
+impl Timing {
+    const fn new() -> Timing {
+        Timing { count: Counter::new(), sum_msec: Counter::new() }
+    }
+
+    pub fn observe(&self, dur: Duration) {
+        self.count.inc();
+        self.sum_msec.add(dur.as_millis().try_into().unwrap_or(u64::MAX));
+    }
+}
+
+pub(crate) static ARCHIVE_UPLOAD: Timing = Timing::new();
+pub(crate) static ARCHIVE_DOWNLOAD: Timing = Timing::new();
+pub(crate) static S3_ERRORS: Counter = Counter::new();
+pub(crate) static CHUNK_BYTES: Gauge = Gauge::new();
+pub(crate) static FILE_BYTES: Gauge = Gauge::new();
+
+/*
+ * Counting the jobs in each state requires a scan of the job table, so the
+ * counts are reused for a short time rather than determined again for every
+ * request.
+ */
+const JOB_STATES_TTL: Duration = Duration::from_secs(60);
+static JOB_STATES: Mutex<Option<(Instant, Vec<(&'static str, u64)>)>> =
+    Mutex::new(None);
+
+fn job_state_counts(c: &Central) -> Result<Vec<(&'static str, u64)>> {
+    let mut js = JOB_STATES.lock().unwrap();
+
+    if let Some((when, counts)) = js.as_ref() {
+        if when.elapsed() < JOB_STATES_TTL {
+            return Ok(counts.clone());
+        }
+    }
+
+    let counts = c.db.job_state_counts()?;
+    *js = Some((Instant::now(), counts.clone()));
+    Ok(counts)
+}
+
+/**
+ * Determine the total size of the files within a directory and any
+ * directories it contains.  The stored byte gauges are updated as data is
+ * written, and then set from the result of this walk by the tasks that remove
+ * stored data.  Files may be removed while we are looking, so any that
+ * disappear are ignored.
+ */
+pub(crate) fn measure_dir(dir: &Path) -> Result<u64> {
+    let mut total = 0;
+
+    let ents = match std::fs::read_dir(dir) {
+        Ok(ents) => ents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+
+    for ent in ents {
+        let ent = ent?;
+        let md = match ent.path().symlink_metadata() {
+            Ok(md) => md,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        if md.is_dir() {
+            total += measure_dir(&ent.path())?;
+        } else if md.is_file() {
+            total += md.len();
+        }
+    }
+
+    Ok(total)
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {name} {help}").unwrap();
+    writeln!(out, "# TYPE {name} {kind}").unwrap();
+}
+
+fn counter(out: &mut String, name: &str, help: &str, c: &Counter) {
+    header(out, name, "counter", help);
+    writeln!(out, "{name} {}", c.get()).unwrap();
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, v: u64) {
+    header(out, name, "gauge", help);
+    writeln!(out, "{name} {v}").unwrap();
+}
+
+fn timing(out: &mut String, name: &str, help: &str, t: &Timing) {
+    header(out, name, "summary", help);
+    writeln!(out, "{name}_sum {:.3}", t.sum_msec.get() as f64 / 1000.0)
+        .unwrap();
+    writeln!(out, "{name}_count {}", t.count.get()).unwrap();
+}
+
+/**
+ * Render the current value of each metric in the Prometheus text exposition
+ * format.  Counters, timings, and the stored byte gauges are maintained as the
+ * server does its work; other gauges are determined from the database and
+ * other state at the time of the request.
+ */
+pub(crate) fn render(c: &Central) -> Result<String> {
+    let mut out = String::new();
+
+    header(&mut out, "buildomat_jobs", "gauge", "Number of jobs by state.");
+    for (state, n) in job_state_counts(c)? {
+        writeln!(out, "buildomat_jobs{{state=\"{state}\"}} {n}").unwrap();
+    }
+
+    gauge(
+        &mut out,
+        "buildomat_workers_active",
+        "Number of workers that have not been destroyed.",
+        c.db.workers_active()?.len().try_into().unwrap(),
+    );
+    gauge(
+        &mut out,
+        "buildomat_archive_queue_depth",
+        "Number of jobs explicitly queued for archival.",
+        c.inner.lock().unwrap().archive_queue.len().try_into().unwrap(),
+    );
+    gauge(
+        &mut out,
+        "buildomat_file_commit_workers",
+        "Number of threads configured to perform file commits.",
+        c.config.files.workers.try_into().unwrap(),
+    );
+    gauge(
+        &mut out,
+        "buildomat_file_commit_queue_depth",
+        "Number of file commits waiting for a thread.",
+        c.files.queue_depth().try_into().unwrap(),
+    );
+    gauge(
+        &mut out,
+        "buildomat_file_commit_active",
+        "Number of file commits in progress.",
+        c.files.active().try_into().unwrap(),
+    );
+
+    gauge(
+        &mut out,
+        "buildomat_chunk_bytes",
+        "Bytes of uploaded chunks stored locally.",
+        CHUNK_BYTES.get(),
+    );
+    gauge(
+        &mut out,
+        "buildomat_file_bytes",
+        "Bytes of input and output files stored locally.",
+        FILE_BYTES.get(),
+    );
+    counter(
+        &mut out,
+        "buildomat_s3_errors_total",
+        "Object store requests that failed after any retries, other than \
+        those for objects that do not exist.",
+        &S3_ERRORS,
+    );
+
+    timing(
+        &mut out,
+        "buildomat_archive_upload_seconds",
+        "Time taken to upload job archives to the object store.",
+        &ARCHIVE_UPLOAD,
+    );
+    timing(
+        &mut out,
+        "buildomat_archive_download_seconds",
+        "Time taken to download job archives from the object store.",
+        &ARCHIVE_DOWNLOAD,
+    );
+
+    Ok(out)
+}
@@ -0,0 +1,149 @@
+/*
+ * Copyright 2023 Oxide Computer Company
+ */
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/*
+ * Once we are tracking this many principals, discard the buckets that have
+ * refilled completely as they are indistinguishable from new buckets.
+ */
+const PRUNE_THRESHOLD: usize = 1000;
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+/**
+ * A token bucket rate limiter, keyed by the name of the authenticated
+ * principal making the request.
+ */
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: HashMap<String, Bucket>,
+}
+
+impl RateLimiter {
+    /**
+     * Take a token from the bucket for this principal.  If the bucket is
+     * empty, returns the time until a token will next be available.
+     */
+    pub fn take(
+        &mut self,
+        key: &str,
+        rate: u32,
+        burst: u32,
+    ) -> Result<(), Duration> {
+        self.take_at(key, rate, burst, Instant::now())
+    }
+
+    fn take_at(
+        &mut self,
+        key: &str,
+        rate: u32,
+        burst: u32,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let rate = f64::from(rate.max(1));
+        let burst = f64::from(burst.max(1));
+
+        if self.buckets.len() >= PRUNE_THRESHOLD {
+            self.buckets.retain(|_, b| {
+                let elapsed = now.saturating_duration_since(b.last);
+                b.tokens + elapsed.as_secs_f64() * rate < burst
+            });
+        }
+
+        let b = self
+            .buckets
+            .entry(key.to_string())
+            .or_insert(Bucket { tokens: burst, last: now });
+
+        /*
+         * Refill the bucket based on the time that has elapsed since we last
+         * looked at it:
+         */
+        let elapsed = now.saturating_duration_since(b.last);
+        b.tokens = (b.tokens + elapsed.as_secs_f64() * rate).min(burst);
+        b.last = now;
+
+        if b.tokens >= 1.0 {
+            b.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - b.tokens) / rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn burst_then_limit() {
+        let mut rl = RateLimiter::default();
+        let now = Instant::now();
+
+        for _ in 0..5 {
+            assert!(rl.take_at("user:a", 2, 5, now).is_ok());
+        }
+
+        /*
+         * The bucket is now empty, and refills at two tokens per second:
+         */
+        let wait = rl.take_at("user:a", 2, 5, now).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+
+        /*
+         * Other principals have their own bucket:
+         */
+        assert!(rl.take_at("user:b", 2, 5, now).is_ok());
+    }
+
+    #[test]
+    fn refill() {
+        let mut rl = RateLimiter::default();
+        let now = Instant::now();
+
+        assert!(rl.take_at("user:a", 1, 1, now).is_ok());
+        assert!(rl.take_at("user:a", 1, 1, now).is_err());
+
+        let later = now + Duration::from_millis(500);
+        let wait = rl.take_at("user:a", 1, 1, later).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+
+        let later = now + Duration::from_secs(1);
+        assert!(rl.take_at("user:a", 1, 1, later).is_ok());
+
+        /*
+         * A bucket never holds more than the burst size, no matter how long
+         * it has been left to refill:
+         */
+        let later = later + Duration::from_secs(3600);
+        assert!(rl.take_at("user:a", 1, 2, later).is_ok());
+        assert!(rl.take_at("user:a", 1, 2, later).is_ok());
+        assert!(rl.take_at("user:a", 1, 2, later).is_err());
+    }
+
+    #[test]
+    fn prune_full_buckets() {
+        let mut rl = RateLimiter::default();
+        let now = Instant::now();
+
+        for i in 0..PRUNE_THRESHOLD {
+            assert!(rl.take_at(&format!("user:{i}"), 1, 1, now).is_ok());
+        }
+        assert_eq!(rl.buckets.len(), PRUNE_THRESHOLD);
+
+        /*
+         * Once every bucket has refilled, they are all discarded before the
+         * new principal is added:
+         */
+        let later = now + Duration::from_secs(2);
+        assert!(rl.take_at("user:new", 1, 1, later).is_ok());
+        assert_eq!(rl.buckets.len(), 1);
+    }
+}
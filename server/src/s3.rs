@@ -0,0 +1,393 @@
+/*
+ * Copyright 2023 Oxide Computer Company
+ */
+
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::result::Result as SResult;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use aws_sdk_s3::operation::get_object::GetObjectError;
+use aws_sdk_s3::types::{
+    CompletedMultipartUpload, CompletedPart, StorageClass,
+};
+use aws_smithy_http::byte_stream::{ByteStream, Length};
+use aws_smithy_http::result::SdkError;
+use rand::{thread_rng, Rng};
+#[allow(unused_imports)]
+use slog::{error, info, warn, Logger};
+
+use crate::config::{ConfigFileStorage, ConfigFileStorageRetry};
+
+/**
+ * Determine whether a failed object store request is worth trying again.
+ * Transport failures, timeouts, throttling, and server errors are generally
+ * transient.  Other service errors, like a request for an object that does not
+ * exist, will not succeed on a subsequent attempt.
+ */
+fn is_retryable<E>(e: &SdkError<E>) -> bool {
+    match e {
+        SdkError::TimeoutError(_)
+        | SdkError::DispatchFailure(_)
+        | SdkError::ResponseError(_) => true,
+        SdkError::ServiceError(_) => e
+            .raw_response()
+            .map(|res| {
+                let status = res.http().status();
+                status.is_server_error() || status.as_u16() == 429
+            })
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/**
+ * Determine whether a failed object store request failed because the object
+ * does not exist.
+ */
+pub(crate) fn is_not_found<E>(e: &SdkError<E>) -> bool {
+    matches!(e, SdkError::ServiceError(_))
+        && e.raw_response()
+            .map(|res| res.http().status().as_u16() == 404)
+            .unwrap_or(false)
+}
+
+/**
+ * An object could not be fetched because it is held in an archival storage
+ * class, like "GLACIER", and must be restored before it can be read.
+ */
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "{0} is in archival storage and must be restored before it can be read"
+)]
+pub(crate) struct NotRetrievable(pub String);
+
+/**
+ * Determine whether an object must be restored before it can be read, given
+ * the storage class and restore status reported when fetching its metadata.
+ * Objects in "GLACIER" or "DEEP_ARCHIVE" can be read only while a restored
+ * copy is available.
+ */
+pub(crate) fn is_cold(
+    class: Option<&StorageClass>,
+    restore: Option<&str>,
+) -> bool {
+    matches!(class, Some(StorageClass::Glacier | StorageClass::DeepArchive))
+        && !restore.map_or(false, |r| r.contains("ongoing-request=\"false\""))
+}
+
+/**
+ * Convert a failed object fetch into an error.  If the object is in archival
+ * storage, the error is a NotRetrievable so that the problem can be reported
+ * clearly to the client.
+ */
+pub(crate) fn fetch_error(
+    e: SdkError<GetObjectError>,
+    key: &str,
+) -> anyhow::Error {
+    if e.as_service_error().map_or(false, |e| e.is_invalid_object_state()) {
+        NotRetrievable(key.to_string()).into()
+    } else {
+        e.into()
+    }
+}
+
+/**
+ * Perform an object store request, retrying with jittered exponential backoff
+ * if it fails in a way that might be transient.  The closure is called once
+ * for each attempt, and must construct a fresh request each time.
+ */
+pub(crate) async fn retry<T, E, F, Fut>(
+    log: &Logger,
+    cfg: &ConfigFileStorageRetry,
+    what: &str,
+    mut func: F,
+) -> SResult<T, SdkError<E>>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = SResult<T, SdkError<E>>>,
+    E: std::error::Error + 'static,
+{
+    let mut attempt = 1;
+
+    loop {
+        match func().await {
+            Ok(res) => return Ok(res),
+            Err(e) if attempt < cfg.max_attempts && is_retryable(&e) => {
+                /*
+                 * Double the delay for each failed attempt, and then add up to
+                 * half again as much to avoid synchronised retries.
+                 */
+                let base = cfg
+                    .base_delay_ms
+                    .saturating_mul(1 << (attempt - 1).min(16));
+                let jitter = thread_rng().gen_range(0..=base / 2);
+                let delay = Duration::from_millis(base.saturating_add(jitter));
+
+                warn!(
+                    log,
+                    "object store {what} failed (attempt {attempt} of {}); \
+                    retrying in {} ms: {e}",
+                    cfg.max_attempts,
+                    delay.as_millis(),
+                );
+
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => {
+                /*
+                 * A missing object is an expected outcome for some requests,
+                 * rather than a problem with the object store.
+                 */
+                if !is_not_found(&e) {
+                    crate::metrics::S3_ERRORS.inc();
+                }
+                return Err(e);
+            }
+        }
+    }
+}
+
+/*
+ * Each part of a multipart upload, except the last, must be at least 5MB.  The
+ * object store allows at most 10,000 parts, so this part size allows for files
+ * up to roughly 640GB; larger files use proportionally larger parts.  No
+ * object may be larger than 5TB.
+ */
+const MULTIPART_PART_SIZE: u64 = 64 * 1024 * 1024;
+const MULTIPART_MAX_PARTS: u64 = 10_000;
+const MAX_OBJECT_SIZE: u64 = 5 * 1024 * 1024 * 1024 * 1024;
+const MULTIPART_CONCURRENCY: usize = 4;
+
+/**
+ * Choose the part size for a multipart upload of a file of this size, such
+ * that the upload needs no more than the maximum number of parts.
+ */
+fn multipart_part_size(size: u64) -> Result<u64> {
+    if size > MAX_OBJECT_SIZE {
+        bail!("file of {size} bytes exceeds the maximum object size");
+    }
+
+    Ok(MULTIPART_PART_SIZE.max(size.div_ceil(MULTIPART_MAX_PARTS)))
+}
+
+async fn upload_part(
+    log: Logger,
+    s3: aws_sdk_s3::Client,
+    cfg: ConfigFileStorage,
+    upload_id: String,
+    key: String,
+    path: PathBuf,
+    part_number: i32,
+    offset: u64,
+    length: u64,
+) -> Result<CompletedPart> {
+    let what = format!("upload of part {part_number} of {key}");
+
+    let res = retry(&log, &cfg.retry, &what, || async {
+        let stream = ByteStream::read_from()
+            .path(&path)
+            .offset(offset)
+            .length(Length::Exact(length))
+            .build()
+            .await
+            .map_err(SdkError::construction_failure)?;
+
+        s3.upload_part()
+            .bucket(&cfg.bucket)
+            .key(&key)
+            .upload_id(&upload_id)
+            .part_number(part_number)
+            .content_length(length.try_into().unwrap())
+            .body(stream)
+            .send()
+            .await
+    })
+    .await?;
+
+    Ok(CompletedPart::builder()
+        .set_e_tag(res.e_tag)
+        .part_number(part_number)
+        .build())
+}
+
+async fn upload_parts(
+    log: &Logger,
+    s3: &aws_sdk_s3::Client,
+    cfg: &ConfigFileStorage,
+    upload_id: &str,
+    key: &str,
+    path: &Path,
+    size: u64,
+    part_size: u64,
+) -> Result<Vec<CompletedPart>> {
+    let mut tasks = tokio::task::JoinSet::new();
+    let mut parts = Vec::new();
+
+    let mut offset = 0;
+    let mut part_number = 1;
+    while offset < size {
+        /*
+         * Keep a bounded number of part uploads in flight at once.
+         */
+        while tasks.len() >= MULTIPART_CONCURRENCY {
+            parts.push(tasks.join_next().await.unwrap()??);
+        }
+
+        let length = part_size.min(size - offset);
+        tasks.spawn(upload_part(
+            log.clone(),
+            s3.clone(),
+            cfg.clone(),
+            upload_id.to_string(),
+            key.to_string(),
+            path.to_path_buf(),
+            part_number,
+            offset,
+            length,
+        ));
+
+        offset += length;
+        part_number += 1;
+    }
+
+    while let Some(res) = tasks.join_next().await {
+        parts.push(res??);
+    }
+
+    parts.sort_by_key(|p| p.part_number());
+    Ok(parts)
+}
+
+/**
+ * Upload a large file to the object store in several parts.  If the upload
+ * fails, we abort it so that the object store does not retain the parts we
+ * have uploaded so far.  Once the upload is complete, we check that the size of
+ * the resulting object matches the size of the local file.
+ */
+pub(crate) async fn upload_multipart(
+    log: &Logger,
+    s3: &aws_sdk_s3::Client,
+    cfg: &ConfigFileStorage,
+    key: &str,
+    path: &Path,
+    size: u64,
+    storage_class: Option<StorageClass>,
+) -> Result<()> {
+    let part_size = multipart_part_size(size)?;
+
+    let res = retry(log, &cfg.retry, "multipart upload creation", || {
+        s3.create_multipart_upload()
+            .bucket(&cfg.bucket)
+            .key(key)
+            .set_storage_class(storage_class.clone())
+            .send()
+    })
+    .await?;
+    let Some(upload_id) = res.upload_id else {
+        bail!("multipart upload for {key} has no upload ID");
+    };
+
+    info!(log, "multipart upload {upload_id} for {key} started";
+        "size" => size, "part_size" => part_size);
+
+    let res = async {
+        let parts =
+            upload_parts(log, s3, cfg, &upload_id, key, path, size, part_size)
+                .await?;
+        let nparts = parts.len();
+
+        retry(log, &cfg.retry, "multipart upload completion", || {
+            s3.complete_multipart_upload()
+                .bucket(&cfg.bucket)
+                .key(key)
+                .upload_id(&upload_id)
+                .multipart_upload(
+                    CompletedMultipartUpload::builder()
+                        .set_parts(Some(parts.clone()))
+                        .build(),
+                )
+                .send()
+        })
+        .await?;
+
+        Ok::<_, anyhow::Error>(nparts)
+    }
+    .await;
+
+    let nparts = match res {
+        Ok(nparts) => nparts,
+        Err(e) => {
+            warn!(log, "aborting multipart upload {upload_id} for {key}: {e}");
+            if let Err(e) = s3
+                .abort_multipart_upload()
+                .bucket(&cfg.bucket)
+                .key(key)
+                .upload_id(&upload_id)
+                .send()
+                .await
+            {
+                error!(
+                    log,
+                    "could not abort multipart upload {upload_id} for {key}: \
+                    {e}"
+                );
+            }
+            return Err(e);
+        }
+    };
+
+    /*
+     * Confirm that the object store has an object of the expected size.
+     */
+    let res = retry(log, &cfg.retry, "object size check", || {
+        s3.head_object().bucket(&cfg.bucket).key(key).send()
+    })
+    .await?;
+    if res.content_length() != i64::try_from(size)? {
+        bail!(
+            "multipart upload for {key}: object size {} != local size {size}",
+            res.content_length(),
+        );
+    }
+
+    info!(log, "multipart upload {upload_id} for {key} complete";
+        "parts" => nparts);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn part_size() {
+        let mb = 1024 * 1024;
+
+        assert_eq!(multipart_part_size(100 * mb).unwrap(), MULTIPART_PART_SIZE);
+        assert_eq!(
+            multipart_part_size(MULTIPART_PART_SIZE * MULTIPART_MAX_PARTS)
+                .unwrap(),
+            MULTIPART_PART_SIZE,
+        );
+
+        /*
+         * Larger files need larger parts, so that the number of parts remains
+         * within the limit:
+         */
+        for size in [
+            MULTIPART_PART_SIZE * MULTIPART_MAX_PARTS + 1,
+            2 * 1024 * 1024 * mb + 7,
+            MAX_OBJECT_SIZE,
+        ] {
+            let ps = multipart_part_size(size).unwrap();
+            assert!(ps > MULTIPART_PART_SIZE);
+            assert!(size.div_ceil(ps) <= MULTIPART_MAX_PARTS);
+        }
+
+        assert!(multipart_part_size(MAX_OBJECT_SIZE + 1).is_err());
+    }
+}
@@ -25,6 +25,37 @@ async fn worker_cleanup_one(log: &Logger, c: &Central) -> Result<()> {
         }
 
         let jobs = c.db.worker_jobs(w.id)?;
+
+        let stale = c.config.worker.ping_stale();
+        if w.is_stale(Utc::now(), stale) {
+            /*
+             * The agent on this worker has stopped checking in with us.  Let
+             * the owner of any job it was running know why the job is going
+             * to stop, and recycle the worker.
+             */
+            warn!(
+                log,
+                "worker {} has not pinged for {} seconds; recycling",
+                w.id,
+                stale.as_secs(),
+            );
+            for j in jobs.iter().filter(|j| !j.failed && !j.complete) {
+                c.db.job_append_event(
+                    j.id,
+                    None,
+                    "control",
+                    Utc::now(),
+                    None,
+                    &format!(
+                        "worker has not responded for {} seconds; aborting",
+                        stale.as_secs(),
+                    ),
+                )?;
+            }
+            c.db.worker_recycle(w.id)?;
+            continue;
+        }
+
         if jobs.is_empty() {
             /*
              * Idle workers should be assigned relatively promptly.  If a worker
@@ -51,7 +82,7 @@ async fn worker_cleanup_one(log: &Logger, c: &Central) -> Result<()> {
              * timestamp on the first control event.
              */
             let control =
-                c.db.job_events(j.id, 0)?
+                c.db.job_events(j.id, 0, None, None)?
                     .iter()
                     .find(|jev| jev.stream == "control")
                     .cloned();
@@ -96,6 +127,9 @@ pub(crate) async fn worker_cleanup(log: Logger, c: Arc<Central>) -> Result<()> {
             error!(log, "worker cleanup task error: {:?}", e);
         }
 
-        tokio::time::sleep(delay).await;
+        if !c.idle(delay).await {
+            info!(log, "stop worker cleanup task");
+            return Ok(());
+        }
     }
 }